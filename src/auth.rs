@@ -0,0 +1,160 @@
+//! Shared authentication state that is refreshed once however many requests need it.
+
+use std::future::Future;
+
+use tokio::sync::{Mutex, RwLock};
+
+/// Holds a credential such as an access token, refreshing it at most once at a time.
+///
+/// Embed it in an [`Api`](crate::Api) implementation and read it from
+/// [`Api::pre_request_async`](crate::Api::pre_request_async). When the credential is missing or stale, the first request
+/// to notice runs the refresh while concurrent requests wait for it and then reuse its result, rather than all
+/// refreshing at once. If the refresh fails, its error is returned to that request and the next one tries again.
+///
+/// ```rust
+/// use std::time::{Duration, Instant};
+///
+/// use api_client::{api, Api, AuthState};
+/// use reqwest::{Client, RequestBuilder};
+///
+/// #[derive(Clone)]
+/// struct Token {
+///     value: String,
+///     expires: Instant,
+/// }
+///
+/// struct ExampleApi {
+///     client: Client,
+///     token: AuthState<Token>,
+/// }
+///
+/// impl ExampleApi {
+///     async fn login(&self) -> api_client::Result<Token> {
+///         let value = self.token_endpoint().await?;
+///         Ok(Token { value, expires: Instant::now() + Duration::from_secs(3600) })
+///     }
+///
+///     api! {
+///         fn token_endpoint() -> String {
+///             POST "https://example.com/oauth/token"
+///             no_pre_request
+///         }
+///
+///         fn example() -> String {
+///             GET "https://example.com"
+///         }
+///     }
+/// }
+///
+/// impl Api for ExampleApi {
+///     fn client(&self) -> &Client {
+///         &self.client
+///     }
+///
+///     async fn pre_request_async(&self, request: RequestBuilder) -> api_client::Result<RequestBuilder> {
+///         let token = self
+///             .token
+///             .get_or_refresh_if(|token| token.expires <= Instant::now(), || self.login())
+///             .await?;
+///         Ok(request.bearer_auth(token.value))
+///     }
+/// }
+/// ```
+#[derive(Debug)]
+pub struct AuthState<T> {
+    /// The current credential, if there is one.
+    value: RwLock<Option<T>>,
+    /// Held while the credential is refreshed, so only one refresh runs at a time.
+    refresh: Mutex<()>,
+}
+
+impl<T> Default for AuthState<T> {
+    #[inline]
+    fn default() -> Self {
+        AuthState::new()
+    }
+}
+
+impl<T> AuthState<T> {
+    /// Creates an empty state, refreshed by the first request.
+    #[must_use]
+    pub fn new() -> Self {
+        AuthState {
+            value: RwLock::new(None),
+            refresh: Mutex::new(()),
+        }
+    }
+
+    /// Creates a state holding `value`, such as a token loaded from disk.
+    #[must_use]
+    pub fn with(value: T) -> Self {
+        AuthState {
+            value: RwLock::new(Some(value)),
+            refresh: Mutex::new(()),
+        }
+    }
+}
+
+impl<T: Clone> AuthState<T> {
+    /// Returns the current credential, if there is one, without refreshing it.
+    pub async fn get(&self) -> Option<T> {
+        self.value.read().await.clone()
+    }
+
+    /// Replaces the current credential with `value`.
+    pub async fn set(&self, value: T) {
+        *self.value.write().await = Some(value);
+    }
+
+    /// Removes the current credential, so the next request refreshes it, such as after the server rejects it.
+    pub async fn invalidate(&self) {
+        *self.value.write().await = None;
+    }
+
+    /// Returns the current credential, or calls `refresh` to get one if there isn't one.
+    ///
+    /// # Errors
+    /// Returns the error from `refresh` if it fails.
+    pub async fn get_or_refresh<F, Fut, E>(&self, refresh: F) -> Result<T, E>
+    where
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = Result<T, E>>,
+    {
+        self.get_or_refresh_if(|_| false, refresh).await
+    }
+
+    /// Returns the current credential, or calls `refresh` to get one if there isn't one or `stale` returns `true` for
+    /// it, such as when it has expired.
+    ///
+    /// # Errors
+    /// Returns the error from `refresh` if it fails.
+    pub async fn get_or_refresh_if<S, F, Fut, E>(&self, stale: S, refresh: F) -> Result<T, E>
+    where
+        S: Fn(&T) -> bool,
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = Result<T, E>>,
+    {
+        if let Some(value) = self.current(&stale).await {
+            return Ok(value);
+        }
+
+        let _refresh = self.refresh.lock().await;
+        // Another request may have refreshed the credential while this one waited.
+        if let Some(value) = self.current(&stale).await {
+            return Ok(value);
+        }
+        let value = refresh().await?;
+        self.set(value.clone()).await;
+        Ok(value)
+    }
+
+    /// Returns the current credential unless it's missing or stale.
+    async fn current(&self, stale: impl Fn(&T) -> bool) -> Option<T> {
+        self.value
+            .read()
+            .await
+            .as_ref()
+            .filter(|value| !stale(value))
+            .cloned()
+    }
+}
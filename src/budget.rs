@@ -0,0 +1,379 @@
+//! Call budgets that cap how many requests may be made within a rolling window.
+
+use std::{
+    collections::{HashMap, VecDeque},
+    fmt,
+    sync::{Arc, Mutex, PoisonError},
+    time::{Duration, SystemTime},
+};
+
+use crate::{Error, Result};
+
+/// What a [`Budget`] does once it is exhausted.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BudgetPolicy {
+    /// Fail the request with [`Error::BudgetExhausted`] without sending it.
+    Error,
+    /// Log a warning and send the request anyway.
+    Warn,
+}
+
+impl Default for BudgetPolicy {
+    #[inline]
+    fn default() -> Self {
+        BudgetPolicy::Error
+    }
+}
+
+/// Keeps the calls recorded by a [`Budget`], so restarting the process doesn't reset it, like a
+/// [`TokenStore`](crate::TokenStore) does for tokens.
+///
+/// [`MemoryBudgetStore`] keeps them in memory and `FileBudgetStore` in a JSON file (requires the `json` feature).
+/// Other storage implements the trait itself.
+#[cfg_attr(feature = "send", async_trait::async_trait)]
+#[cfg_attr(not(feature = "send"), async_trait::async_trait(?Send))]
+pub trait BudgetStore: Send + Sync {
+    /// Returns the call times recorded for the budget named `budget`, oldest first. Calls that can't be read are
+    /// treated as missing.
+    async fn load(&self, budget: &str) -> Vec<SystemTime>;
+
+    /// Replaces the call times recorded for the budget named `budget` with `calls`.
+    ///
+    /// # Errors
+    /// Implementations may return an error if the calls can't be stored.
+    async fn save(&self, budget: &str, calls: &[SystemTime]) -> Result<()>;
+}
+
+/// A [`BudgetStore`] keeping calls in memory, so they're lost when the process exits.
+#[derive(Debug, Default)]
+pub struct MemoryBudgetStore {
+    /// The calls of each budget.
+    calls: Mutex<HashMap<String, Vec<SystemTime>>>,
+}
+
+impl MemoryBudgetStore {
+    /// Creates an empty store.
+    #[must_use]
+    pub fn new() -> Self {
+        MemoryBudgetStore::default()
+    }
+}
+
+#[cfg_attr(feature = "send", async_trait::async_trait)]
+#[cfg_attr(not(feature = "send"), async_trait::async_trait(?Send))]
+impl BudgetStore for MemoryBudgetStore {
+    async fn load(&self, budget: &str) -> Vec<SystemTime> {
+        self.calls
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .get(budget)
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    async fn save(&self, budget: &str, calls: &[SystemTime]) -> Result<()> {
+        self.calls
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .insert(budget.to_string(), calls.to_vec());
+        Ok(())
+    }
+}
+
+#[cfg_attr(feature = "send", async_trait::async_trait)]
+#[cfg_attr(not(feature = "send"), async_trait::async_trait(?Send))]
+impl<T: BudgetStore + ?Sized> BudgetStore for Arc<T> {
+    async fn load(&self, budget: &str) -> Vec<SystemTime> {
+        (**self).load(budget).await
+    }
+
+    async fn save(&self, budget: &str, calls: &[SystemTime]) -> Result<()> {
+        (**self).save(budget, calls).await
+    }
+}
+
+#[cfg(feature = "json")]
+pub use file::FileBudgetStore;
+
+/// Storing calls in a JSON file.
+#[cfg(feature = "json")]
+mod file {
+    use std::{
+        path::PathBuf,
+        time::{Duration, SystemTime, UNIX_EPOCH},
+    };
+
+    use serde_json::{Map, Value};
+    use tokio::{fs, sync::Mutex};
+
+    use super::BudgetStore;
+    use crate::{token_store::write_file, Result};
+
+    /// A [`BudgetStore`] keeping calls in a JSON file, as seconds since the Unix epoch under the name of each budget
+    /// (requires the `json` feature).
+    ///
+    /// Like [`FileTokenStore`](crate::FileTokenStore), the file is replaced by renaming a temporary file over it, so
+    /// a load never sees a partly written file. Saves of budgets sharing the store are made one at a time, so they
+    /// don't overwrite each other's calls.
+    ///
+    /// ```rust,no_run
+    /// use std::time::Duration;
+    ///
+    /// use api_client::{Budget, FileBudgetStore};
+    ///
+    /// let reports = Budget::new("reports", 1000, Duration::from_secs(30 * 24 * 60 * 60))
+    ///     .store(FileBudgetStore::new("/home/user/.config/example/budgets.json"));
+    /// ```
+    #[derive(Debug)]
+    pub struct FileBudgetStore {
+        /// The path of the file.
+        path: PathBuf,
+        /// Held while the file is read and replaced by a save.
+        saving: Mutex<()>,
+    }
+
+    impl FileBudgetStore {
+        /// Creates a store keeping calls at `path`.
+        #[must_use]
+        pub fn new(path: impl Into<PathBuf>) -> Self {
+            FileBudgetStore {
+                path: path.into(),
+                saving: Mutex::new(()),
+            }
+        }
+
+        /// Reads the calls of every budget in the file, or none if it can't be read.
+        async fn read(&self) -> Map<String, Value> {
+            let Ok(bytes) = fs::read(&self.path).await else {
+                return Map::new();
+            };
+            match serde_json::from_slice(&bytes) {
+                Ok(Value::Object(budgets)) => budgets,
+                _ => Map::new(),
+            }
+        }
+    }
+
+    #[cfg_attr(feature = "send", async_trait::async_trait)]
+    #[cfg_attr(not(feature = "send"), async_trait::async_trait(?Send))]
+    impl BudgetStore for FileBudgetStore {
+        async fn load(&self, budget: &str) -> Vec<SystemTime> {
+            let budgets = self.read().await;
+            let Some(Value::Array(calls)) = budgets.get(budget) else {
+                return Vec::new();
+            };
+            calls
+                .iter()
+                .filter_map(Value::as_f64)
+                .map(|seconds| UNIX_EPOCH + Duration::from_secs_f64(seconds.max(0.0)))
+                .collect()
+        }
+
+        async fn save(&self, budget: &str, calls: &[SystemTime]) -> Result<()> {
+            let _saving = self.saving.lock().await;
+            let mut budgets = self.read().await;
+            let calls = calls
+                .iter()
+                .filter_map(|call| call.duration_since(UNIX_EPOCH).ok())
+                .map(|since| Value::from(since.as_secs_f64()))
+                .collect();
+            budgets.insert(budget.to_string(), Value::Array(calls));
+            write_file(&self.path, Value::Object(budgets).to_string().as_bytes()).await?;
+            Ok(())
+        }
+    }
+}
+
+/// Caps the number of calls made within a rolling window.
+///
+/// Endpoints opt in with a `budget = "name"` clause, and the [`Api`](crate::Api) implementation hands out the budget
+/// for that name from [`Api::budget`](crate::Api::budget). Unlike rate limiting this never delays requests; it is a
+/// ceiling on cost.
+///
+/// ```rust
+/// use std::time::Duration;
+///
+/// use api_client::{api, Api, Budget, BudgetPolicy};
+/// use reqwest::Client;
+///
+/// struct ExampleApi {
+///     client: Client,
+///     reports: Budget,
+/// }
+///
+/// impl Api for ExampleApi {
+///     fn client(&self) -> &Client {
+///         &self.client
+///     }
+///
+///     fn budget(&self, name: &str) -> Option<&Budget> {
+///         (name == "reports").then(|| &self.reports)
+///     }
+/// }
+///
+/// impl ExampleApi {
+///     api! {
+///         fn report(id: u32) -> String {
+///             GET "https://example.com/reports/{id}"
+///             budget = "reports"
+///         }
+///     }
+/// }
+///
+/// let api = ExampleApi {
+///     client: Client::new(),
+///     reports: Budget::new("reports", 1000, Duration::from_secs(30 * 24 * 60 * 60)).policy(BudgetPolicy::Warn),
+/// };
+/// # async fn run(api: ExampleApi) {
+/// assert_eq!(api.reports.remaining().await, 1000);
+/// # }
+/// ```
+pub struct Budget {
+    /// Name of the budget, used for errors, logs, and the store.
+    name: String,
+    /// Maximum number of calls within `window`.
+    limit: usize,
+    /// Length of the rolling window.
+    window: Duration,
+    /// What to do once the budget is exhausted.
+    policy: BudgetPolicy,
+    /// Where calls are persisted, if anywhere.
+    store: Option<Box<dyn BudgetStore>>,
+    /// Calls made within the window, oldest first; `None` until loaded from the store, which concurrent first calls
+    /// wait for.
+    calls: tokio::sync::Mutex<Option<VecDeque<SystemTime>>>,
+}
+
+impl Budget {
+    /// Creates a budget allowing `limit` calls per `window`.
+    #[must_use]
+    pub fn new(name: impl Into<String>, limit: usize, window: Duration) -> Self {
+        Budget {
+            name: name.into(),
+            limit,
+            window,
+            policy: BudgetPolicy::default(),
+            store: None,
+            calls: tokio::sync::Mutex::new(None),
+        }
+    }
+
+    /// Sets what happens once the budget is exhausted.
+    #[must_use]
+    pub fn policy(mut self, policy: BudgetPolicy) -> Self {
+        self.policy = policy;
+        self
+    }
+
+    /// Persists recorded calls in `store`.
+    #[must_use]
+    pub fn store(mut self, store: impl BudgetStore + 'static) -> Self {
+        self.store = Some(Box::new(store));
+        self
+    }
+
+    /// Returns the name of the budget.
+    #[must_use]
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Returns the maximum number of calls within the window.
+    #[must_use]
+    pub fn limit(&self) -> usize {
+        self.limit
+    }
+
+    /// Returns the length of the rolling window.
+    #[must_use]
+    pub fn window(&self) -> Duration {
+        self.window
+    }
+
+    /// Returns the number of calls made within the current window.
+    pub async fn used(&self) -> usize {
+        self.with_calls(|calls| calls.len()).await
+    }
+
+    /// Returns the number of calls left within the current window.
+    pub async fn remaining(&self) -> usize {
+        self.limit.saturating_sub(self.used().await)
+    }
+
+    /// Records a call against the budget, and saves the calls to the store, if there is one. Failing to save them is
+    /// logged rather than failing the call.
+    ///
+    /// # Errors
+    /// Returns [`Error::BudgetExhausted`] if the budget is exhausted and its policy is [`BudgetPolicy::Error`]. The
+    /// call is not recorded in that case.
+    pub async fn acquire(&self) -> Result<()> {
+        let mut calls = self.calls().await;
+        let calls = calls.get_or_insert_with(VecDeque::new);
+        if calls.len() >= self.limit {
+            match self.policy {
+                BudgetPolicy::Error => {
+                    return Err(Error::BudgetExhausted {
+                        budget: self.name.clone(),
+                        limit: self.limit,
+                        window: self.window,
+                    })
+                }
+                BudgetPolicy::Warn => log::warn!(
+                    "call budget `{}` exhausted ({} calls per {:?})",
+                    self.name,
+                    self.limit,
+                    self.window
+                ),
+            }
+        }
+
+        calls.push_back(SystemTime::now());
+        if let Some(store) = &self.store {
+            if let Err(error) = store.save(&self.name, calls.make_contiguous()).await {
+                log::warn!("failed to save call budget `{}`: {error}", self.name);
+            }
+        }
+        Ok(())
+    }
+
+    /// Runs `f` with the calls made within the current window.
+    async fn with_calls<T>(&self, f: impl FnOnce(&mut VecDeque<SystemTime>) -> T) -> T {
+        let mut calls = self.calls().await;
+        f(calls.get_or_insert_with(VecDeque::new))
+    }
+
+    /// Locks the calls made within the current window, loading them from the store first if needed, and dropping
+    /// those that have left the window. The lock is held while loading, so concurrent first calls see what was
+    /// loaded.
+    async fn calls(&self) -> tokio::sync::MutexGuard<'_, Option<VecDeque<SystemTime>>> {
+        let mut calls = self.calls.lock().await;
+        if calls.is_none() {
+            *calls = Some(match &self.store {
+                Some(store) => store.load(&self.name).await.into(),
+                None => VecDeque::new(),
+            });
+        }
+
+        let now = SystemTime::now();
+        if let Some(calls) = calls.as_mut() {
+            while calls.front().is_some_and(|&call| {
+                now.duration_since(call)
+                    .is_ok_and(|elapsed| elapsed >= self.window)
+            }) {
+                calls.pop_front();
+            }
+        }
+        calls
+    }
+}
+
+impl fmt::Debug for Budget {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Budget")
+            .field("name", &self.name)
+            .field("limit", &self.limit)
+            .field("window", &self.window)
+            .field("policy", &self.policy)
+            .finish_non_exhaustive()
+    }
+}
@@ -0,0 +1,370 @@
+//! A builder that configures the structs generated by the [`api`](crate::api) macro.
+
+use std::{fmt, sync::Arc};
+
+use reqwest::{
+    header::{HeaderMap, HeaderValue},
+    Client, ClientBuilder, Url,
+};
+
+use crate::{header, url, Error, Middleware};
+
+/// Used internally by the [`api`](crate::api) macro to hold the configuration of a generated struct besides its
+/// client.
+#[doc(hidden)]
+#[derive(Clone, Default)]
+pub struct ApiConfig<A = ()> {
+    /// Returned by [`Api::base_url`](crate::Api::base_url).
+    pub base_url: Option<Url>,
+    /// Returned by [`Api::default_headers`](crate::Api::default_headers).
+    pub headers: HeaderMap,
+    /// Returned by [`Api::default_query`](crate::Api::default_query).
+    pub query: Vec<(String, String)>,
+    /// Returned by [`Api::middlewares`](crate::Api::middlewares).
+    pub middlewares: Vec<Arc<dyn Middleware>>,
+    /// Returned by [`Api::concurrency_permits`](crate::Api::concurrency_permits).
+    #[cfg(feature = "concurrency")]
+    pub concurrency: Option<Arc<tokio::sync::Semaphore>>,
+    /// The credentials applied by [`Api::pre_request_async`](crate::Api::pre_request_async).
+    pub auth: A,
+}
+
+impl<A: Default> ApiConfig<A> {
+    /// Creates a configuration with only a base URL.
+    #[must_use]
+    pub fn new(base_url: Option<Url>) -> Self {
+        ApiConfig {
+            base_url,
+            ..ApiConfig::default()
+        }
+    }
+}
+
+impl<A: fmt::Debug> fmt::Debug for ApiConfig<A> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut debug = f.debug_struct("ApiConfig");
+        debug
+            .field("base_url", &self.base_url)
+            .field("headers", &self.headers)
+            .field("query", &self.query)
+            .field("middlewares", &self.middlewares.len());
+        #[cfg(feature = "concurrency")]
+        debug.field("concurrency", &self.concurrency);
+        debug.field("auth", &self.auth).finish()
+    }
+}
+
+/// Configures a struct generated by the [`api`](crate::api) macro, returned by its `builder` function.
+///
+/// The base URL and header values are checked by [`ApiBuilder::build`], which returns the first invalid one as an
+/// error.
+///
+/// ```rust,no_run
+/// use api_client::api;
+/// use reqwest::Client;
+///
+/// api!(pub struct ExampleApi, base = "https://example.com/v1");
+///
+/// # fn main() -> api_client::Result<()> {
+/// let api = ExampleApi::builder()
+///     .client(Client::new())
+///     .base_url("https://staging.example.com/v1")
+///     .default_header("x-tenant", "acme")
+///     .build()?;
+/// # Ok(())
+/// # }
+/// ```
+///
+/// For services requiring mutual TLS, `ApiBuilder::identity` sets the client certificate and
+/// `ApiBuilder::add_root_certificate` trusts a private certificate authority (requires the `rustls-tls` or
+/// `native-tls` feature, which picks the TLS backend; `rustls-tls` wins if both are enabled). They configure the client
+/// the builder creates, so can't be combined with [`ApiBuilder::client`]. With `native-tls`, load the identity with
+/// `Identity::from_pkcs8_pem` instead.
+///
+#[cfg_attr(feature = "rustls-tls", doc = "```rust,no_run")]
+#[cfg_attr(not(feature = "rustls-tls"), doc = "```rust,ignore")]
+/// use api_client::api;
+/// use reqwest::{Certificate, Identity};
+///
+/// api!(pub struct PartnerApi, base = "https://partner.example.com/v1");
+///
+/// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+/// let identity = Identity::from_pem(&std::fs::read("client.pem")?)?;
+/// let ca = Certificate::from_pem(&std::fs::read("partner-ca.pem")?)?;
+/// let api = PartnerApi::builder()
+///     .identity(identity)
+///     .add_root_certificate(ca)
+///     .build()?;
+/// # Ok(())
+/// # }
+/// ```
+#[must_use]
+pub struct ApiBuilder<T, A = ()> {
+    /// The client set with [`ApiBuilder::client`].
+    client: Option<Client>,
+    /// Applies the struct's options to the client builder, used if no client was set.
+    configure: fn(ClientBuilder) -> ClientBuilder,
+    /// The client certificate set with [`ApiBuilder::identity`].
+    #[cfg(any(feature = "rustls-tls", feature = "native-tls"))]
+    identity: Option<reqwest::Identity>,
+    /// The certificates added with [`ApiBuilder::add_root_certificate`].
+    #[cfg(any(feature = "rustls-tls", feature = "native-tls"))]
+    root_certificates: Vec<reqwest::Certificate>,
+    /// The cookies of the [`CookieFile`](crate::CookieFile) set with [`ApiBuilder::cookie_file`].
+    #[cfg(feature = "cookie-store")]
+    cookie_store: Option<Arc<reqwest_cookie_store::CookieStoreMutex>>,
+    /// The base URL, or the error from parsing it.
+    base_url: Result<Option<Url>, Error>,
+    /// The default headers, or the first invalid one.
+    headers: Result<HeaderMap, Error>,
+    /// The default query parameters.
+    query: Vec<(String, String)>,
+    /// The middlewares added with [`ApiBuilder::middleware`].
+    middlewares: Vec<Arc<dyn Middleware>>,
+    /// The limit set with [`ApiBuilder::max_concurrency`].
+    #[cfg(feature = "concurrency")]
+    concurrency: Option<usize>,
+    /// The credentials set with [`ApiBuilder::auth`].
+    auth: Option<A>,
+    /// Creates the struct from its client and configuration.
+    build: fn(Client, ApiConfig<A>) -> T,
+}
+
+impl<T, A: fmt::Debug> fmt::Debug for ApiBuilder<T, A> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut debug = f.debug_struct("ApiBuilder");
+        debug
+            .field("client", &self.client)
+            .field("base_url", &self.base_url)
+            .field("headers", &self.headers)
+            .field("query", &self.query)
+            .field("middlewares", &self.middlewares.len());
+        #[cfg(feature = "concurrency")]
+        debug.field("concurrency", &self.concurrency);
+        #[cfg(any(feature = "rustls-tls", feature = "native-tls"))]
+        debug
+            .field("identity", &self.identity.is_some())
+            .field("root_certificates", &self.root_certificates.len());
+        #[cfg(feature = "cookie-store")]
+        debug.field("cookie_store", &self.cookie_store.is_some());
+        debug.field("auth", &self.auth).finish_non_exhaustive()
+    }
+}
+
+impl<T, A> ApiBuilder<T, A> {
+    /// Used internally by the [`api`](crate::api) macro to create the builder of a generated struct.
+    #[doc(hidden)]
+    pub fn new(
+        configure: fn(ClientBuilder) -> ClientBuilder,
+        base_url: Option<Url>,
+        build: fn(Client, ApiConfig<A>) -> T,
+    ) -> Self {
+        ApiBuilder {
+            client: None,
+            configure,
+            #[cfg(any(feature = "rustls-tls", feature = "native-tls"))]
+            identity: None,
+            #[cfg(any(feature = "rustls-tls", feature = "native-tls"))]
+            root_certificates: Vec::new(),
+            #[cfg(feature = "cookie-store")]
+            cookie_store: None,
+            base_url: Ok(base_url),
+            headers: Ok(HeaderMap::new()),
+            query: Vec::new(),
+            middlewares: Vec::new(),
+            #[cfg(feature = "concurrency")]
+            concurrency: None,
+            auth: None,
+            build,
+        }
+    }
+
+    /// Sends requests with `client` instead of building a new one.
+    pub fn client(mut self, client: Client) -> Self {
+        self.client = Some(client);
+        self
+    }
+
+    /// Resolves relative endpoint URLs against `base_url` instead of the declared base URL.
+    pub fn base_url(mut self, base_url: impl AsRef<str>) -> Self {
+        let base_url = base_url.as_ref();
+        self.base_url = Url::parse(base_url)
+            .map(|url| Some(url::base(url)))
+            .map_err(|_| Error::InvalidBaseUrl {
+                url: base_url.to_string(),
+            });
+        self
+    }
+
+    /// Adds a header sent with every request, as returned by [`Api::default_headers`](crate::Api::default_headers).
+    pub fn default_header<V>(mut self, name: &str, value: V) -> Self
+    where
+        HeaderValue: TryFrom<V>,
+    {
+        if let Ok(headers) = &mut self.headers {
+            let header = header::name(name).and_then(|name| {
+                let value =
+                    HeaderValue::try_from(value).map_err(|_| Error::InvalidHeaderValue {
+                        name: name.to_string(),
+                    })?;
+                Ok((name, value))
+            });
+            match header {
+                Ok((name, value)) => {
+                    headers.append(name, value);
+                }
+                Err(error) => self.headers = Err(error),
+            }
+        }
+        self
+    }
+
+    /// Adds a query parameter sent with every request, as returned by
+    /// [`Api::default_query`](crate::Api::default_query).
+    pub fn default_query(mut self, key: impl Into<String>, value: impl Into<String>) -> Self {
+        self.query.push((key.into(), value.into()));
+        self
+    }
+
+    /// Adds a middleware after those already added, as returned by [`Api::middlewares`](crate::Api::middlewares).
+    pub fn middleware(mut self, middleware: impl Middleware + 'static) -> Self {
+        self.middlewares.push(Arc::new(middleware));
+        self
+    }
+
+    /// Sends requests with a tower `service` instead of the client, after the middlewares already added.
+    #[cfg(feature = "tower")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "tower")))]
+    pub fn service<S>(self, service: S) -> Self
+    where
+        S: tower_service::Service<reqwest::Request, Response = reqwest::Response>
+            + Clone
+            + Send
+            + Sync
+            + 'static,
+        S::Future: crate::MaybeSend,
+        S::Error: Into<Box<dyn std::error::Error + Send + Sync>>,
+    {
+        self.middleware(crate::TowerService::new(service))
+    }
+
+    /// Limits the number of requests in flight at once to `max`, as returned by
+    /// [`Api::concurrency_permits`](crate::Api::concurrency_permits). A limit of 0 would block every request, so
+    /// [`ApiBuilder::build`] rejects it.
+    #[cfg(feature = "concurrency")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "concurrency")))]
+    pub fn max_concurrency(mut self, max: usize) -> Self {
+        self.concurrency = Some(max);
+        self
+    }
+
+    /// Presents `identity`, a client certificate and its private key, to servers requiring mutual TLS.
+    #[cfg(any(feature = "rustls-tls", feature = "native-tls"))]
+    #[cfg_attr(docsrs, doc(cfg(any(feature = "rustls-tls", feature = "native-tls"))))]
+    pub fn identity(mut self, identity: reqwest::Identity) -> Self {
+        self.identity = Some(identity);
+        self
+    }
+
+    /// Trusts server certificates issued by `certificate`, such as a private certificate authority, besides the
+    /// system's.
+    #[cfg(any(feature = "rustls-tls", feature = "native-tls"))]
+    #[cfg_attr(docsrs, doc(cfg(any(feature = "rustls-tls", feature = "native-tls"))))]
+    pub fn add_root_certificate(mut self, certificate: reqwest::Certificate) -> Self {
+        self.root_certificates.push(certificate);
+        self
+    }
+
+    /// Sends and stores cookies in `cookies`, such as the session cookie set by logging in, instead of the client's
+    /// own cookie store, so they can be saved with [`CookieFile::save`](crate::CookieFile::save).
+    #[cfg(feature = "cookie-store")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "cookie-store")))]
+    pub fn cookie_file(mut self, cookies: &crate::CookieFile) -> Self {
+        self.cookie_store = Some(cookies.store());
+        self
+    }
+
+    /// Attaches `auth` to every request, for structs declared with `with <scheme>`.
+    pub fn auth(mut self, auth: A) -> Self {
+        self.auth = Some(auth);
+        self
+    }
+
+    /// Creates the configured struct.
+    ///
+    /// # Errors
+    /// Returns [`Error::InvalidBaseUrl`], [`Error::InvalidHeaderName`] or [`Error::InvalidHeaderValue`] if the base URL
+    /// or a default header is invalid, [`Error::Transport`] if the HTTP client can't be built, or [`Error::Config`] if
+    /// TLS settings or a cookie file are combined with a client set with [`ApiBuilder::client`], or the concurrency
+    /// limit is 0.
+    pub fn build(self) -> Result<T, Error>
+    where
+        A: Default,
+    {
+        let base_url = self.base_url?;
+        let headers = self.headers?;
+        #[cfg(feature = "concurrency")]
+        if self.concurrency == Some(0) {
+            return Err(Error::Config {
+                message: "the concurrency limit must be at least 1".to_string(),
+            });
+        }
+        #[allow(unused_mut)]
+        let mut builder = (self.configure)(Client::builder());
+        #[cfg(any(feature = "rustls-tls", feature = "native-tls"))]
+        {
+            if self.client.is_some()
+                && (self.identity.is_some() || !self.root_certificates.is_empty())
+            {
+                return Err(Error::Config {
+                    message: "TLS settings can't be applied to a client set with `client`"
+                        .to_string(),
+                });
+            }
+            if self.identity.is_some() || !self.root_certificates.is_empty() {
+                #[cfg(feature = "rustls-tls")]
+                {
+                    builder = builder.use_rustls_tls();
+                }
+                #[cfg(not(feature = "rustls-tls"))]
+                {
+                    builder = builder.use_native_tls();
+                }
+            }
+            if let Some(identity) = self.identity {
+                builder = builder.identity(identity);
+            }
+            for certificate in self.root_certificates {
+                builder = builder.add_root_certificate(certificate);
+            }
+        }
+        #[cfg(feature = "cookie-store")]
+        if let Some(store) = self.cookie_store {
+            if self.client.is_some() {
+                return Err(Error::Config {
+                    message: "a cookie file can't be used by a client set with `client`"
+                        .to_string(),
+                });
+            }
+            builder = builder.cookie_provider(store);
+        }
+        let client = match self.client {
+            Some(client) => client,
+            None => builder.build()?,
+        };
+        Ok((self.build)(
+            client,
+            ApiConfig {
+                base_url,
+                headers,
+                query: self.query,
+                middlewares: self.middlewares,
+                #[cfg(feature = "concurrency")]
+                concurrency: self
+                    .concurrency
+                    .map(|max| Arc::new(tokio::sync::Semaphore::new(max))),
+                auth: self.auth.unwrap_or_default(),
+            },
+        ))
+    }
+}
@@ -0,0 +1,73 @@
+//! Responses passed to and from the caching hooks of [`Api`](crate::Api).
+
+use reqwest::{header::HeaderMap, ResponseBuilderExt, StatusCode, Url};
+
+/// A response returned by [`Api::before_send`](crate::Api::before_send) in place of sending the request, such as one
+/// read from a cache.
+///
+/// The generated method decodes it like a response from the server, so its status is checked and its body is
+/// deserialized into the endpoint's return kind.
+#[derive(Clone, Debug)]
+#[non_exhaustive]
+pub struct CachedResponse {
+    /// The status of the response.
+    pub status: StatusCode,
+    /// The headers of the response, such as its `Content-Type`.
+    pub headers: HeaderMap,
+    /// The body of the response.
+    pub body: Vec<u8>,
+}
+
+impl CachedResponse {
+    /// Creates a `200 OK` response with `body` and no headers.
+    #[must_use]
+    pub fn new(body: impl Into<Vec<u8>>) -> Self {
+        CachedResponse {
+            status: StatusCode::OK,
+            headers: HeaderMap::new(),
+            body: body.into(),
+        }
+    }
+
+    /// Sets the status of the response.
+    #[must_use]
+    pub fn status(mut self, status: StatusCode) -> Self {
+        self.status = status;
+        self
+    }
+
+    /// Sets the headers of the response.
+    #[must_use]
+    pub fn headers(mut self, headers: HeaderMap) -> Self {
+        self.headers = headers;
+        self
+    }
+
+    /// Converts the response into one the generated method can decode, as if it had been received from `url`.
+    pub(crate) fn into_response(self, url: Url) -> reqwest::Response {
+        let (mut parts, ()) = http::Response::builder()
+            .url(url)
+            .body(())
+            .unwrap_or_default()
+            .into_parts();
+        parts.status = self.status;
+        parts.headers = self.headers;
+        reqwest::Response::from(http::Response::from_parts(parts, self.body))
+    }
+}
+
+/// A response received from the server, passed to [`Api::after_receive`](crate::Api::after_receive).
+#[derive(Clone, Copy, Debug)]
+#[non_exhaustive]
+pub struct ResponseSummary<'a> {
+    /// The status of the response.
+    pub status: StatusCode,
+    /// The headers of the response.
+    pub headers: &'a HeaderMap,
+    /// The URL the response was received from, after any redirects.
+    pub url: &'a Url,
+    /// The body of a successful response, for endpoints that read the whole body before decoding it. It's `None` for
+    /// unsuccessful responses, return kinds that stream the body or ignore it such as `ByteStream`, `Download` and
+    /// `StatusCode`, and endpoints reporting progress.
+    pub body: Option<&'a [u8]>,
+}
@@ -0,0 +1,28 @@
+//! Cookie helpers used internally by the [`api`](crate::api) macro.
+
+use std::fmt::{self, Write};
+
+use percent_encoding::{utf8_percent_encode, AsciiSet, CONTROLS};
+
+/// Characters that can't appear in a cookie value, plus `%` so encoded values stay unambiguous.
+const COOKIE_VALUE: &AsciiSet = &CONTROLS
+    .add(b' ')
+    .add(b'"')
+    .add(b',')
+    .add(b';')
+    .add(b'\\')
+    .add(b'%');
+
+/// Appends a `name=value` pair to a `Cookie` header, percent-encoding the characters in `value` that a cookie value
+/// can't contain.
+pub fn push(cookie: &mut String, name: &str, value: impl fmt::Display) {
+    if !cookie.is_empty() {
+        cookie.push_str("; ");
+    }
+    let value = value.to_string();
+    let _ = write!(
+        cookie,
+        "{name}={}",
+        utf8_percent_encode(&value, COOKIE_VALUE)
+    );
+}
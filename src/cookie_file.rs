@@ -0,0 +1,85 @@
+//! A cookie store saved to a file, so sessions outlive the program.
+
+use std::{
+    io,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use reqwest_cookie_store::{CookieStore, CookieStoreMutex};
+
+use crate::{Error, Result};
+
+/// A cookie store kept in a JSON file, so a session cookie set by logging in is still sent the next time the program
+/// runs (requires the `cookie-store` feature).
+///
+/// Pass it to [`ApiBuilder::cookie_file`](crate::ApiBuilder::cookie_file) to send requests with its cookies, and call
+/// [`CookieFile::save`] to write them back, such as before the program exits. Cookies without an expiry, which a
+/// browser would forget when it closes, are kept too.
+///
+/// ```rust,no_run
+/// use api_client::{api, CookieFile};
+///
+/// api!(pub struct ExampleApi, base = "https://example.com/");
+///
+/// # async fn run() -> api_client::Result<()> {
+/// let cookies = CookieFile::load("cookies.json").await?;
+/// let api = ExampleApi::builder().cookie_file(&cookies).build()?;
+/// // ...
+/// cookies.save().await?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Debug, Clone)]
+pub struct CookieFile {
+    /// The file the cookies are loaded from and saved to.
+    path: PathBuf,
+    /// The cookies, shared with the clients using them.
+    store: Arc<CookieStoreMutex>,
+}
+
+impl CookieFile {
+    /// Loads the cookies saved in the file at `path`, or starts with none if there's no file there yet.
+    ///
+    /// # Errors
+    /// Returns [`Error::Io`] if the file can't be read, or [`Error::Service`] if it doesn't hold saved cookies.
+    pub async fn load(path: impl Into<PathBuf>) -> Result<Self> {
+        let path = path.into();
+        let store = match tokio::fs::read(&path).await {
+            Ok(bytes) => CookieStore::load_json_all(bytes.as_slice()).map_err(Error::Service)?,
+            Err(error) if error.kind() == io::ErrorKind::NotFound => CookieStore::default(),
+            Err(error) => return Err(error.into()),
+        };
+        Ok(CookieFile {
+            path,
+            store: Arc::new(CookieStoreMutex::new(store)),
+        })
+    }
+
+    /// Returns the path of the file the cookies are saved to.
+    #[must_use]
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Returns the cookie store, to pass to [`ClientBuilder::cookie_provider`](reqwest::ClientBuilder::cookie_provider).
+    #[must_use]
+    pub fn store(&self) -> Arc<CookieStoreMutex> {
+        self.store.clone()
+    }
+
+    /// Writes the current cookies to the file, replacing what it held.
+    ///
+    /// # Errors
+    /// Returns [`Error::Io`] if the file can't be written, or [`Error::Service`] if the cookies can't be serialized.
+    pub async fn save(&self) -> Result<()> {
+        let mut json = Vec::new();
+        self.store
+            .lock()
+            .map_err(|_| Error::Service("cookie store lock poisoned".into()))?
+            .save_incl_expired_and_nonpersistent_json(&mut json)
+            .map_err(Error::Service)?;
+        tokio::fs::write(&self.path, json).await?;
+        Ok(())
+    }
+}
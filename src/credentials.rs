@@ -0,0 +1,338 @@
+//! Credentials attached to requests by the structs generated by the [`api`](crate::api) macro.
+
+use std::{
+    fmt,
+    future::Future,
+    sync::{Arc, PoisonError, RwLock},
+    time::Duration,
+};
+
+use reqwest::{
+    header::{HeaderValue, AUTHORIZATION},
+    RequestBuilder,
+};
+use tokio::sync::OnceCell;
+
+use crate::{header, token_store::SharedStore, Error, MaybeSend, Result, StoredToken, TokenStore};
+
+/// Attaches credentials to requests, such as an `Authorization` header.
+///
+/// A struct declared with `with <scheme>`, as in `api!(pub struct ExampleApi with bearer_auth)`, holds one and applies
+/// it in [`Api::pre_request_async`](crate::Api::pre_request_async). Implementations written by hand can call it from
+/// their own [`Api::pre_request_async`](crate::Api::pre_request_async).
+pub trait Authenticate {
+    /// Returns `request` with the credentials attached.
+    ///
+    /// # Errors
+    /// Implementations may return an error to abort the request before it is sent, such as when the credentials can't
+    /// be encoded in a header.
+    fn authenticate(
+        &self,
+        request: RequestBuilder,
+    ) -> impl Future<Output = Result<RequestBuilder>> + MaybeSend;
+
+    /// Gets new credentials after the server rejected the current ones, returning whether the request should be
+    /// retried with them; see [`Api::reauthenticate`](crate::Api::reauthenticate). The default returns `false`.
+    ///
+    /// # Errors
+    /// Implementations may return an error to fail the request instead.
+    #[inline]
+    fn reauthenticate(&self) -> impl Future<Output = Result<bool>> + MaybeSend {
+        async { Ok(false) }
+    }
+}
+
+/// No credentials, used by structs declared without `with <scheme>`.
+impl Authenticate for () {
+    #[inline]
+    async fn authenticate(&self, request: RequestBuilder) -> Result<RequestBuilder> {
+        Ok(request)
+    }
+}
+
+/// Credentials that may not have been set, such as those of a struct created without them, which sends requests as
+/// they are.
+impl<A: Authenticate + Sync> Authenticate for Option<A> {
+    async fn authenticate(&self, request: RequestBuilder) -> Result<RequestBuilder> {
+        match self {
+            Some(auth) => auth.authenticate(request).await,
+            None => Ok(request),
+        }
+    }
+
+    async fn reauthenticate(&self) -> Result<bool> {
+        match self {
+            Some(auth) => auth.reauthenticate().await,
+            None => Ok(false),
+        }
+    }
+}
+
+/// A secret such as a token or password, which is redacted from [`Debug`] output.
+#[derive(Clone, PartialEq, Eq)]
+pub struct Secret(String);
+
+impl Secret {
+    /// Wraps `secret`.
+    #[must_use]
+    pub fn new(secret: impl Into<String>) -> Self {
+        Secret(secret.into())
+    }
+
+    /// Returns the secret itself.
+    #[must_use]
+    pub fn expose(&self) -> &str {
+        &self.0
+    }
+}
+
+impl fmt::Debug for Secret {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("\"[redacted]\"")
+    }
+}
+
+impl From<String> for Secret {
+    #[inline]
+    fn from(secret: String) -> Self {
+        Secret(secret)
+    }
+}
+
+impl From<&str> for Secret {
+    #[inline]
+    fn from(secret: &str) -> Self {
+        Secret(secret.to_string())
+    }
+}
+
+/// Converts `value` into a header value marked as sensitive, so it's redacted from debug output and not compressed by
+/// HTTP/2.
+pub(crate) fn sensitive(name: &str, value: &str) -> Result<HeaderValue> {
+    let mut value = HeaderValue::try_from(value).map_err(|_| Error::InvalidHeaderValue {
+        name: name.to_string(),
+    })?;
+    value.set_sensitive(true);
+    Ok(value)
+}
+
+/// Sends a token in an `Authorization: Bearer <token>` header, used by structs declared `with bearer_auth`.
+///
+/// Clones share the token, so [`BearerAuth::set_token`] on any of them changes it for all. Requests are sent without
+/// the header while there is no token.
+///
+/// With a [`TokenStore`], the first request without a token loads it from the store, unless it has expired, and
+/// [`BearerAuth::save_token`] saves new ones to it.
+///
+/// ```rust,no_run
+/// api_client::api!(pub struct ExampleApi with bearer_auth, base = "https://example.com/v1");
+///
+/// let api = ExampleApi::with_token("secret");
+/// api.set_token("rotated");
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct BearerAuth {
+    /// The current token, if there is one.
+    token: Arc<RwLock<Option<Secret>>>,
+    /// Where the token is kept, if anywhere.
+    store: Option<SharedStore>,
+    /// Set once the token was loaded from the store, which concurrent first requests wait for.
+    loaded: Arc<OnceCell<()>>,
+}
+
+impl BearerAuth {
+    /// Creates an authenticator sending `token`.
+    #[must_use]
+    pub fn new(token: impl Into<Secret>) -> Self {
+        BearerAuth {
+            token: Arc::new(RwLock::new(Some(token.into()))),
+            ..BearerAuth::default()
+        }
+    }
+
+    /// Keeps the token in `store`.
+    #[must_use]
+    pub fn store(mut self, store: impl TokenStore + 'static) -> Self {
+        self.store = Some(SharedStore(Arc::new(store)));
+        self
+    }
+
+    /// Replaces the token sent with later requests, like [`BearerAuth::set_token`], and saves it to the
+    /// [`TokenStore`], if there is one.
+    ///
+    /// # Errors
+    /// Returns the error of the store if the token can't be saved. The token is used either way.
+    pub async fn save_token(&self, token: impl Into<Secret>) -> Result<()> {
+        let token = token.into();
+        self.set_token(token.clone());
+        match &self.store {
+            Some(SharedStore(store)) => store.save(&StoredToken::new(token)).await,
+            None => Ok(()),
+        }
+    }
+
+    /// Replaces the token sent with later requests.
+    pub fn set_token(&self, token: impl Into<Secret>) {
+        *self.token.write().unwrap_or_else(PoisonError::into_inner) = Some(token.into());
+    }
+
+    /// Removes the token, so later requests are sent without an `Authorization` header.
+    pub fn clear_token(&self) {
+        *self.token.write().unwrap_or_else(PoisonError::into_inner) = None;
+    }
+
+    /// Returns the current token, if there is one.
+    fn token(&self) -> Option<Secret> {
+        self.token
+            .read()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone()
+    }
+}
+
+impl Authenticate for BearerAuth {
+    async fn authenticate(&self, request: RequestBuilder) -> Result<RequestBuilder> {
+        if let Some(SharedStore(store)) = &self.store {
+            self.loaded
+                .get_or_init(|| async {
+                    if self.token().is_some() {
+                        return;
+                    }
+                    if let Some(stored) = store.load().await {
+                        if !stored.is_expired(Duration::ZERO) {
+                            self.set_token(stored.access_token);
+                        }
+                    }
+                })
+                .await;
+        }
+        match self.token() {
+            Some(token) => bearer(request, &token),
+            None => Ok(request),
+        }
+    }
+}
+
+/// Sets the `Authorization` header on `request` to `Bearer <token>`, marked as sensitive.
+pub(crate) fn bearer(request: RequestBuilder, token: &Secret) -> Result<RequestBuilder> {
+    let value = sensitive(
+        AUTHORIZATION.as_str(),
+        &format!("Bearer {}", token.expose()),
+    )?;
+    header::replace(request, AUTHORIZATION, value)
+}
+
+/// Where an [`ApiKey`] is sent.
+#[derive(Clone, Debug, Default)]
+enum Placement {
+    /// Nowhere, for a struct created without a key.
+    #[default]
+    None,
+    /// In the header with this name.
+    Header(String),
+    /// In the query parameter with this name.
+    Query(String),
+}
+
+/// Sends a static API key in a header or query parameter, used by structs declared `with api_key`.
+///
+/// In a query parameter, the key is left out of requests whose URL already has a parameter of the same name, such as
+/// one declared on the endpoint, so it's never sent twice.
+///
+/// ```rust,no_run
+/// use api_client::ApiKey;
+///
+/// api_client::api!(pub struct ExampleApi with api_key, base = "https://example.com/v1");
+///
+/// let api = ExampleApi::with_api_key(ApiKey::header("x-api-key", "secret"));
+/// let api = ExampleApi::with_api_key(ApiKey::query("api_key", "secret"));
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct ApiKey {
+    /// Where the key is sent.
+    placement: Placement,
+    /// The key.
+    key: Option<Secret>,
+}
+
+impl ApiKey {
+    /// Creates an authenticator sending `key` in the header called `name`.
+    #[must_use]
+    pub fn header(name: impl Into<String>, key: impl Into<Secret>) -> Self {
+        ApiKey {
+            placement: Placement::Header(name.into()),
+            key: Some(key.into()),
+        }
+    }
+
+    /// Creates an authenticator sending `key` in the query parameter called `name`.
+    #[must_use]
+    pub fn query(name: impl Into<String>, key: impl Into<Secret>) -> Self {
+        ApiKey {
+            placement: Placement::Query(name.into()),
+            key: Some(key.into()),
+        }
+    }
+}
+
+impl Authenticate for ApiKey {
+    async fn authenticate(&self, request: RequestBuilder) -> Result<RequestBuilder> {
+        let Some(key) = &self.key else {
+            return Ok(request);
+        };
+        match &self.placement {
+            Placement::None => Ok(request),
+            Placement::Header(name) => {
+                let value = sensitive(name, key.expose())?;
+                header::replace(request, header::name(name)?, value)
+            }
+            Placement::Query(name) => {
+                let (client, request) = request.build_split();
+                let request = request?;
+                let present = request.url().query_pairs().any(|(key, _)| key == *name);
+                let request = RequestBuilder::from_parts(client, request);
+                Ok(if present {
+                    request
+                } else {
+                    request.query(&[(name.as_str(), key.expose())])
+                })
+            }
+        }
+    }
+}
+
+/// Sends a username and password as HTTP basic auth, used by structs declared `with basic_auth`.
+///
+/// An endpoint's own `basic_auth` clause replaces these credentials.
+///
+/// ```rust,no_run
+/// api_client::api!(pub struct ExampleApi with basic_auth, base = "https://example.com/v1");
+///
+/// let api = ExampleApi::with_basic_auth("admin", "secret");
+/// ```
+#[derive(Clone, Debug, Default)]
+pub struct BasicAuth {
+    /// The username and password, if there are any.
+    credentials: Option<(String, Secret)>,
+}
+
+impl BasicAuth {
+    /// Creates an authenticator sending `username` and `password`.
+    #[must_use]
+    pub fn new(username: impl Into<String>, password: impl Into<Secret>) -> Self {
+        BasicAuth {
+            credentials: Some((username.into(), password.into())),
+        }
+    }
+}
+
+impl Authenticate for BasicAuth {
+    async fn authenticate(&self, request: RequestBuilder) -> Result<RequestBuilder> {
+        match &self.credentials {
+            Some((username, password)) => {
+                header::basic_auth(request, username, Some(password.expose()))
+            }
+            None => Ok(request),
+        }
+    }
+}
@@ -0,0 +1,207 @@
+//! CSRF tokens fetched from an endpoint and echoed back on mutating requests.
+
+use reqwest::{header::HeaderName, Method, Request, Response, StatusCode};
+
+use crate::{
+    credentials::sensitive, decode, AuthState, CachedResponse, Error, Middleware, Next, Result,
+    Secret,
+};
+
+/// Where the token is read from in the response of the token endpoint.
+#[derive(Clone, Debug)]
+enum Extract {
+    /// The header with this name.
+    Header(HeaderName),
+    /// The top-level field of a JSON body with this name.
+    #[cfg(feature = "json")]
+    Json(String),
+}
+
+/// Fetches a CSRF token and sends it in a header with every mutating request, such as a `POST`, `PUT` or `DELETE`
+/// (requires the `auth` feature).
+///
+/// Add it as a [`Middleware`], such as with the `middleware` method of a generated struct's
+/// [`ApiBuilder`](crate::ApiBuilder). The token is fetched with a `GET` request to the token URL the first time a
+/// mutating request is sent, resolved against that request's URL if relative, and then cached. When a mutating
+/// request gets a `403 Forbidden` response whose body contains the expiry marker, `token expired` by default, a new
+/// token is fetched and the request is sent once more. Requests whose body is a stream can't be sent twice, so they
+/// fail with [`Error::NotRetryable`] instead. A token response with an error status fails the request with
+/// [`Error::UnexpectedStatus`], and one without a token with [`Error::Protocol`].
+///
+/// The token request passes through the middleware after this one and the same client, so it carries the session
+/// cookies the token is usually tied to.
+///
+/// ```rust
+/// use api_client::{api, CsrfToken};
+///
+/// api!(pub struct ExampleApi, base = "https://example.com/api/");
+///
+/// let api = ExampleApi::builder()
+///     .middleware(CsrfToken::header("/csrf", "x-csrf-token"))
+///     .build()
+///     .unwrap();
+/// ```
+#[derive(Debug)]
+pub struct CsrfToken {
+    /// The URL of the token endpoint, relative to the URL of the request needing it.
+    url: String,
+    /// Where the token is read from.
+    extract: Extract,
+    /// The header the token is sent in.
+    header: HeaderName,
+    /// The text in the body of a `403 Forbidden` response telling the token has expired.
+    expired: String,
+    /// The cached token.
+    token: AuthState<Secret>,
+}
+
+impl CsrfToken {
+    /// Creates a middleware reading the token from the header called `name` in the response of `url`, and sending it
+    /// back in a header of the same name.
+    ///
+    /// # Panics
+    /// Panics if `name` isn't a valid header name.
+    #[must_use]
+    pub fn header(url: impl Into<String>, name: &str) -> Self {
+        let name = HeaderName::try_from(name).expect("invalid CSRF header name");
+        CsrfToken::with(url.into(), Extract::Header(name.clone()), name)
+    }
+
+    /// Creates a middleware reading the token from the top-level `field` of the JSON body of `url`, and sending it
+    /// back in an `X-CSRF-Token` header (requires the `json` feature).
+    #[cfg(feature = "json")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "json")))]
+    #[must_use]
+    pub fn json(url: impl Into<String>, field: impl Into<String>) -> Self {
+        CsrfToken::with(
+            url.into(),
+            Extract::Json(field.into()),
+            HeaderName::from_static("x-csrf-token"),
+        )
+    }
+
+    /// Creates a middleware with the default header and expiry marker.
+    fn with(url: String, extract: Extract, header: HeaderName) -> Self {
+        CsrfToken {
+            url,
+            extract,
+            header,
+            expired: "token expired".to_string(),
+            token: AuthState::new(),
+        }
+    }
+
+    /// Sends the token in the header called `name` instead.
+    ///
+    /// # Panics
+    /// Panics if `name` isn't a valid header name.
+    #[must_use]
+    pub fn send_as(mut self, name: &str) -> Self {
+        self.header = HeaderName::try_from(name).expect("invalid CSRF header name");
+        self
+    }
+
+    /// Fetches a new token when a `403 Forbidden` response body contains `marker`, instead of `token expired`.
+    #[must_use]
+    pub fn expired_when(mut self, marker: impl Into<String>) -> Self {
+        self.expired = marker.into();
+        self
+    }
+
+    /// Discards the cached token, so the next mutating request fetches a new one.
+    pub async fn invalidate(&self) {
+        self.token.invalidate().await;
+    }
+
+    /// Sets the header of `request` to the cached token, fetching it if there is none.
+    async fn attach(&self, request: &mut Request, next: Next<'_>) -> Result<()> {
+        let token = self
+            .token
+            .get_or_refresh(|| self.fetch(request, next))
+            .await?;
+        let value = sensitive(self.header.as_str(), token.expose())?;
+        request.headers_mut().insert(self.header.clone(), value);
+        Ok(())
+    }
+
+    /// Requests a new token from the token endpoint.
+    async fn fetch(&self, request: &Request, next: Next<'_>) -> Result<Secret> {
+        let url = request
+            .url()
+            .join(&self.url)
+            .map_err(|_| Error::InvalidBaseUrl {
+                url: self.url.clone(),
+            })?;
+        let response = next.run(Request::new(Method::GET, url)).await?;
+        let response = decode::expect_status(response, &[200..=299]).await?;
+        let missing = |what: String| Error::Protocol {
+            message: format!("CSRF token response has no {what}"),
+        };
+        match &self.extract {
+            Extract::Header(name) => {
+                let value = response
+                    .headers()
+                    .get(name)
+                    .and_then(|value| value.to_str().ok())
+                    .ok_or_else(|| missing(format!("`{name}` header")))?;
+                Ok(Secret::new(value))
+            }
+            #[cfg(feature = "json")]
+            Extract::Json(field) => {
+                let bytes = decode::body(response, None, |_, _| {}).await?;
+                let body: crate::Value = serde_json::from_slice(&bytes)
+                    .map_err(|error| Error::decode_redacted(&bytes, error))?;
+                let value = body
+                    .get(field)
+                    .and_then(crate::Value::as_str)
+                    .ok_or_else(|| missing(format!("`{field}` field")))?;
+                Ok(Secret::new(value))
+            }
+        }
+    }
+}
+
+/// Returns whether requests with `method` can change state on the server, so need a token.
+fn mutating(method: &Method) -> bool {
+    !matches!(
+        *method,
+        Method::GET | Method::HEAD | Method::OPTIONS | Method::TRACE
+    )
+}
+
+#[cfg_attr(feature = "send", async_trait::async_trait)]
+#[cfg_attr(not(feature = "send"), async_trait::async_trait(?Send))]
+impl Middleware for CsrfToken {
+    async fn handle(&self, mut request: Request, next: Next<'_>) -> Result<Response> {
+        if !mutating(request.method()) {
+            return next.run(request).await;
+        }
+
+        self.attach(&mut request, next).await?;
+        let retry = request.try_clone();
+        let response = next.run(request).await?;
+        if response.status() != StatusCode::FORBIDDEN {
+            return Ok(response);
+        }
+
+        let status = response.status();
+        let headers = response.headers().clone();
+        let url = response.url().clone();
+        let body = decode::body(response, None, |_, _| {}).await?;
+        let marker = self.expired.as_bytes();
+        if !marker.is_empty() && !body.windows(marker.len()).any(|window| window == marker) {
+            return Ok(CachedResponse {
+                status,
+                headers,
+                body,
+            }
+            .into_response(url));
+        }
+
+        self.token.invalidate().await;
+        let mut retry = retry.ok_or(Error::NotRetryable)?;
+        retry.headers_mut().remove(&self.header);
+        self.attach(&mut retry, next).await?;
+        next.run(retry).await
+    }
+}
@@ -0,0 +1,215 @@
+//! Response decoders used internally by the [`api`](crate::api) macro.
+
+use std::ops::RangeInclusive;
+
+use reqwest::Response;
+
+#[cfg(feature = "json")]
+use crate::ApiError;
+use crate::Error;
+
+/// Reads a whole response body one chunk at a time, calling `progress` with the number of bytes read so far and the
+/// `Content-Length` of the response, if known, after each chunk.
+///
+/// # Errors
+/// Returns [`Error::BodyTooLarge`] as soon as the body is known to exceed `limit`, either from its `Content-Length` or
+/// from the bytes read so far. Returns [`Error::Transport`] if reading the body fails.
+pub async fn body(
+    mut response: Response,
+    limit: Option<u64>,
+    mut progress: impl FnMut(u64, Option<u64>),
+) -> Result<Vec<u8>, Error> {
+    let total = response.content_length();
+    if let (Some(limit), Some(total)) = (limit, total) {
+        if total > limit {
+            return Err(Error::BodyTooLarge {
+                limit,
+                received: total,
+            });
+        }
+    }
+
+    let mut bytes = Vec::with_capacity(
+        total
+            .and_then(|total| usize::try_from(total).ok())
+            .unwrap_or(0),
+    );
+    while let Some(chunk) = response.chunk().await? {
+        bytes.extend_from_slice(&chunk);
+        let received = bytes.len() as u64;
+        if let Some(limit) = limit.filter(|&limit| received > limit) {
+            return Err(Error::BodyTooLarge { limit, received });
+        }
+        progress(received, total);
+    }
+    Ok(bytes)
+}
+
+/// Maximum number of bytes of a response body kept in [`Error::UnexpectedStatus`] and [`Error::Decode`].
+const SNIPPET_LIMIT: usize = 512;
+
+/// Returns the start of `bytes`, with invalid UTF-8 replaced.
+pub(crate) fn snippet(bytes: &[u8]) -> String {
+    String::from_utf8_lossy(&bytes[..bytes.len().min(SNIPPET_LIMIT)]).into_owned()
+}
+
+/// Returns `response` if its status is in one of the `expected` ranges.
+///
+/// # Errors
+/// Returns [`Error::UnexpectedStatus`] with the status and the start of the body if it isn't, or [`Error::Transport`]
+/// if reading that body fails.
+pub async fn expect_status(
+    mut response: Response,
+    expected: &[RangeInclusive<u16>],
+) -> Result<Response, Error> {
+    let status = response.status();
+    if expected
+        .iter()
+        .any(|range| range.contains(&status.as_u16()))
+    {
+        return Ok(response);
+    }
+
+    let mut bytes = Vec::new();
+    while let Some(chunk) = response.chunk().await? {
+        bytes.extend_from_slice(&chunk);
+        if bytes.len() >= SNIPPET_LIMIT {
+            bytes.truncate(SNIPPET_LIMIT);
+            break;
+        }
+    }
+    Err(Error::UnexpectedStatus {
+        status,
+        url: Some(response.url().clone()),
+        body: snippet(&bytes),
+    })
+}
+
+/// Decodes a JSON response body, returning `empty` instead if it's `Some` and the body is empty or only whitespace.
+///
+/// When `path` is a non-empty dotted path such as `"data.items"`, only the value at that path is decoded into `T`.
+/// Path segments index into objects by key, and into arrays by position.
+///
+/// # Errors
+/// Returns [`Error::Decode`] if the body is not valid JSON for `T` or has no value at `path`, or any error of [`body`].
+#[cfg(feature = "json")]
+#[cfg_attr(docsrs, doc(cfg(feature = "json")))]
+pub async fn json<T: serde::de::DeserializeOwned>(
+    response: Response,
+    limit: Option<u64>,
+    path: Option<&str>,
+    empty: Option<T>,
+) -> Result<T, Error> {
+    let bytes = body(response, limit, |_, _| {}).await?;
+    let decode_error = |source: serde_json::Error| Error::decode(&bytes, source);
+    match (empty, path) {
+        (Some(empty), _) if bytes.iter().all(u8::is_ascii_whitespace) => Ok(empty),
+        (_, Some(path)) if !path.is_empty() => {
+            let mut value: serde_json::Value =
+                serde_json::from_slice(&bytes).map_err(decode_error)?;
+            for segment in path.split('.') {
+                value = match value {
+                    serde_json::Value::Object(mut object) => object.remove(segment),
+                    serde_json::Value::Array(mut array) => segment
+                        .parse()
+                        .ok()
+                        .filter(|&index| index < array.len())
+                        .map(|index| array.swap_remove(index)),
+                    _ => None,
+                }
+                .ok_or_else(|| {
+                    Error::decode(&bytes, format!("response body has no value at `{path}`"))
+                })?;
+            }
+            serde_json::from_value(value).map_err(decode_error)
+        }
+        _ => serde_json::from_slice(&bytes).map_err(decode_error),
+    }
+}
+
+/// Decodes a JSON response body into `T` on a success status, or into `E` on an error status.
+///
+/// Success bodies are decoded as by [`json`], with the same `path` and `empty` handling.
+///
+/// # Errors
+/// Returns [`ApiError::Api`] with the decoded body on an error status, or [`ApiError::Unparsed`] with the raw text if
+/// that body isn't an `E`. Returns [`ApiError::Other`] if reading the body fails, it exceeds `limit`, or a success body
+/// isn't a `T`.
+#[cfg(feature = "json")]
+#[cfg_attr(docsrs, doc(cfg(feature = "json")))]
+pub async fn json_or_error<T, E>(
+    response: Response,
+    limit: Option<u64>,
+    path: Option<&str>,
+    empty: Option<T>,
+) -> Result<T, ApiError<E>>
+where
+    T: serde::de::DeserializeOwned,
+    E: serde::de::DeserializeOwned,
+{
+    let status = response.status();
+    if !status.is_client_error() && !status.is_server_error() {
+        return Ok(json(response, limit, path, empty).await?);
+    }
+
+    let bytes = body(response, limit, |_, _| {}).await?;
+    match serde_json::from_slice(&bytes) {
+        Ok(body) => Err(ApiError::Api { status, body }),
+        Err(_) => Err(ApiError::Unparsed {
+            status,
+            body: String::from_utf8_lossy(&bytes).into_owned(),
+        }),
+    }
+}
+
+/// Decodes a `MessagePack` response body.
+///
+/// # Errors
+/// Returns [`Error::Decode`] if the body is not a valid `MessagePack` encoding of `T`.
+#[cfg(feature = "msgpack")]
+#[cfg_attr(docsrs, doc(cfg(feature = "msgpack")))]
+pub fn msgpack<T: serde::de::DeserializeOwned>(bytes: &[u8]) -> Result<T, Error> {
+    rmp_serde::from_slice(bytes).map_err(|source| Error::decode(bytes, source))
+}
+
+/// Decodes a CBOR response body.
+///
+/// # Errors
+/// Returns [`Error::Decode`] if the body is not a valid CBOR encoding of `T`.
+#[cfg(feature = "cbor")]
+#[cfg_attr(docsrs, doc(cfg(feature = "cbor")))]
+pub fn cbor<T: serde::de::DeserializeOwned>(bytes: &[u8]) -> Result<T, Error> {
+    ciborium::de::from_reader(bytes).map_err(|source| Error::decode(bytes, source))
+}
+
+/// Decodes a YAML response body.
+///
+/// # Errors
+/// Returns [`Error::Decode`] if the body is not a valid YAML document for `T`. The source error includes the line and
+/// column of the failure.
+#[cfg(feature = "yaml")]
+#[cfg_attr(docsrs, doc(cfg(feature = "yaml")))]
+pub fn yaml<T: serde::de::DeserializeOwned>(bytes: &[u8]) -> Result<T, Error> {
+    serde_yaml::from_slice(bytes).map_err(|source| Error::decode(bytes, source))
+}
+
+/// Decodes a CSV response body into one `T` per record.
+///
+/// When `has_headers` is true the first row is treated as a header row and used to match columns to fields.
+///
+/// # Errors
+/// Returns [`Error::Decode`] if any record can't be deserialized into `T`. The source error includes the position of
+/// the failing record.
+#[cfg(feature = "csv")]
+#[cfg_attr(docsrs, doc(cfg(feature = "csv")))]
+pub fn csv<T: serde::de::DeserializeOwned>(
+    bytes: &[u8],
+    has_headers: bool,
+) -> Result<Vec<T>, Error> {
+    csv_crate::ReaderBuilder::new()
+        .has_headers(has_headers)
+        .from_reader(bytes)
+        .deserialize()
+        .collect::<Result<_, _>>()
+        .map_err(|source| Error::decode(bytes, source))
+}
@@ -0,0 +1,386 @@
+//! HTTP Digest authentication (RFC 7616).
+
+use std::{
+    fmt::Write,
+    sync::{Mutex, PoisonError},
+};
+
+use md5::{Digest, Md5};
+use reqwest::{
+    header::{AUTHORIZATION, WWW_AUTHENTICATE},
+    Request, Response, StatusCode, Url,
+};
+use ring::{
+    digest,
+    rand::{SecureRandom, SystemRandom},
+};
+
+use crate::{credentials::sensitive, Error, Middleware, Next, Result, Secret};
+
+/// A hash algorithm a server may ask for.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Algorithm {
+    /// MD5, the default when the server doesn't name one.
+    Md5,
+    /// SHA-256.
+    Sha256,
+}
+
+impl Algorithm {
+    /// Returns the hex-encoded hash of `data`.
+    fn hash(self, data: &str) -> String {
+        match self {
+            Algorithm::Md5 => hex(&Md5::digest(data.as_bytes())),
+            Algorithm::Sha256 => hex(digest::digest(&digest::SHA256, data.as_bytes()).as_ref()),
+        }
+    }
+}
+
+/// The parameters of a `WWW-Authenticate: Digest` challenge, and how many requests have answered it.
+#[derive(Clone, Debug)]
+pub(crate) struct Challenge {
+    /// The protection space.
+    realm: String,
+    /// The server's nonce.
+    nonce: String,
+    /// Data the server wants echoed back.
+    opaque: Option<String>,
+    /// The URIs the server says the challenge covers, from its `domain` parameter.
+    domain: Vec<String>,
+    /// The hash algorithm.
+    algorithm: Algorithm,
+    /// Whether the `-sess` variant of the algorithm is used.
+    session: bool,
+    /// Whether the server supports `qop=auth`, rather than the RFC 2069 compatibility mode.
+    qop: bool,
+    /// The number of requests sent with this nonce.
+    count: u32,
+}
+
+impl Challenge {
+    /// Parses the first challenge of `response` using a supported algorithm.
+    fn from_response(response: &Response) -> Option<Challenge> {
+        response
+            .headers()
+            .get_all(WWW_AUTHENTICATE)
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .find_map(Challenge::parse)
+    }
+
+    /// Parses a `Digest` challenge, returning `None` for other schemes, or algorithms and qop values that aren't
+    /// supported.
+    pub(crate) fn parse(header: &str) -> Option<Challenge> {
+        let (scheme, params) = header.trim().split_once(' ')?;
+        if !scheme.eq_ignore_ascii_case("digest") {
+            return None;
+        }
+        let params = parse_params(params);
+        let param = |name: &str| {
+            params
+                .iter()
+                .find(|(key, _)| key.eq_ignore_ascii_case(name))
+                .map(|(_, value)| value.clone())
+        };
+
+        let algorithm = param("algorithm").unwrap_or_else(|| "MD5".to_string());
+        let (algorithm, session) = match algorithm.to_ascii_uppercase().as_str() {
+            "MD5" => (Algorithm::Md5, false),
+            "MD5-SESS" => (Algorithm::Md5, true),
+            "SHA-256" => (Algorithm::Sha256, false),
+            "SHA-256-SESS" => (Algorithm::Sha256, true),
+            _ => return None,
+        };
+        let qop = match param("qop") {
+            Some(qop) => {
+                if !qop.split(',').any(|qop| qop.trim() == "auth") {
+                    return None;
+                }
+                true
+            }
+            None => false,
+        };
+        Some(Challenge {
+            realm: param("realm")?,
+            nonce: param("nonce")?,
+            opaque: param("opaque"),
+            domain: param("domain")
+                .map(|domain| domain.split_whitespace().map(str::to_string).collect())
+                .unwrap_or_default(),
+            algorithm,
+            session,
+            qop,
+            count: 0,
+        })
+    }
+
+    /// Returns the `Authorization` header answering this challenge for a request with `method` to `uri`, its path
+    /// and query, counting it as the next use of the nonce.
+    pub(crate) fn authorization(
+        &mut self,
+        username: &str,
+        password: &str,
+        method: &str,
+        uri: &str,
+        cnonce: &str,
+    ) -> String {
+        self.count += 1;
+        let nc = format!("{:08x}", self.count);
+        let hash = |data: String| self.algorithm.hash(&data);
+
+        let mut ha1 = hash(format!("{username}:{}:{password}", self.realm));
+        if self.session {
+            ha1 = hash(format!("{ha1}:{}:{cnonce}", self.nonce));
+        }
+        let ha2 = hash(format!("{method}:{uri}"));
+        let response = if self.qop {
+            hash(format!("{ha1}:{}:{nc}:{cnonce}:auth:{ha2}", self.nonce))
+        } else {
+            hash(format!("{ha1}:{}:{ha2}", self.nonce))
+        };
+
+        let algorithm = match (self.algorithm, self.session) {
+            (Algorithm::Md5, false) => "MD5",
+            (Algorithm::Md5, true) => "MD5-sess",
+            (Algorithm::Sha256, false) => "SHA-256",
+            (Algorithm::Sha256, true) => "SHA-256-sess",
+        };
+        let mut header = format!(
+            "Digest username=\"{}\", realm=\"{}\", uri=\"{}\", algorithm={algorithm}, nonce=\"{}\"",
+            quote(username),
+            quote(&self.realm),
+            quote(uri),
+            quote(&self.nonce),
+        );
+        if self.qop {
+            let _ = write!(header, ", nc={nc}, cnonce=\"{cnonce}\", qop=auth");
+        }
+        let _ = write!(header, ", response=\"{response}\"");
+        if let Some(opaque) = &self.opaque {
+            let _ = write!(header, ", opaque=\"{}\"", quote(opaque));
+        }
+        header
+    }
+}
+
+/// Splits the auth-params of a challenge into names and unquoted values.
+fn parse_params(params: &str) -> Vec<(String, String)> {
+    let mut parsed = Vec::new();
+    let mut chars = params.chars().peekable();
+    loop {
+        while chars.next_if(|c| *c == ',' || c.is_whitespace()).is_some() {}
+        let name: String =
+            std::iter::from_fn(|| chars.next_if(|c| *c != '=' && *c != ',')).collect();
+        if chars.next_if_eq(&'=').is_none() {
+            if chars.peek().is_none() {
+                return parsed;
+            }
+            continue;
+        }
+        while chars.next_if(|c| c.is_whitespace()).is_some() {}
+        let mut value = String::new();
+        if chars.next_if_eq(&'"').is_some() {
+            while let Some(c) = chars.next() {
+                match c {
+                    '\\' => value.extend(chars.next()),
+                    '"' => break,
+                    c => value.push(c),
+                }
+            }
+        } else {
+            value.extend(std::iter::from_fn(|| chars.next_if(|c| *c != ',')));
+        }
+        parsed.push((name.trim().to_string(), value.trim_end().to_string()));
+    }
+}
+
+/// Escapes quotes and backslashes for a quoted string.
+fn quote(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+/// Answers HTTP Digest challenges (RFC 7616) with a username and password (requires the `digest` feature).
+///
+/// Add it as a [`Middleware`], such as with the `middleware` method of a generated struct's
+/// [`ApiBuilder`](crate::ApiBuilder). When a request gets a `401 Unauthorized` response with a
+/// `WWW-Authenticate: Digest` challenge, it's sent once more with an `Authorization` header answering it. The challenge
+/// is then kept for its protection space, the realm on that server, which covers the directory of the request and the
+/// URIs listed by the challenge's `domain` parameter. Later requests in the space answer it up front, without the extra
+/// round trip, until the server sends a new one, while other realms of the same server are kept apart.
+///
+/// The `MD5` and `SHA-256` algorithms are supported, along with their `-sess` variants, with `qop=auth` or without a
+/// qop. Requests whose body is a stream can't be sent twice, so they fail with [`Error::NotRetryable`] unless the
+/// challenge is already known.
+///
+/// ```rust
+/// use api_client::{api, DigestAuth};
+///
+/// api!(pub struct ExampleApi, base = "https://appliance.example.com/api/");
+///
+/// let api = ExampleApi::builder()
+///     .middleware(DigestAuth::new("admin", "secret"))
+///     .build()
+///     .unwrap();
+/// ```
+#[derive(Debug)]
+pub struct DigestAuth {
+    /// The username.
+    username: String,
+    /// The password.
+    password: Secret,
+    /// The protection spaces challenges were received for.
+    spaces: Mutex<Vec<Space>>,
+}
+
+impl DigestAuth {
+    /// Creates a middleware answering challenges with `username` and `password`.
+    #[must_use]
+    pub fn new(username: impl Into<String>, password: impl Into<Secret>) -> Self {
+        DigestAuth {
+            username: username.into(),
+            password: password.into(),
+            spaces: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// Sets the `Authorization` header of `request` answering `challenge`.
+    fn authorize(&self, request: &mut Request, challenge: &mut Challenge) -> Result<()> {
+        let url = request.url();
+        let uri = match url.query() {
+            Some(query) => format!("{}?{query}", url.path()),
+            None => url.path().to_string(),
+        };
+        let header = challenge.authorization(
+            &self.username,
+            self.password.expose(),
+            request.method().as_str(),
+            &uri,
+            &cnonce()?,
+        );
+        request
+            .headers_mut()
+            .insert(AUTHORIZATION, sensitive(AUTHORIZATION.as_str(), &header)?);
+        Ok(())
+    }
+
+    /// Answers the challenge of the protection space `request` is in, if it's in one, picking the space whose path
+    /// matches most of the request's.
+    fn authorize_cached(&self, request: &mut Request) -> Result<()> {
+        let mut spaces = self.spaces.lock().unwrap_or_else(PoisonError::into_inner);
+        let space = spaces
+            .iter_mut()
+            .filter_map(|space| Some((space.covers(request.url())?, space)))
+            .max_by_key(|(matched, _)| *matched);
+        match space {
+            Some((_, space)) => self.authorize(request, &mut space.challenge),
+            None => Ok(()),
+        }
+    }
+
+    /// Keeps `challenge`, received for a request to `url`, as the challenge of its protection space.
+    fn remember(&self, url: &Url, challenge: Challenge) {
+        let origin = origin(url);
+        let mut prefixes: Vec<String> = challenge
+            .domain
+            .iter()
+            .filter_map(|uri| url.join(uri).ok())
+            .filter(|uri| self::origin(uri) == origin)
+            .map(|uri| uri.path().to_string())
+            .collect();
+        let path = url.path();
+        prefixes.push(path[..=path.rfind('/').unwrap_or(0)].to_string());
+
+        let mut spaces = self.spaces.lock().unwrap_or_else(PoisonError::into_inner);
+        match spaces
+            .iter_mut()
+            .find(|space| space.origin == origin && space.challenge.realm == challenge.realm)
+        {
+            Some(space) => {
+                space.challenge = challenge;
+                for prefix in prefixes {
+                    if !space.prefixes.contains(&prefix) {
+                        space.prefixes.push(prefix);
+                    }
+                }
+            }
+            None => spaces.push(Space {
+                origin,
+                challenge,
+                prefixes,
+            }),
+        }
+    }
+}
+
+/// A protection space: a realm of a server, with its latest challenge and the paths it covers.
+#[derive(Debug)]
+struct Space {
+    /// The scheme, host and port of the server.
+    origin: String,
+    /// The latest challenge of the realm.
+    challenge: Challenge,
+    /// The paths whose URIs, and those below them, are in the space.
+    prefixes: Vec<String>,
+}
+
+impl Space {
+    /// Returns the length of the longest path of the space `url` starts with, or `None` if it's not in the space.
+    fn covers(&self, url: &Url) -> Option<usize> {
+        if origin(url) != self.origin {
+            return None;
+        }
+        self.prefixes
+            .iter()
+            .filter(|prefix| url.path().starts_with(prefix.as_str()))
+            .map(String::len)
+            .max()
+    }
+}
+
+#[cfg_attr(feature = "send", async_trait::async_trait)]
+#[cfg_attr(not(feature = "send"), async_trait::async_trait(?Send))]
+impl Middleware for DigestAuth {
+    async fn handle(&self, mut request: Request, next: Next<'_>) -> Result<Response> {
+        self.authorize_cached(&mut request)?;
+        let retry = request.try_clone();
+        let response = next.run(request).await?;
+        if response.status() != StatusCode::UNAUTHORIZED {
+            return Ok(response);
+        }
+        let Some(mut challenge) = Challenge::from_response(&response) else {
+            return Ok(response);
+        };
+
+        let mut retry = retry.ok_or(Error::NotRetryable)?;
+        self.authorize(&mut retry, &mut challenge)?;
+        self.remember(retry.url(), challenge);
+        next.run(retry).await
+    }
+}
+
+/// Returns the scheme, host and port of `url`, the server a protection space belongs to.
+fn origin(url: &Url) -> String {
+    format!(
+        "{}://{}:{}",
+        url.scheme(),
+        url.host_str().unwrap_or_default(),
+        url.port_or_known_default().unwrap_or_default()
+    )
+}
+
+/// Returns a random client nonce.
+fn cnonce() -> Result<String> {
+    let mut bytes = [0; 16];
+    SystemRandom::new()
+        .fill(&mut bytes)
+        .map_err(|_| Error::Service("failed to generate a client nonce".into()))?;
+    Ok(hex(&bytes))
+}
+
+/// Encodes `bytes` as lowercase hex.
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().fold(String::new(), |mut hex, byte| {
+        let _ = write!(hex, "{byte:02x}");
+        hex
+    })
+}
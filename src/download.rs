@@ -0,0 +1,74 @@
+//! Streaming response bodies straight to a file.
+
+use std::path::{Path, PathBuf};
+
+use reqwest::{header::CONTENT_TYPE, Response};
+use tokio::{fs, io::AsyncWriteExt};
+
+use crate::Error;
+
+/// A completed download, returned by `-> Download` endpoints.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Download {
+    /// Where the body was written.
+    pub path: PathBuf,
+    /// Number of bytes written.
+    pub bytes: u64,
+    /// The `Content-Type` of the response, if it had a valid one.
+    pub content_type: Option<String>,
+}
+
+/// Writes the body of `response` to `path` one chunk at a time, removing the partial file on failure.
+///
+/// When `create_dirs` is true, missing parent directories of `path` are created first. After each chunk is written,
+/// `progress` is called with the number of bytes written so far and the `Content-Length` of the response, if known.
+///
+/// # Errors
+/// Returns an error if reading the body or writing the file fails.
+#[doc(hidden)]
+pub async fn save(
+    mut response: Response,
+    path: impl AsRef<Path>,
+    create_dirs: bool,
+    mut progress: impl FnMut(u64, Option<u64>),
+) -> Result<Download, Error> {
+    let path = path.as_ref();
+    let total = response.content_length();
+    let content_type = response
+        .headers()
+        .get(CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .map(str::to_string);
+
+    if create_dirs {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent).await?;
+        }
+    }
+
+    let mut file = fs::File::create(path).await?;
+    let written = async {
+        let mut bytes = 0;
+        while let Some(chunk) = response.chunk().await? {
+            file.write_all(&chunk).await?;
+            bytes += chunk.len() as u64;
+            progress(bytes, total);
+        }
+        file.flush().await?;
+        Ok::<_, Error>(bytes)
+    }
+    .await;
+    drop(file);
+
+    match written {
+        Ok(bytes) => Ok(Download {
+            path: path.to_path_buf(),
+            bytes,
+            content_type,
+        }),
+        Err(error) => {
+            let _ = fs::remove_file(path).await;
+            Err(error)
+        }
+    }
+}
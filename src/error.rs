@@ -0,0 +1,372 @@
+//! The error types returned by the [`api`](crate::api) macro.
+
+use std::{error::Error as StdError, fmt, io, time::Duration};
+
+use reqwest::{StatusCode, Url};
+
+use crate::decode::snippet;
+
+/// Errors returned by endpoints generated with the [`api`](crate::api) macro.
+///
+/// Failures to connect, unexpected statuses and bodies that can't be decoded are separate variants, and
+/// [`Error::status`], [`Error::url`] and [`Error::body`] return what's known about the response whichever the variant.
+/// New variants may be added in minor releases, so matches need a wildcard arm.
+///
+/// # Migrating from 0.2
+/// - Generated endpoints return [`crate::Result`], whose error is this type, rather than `reqwest::Result`, including
+///   those whose body reqwest decoded before. A `reqwest::Error` from sending the request or reading the body is
+///   wrapped in [`Error::Transport`], so callers returning `reqwest::Result` need `map_err`, and checks like
+///   `error.is_timeout()` become `matches!(&error, Error::Transport(error) if error.is_timeout())`.
+///   [`Api::pre_request`](crate::Api::pre_request) still returns `reqwest::Result`.
+/// - `Error` is `#[non_exhaustive]`: add a `_ =>` arm to exhaustive matches.
+/// - [`Error::UnexpectedStatus`] has a `url` field and [`Error::Decode`] a `body` field with the start of the body
+///   that failed to decode. Both are `#[non_exhaustive]`, so patterns naming their fields need a `..`, as in
+///   `Error::UnexpectedStatus { status, .. }`.
+/// - Endpoints check the response status whatever they return, except `StatusCode` and `Headers`, so an error status
+///   fails with [`Error::UnexpectedStatus`] rather than being decoded as a body, or failing with
+///   [`Error::Transport`] for streams and downloads. Add an `expect` clause to accept such statuses.
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum Error {
+    /// Sending the request or reading the response failed.
+    Transport(reqwest::Error),
+    /// A middleware sending the request in place of the client, such as [`Execute`](crate::Execute), failed.
+    Service(Box<dyn StdError + Send + Sync + 'static>),
+    /// Reading or writing a local file failed.
+    Io(io::Error),
+    /// The response body could not be decoded into the declared type.
+    #[non_exhaustive]
+    Decode {
+        /// Length of the response body in bytes.
+        length: usize,
+        /// The start of the response body, with invalid UTF-8 replaced.
+        body: String,
+        /// The underlying deserialization error.
+        source: Box<dyn StdError + Send + Sync + 'static>,
+    },
+    /// A base URL passed to [`ApiBuilder::base_url`](crate::ApiBuilder::base_url) is not a valid absolute URL.
+    InvalidBaseUrl {
+        /// The base URL.
+        url: String,
+    },
+    /// A header name declared on the endpoint is not a valid HTTP header name, so the request was not sent, or one
+    /// passed to [`ApiBuilder::default_header`](crate::ApiBuilder::default_header) isn't.
+    InvalidHeaderName {
+        /// The declared header name.
+        name: String,
+    },
+    /// A header value declared on the endpoint is not a valid HTTP header value, so the request was not sent, or one
+    /// passed to [`ApiBuilder::default_header`](crate::ApiBuilder::default_header) isn't.
+    InvalidHeaderValue {
+        /// The name of the header.
+        name: String,
+    },
+    /// The response has an error status, or one the endpoint's `expect` clause doesn't allow, so the body was not
+    /// decoded.
+    #[non_exhaustive]
+    UnexpectedStatus {
+        /// The response status.
+        status: StatusCode,
+        /// The URL of the response, if known.
+        url: Option<Url>,
+        /// The start of the response body, with invalid UTF-8 replaced.
+        body: String,
+    },
+    /// The response body is larger than the endpoint's size limit, so it was not read in full.
+    BodyTooLarge {
+        /// The size limit in bytes.
+        limit: u64,
+        /// Number of bytes received, or the `Content-Length` of the response if it was already too large.
+        received: u64,
+    },
+    /// The settings passed to [`ApiBuilder`](crate::ApiBuilder) conflict, such as TLS settings combined with a client
+    /// set with [`ApiBuilder::client`](crate::ApiBuilder::client), so the struct was not built.
+    Config {
+        /// What is wrong with the settings.
+        message: String,
+    },
+    /// The response was decoded, but failed the [`Validate`](crate::Validate) check of an endpoint with a `validate`
+    /// clause.
+    Invalid {
+        /// Why the response is invalid.
+        message: String,
+    },
+    /// The server responded `401 Unauthorized` and new credentials were found, such as by
+    /// [`Api::reauthenticate`](crate::Api::reauthenticate), but the request couldn't be retried with them because its
+    /// body is a stream.
+    NotRetryable,
+    /// A server taking part in an authentication flow responded in a way the flow doesn't allow, such as a CSRF token
+    /// endpoint responding without a token, or an authorization redirect with the wrong `state`.
+    Protocol {
+        /// What the server did wrong.
+        message: String,
+    },
+    /// There are no credentials to send or refresh, such as before the first
+    /// `AuthorizationCode::login` (requires the `oauth2-pkce` feature), so the user has to log in.
+    LoginRequired,
+    /// The endpoint's call [`Budget`](crate::Budget) is exhausted, so the request was not sent.
+    BudgetExhausted {
+        /// Name of the exhausted budget.
+        budget: String,
+        /// Maximum number of calls within `window`.
+        limit: usize,
+        /// Length of the budget's rolling window.
+        window: Duration,
+    },
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Transport(error) => error.fmt(f),
+            Error::Service(error) => error.fmt(f),
+            Error::Io(error) => error.fmt(f),
+            Error::Decode { length, source, .. } => {
+                write!(
+                    f,
+                    "failed to decode response body ({length} bytes): {source}"
+                )
+            }
+            Error::InvalidBaseUrl { url } => write!(f, "invalid base URL `{url}`"),
+            Error::InvalidHeaderName { name } => write!(f, "invalid header name `{name}`"),
+            Error::InvalidHeaderValue { name } => write!(f, "invalid value for header `{name}`"),
+            Error::UnexpectedStatus { status, body, .. } => {
+                write!(f, "unexpected response status {status}: {body}")
+            }
+            Error::BodyTooLarge { limit, received } => write!(
+                f,
+                "response body exceeds the {limit} byte limit ({received} bytes received)"
+            ),
+            Error::Config { message } => write!(f, "invalid configuration: {message}"),
+            Error::Invalid { message } => write!(f, "invalid response: {message}"),
+            Error::NotRetryable => f.write_str(
+                "request was unauthorized and can't be retried with new credentials because its body is a stream",
+            ),
+            Error::Protocol { message } => write!(f, "protocol error: {message}"),
+            Error::LoginRequired => f.write_str("no credentials to send or refresh; log in first"),
+            Error::BudgetExhausted {
+                budget,
+                limit,
+                window,
+            } => write!(
+                f,
+                "call budget `{budget}` exhausted ({limit} calls per {window:?})"
+            ),
+        }
+    }
+}
+
+impl Error {
+    /// Creates an [`Error::Decode`] for the response body `bytes`.
+    #[cfg(any(
+        feature = "json",
+        feature = "msgpack",
+        feature = "cbor",
+        feature = "yaml",
+        feature = "csv"
+    ))]
+    pub(crate) fn decode(
+        bytes: &[u8],
+        source: impl Into<Box<dyn StdError + Send + Sync + 'static>>,
+    ) -> Self {
+        Error::Decode {
+            length: bytes.len(),
+            body: snippet(bytes),
+            source: source.into(),
+        }
+    }
+
+    /// Creates an [`Error::Decode`] for the response body `bytes` without keeping its start, for bodies that may hold
+    /// credentials.
+    #[cfg(all(feature = "auth", feature = "json"))]
+    pub(crate) fn decode_redacted(
+        bytes: &[u8],
+        source: impl Into<Box<dyn StdError + Send + Sync + 'static>>,
+    ) -> Self {
+        Error::Decode {
+            length: bytes.len(),
+            body: String::new(),
+            source: source.into(),
+        }
+    }
+
+    /// Creates an [`Error::UnexpectedStatus`] for a response without a known URL.
+    pub(crate) fn status_without_url(status: StatusCode, body: String) -> Self {
+        Error::UnexpectedStatus {
+            status,
+            url: None,
+            body,
+        }
+    }
+
+    /// Returns the status of the response, if the request got one.
+    #[must_use]
+    pub fn status(&self) -> Option<StatusCode> {
+        match self {
+            Error::Transport(error) => error.status(),
+            Error::UnexpectedStatus { status, .. } => Some(*status),
+            _ => None,
+        }
+    }
+
+    /// Returns the URL of the request or response, if known.
+    #[must_use]
+    pub fn url(&self) -> Option<&Url> {
+        match self {
+            Error::Transport(error) => error.url(),
+            Error::UnexpectedStatus { url, .. } => url.as_ref(),
+            _ => None,
+        }
+    }
+
+    /// Returns the start of the response body, with invalid UTF-8 replaced, for error statuses and bodies that failed
+    /// to decode.
+    #[must_use]
+    pub fn body(&self) -> Option<&str> {
+        match self {
+            Error::UnexpectedStatus { body, .. } | Error::Decode { body, .. } => Some(body),
+            _ => None,
+        }
+    }
+}
+
+impl StdError for Error {
+    fn source(&self) -> Option<&(dyn StdError + 'static)> {
+        match self {
+            Error::Transport(error) => Some(error),
+            Error::Service(error) => Some(error.as_ref()),
+            Error::Io(error) => Some(error),
+            Error::Decode { source, .. } => Some(source.as_ref()),
+            Error::InvalidBaseUrl { .. }
+            | Error::InvalidHeaderName { .. }
+            | Error::InvalidHeaderValue { .. }
+            | Error::UnexpectedStatus { .. }
+            | Error::BodyTooLarge { .. }
+            | Error::Config { .. }
+            | Error::Invalid { .. }
+            | Error::NotRetryable
+            | Error::Protocol { .. }
+            | Error::LoginRequired
+            | Error::BudgetExhausted { .. } => None,
+        }
+    }
+}
+
+impl From<reqwest::Error> for Error {
+    #[inline]
+    fn from(error: reqwest::Error) -> Self {
+        Error::Transport(error)
+    }
+}
+
+impl From<io::Error> for Error {
+    #[inline]
+    fn from(error: io::Error) -> Self {
+        Error::Io(error)
+    }
+}
+
+/// Used internally by the [`api`](crate::api) macro to pass the errors of failed endpoints to
+/// [`Api::on_error`](crate::Api::on_error).
+pub trait Failure {
+    /// Calls `report` with this error as an [`Error`].
+    fn report(&self, report: impl FnOnce(&Error));
+}
+
+impl Failure for Error {
+    #[inline]
+    fn report(&self, report: impl FnOnce(&Error)) {
+        report(self);
+    }
+}
+
+/// Errors returned by endpoints declared as `-> Result<Json<T>, Json<E>>`, which decode error responses into `E`.
+#[derive(Debug)]
+pub enum ApiError<E> {
+    /// The server responded with an error status and a body that deserialized into `E`.
+    Api {
+        /// The response status.
+        status: StatusCode,
+        /// The deserialized error body.
+        body: E,
+    },
+    /// The server responded with an error status and a body that isn't an `E`, such as an HTML error page.
+    Unparsed {
+        /// The response status.
+        status: StatusCode,
+        /// The response body, with invalid UTF-8 replaced.
+        body: String,
+    },
+    /// The request failed for any other reason.
+    Other(Error),
+}
+
+impl<E> ApiError<E> {
+    /// Returns the status of the error response, if the server sent one.
+    #[must_use]
+    pub fn status(&self) -> Option<StatusCode> {
+        match self {
+            ApiError::Api { status, .. } | ApiError::Unparsed { status, .. } => Some(*status),
+            ApiError::Other(_) => None,
+        }
+    }
+}
+
+impl<E: fmt::Debug> fmt::Display for ApiError<E> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ApiError::Api { status, body } => write!(f, "server responded with {status}: {body:?}"),
+            ApiError::Unparsed { status, body } => {
+                write!(f, "server responded with {status}: {body}")
+            }
+            ApiError::Other(error) => error.fmt(f),
+        }
+    }
+}
+
+impl<E: fmt::Debug> StdError for ApiError<E> {
+    fn source(&self) -> Option<&(dyn StdError + 'static)> {
+        match self {
+            ApiError::Other(error) => Some(error),
+            ApiError::Api { .. } | ApiError::Unparsed { .. } => None,
+        }
+    }
+}
+
+impl<E> From<Error> for ApiError<E> {
+    #[inline]
+    fn from(error: Error) -> Self {
+        ApiError::Other(error)
+    }
+}
+
+impl<E> From<reqwest::Error> for ApiError<E> {
+    #[inline]
+    fn from(error: reqwest::Error) -> Self {
+        ApiError::Other(Error::Transport(error))
+    }
+}
+
+impl<E> From<io::Error> for ApiError<E> {
+    #[inline]
+    fn from(error: io::Error) -> Self {
+        ApiError::Other(Error::Io(error))
+    }
+}
+
+impl<E> Failure for ApiError<E> {
+    fn report(&self, report: impl FnOnce(&Error)) {
+        match self {
+            ApiError::Api { status, .. } => {
+                report(&Error::status_without_url(*status, String::new()));
+            }
+            ApiError::Unparsed { status, body } => {
+                report(&Error::status_without_url(
+                    *status,
+                    snippet(body.as_bytes()),
+                ));
+            }
+            ApiError::Other(error) => report(error),
+        }
+    }
+}
@@ -0,0 +1,72 @@
+//! Contract checks for response examples declared with `example = "..."` in the [`api`](crate::api) macro.
+
+use std::{error::Error as StdError, fmt, fs};
+
+use serde::de::DeserializeOwned;
+
+/// A response example that could not be deserialized into its endpoint's response type.
+#[derive(Debug)]
+pub struct ExampleError {
+    /// Name of the endpoint the example was declared on.
+    pub endpoint: &'static str,
+    /// Path of the example file, relative to the crate root.
+    pub file: &'static str,
+    /// Path to the value that failed to deserialize, such as `items[0].id`.
+    ///
+    /// This is `.` when the example could not be read, or the error was at the root.
+    pub path: String,
+    /// The underlying I/O or deserialization error.
+    pub source: Box<dyn StdError + Send + Sync + 'static>,
+}
+
+impl fmt::Display for ExampleError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "example `{}` for `{}` failed at `{}`: {}",
+            self.file, self.endpoint, self.path, self.source
+        )
+    }
+}
+
+impl StdError for ExampleError {
+    fn source(&self) -> Option<&(dyn StdError + 'static)> {
+        Some(self.source.as_ref())
+    }
+}
+
+/// Deserializes each example into `T`, pushing any failures onto `errors`.
+///
+/// Used internally by the `verify_examples` function generated by the [`api`](crate::api) macro.
+#[doc(hidden)]
+pub fn verify<T: DeserializeOwned>(
+    errors: &mut Vec<ExampleError>,
+    endpoint: &'static str,
+    root: &str,
+    files: &[&'static str],
+) {
+    for &file in files {
+        let contents = match fs::read_to_string(format!("{root}/{file}")) {
+            Ok(contents) => contents,
+            Err(error) => {
+                errors.push(ExampleError {
+                    endpoint,
+                    file,
+                    path: ".".to_string(),
+                    source: Box::new(error),
+                });
+                continue;
+            }
+        };
+
+        let deserializer = &mut serde_json::Deserializer::from_str(&contents);
+        if let Err(error) = serde_path_to_error::deserialize::<_, T>(deserializer) {
+            errors.push(ExampleError {
+                endpoint,
+                file,
+                path: error.path().to_string(),
+                source: Box::new(error.into_inner()),
+            });
+        }
+    }
+}
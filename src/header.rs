@@ -0,0 +1,87 @@
+//! Header helpers used internally by the [`api`](crate::api) macro.
+
+use std::fmt::Display;
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+use reqwest::{
+    header::{HeaderMap, HeaderName, HeaderValue, AUTHORIZATION},
+    RequestBuilder,
+};
+
+use crate::Error;
+
+/// Parses a header name declared as a string literal, such as `"x-api-key"`.
+///
+/// # Errors
+/// Returns [`Error::InvalidHeaderName`] if `name` is not a valid HTTP header name.
+pub fn name(name: &str) -> Result<HeaderName, Error> {
+    HeaderName::from_bytes(name.as_bytes()).map_err(|_| Error::InvalidHeaderName {
+        name: name.to_string(),
+    })
+}
+
+/// Adds the `name` header to `builder`, replacing its value from `defaults` if it has one, so an endpoint's headers
+/// override [`Api::default_headers`](crate::Api::default_headers).
+///
+/// # Errors
+/// Returns [`Error::InvalidHeaderValue`] if `value` is not a valid header value.
+pub fn add<V>(
+    builder: RequestBuilder,
+    defaults: &HeaderMap,
+    name: HeaderName,
+    value: V,
+) -> Result<RequestBuilder, Error>
+where
+    HeaderValue: TryFrom<V>,
+{
+    if defaults.contains_key(&name) {
+        return replace(builder, name, value);
+    }
+    let value = HeaderValue::try_from(value).map_err(|_| Error::InvalidHeaderValue {
+        name: name.to_string(),
+    })?;
+    Ok(builder.header::<HeaderName, HeaderValue>(name, value))
+}
+
+/// Sets the `name` header on `builder` to `value`, replacing any value it already has, such as the `Content-Type` set
+/// by a request body.
+///
+/// # Errors
+/// Returns [`Error::InvalidHeaderValue`] if `value` is not a valid header value.
+pub fn replace<V>(
+    builder: RequestBuilder,
+    name: HeaderName,
+    value: V,
+) -> Result<RequestBuilder, Error>
+where
+    HeaderValue: TryFrom<V>,
+{
+    let value = HeaderValue::try_from(value).map_err(|_| Error::InvalidHeaderValue {
+        name: name.to_string(),
+    })?;
+    let mut headers = HeaderMap::new();
+    headers.insert(name, value);
+    Ok(builder.headers(headers))
+}
+
+/// Sets the `Authorization` header on `builder` to HTTP basic auth credentials, replacing any it already has, such as a
+/// token added by [`Api::pre_request`](crate::Api::pre_request).
+///
+/// # Errors
+/// Returns [`Error::InvalidHeaderValue`] if the encoded credentials are not a valid header value.
+pub fn basic_auth<U: Display, P: Display>(
+    builder: RequestBuilder,
+    username: U,
+    password: Option<P>,
+) -> Result<RequestBuilder, Error> {
+    let credentials = match password {
+        Some(password) => format!("{username}:{password}"),
+        None => format!("{username}:"),
+    };
+    let mut value = HeaderValue::try_from(format!("Basic {}", STANDARD.encode(credentials)))
+        .map_err(|_| Error::InvalidHeaderValue {
+            name: AUTHORIZATION.to_string(),
+        })?;
+    value.set_sensitive(true);
+    replace(builder, AUTHORIZATION, value)
+}
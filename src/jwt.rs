@@ -0,0 +1,181 @@
+//! JSON Web Tokens cached until shortly before they expire.
+
+use std::{
+    future::Future,
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+
+use crate::{AuthState, Result, Secret, Value};
+
+/// A JSON Web Token and the expiry read from its `exp` claim when it was created.
+///
+/// The signature isn't validated, since only the server that issued the token can trust it; the payload is only read
+/// to know when to refresh the token.
+#[derive(Clone, Debug)]
+pub struct Jwt {
+    /// The token itself.
+    token: Secret,
+    /// When the token expires, if it has an `exp` claim.
+    expires: Option<SystemTime>,
+}
+
+impl Jwt {
+    /// Wraps `token`, reading its `exp` claim.
+    ///
+    /// Tokens without an `exp` claim, or whose payload can't be read, never expire.
+    #[must_use]
+    pub fn new(token: impl Into<Secret>) -> Self {
+        let token = token.into();
+        let expires = expiry(token.expose());
+        Jwt { token, expires }
+    }
+
+    /// Returns the token itself.
+    #[must_use]
+    pub fn token(&self) -> &Secret {
+        &self.token
+    }
+
+    /// Returns when the token expires, or `None` if it has no `exp` claim.
+    #[must_use]
+    pub fn expires(&self) -> Option<SystemTime> {
+        self.expires
+    }
+
+    /// Returns whether the token has expired or expires within `leeway`. Tokens without an `exp` claim never do.
+    #[must_use]
+    pub fn is_expired(&self, leeway: Duration) -> bool {
+        self.expires
+            .is_some_and(|expires| SystemTime::now() + leeway >= expires)
+    }
+}
+
+/// Reads the `exp` claim, in seconds since the Unix epoch, from the payload of `token`.
+fn expiry(token: &str) -> Option<SystemTime> {
+    let payload = token.split('.').nth(1)?;
+    let payload = URL_SAFE_NO_PAD.decode(payload.trim_end_matches('=')).ok()?;
+    let claims: Value = serde_json::from_slice(&payload).ok()?;
+    let exp = claims.get("exp")?.as_f64()?;
+    UNIX_EPOCH.checked_add(Duration::try_from_secs_f64(exp).ok()?)
+}
+
+/// Caches a [`Jwt`] and requests a new one shortly before it expires (requires the `jwt` feature).
+///
+/// Call [`JwtCache::get_or_refresh`] from [`Api::pre_request_async`](crate::Api::pre_request_async): the token is
+/// decoded once when it's stored, and `refresh` is called when there's none or it expires within the leeway, 30
+/// seconds by default, so requests don't go out with a token about to be rejected. As with [`AuthState`], concurrent
+/// requests share a single refresh.
+///
+/// ```rust
+/// use api_client::{api, Api, JwtCache};
+/// use reqwest::{Client, RequestBuilder};
+///
+/// struct ExampleApi {
+///     client: Client,
+///     token: JwtCache,
+/// }
+///
+/// impl ExampleApi {
+///     api! {
+///         fn login() -> String {
+///             POST "https://example.com/login"
+///             no_pre_request
+///         }
+///
+///         fn example() -> String {
+///             GET "https://example.com"
+///         }
+///     }
+/// }
+///
+/// impl Api for ExampleApi {
+///     fn client(&self) -> &Client {
+///         &self.client
+///     }
+///
+///     async fn pre_request_async(&self, request: RequestBuilder) -> api_client::Result<RequestBuilder> {
+///         let token = self.token.get_or_refresh(|| self.login()).await?;
+///         Ok(request.bearer_auth(token.token().expose()))
+///     }
+///
+///     async fn reauthenticate(&self) -> api_client::Result<bool> {
+///         self.token.invalidate().await;
+///         Ok(true)
+///     }
+/// }
+/// ```
+#[derive(Debug)]
+pub struct JwtCache {
+    /// The cached token.
+    token: AuthState<Jwt>,
+    /// How long before it expires a token is refreshed.
+    leeway: Duration,
+}
+
+impl Default for JwtCache {
+    #[inline]
+    fn default() -> Self {
+        JwtCache::new()
+    }
+}
+
+impl JwtCache {
+    /// Creates an empty cache, filled by the first request.
+    #[must_use]
+    pub fn new() -> Self {
+        JwtCache {
+            token: AuthState::new(),
+            leeway: Duration::from_secs(30),
+        }
+    }
+
+    /// Refreshes tokens `leeway` before they expire instead of 30 seconds before.
+    #[must_use]
+    pub fn leeway(mut self, leeway: Duration) -> Self {
+        self.leeway = leeway;
+        self
+    }
+
+    /// Returns the cached token, if there is one, even if it has expired.
+    pub async fn get(&self) -> Option<Jwt> {
+        self.token.get().await
+    }
+
+    /// Stores `token`, such as one loaded from disk, reading its expiry.
+    pub async fn set(&self, token: impl Into<Secret>) {
+        self.token.set(Jwt::new(token)).await;
+    }
+
+    /// Discards the cached token, so the next request refreshes it, such as after the server rejects it.
+    pub async fn invalidate(&self) {
+        self.token.invalidate().await;
+    }
+
+    /// Returns whether there's no cached token or it expires within `leeway`.
+    pub async fn is_expired(&self, leeway: Duration) -> bool {
+        self.get()
+            .await
+            .map_or(true, |token| token.is_expired(leeway))
+    }
+
+    /// Returns the cached token, or calls `refresh` to get a new one if there isn't one or it expires within the
+    /// leeway.
+    ///
+    /// # Errors
+    /// Returns the error from `refresh` if it fails.
+    pub async fn get_or_refresh<F, Fut, T>(&self, refresh: F) -> Result<Jwt>
+    where
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = Result<T>>,
+        T: Into<Secret>,
+    {
+        self.token
+            .get_or_refresh_if(
+                |token| token.is_expired(self.leeway),
+                || async { refresh().await.map(Jwt::new) },
+            )
+            .await
+    }
+}
@@ -3,13 +3,146 @@
 #![warn(clippy::missing_docs_in_private_items)]
 #![warn(clippy::pedantic)]
 
-use reqwest::{Client, RequestBuilder, Result};
+use std::future::Future;
 
+use reqwest::{header::HeaderMap, Client, RequestBuilder, StatusCode, Url};
+
+#[cfg(feature = "auth")]
+mod auth;
+mod budget;
+mod builder;
+mod cache;
 #[doc(hidden)]
-pub use reqwest;
+pub mod cookie;
+#[cfg(feature = "cookie-store")]
+mod cookie_file;
+mod credentials;
+#[cfg(feature = "auth")]
+mod csrf;
+#[doc(hidden)]
+pub mod decode;
+#[cfg(feature = "digest")]
+mod digest;
+#[cfg(feature = "download")]
+#[doc(hidden)]
+pub mod download;
+mod error;
+#[cfg(feature = "examples")]
+#[cfg_attr(docsrs, doc(cfg(feature = "examples")))]
+pub mod examples;
+#[doc(hidden)]
+pub mod header;
+#[cfg(feature = "jwt")]
+mod jwt;
+#[doc(hidden)]
+pub mod method;
+#[doc(hidden)]
+pub mod middleware;
+#[cfg(feature = "oauth2")]
+mod oauth2;
+#[cfg(feature = "oauth2-pkce")]
+mod pkce;
+#[doc(hidden)]
+pub mod query;
+#[cfg(feature = "rate-limit")]
+mod rate_limit;
+#[doc(hidden)]
+pub mod request;
+mod response;
+#[cfg(feature = "sigv4")]
+mod sigv4;
+#[cfg(feature = "stream")]
+mod stream;
+mod token_store;
+#[cfg(feature = "tower")]
+mod tower;
+#[cfg(doctest)]
+mod ui;
+#[doc(hidden)]
+pub mod url;
+#[doc(hidden)]
+pub mod validate;
 
-/// Used internally to the api! macro.
+#[cfg(feature = "auth")]
+#[cfg_attr(docsrs, doc(cfg(feature = "auth")))]
+pub use auth::AuthState;
+#[cfg(feature = "json")]
+#[cfg_attr(docsrs, doc(cfg(feature = "json")))]
+pub use budget::FileBudgetStore;
+pub use budget::{Budget, BudgetPolicy, BudgetStore, MemoryBudgetStore};
+pub use builder::ApiBuilder;
+#[doc(hidden)]
+pub use builder::ApiConfig;
+pub use cache::{CachedResponse, ResponseSummary};
+#[cfg(feature = "cookie-store")]
+#[cfg_attr(docsrs, doc(cfg(feature = "cookie-store")))]
+pub use cookie_file::CookieFile;
+pub use credentials::{ApiKey, Authenticate, BasicAuth, BearerAuth, Secret};
+#[cfg(feature = "auth")]
+#[cfg_attr(docsrs, doc(cfg(feature = "auth")))]
+pub use csrf::CsrfToken;
+#[cfg(feature = "digest")]
+#[cfg_attr(docsrs, doc(cfg(feature = "digest")))]
+pub use digest::DigestAuth;
+#[cfg(feature = "download")]
+#[cfg_attr(docsrs, doc(cfg(feature = "download")))]
+pub use download::Download;
 #[doc(hidden)]
+pub use error::Failure;
+pub use error::{ApiError, Error};
+#[cfg(feature = "jwt")]
+#[cfg_attr(docsrs, doc(cfg(feature = "jwt")))]
+pub use jwt::{Jwt, JwtCache};
+pub use middleware::{Execute, Middleware, Next};
+#[cfg(feature = "oauth2")]
+#[cfg_attr(docsrs, doc(cfg(feature = "oauth2")))]
+pub use oauth2::ClientCredentials;
+#[cfg(feature = "oauth2-pkce")]
+#[cfg_attr(docsrs, doc(cfg(feature = "oauth2-pkce")))]
+pub use pkce::{AuthorizationCode, Pkce, RedirectListener};
+pub use query::{QueryStyle, QueryValue};
+#[cfg(feature = "rate-limit")]
+#[cfg_attr(docsrs, doc(cfg(feature = "rate-limit")))]
+pub use rate_limit::RateLimiter;
+#[cfg(feature = "json")]
+#[cfg_attr(docsrs, doc(cfg(feature = "json")))]
+pub use request::Json;
+pub use request::{send, send_with, FromResponse, SendOptions};
+#[doc(hidden)]
+pub use reqwest;
+#[cfg(feature = "json")]
+#[cfg_attr(docsrs, doc(cfg(feature = "json")))]
+pub use response::{Created, JsonBytes};
+pub use response::{Headers, Text};
+#[cfg(feature = "json")]
+#[cfg_attr(docsrs, doc(cfg(feature = "json")))]
+pub use serde_json::Value;
+#[cfg(feature = "sigv4")]
+#[cfg_attr(docsrs, doc(cfg(feature = "sigv4")))]
+pub use sigv4::{AwsCredentials, ProvideCredentials, SigV4};
+#[cfg(all(feature = "stream", feature = "json"))]
+#[cfg_attr(docsrs, doc(cfg(all(feature = "stream", feature = "json"))))]
+pub use stream::Lines;
+#[cfg(feature = "stream")]
+#[cfg_attr(docsrs, doc(cfg(feature = "stream")))]
+pub use stream::{ByteStream, Event, Events};
+#[cfg(feature = "json")]
+#[cfg_attr(docsrs, doc(cfg(feature = "json")))]
+pub use token_store::FileTokenStore;
+#[cfg(feature = "keyring")]
+#[cfg_attr(docsrs, doc(cfg(feature = "keyring")))]
+pub use token_store::KeyringTokenStore;
+pub use token_store::{MemoryTokenStore, StoredToken, TokenStore};
+#[cfg(feature = "tower")]
+#[cfg_attr(docsrs, doc(cfg(feature = "tower")))]
+pub use tower::TowerService;
+pub use validate::Validate;
+
+/// A `Result` alias where the `Err` case is [`Error`].
+pub type Result<T, E = Error> = std::result::Result<T, E>;
+
+/// The body of a request sent with [`send`], like the `Json<T>`, `Form<T>` and `Multipart` parameters of endpoints
+/// declared with the [`api`] macro. Requests without a body pass `Body::<()>::None`.
 pub enum Body<'a, T: ?Sized = ()> {
     /// No body.
     None,
@@ -25,12 +158,80 @@ pub enum Body<'a, T: ?Sized = ()> {
     Multipart(reqwest::multipart::Form),
 }
 
+impl<T: serde::Serialize + ?Sized> Body<'_, T> {
+    /// Sets this body on `builder`.
+    #[doc(hidden)]
+    pub fn apply(self, builder: RequestBuilder) -> RequestBuilder {
+        match self {
+            Body::None => builder,
+            #[cfg(feature = "json")]
+            Body::Json(body) => builder.json(body),
+            Body::Form(body) => builder.form(body),
+            #[cfg(feature = "multipart")]
+            Body::Multipart(form) => builder.multipart(form),
+        }
+    }
+}
+
+/// Used internally to require [`Send`] only with the `send` feature.
+#[cfg(feature = "send")]
+#[doc(hidden)]
+pub trait MaybeSend: Send {}
+
+#[cfg(feature = "send")]
+impl<T: Send + ?Sized> MaybeSend for T {}
+
+/// Used internally to require [`Send`] only with the `send` feature.
+#[cfg(not(feature = "send"))]
+#[doc(hidden)]
+pub trait MaybeSend {}
+
+#[cfg(not(feature = "send"))]
+impl<T: ?Sized> MaybeSend for T {}
+
+/// The endpoint a request is being built for, passed to [`Api::pre_request_for`], and listed in the constants declared
+/// with `const NAME;` in an [`api`] block.
+#[derive(Clone, Debug)]
+#[non_exhaustive]
+pub struct EndpointInfo {
+    /// The name of the endpoint method.
+    pub name: &'static str,
+    /// The request method.
+    pub method: reqwest::Method,
+    /// The endpoint's URL as declared, before its placeholders are filled in or it's joined onto the base URL.
+    pub url_template: &'static str,
+    /// The names of the headers declared on the endpoint, as written. Names given by a constant are listed as the
+    /// constant's path rather than its value.
+    pub headers: &'static [&'static str],
+}
+
+impl EndpointInfo {
+    /// Used internally by the [`api`] macro.
+    #[doc(hidden)]
+    #[must_use]
+    pub const fn new(
+        name: &'static str,
+        method: reqwest::Method,
+        url_template: &'static str,
+        headers: &'static [&'static str],
+    ) -> Self {
+        EndpointInfo {
+            name,
+            method,
+            url_template,
+            headers,
+        }
+    }
+}
+
 /// The main API trait.
 ///
-/// If you need custom behavior, such as authentication, you should implement this trait on your custom struct. See the [Api::pre_request] method for more details.
+/// If you need custom behavior, such as authentication, you should implement this trait on your custom struct. See the [`Api::pre_request`] method for more details.
 ///
 /// Otherwise, you can use the [api] macro to generate a struct with a proper implementation of this trait.
-#[async_trait::async_trait(?Send)]
+///
+/// References, [`Box`]es and [`Arc`](std::sync::Arc)s of an implementation implement it too, so generic code over
+/// `A: Api` accepts shared handles to a client.
 pub trait Api {
     /// Returns a reference to a reqwest Client to create requests.
     fn client(&self) -> &Client;
@@ -70,11 +271,346 @@ pub trait Api {
     ///     }
     /// }
     /// ```
+    ///
+    /// Structs generated with `with basic_auth` do this for you; see [the macro](api#authentication).
+    ///
+    /// Endpoints with a `no_pre_request` clause, such as a token endpoint or a public health check, skip this method,
+    /// and those with an `auth = <scheme>` clause go to [`Api::auth_for`] instead.
+    ///
+    /// # Errors
+    /// Implementations may return an error to abort the request before it is sent.
     #[inline]
-    fn pre_request(&self, request: RequestBuilder) -> Result<RequestBuilder> {
+    fn pre_request(&self, request: RequestBuilder) -> reqwest::Result<RequestBuilder> {
         Ok(request)
     }
 
+    /// Like [`Api::pre_request`], but also given the endpoint being requested, such as for signing schemes that cover
+    /// the method and path. The default implementation calls [`Api::pre_request`], so override one or the other.
+    ///
+    /// # Usage
+    /// ```rust,ignore
+    /// impl Api for ExampleApi {
+    ///     fn client(&self) -> &Client {
+    ///         &self.client
+    ///     }
+    ///
+    ///     fn pre_request_for(&self, endpoint: &EndpointInfo, request: RequestBuilder) -> reqwest::Result<RequestBuilder> {
+    ///         Ok(request.header("x-signature", self.sign(&endpoint.method, endpoint.url_template)))
+    ///     }
+    /// }
+    /// ```
+    ///
+    /// # Errors
+    /// Implementations may return an error to abort the request before it is sent.
+    #[inline]
+    fn pre_request_for(
+        &self,
+        _endpoint: &EndpointInfo,
+        request: RequestBuilder,
+    ) -> reqwest::Result<RequestBuilder> {
+        self.pre_request(request)
+    }
+
+    /// Like [`Api::pre_request`], but can await, such as to refresh an expired token over the network before
+    /// attaching it. It's called right after [`Api::pre_request`], and skipped by the same `no_pre_request` clause.
+    ///
+    /// # Usage
+    /// ```rust,ignore
+    /// impl Api for ExampleApi {
+    ///     fn client(&self) -> &Client {
+    ///         &self.client
+    ///     }
+    ///
+    ///     async fn pre_request_async(&self, request: RequestBuilder) -> api_client::Result<RequestBuilder> {
+    ///         let token = self.tokens.fresh().await?;
+    ///         Ok(request.bearer_auth(token))
+    ///     }
+    /// }
+    /// ```
+    ///
+    /// # Errors
+    /// Implementations may return an error to abort the request before it is sent.
+    #[inline]
+    fn pre_request_async(
+        &self,
+        request: RequestBuilder,
+    ) -> impl Future<Output = Result<RequestBuilder>> + MaybeSend {
+        async { Ok(request) }
+    }
+
+    /// Signs a request after it's built, such as with an HMAC of its method, path and body, by adding headers or query
+    /// parameters to it. It's called right before each request is passed to [`Api::middlewares`], after
+    /// [`Api::acquire_permit`], including for endpoints with the `no_pre_request` clause and for retries.
+    ///
+    /// Unlike [`Api::pre_request`], it sees the final URL, headers and body. Streamed bodies, such as multipart forms,
+    /// have no bytes to read, so [`reqwest::Body::as_bytes`] returns `None` for them.
+    ///
+    /// # Usage
+    /// ```rust,ignore
+    /// impl Api for ExampleApi {
+    ///     fn client(&self) -> &Client {
+    ///         &self.client
+    ///     }
+    ///
+    ///     fn sign(&self, request: &mut reqwest::Request) -> api_client::Result<()> {
+    ///         let timestamp = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_millis().to_string();
+    ///         let body = request.body().and_then(reqwest::Body::as_bytes).unwrap_or_default();
+    ///         let mut mac = Hmac::<Sha256>::new_from_slice(&self.secret).unwrap();
+    ///         mac.update(timestamp.as_bytes());
+    ///         mac.update(request.method().as_str().as_bytes());
+    ///         mac.update(request.url().path().as_bytes());
+    ///         mac.update(body);
+    ///         let signature = hex::encode(mac.finalize().into_bytes());
+    ///
+    ///         let headers = request.headers_mut();
+    ///         headers.insert("x-timestamp", timestamp.parse().unwrap());
+    ///         headers.insert("x-signature", signature.parse().unwrap());
+    ///         Ok(())
+    ///     }
+    /// }
+    /// ```
+    ///
+    /// # Errors
+    /// Implementations may return an error to abort the request before it is sent.
+    #[inline]
+    fn sign(&self, _request: &mut reqwest::Request) -> Result<()> {
+        Ok(())
+    }
+
+    /// Prepares the request of an endpoint with an `auth = <scheme>` clause, given the scheme's name, in place of
+    /// [`Api::pre_request`] and [`Api::pre_request_async`], such as to attach a different token than most endpoints,
+    /// or none. The default sends the request as it is, so `auth = none` works without overriding this.
+    ///
+    /// These endpoints aren't retried after [`Api::reauthenticate`], since it refreshes the default credentials.
+    ///
+    /// # Usage
+    /// ```rust,ignore
+    /// impl Api for ExampleApi {
+    ///     fn client(&self) -> &Client {
+    ///         &self.client
+    ///     }
+    ///
+    ///     async fn pre_request_async(&self, request: RequestBuilder) -> api_client::Result<RequestBuilder> {
+    ///         Ok(request.bearer_auth(&self.tenant_token))
+    ///     }
+    ///
+    ///     async fn auth_for(&self, scheme: &str, request: RequestBuilder) -> api_client::Result<RequestBuilder> {
+    ///         match scheme {
+    ///             "service" => Ok(request.bearer_auth(self.service_token().await?)),
+    ///             _ => Ok(request),
+    ///         }
+    ///     }
+    /// }
+    /// ```
+    ///
+    /// # Errors
+    /// Implementations may return an error to abort the request before it is sent, such as for an unknown scheme.
+    #[inline]
+    fn auth_for(
+        &self,
+        _scheme: &str,
+        request: RequestBuilder,
+    ) -> impl Future<Output = Result<RequestBuilder>> + MaybeSend {
+        async { Ok(request) }
+    }
+
+    /// Waits until a request may be sent, such as to stay within a rate limit. It's awaited right before each request
+    /// is sent, after it's built, including for endpoints with the `no_pre_request` clause.
+    ///
+    /// # Usage
+    /// ```rust,ignore
+    /// impl Api for ExampleApi {
+    ///     fn client(&self) -> &Client {
+    ///         &self.client
+    ///     }
+    ///
+    ///     async fn acquire_permit(&self) {
+    ///         self.limiter.acquire().await;
+    ///     }
+    /// }
+    /// ```
+    #[inline]
+    fn acquire_permit(&self) -> impl Future<Output = ()> + MaybeSend {
+        async {}
+    }
+
+    /// Returns a response to use in place of sending `request` for `endpoint`, such as one from a cache, or `None` to
+    /// send it.
+    ///
+    /// It's called once the request is fully built, before [`Api::acquire_permit`] and any [`Middleware`]. The
+    /// response is decoded as if it had been received from the server, and isn't passed to [`Api::after_receive`].
+    ///
+    /// # Usage
+    /// ```rust,ignore
+    /// impl Api for ExampleApi {
+    ///     fn client(&self) -> &Client {
+    ///         &self.client
+    ///     }
+    ///
+    ///     async fn before_send(&self, _endpoint: &EndpointInfo, request: &Request) -> Option<CachedResponse> {
+    ///         let body = self.cache.lock().unwrap().get(request.url().as_str())?.clone();
+    ///         Some(CachedResponse::new(body))
+    ///     }
+    ///
+    ///     async fn after_receive(&self, _endpoint: &EndpointInfo, response: &ResponseSummary<'_>) {
+    ///         if let Some(body) = response.body {
+    ///             self.cache.lock().unwrap().insert(response.url.to_string(), body.to_vec());
+    ///         }
+    ///     }
+    /// }
+    /// ```
+    #[inline]
+    fn before_send(
+        &self,
+        _endpoint: &EndpointInfo,
+        _request: &reqwest::Request,
+    ) -> impl Future<Output = Option<CachedResponse>> + MaybeSend {
+        async { None }
+    }
+
+    /// Called with each response received from the server for `endpoint`, before it's decoded, such as to store it
+    /// in a cache. See [`ResponseSummary::body`] for when the body is included.
+    #[inline]
+    fn after_receive(
+        &self,
+        _endpoint: &EndpointInfo,
+        _response: &ResponseSummary<'_>,
+    ) -> impl Future<Output = ()> + MaybeSend {
+        async {}
+    }
+
+    /// Called when the server responds `401 Unauthorized`, such as because it revoked a token early, to get new
+    /// credentials. Returning `true` retries the request once: its `Authorization` header is removed and its headers are
+    /// prepared again by [`Api::pre_request_for`], the endpoint's [`PreRequestWith`] context and
+    /// [`Api::pre_request_async`] to pick up the new credentials. The default returns `false`, so the response is
+    /// decoded as is.
+    ///
+    /// Requests with a streamed body can't be sent twice, so they fail with [`Error::NotRetryable`] instead. Endpoints
+    /// with a `no_pre_request` or `auth = <scheme>` clause are never retried. Structs declared with `with <scheme>`
+    /// forward this to [`Authenticate::reauthenticate`].
+    ///
+    /// # Usage
+    /// ```rust,ignore
+    /// impl Api for ExampleApi {
+    ///     fn client(&self) -> &Client {
+    ///         &self.client
+    ///     }
+    ///
+    ///     async fn pre_request_async(&self, request: RequestBuilder) -> api_client::Result<RequestBuilder> {
+    ///         Ok(request.bearer_auth(self.tokens.fresh().await?))
+    ///     }
+    ///
+    ///     async fn reauthenticate(&self) -> api_client::Result<bool> {
+    ///         self.tokens.invalidate().await;
+    ///         Ok(true)
+    ///     }
+    /// }
+    /// ```
+    ///
+    /// # Errors
+    /// Implementations may return an error to fail the request instead of decoding the response.
+    #[inline]
+    fn reauthenticate(&self) -> impl Future<Output = Result<bool>> + MaybeSend {
+        async { Ok(false) }
+    }
+
+    /// Called with the name of the endpoint whenever one fails, before the error is returned, to log or count failures
+    /// in one place.
+    ///
+    /// This covers failures to send the request or read the response, unexpected statuses and decode errors. Error
+    /// responses decoded by a `Result<Json<T>, Json<E>>` endpoint are reported as [`Error::UnexpectedStatus`], with
+    /// the body only if it couldn't be decoded into `E`.
+    ///
+    /// # Usage
+    /// ```rust,ignore
+    /// impl Api for ExampleApi {
+    ///     fn client(&self) -> &Client {
+    ///         &self.client
+    ///     }
+    ///
+    ///     fn on_error(&self, endpoint: &str, error: &Error) {
+    ///         log::warn!("{endpoint} failed: {error}");
+    ///     }
+    /// }
+    /// ```
+    #[inline]
+    fn on_error(&self, _endpoint: &str, _error: &Error) {}
+
+    /// Returns headers to send with every request, such as a client version or tenant header.
+    ///
+    /// They're added before [`Api::pre_request`] and the endpoint's own headers, and an endpoint declaring a header of
+    /// the same name replaces the default rather than sending both.
+    #[inline]
+    fn default_headers(&self) -> HeaderMap {
+        HeaderMap::new()
+    }
+
+    /// Returns query parameters to send with every request, such as an API key or response format.
+    ///
+    /// They're appended after the endpoint's own query parameters, including those added by [`Api::pre_request`], and
+    /// a key the request already has is left out rather than sent twice.
+    #[inline]
+    fn default_query(&self) -> &[(String, String)] {
+        &[]
+    }
+
+    /// Returns the [`Middleware`] that requests pass through, in order, after they're built and before they're sent.
+    ///
+    /// Unlike [`Api::pre_request`], middleware also sees the response, so it can log, time or retry requests.
+    #[inline]
+    fn middlewares(&self) -> &[std::sync::Arc<dyn Middleware>] {
+        &[]
+    }
+
+    /// Returns a semaphore that each request takes a permit from before it's sent, limiting how many requests are in
+    /// flight at once.
+    ///
+    /// The permit is held until the response has been read and decoded, or until the request fails, and is returned
+    /// if the request is cancelled while waiting for it. Streaming return kinds such as `Stream` hold it only until
+    /// their headers arrive. The `max_concurrency` method of a generated struct's [`ApiBuilder`] sets this up.
+    #[cfg(feature = "concurrency")]
+    #[cfg_attr(docsrs, doc(cfg(feature = "concurrency")))]
+    #[inline]
+    fn concurrency_permits(&self) -> Option<&tokio::sync::Semaphore> {
+        None
+    }
+
+    /// Returns the base URL that relative endpoint URLs are joined onto.
+    ///
+    /// Leading slashes of endpoint URLs are ignored, so `"/users"` and `"users"` both resolve to `users` under the base
+    /// path. The base should end with a `/`, or its last path segment is replaced. Absolute endpoint URLs are not
+    /// affected.
+    #[inline]
+    fn base_url(&self) -> Option<&Url> {
+        None
+    }
+
+    /// Returns the default size limit, in bytes, for response bodies read in full, such as `String`, `Json<T>` or
+    /// `MsgPack<T>` ones.
+    ///
+    /// Endpoints with a `limit = ...` clause use their own limit instead. Without either, bodies are not limited.
+    #[inline]
+    fn body_limit(&self) -> Option<u64> {
+        None
+    }
+
+    /// Returns the default dotted path of the value to decode from `Json<T>` response bodies, for APIs that wrap every
+    /// payload in an envelope such as `{"data": ...}`.
+    ///
+    /// Endpoints declared as `-> Json<T> at "path"` use their own path instead, and `at ""` decodes the whole body.
+    #[inline]
+    fn envelope(&self) -> Option<&str> {
+        None
+    }
+
+    /// Returns the call budget named by an endpoint's `budget = "..."` clause.
+    ///
+    /// Endpoints whose budget isn't returned here are not limited. See [`Budget`] for an example.
+    #[inline]
+    fn budget(&self, _name: &str) -> Option<&Budget> {
+        None
+    }
+
     /// Used internally in the api! macro. Mostly for ergonmics.
     ///
     /// # Usage
@@ -98,470 +634,9495 @@ pub trait Api {
     }
 }
 
-/// Magic macro for API structs.
-///
-/// # Simple Usage (auto generated struct)
-/// ```rust
-/// use api_client::{api, Api};
-/// use reqwest::{Client, RequestBuilder};
-///
-/// api!(pub struct ExampleApi);
+/// Prepares requests with a context passed to each call, for endpoints with a `context = <parameter>` clause, such as
+/// the tenant a shared client is calling on behalf of.
 ///
-/// impl ExampleApi {
-///     api! {
-///         fn example() -> String {
-///            GET "https://example.com"
-///         }
-///     }
-/// }
-/// ```
+/// It's called right after [`Api::pre_request_for`] and before [`Api::pre_request_async`], and skipped by the
+/// `no_pre_request` clause like them. The context parameter must be a reference, and an implementation can handle
+/// several context types.
 ///
-/// # Advanced Usage (manually created struct and [Api] implementation)
 /// ```rust
-/// use api_client::{api, Api};
+/// use api_client::{api, Api, PreRequestWith};
 /// use reqwest::{Client, RequestBuilder};
 ///
-/// struct ExampleApi {
-///     client: Client,
-///     username: String,
-///     password: String
+/// struct Tenant {
+///     token: String,
 /// }
 ///
-/// impl Api for ExampleApi {
-///     fn client(&self) -> &Client {
-///         &self.client
-///     }
+/// api!(pub struct ExampleApi);
 ///
-///     fn pre_request(&self, request: RequestBuilder) -> reqwest::Result<RequestBuilder> {
-///         Ok(request.basic_auth(&self.username, Some(&self.password)))
+/// impl PreRequestWith<Tenant> for ExampleApi {
+///     fn pre_request_with(&self, tenant: &Tenant, request: RequestBuilder) -> reqwest::Result<RequestBuilder> {
+///         Ok(request.bearer_auth(&tenant.token))
 ///     }
 /// }
 ///
 /// impl ExampleApi {
 ///     api! {
-///         fn example() -> String {
-///            GET "https://example.com"
+///         fn example(tenant: &Tenant) -> String {
+///             GET "https://example.com"
+///             context = tenant
 ///         }
 ///     }
 /// }
 /// ```
-#[macro_export]
-macro_rules! api {
-    () => {};
+pub trait PreRequestWith<C: ?Sized>: Api {
+    /// Returns `request` prepared for `context`.
+    ///
+    /// # Errors
+    /// Implementations may return an error to abort the request before it is sent.
+    fn pre_request_with(
+        &self,
+        context: &C,
+        request: RequestBuilder,
+    ) -> reqwest::Result<RequestBuilder>;
+}
 
-    ($(#[$attr:meta])* $vis:vis struct $ident:ident) => {
-        $(#[$attr])*
-        $vis struct $ident(::reqwest::Client);
+/// What the `login = <method>` of a struct declared with [`api`] returns, telling whether logging in succeeded so a
+/// request that got `401 Unauthorized` is worth sending again.
+///
+/// Login methods can return `Result<()>`, failing if the login is rejected, or `Result<StatusCode>` with the status of
+/// the login response, which succeeded if it's a `2xx` status.
+pub trait LoginOutcome {
+    /// Returns whether logging in succeeded.
+    fn succeeded(&self) -> bool;
+}
 
-        impl $crate::Api for $ident {
-            fn client(&self) -> &::reqwest::Client {
-                &self.0
-            }
+impl LoginOutcome for () {
+    #[inline]
+    fn succeeded(&self) -> bool {
+        true
+    }
+}
 
-            fn new() -> Self where Self: Sized {
-                $ident(::reqwest::Client::new())
-            }
-        }
-    };
+impl LoginOutcome for StatusCode {
+    #[inline]
+    fn succeeded(&self) -> bool {
+        self.is_success()
+    }
+}
 
-    ($(#[$attr:meta])* $vis:vis fn $ident:ident(request: Json<$req:ty>$(, $name:ident: $ty:ty)*) -> StatusCode { $method:tt $url:literal $($headername:tt: $headervalue:expr)* } $($rest:tt)*) => {
-        $(#[$attr])*
-        #[inline]
-        $vis async fn $ident(&self, request: &$req, $($name $ty),*) -> ::reqwest::Result<::reqwest::StatusCode> {
-            use $crate::Api as _;
-            self.pre_request(self.client().request($crate::reqwest::Method::$method, format!($url).as_str()))?
-                $(.header($headername, format!($headervalue).as_str()))*
-                .json(request)
-                .send()
-                .await
-                .map(|res| res.status())
+/// Used internally by the [`api`] macro to implement [`Api::concurrency_permits`] for a generated struct.
+#[cfg(feature = "concurrency")]
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __concurrency_permits {
+    () => {
+        fn concurrency_permits(&self) -> ::core::option::Option<&$crate::middleware::Semaphore> {
+            self.1.concurrency.as_deref()
         }
-        api!($($rest)*);
     };
+}
 
-    ($(#[$attr:meta])* $vis:vis fn $ident:ident(request: Json<$req:ty>$(, $name:ident: $ty:ty)*) -> String { $method:tt $url:literal $($headername:tt: $headervalue:expr)* } $($rest:tt)*) => {
+/// Used internally by the [`api`] macro, implementing nothing without the `concurrency` feature.
+#[cfg(not(feature = "concurrency"))]
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __concurrency_permits {
+    () => {};
+}
+
+/// Used internally by the [`api`] macro to enable the cookie store of a generated struct's client.
+#[cfg(feature = "cookies")]
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __cookie_store {
+    ($builder:expr) => {
+        $builder.cookie_store(true)
+    };
+}
+
+/// Used internally by the [`api`] macro, rejecting the `cookie_store` option without the `cookies` feature.
+#[cfg(not(feature = "cookies"))]
+#[doc(hidden)]
+#[macro_export]
+macro_rules! __cookie_store {
+    ($builder:expr) => {
+        compile_error!(
+            "the `cookie_store` struct option requires the `cookies` feature of api-client"
+        )
+    };
+}
+
+/// Implements [`Api`] for a pointer to an implementation, forwarding every method to it.
+macro_rules! forward_api {
+    ($($pointer:ty),*) => {$(
+        impl<T: Api + ?Sized> Api for $pointer {
+            #[inline]
+            fn client(&self) -> &Client {
+                (**self).client()
+            }
+
+            #[inline]
+            fn pre_request(&self, request: RequestBuilder) -> reqwest::Result<RequestBuilder> {
+                (**self).pre_request(request)
+            }
+
+            #[inline]
+            fn pre_request_for(
+                &self,
+                endpoint: &EndpointInfo,
+                request: RequestBuilder,
+            ) -> reqwest::Result<RequestBuilder> {
+                (**self).pre_request_for(endpoint, request)
+            }
+
+            #[inline]
+            fn pre_request_async(
+                &self,
+                request: RequestBuilder,
+            ) -> impl Future<Output = Result<RequestBuilder>> + MaybeSend {
+                (**self).pre_request_async(request)
+            }
+
+            #[inline]
+            fn auth_for(
+                &self,
+                scheme: &str,
+                request: RequestBuilder,
+            ) -> impl Future<Output = Result<RequestBuilder>> + MaybeSend {
+                (**self).auth_for(scheme, request)
+            }
+
+            #[inline]
+            fn sign(&self, request: &mut reqwest::Request) -> Result<()> {
+                (**self).sign(request)
+            }
+
+            #[inline]
+            fn acquire_permit(&self) -> impl Future<Output = ()> + MaybeSend {
+                (**self).acquire_permit()
+            }
+
+            #[inline]
+            fn before_send(
+                &self,
+                endpoint: &EndpointInfo,
+                request: &reqwest::Request,
+            ) -> impl Future<Output = Option<CachedResponse>> + MaybeSend {
+                (**self).before_send(endpoint, request)
+            }
+
+            #[inline]
+            fn after_receive(
+                &self,
+                endpoint: &EndpointInfo,
+                response: &ResponseSummary<'_>,
+            ) -> impl Future<Output = ()> + MaybeSend {
+                (**self).after_receive(endpoint, response)
+            }
+
+            #[inline]
+            fn reauthenticate(&self) -> impl Future<Output = Result<bool>> + MaybeSend {
+                (**self).reauthenticate()
+            }
+
+            #[inline]
+            fn on_error(&self, endpoint: &str, error: &Error) {
+                (**self).on_error(endpoint, error);
+            }
+
+            #[inline]
+            fn default_headers(&self) -> HeaderMap {
+                (**self).default_headers()
+            }
+
+            #[inline]
+            fn default_query(&self) -> &[(String, String)] {
+                (**self).default_query()
+            }
+
+            #[inline]
+            fn middlewares(&self) -> &[std::sync::Arc<dyn Middleware>] {
+                (**self).middlewares()
+            }
+
+            #[cfg(feature = "concurrency")]
+            #[inline]
+            fn concurrency_permits(&self) -> Option<&tokio::sync::Semaphore> {
+                (**self).concurrency_permits()
+            }
+
+            #[inline]
+            fn base_url(&self) -> Option<&Url> {
+                (**self).base_url()
+            }
+
+            #[inline]
+            fn body_limit(&self) -> Option<u64> {
+                (**self).body_limit()
+            }
+
+            #[inline]
+            fn envelope(&self) -> Option<&str> {
+                (**self).envelope()
+            }
+
+            #[inline]
+            fn budget(&self, name: &str) -> Option<&Budget> {
+                (**self).budget(name)
+            }
+        }
+    )*};
+}
+
+forward_api!(&T, Box<T>, std::sync::Arc<T>);
+
+/// Magic macro for API structs.
+///
+/// # Simple Usage (auto generated struct)
+/// ```rust
+/// use api_client::{api, Api};
+/// use reqwest::{Client, RequestBuilder};
+///
+/// api!(pub struct ExampleApi);
+///
+/// impl ExampleApi {
+///     api! {
+///         fn example() -> String {
+///            GET "https://example.com"
+///         }
+///     }
+/// }
+/// ```
+///
+/// The generated struct derives [`Clone`] and [`Debug`], and implements [`Default`] with [`Api::new`]. Attributes on
+/// the struct are kept, and `Clone`, `Debug` and `Default` in its own `#[derive(...)]` are merged with these.
+///
+/// # Methods
+/// The method is one of the [`Method`](reqwest::Method) constants, such as `GET`, or a string literal for extension
+/// methods such as `"PROPFIND"`, which is checked at compile time. To choose the method at runtime, put an expression
+/// such as a parameter in braces, as in `{method} "https://example.com/ingest"`.
+///
+/// # Base URL
+/// A struct declared with a base URL, as in `api!(pub struct ExampleApi, base = "https://example.com/v2")`, joins
+/// relative endpoint URLs onto it; see [`Api::base_url`]. The struct also gets a `with_base_url` constructor to point
+/// it elsewhere at runtime, such as at a mock server.
+/// ```rust,ignore
+/// api!(pub struct ExampleApi, base = "https://example.com/v2");
+///
+/// impl ExampleApi {
+///     api! {
+///         fn todo(id: u32) -> Json<Todo> {
+///             GET "todos/{id}"
+///         }
+///     }
+/// }
+/// ```
+///
+/// # Environments
+/// A struct served from several hosts, such as production and staging, can list them in an `environments` option.
+/// This generates an `Environment` enum with a variant for each, and a `Custom(Url)` variant for URLs chosen at
+/// runtime such as a mock server. The struct's `with_environment` constructor resolves relative endpoint URLs against
+/// the chosen environment, while [`Api::new`] uses the first one unless a `base` is given. Structs declared in the same
+/// module name their enums, as in `environments TodoEnvironment { ... }`.
+/// ```rust,ignore
+/// api!(pub struct ExampleApi, environments {
+///     Prod = "https://api.example.com",
+///     Staging = "https://staging.example.com",
+/// });
+///
+/// let api = ExampleApi::with_environment(Environment::Staging);
+/// let mock = ExampleApi::with_environment(Environment::Custom(server.url().parse()?));
+/// ```
+///
+/// # Impl blocks
+/// Instead of calling `api!` inside an `impl` block, the whole block can be passed to it, as in
+/// `api! { impl ExampleApi { ... } }`. Functions whose body starts with a method and a URL are endpoints, and anything
+/// else, such as helper methods or constants, is kept as written.
+/// ```rust,ignore
+/// api!(pub struct ExampleApi);
+///
+/// api! {
+///     impl ExampleApi {
+///         const FIRST: u32 = 1;
+///
+///         fn todo(id: u32) -> Json<Todo> {
+///             GET "https://example.com/todos/{id}"
+///         }
+///
+///         pub async fn first_todo(&self) -> api_client::Result<Todo> {
+///             self.todo(Self::FIRST).await
+///         }
+///     }
+/// }
+/// ```
+///
+/// # Groups
+/// Endpoints sharing a path prefix can be declared in a `group "prefix" (name: Type, ...) { ... }` block. The prefix
+/// is prepended to the URL of every endpoint in the group, and the group's parameters to their parameters, after
+/// `&self`. Groups can be nested, adding to the prefix and parameters of the group around them, and the parameters can
+/// be left out if there are none.
+/// ```rust,ignore
+/// impl ExampleApi {
+///     api! {
+///         group "projects/{project}" (project: &str) {
+///             fn envs() -> Json<Vec<Env>> {
+///                 GET "/envs"
+///             }
+///
+///             group "/members/{member}" (member: u32) {
+///                 fn member() -> Json<Member> {
+///                     GET ""
+///                 }
+///             }
+///         }
+///     }
+/// }
+///
+/// let member = api.member("example", 7).await?;
+/// ```
+///
+/// # Fields
+/// A struct declared with fields, such as a token or tenant id, gets a `client` field before them and a `new`
+/// constructor taking them in order. Trait methods such as [`Api::pre_request`] or [`Api::base_url`] can be overridden
+/// in an `impl Api` block after the struct, which may carry attributes of its own. Such structs
+/// don't derive or implement anything else, so a secret field isn't printed by a derived [`Debug`].
+/// ```rust
+/// use api_client::{api, Api};
+/// use reqwest::RequestBuilder;
+///
+/// api!(
+///     pub struct ExampleApi {
+///         token: String,
+///         pub tenant: u32,
+///     }
+///
+///     impl Api {
+///         fn pre_request(&self, request: RequestBuilder) -> reqwest::Result<RequestBuilder> {
+///             Ok(request.bearer_auth(&self.token))
+///         }
+///     }
+/// );
+///
+/// impl ExampleApi {
+///     api! {
+///         fn projects(&self) -> String {
+///             GET "https://example.com/projects"
+///             "x-tenant": self.tenant.to_string();
+///         }
+///     }
+/// }
+///
+/// let api = ExampleApi::new(String::from("secret"), 7);
+/// ```
+///
+/// # Sharing a client
+/// Generated structs create their own [`Client`] in [`Api::new`]. To use one configured elsewhere, such as with a proxy
+/// or timeouts, or to share one connection pool between several structs, create them with `with_client` or
+/// [`From<Client>`](From) instead. Structs declared with a base URL keep it.
+/// ```rust,ignore
+/// let client = Client::builder().timeout(Duration::from_secs(10)).build()?;
+/// let todos = TodoApi::with_client(client.clone());
+/// let users = UserApi::from(client);
+/// ```
+///
+/// `try_new` creates a struct the way [`Api::new`] does, but returns an error if the HTTP client can't be built, such as
+/// when TLS initialization fails, where [`Api::new`] panics. Prefer it outside of tests and examples. `from_builder`
+/// builds the client from a [`ClientBuilder`](reqwest::ClientBuilder), applying the struct's options such as
+/// `cookie_store` on top.
+/// ```rust,ignore
+/// let api = TodoApi::from_builder(Client::builder().user_agent("todo-sync/1.0"))?;
+/// ```
+///
+/// To set the client, base URL, [default headers](Api::default_headers) and [default query
+/// parameters](Api::default_query) in one place, use the [`ApiBuilder`] returned by `builder`. Structs declared with
+/// fields don't have these constructors.
+/// ```rust,ignore
+/// let api = TodoApi::builder()
+///     .client(client)
+///     .base_url("https://staging.example.com/v1")
+///     .default_header("x-tenant", tenant)
+///     .default_query("api_key", api_key)
+///     .build()?;
+/// ```
+///
+/// # Client options
+/// Generated structs build their [`Client`] with [`Client::new`], which has no timeout at all. Settings listed in
+/// braces after the struct name, or after a comma, are applied to a [`Client::builder`] instead, each `name = value`
+/// calling the builder method of that name. A value of `N ms`, `N s` or `N min` is a [`Duration`](std::time::Duration),
+/// and `base = "url"` sets the base URL.
+/// ```rust,ignore
+/// api!(pub struct ExampleApi {
+///     base = "https://example.com/v2",
+///     timeout = 10 s,
+///     connect_timeout = 500 ms,
+///     user_agent = concat!("example/", env!("CARGO_PKG_VERSION")),
+///     gzip = true,
+/// });
+/// ```
+///
+/// Some settings, such as `gzip`, need the matching reqwest feature enabled in your own `Cargo.toml`. Create structs
+/// with options using `try_new`, which returns the builder's error if the settings can't be combined into a client.
+/// [`Api::new`] panics with that error in its message instead.
+///
+/// # Authentication
+/// Adding `with <scheme>` after the struct name, as in `api!(pub struct ExampleApi with bearer_auth)`, makes the struct
+/// hold credentials that are attached to every request by [`Api::pre_request_async`], unless the endpoint has a
+/// `no_pre_request` clause. The credentials can also be passed to the `auth` method of the [`ApiBuilder`].
+/// - `bearer_auth` sends a [`BearerAuth`] token, set with the `with_token` constructor or `set_token`.
+/// - `api_key` sends an [`ApiKey`] in a header or query parameter, set with the `with_api_key` constructor.
+/// - `basic_auth` sends a username and password with [`BasicAuth`], set with the `with_basic_auth` constructor.
+/// - `client_credentials` sends OAuth 2.0 access tokens fetched and refreshed by a `ClientCredentials`, set with the
+///   `with_client_credentials` constructor, or passed to the builder's `auth` in a `Some` (requires the `oauth2`
+///   feature).
+/// - `authorization_code` sends OAuth 2.0 access tokens of a user logged in with an `AuthorizationCode`, set with the
+///   `with_authorization_code` constructor, or passed to the builder's `auth` in a `Some` (requires the `oauth2-pkce`
+///   feature).
+/// ```rust,ignore
+/// api!(pub struct ExampleApi with bearer_auth, base = "https://example.com/v2");
+///
+/// let api = ExampleApi::with_token(token);
+/// ```
+///
+/// When the server responds `401 Unauthorized`, [`Api::reauthenticate`] is called, and the request is sent once more
+/// with new credentials if it returns `true`. Of the schemes above, only `client_credentials` and
+/// `authorization_code` do, discarding their cached access token. A request whose body is a stream can't be sent
+/// twice and fails with [`Error::NotRetryable`].
+///
+/// # Cookie store
+/// Adding `cookie_store` after the struct name, as in `api!(pub struct ExampleApi, cookie_store)` or
+/// `api!(pub struct ExampleApi, base = "https://example.com/v2", cookie_store)`, builds the client with a cookie store,
+/// so cookies set by responses are sent back on later requests. It requires the `cookies` feature, and fails to compile
+/// without it.
+///
+/// For services that log in with a session cookie, add `login = <method>` naming an async method of the struct that
+/// takes no arguments and posts the credentials. The response sets the session cookie, and when a session expires and
+/// a request gets `401 Unauthorized`, [`Api::reauthenticate`] calls the method again and retries the request once if
+/// the login succeeded, as told by its [`LoginOutcome`]: an `Ok(())`, or an `Ok` with a `2xx` status. The login
+/// endpoint needs the `no_pre_request` clause, so it isn't retried itself.
+/// ```rust,ignore
+/// api!(pub struct ExampleApi, base = "https://example.com/", cookie_store, login = login);
+///
+/// impl ExampleApi {
+///     async fn login(&self) -> api_client::Result<StatusCode> {
+///         self.post_login(&Credentials { username: USERNAME, password: PASSWORD }).await
+///     }
+///
+///     api! {
+///         fn post_login(credentials: Form<Credentials>) -> StatusCode {
+///             POST "login"
+///             no_pre_request
+///         }
+///
+///         fn orders() -> Json<Vec<Order>> {
+///             GET "orders"
+///         }
+///     }
+/// }
+///
+/// let api = ExampleApi::try_new()?;
+/// ```
+///
+/// To keep the session between runs, load a `CookieFile` and pass it to the builder's `cookie_file` instead of
+/// using the `cookie_store` option, then save it before the program exits (requires the `cookie-store` feature):
+/// ```rust,ignore
+/// let cookies = CookieFile::load("cookies.json").await?;
+/// let api = ExampleApi::builder().cookie_file(&cookies).build()?;
+/// // ...
+/// cookies.save().await?;
+/// ```
+///
+/// # Parameters
+/// Parameters can be used in the URL and header templates, and a `{name}` placeholder that isn't a parameter or
+/// constant in scope fails to compile, naming the placeholder. Parameters in the path of the URL are percent-encoded as a
+/// single path segment, so a value like `a/b c` can't change the request path, and those in its query or fragment are
+/// encoded so they can't add query pairs. A parameter at the start of the URL, such as a base URL, is left as is. Add
+/// a `raw = name` clause to leave a parameter unencoded, such as one holding several path segments.
+///
+/// A parameter that appears in neither the method, the URL, a header, a query parameter nor any other clause of its
+/// endpoint fails to compile, naming the parameter. Start its name with `_`, as in `_force: bool`, to accept it unused.
+///
+/// A few parameter types are handled specially, and are taken by reference in the generated method:
+/// - `Json<T>` sends a `T` as the JSON request body.
+/// - `Form<T>` sends a `T` as a URL-encoded form request body.
+/// - `Multipart` sends a [`reqwest::multipart::Form`] as a `multipart/form-data` request body (requires the
+///   `multipart` feature).
+/// - `Query<T>` serializes a `T` into the query string, percent-encoding its values. `Option` fields that are `None`
+///   are left out.
+///
+/// ```rust,ignore
+/// impl ExampleApi {
+///     api! {
+///         fn search(query: Query<Search>, request: Json<Filters>) -> Json<Vec<Todo>> {
+///             POST "https://example.com/search"
+///         }
+///     }
+/// }
+/// ```
+///
+/// Query parameters can also be listed after the URL, one `? key: "value"` per parameter, with the value formatted like
+/// the URL. Keys can be repeated, and are either identifiers or string literals. The value can also be a parameter
+/// implementing [`QueryValue`], such as an `Option<u32>` that's left out when `None`, and `? name` is short for
+/// `? name: name`. Parameters with several values, such as a `Vec<u32>` or slice, repeat the key for each value unless
+/// the endpoint picks another [`QueryStyle`] with a `query_style = Comma` or `query_style = Brackets` clause.
+/// ```rust,ignore
+/// impl ExampleApi {
+///     api! {
+///         fn search(term: &str, page: u32) -> Json<Vec<Todo>> {
+///             GET "https://example.com/search"
+///             ? q: "{term}"
+///             ? "page[number]": "{page}"
+///         }
+///
+///         fn list(status: Option<&str>, limit: Option<u32>) -> Json<Vec<Todo>> {
+///             GET "https://example.com/todos"
+///             ? status
+///             ? "page[size]": limit
+///         }
+///     }
+/// }
+/// ```
+///
+/// # Generated documentation
+/// Each generated method's docs end with its method and URL template, such as `` `GET https://example.com/todos/{id}` ``,
+/// followed by its declared headers, after any doc comments written on the endpoint.
+///
+/// # Endpoint constants
+/// A `const NAME;` line before the endpoints of an `api!` block declares a constant listing them as [`EndpointInfo`]s,
+/// with their names, methods, URL templates and declared header names, such as to list the operations of an API or
+/// label metrics by endpoint. Endpoints left out by `#[cfg]` are left out of the constant. Methods must be identifiers
+/// such as `GET` or constant expressions in braces, rather than string literals. In a `trait Name for Struct`
+/// declaration, the constant becomes an associated constant of the trait.
+/// ```rust
+/// use api_client::{api, Api};
+///
+/// api!(pub struct TodoApi, base = "https://example.com");
+///
+/// impl TodoApi {
+///     api! {
+///         /// The endpoints of the API.
+///         pub const ENDPOINTS;
+///
+///         fn todo(id: u32) -> String {
+///             GET "/todos/{id}"
+///             "Accept": "text/plain"
+///         }
+///     }
+/// }
+///
+/// for endpoint in TodoApi::ENDPOINTS {
+///     println!("{} {} {}", endpoint.name, endpoint.method, endpoint.url_template);
+/// }
+/// ```
+///
+/// # Conditional endpoints
+/// Attributes on an endpoint, including doc comments, `#[cfg(...)]` and `#[cfg_attr(...)]`, are applied to its
+/// generated method, so endpoints can depend on cargo features of the calling crate. Endpoints left out by `#[cfg]`
+/// are also left out of `verify_examples()`.
+/// ```rust,ignore
+/// impl ExampleApi {
+///     api! {
+///         #[cfg(feature = "admin")]
+///         fn delete_everything() -> StatusCode {
+///             DELETE "https://example.com/everything"
+///         }
+///     }
+/// }
+/// ```
+///
+/// A parameter can declare a default value, as in `page_size: u32 = 100`. The generated method then takes an
+/// `impl Into<Option<u32>>`, so callers pass either a value or `None` for the default. In a request builder, such
+/// parameters get a setter instead, and are left out of the method creating the builder.
+/// ```rust,ignore
+/// impl ExampleApi {
+///     api! {
+///         fn list(page_size: u32 = 100) -> Json<Vec<Todo>> {
+///             GET "https://example.com/todos"
+///             ? page_size
+///         }
+///     }
+/// }
+///
+/// let first = api.list(None).await?;
+/// let smaller = api.list(10).await?;
+/// ```
+///
+/// # Generic endpoints
+/// Endpoints can declare generic parameters after their name, which are forwarded to the generated method, so one
+/// endpoint can decode several response types. Callers pick the type by inference or with a turbofish. Lifetime
+/// parameters work the same way, for request types and parameters that borrow, as in
+/// `fn create<'a>(request: Json<CreateNote<'a>>, tag: &'a str) -> Json<Note>`. Longer bounds can go in a `where`
+/// clause between the return kind and the endpoint block, which is also where bounds such as `Fn(u32) -> u32` with a
+/// parenthesized argument list must be written.
+/// ```rust,ignore
+/// impl ExampleApi {
+///     api! {
+///         fn resource<T: DeserializeOwned>(path: &str) -> Json<T> {
+///             GET "https://example.com/{path}"
+///         }
+///
+///         fn echo<T>(request: Json<T>) -> Json<T>
+///         where
+///             T: Serialize + DeserializeOwned,
+///         {
+///             POST "https://example.com/echo"
+///         }
+///     }
+/// }
+///
+/// let todo = api.resource::<Todo>("todos/1").await?;
+/// ```
+///
+/// # Request builders
+/// An endpoint with many optional parameters can instead generate a builder, by declaring it outside the `impl` block
+/// as `struct Name<'a> for ExampleApi { ... }` around the endpoint. The method on `ExampleApi` takes the required
+/// parameters and returns the builder, which has a setter for each `Option` parameter and a `send` method performing
+/// the request. Borrowed parameters must use the builder's lifetime. Only plain parameters are supported, not
+/// `Json<T>`, `Form<T>`, `Query<T>` or `Multipart`.
+/// ```rust,ignore
+/// api! {
+///     pub struct Search<'a> for ExampleApi {
+///         pub fn search(term: &'a str, status: Option<&'a str>, limit: Option<u32>) -> Json<Vec<Todo>> {
+///             GET "https://example.com/search"
+///             ? term
+///             ? status
+///             ? limit
+///         }
+///     }
+/// }
+///
+/// let todos = api.search("milk").status("open").limit(3).send().await?;
+/// ```
+///
+/// # Headers
+/// Each `NAME: value` line adds a request header. The name is either a [`HeaderName`](reqwest::header::HeaderName)
+/// constant such as `USER_AGENT`, or a string literal such as `"x-api-key"` for non-standard headers. An invalid
+/// literal name fails with [`Error::InvalidHeaderName`] before the request is sent. A string literal value is formatted like the URL. Any other
+/// expression is used as is, and can be anything that converts into a [`HeaderValue`](reqwest::header::HeaderValue),
+/// such as a `String` or a `HeaderValue`. End it with a `;` or `,` unless it's the last clause. Expressions using
+/// `self` need the endpoint to declare `&self` as its first parameter.
+///
+/// Clauses may also be separated by commas, and parameter lists may end with a trailing comma.
+///
+/// A header written as `NAME?: value` takes an `Option`, and is only sent when it's `Some`.
+///
+/// Requests advertise the return kind with an `Accept` header, such as `application/json` for `Json<T>`,
+/// `text/plain, */*;q=0.8` for `String` and `Text`, and `*/*` for kinds like `Bytes` that take any body. This takes
+/// precedence over an `Accept` header set on the client. Endpoints declaring their own `ACCEPT` header send only that.
+/// Likewise, a declared `CONTENT_TYPE` header replaces the one set by a `Json`, `Form` or `Multipart` body rather than
+/// being sent alongside it, and so do headers of the same name as one from [`Api::default_headers`]. A header value
+/// that isn't valid fails with [`Error::InvalidHeaderValue`] before the request is sent.
+/// ```rust,ignore
+/// impl ExampleApi {
+///     api! {
+///         fn todo(&self, id: u32, etag: Option<&str>) -> Json<Todo> {
+///             GET "https://example.com/todos/{id}"
+///             USER_AGENT: "example/{id}"
+///             "x-request-id": "{id}"
+///             AUTHORIZATION: format!("Bearer {}", self.token());
+///             ACCEPT: HeaderValue::from_static("application/json");
+///             IF_NONE_MATCH?: etag
+///         }
+///     }
+/// }
+/// ```
+///
+/// # Cookies
+/// Each `cookie name: value` line adds a cookie, and all of an endpoint's cookies are sent in a single `Cookie` header.
+/// The name is an identifier or a string literal, and the value is a string literal formatted like the URL or any
+/// expression that implements [`Display`](std::fmt::Display). Characters a cookie value can't contain, such as spaces
+/// and `;`, are percent-encoded, as is `%`.
+/// ```rust,ignore
+/// impl ExampleApi {
+///     api! {
+///         fn dashboard(session: &str, locale: &str) -> String {
+///             GET "https://example.com/dashboard"
+///             cookie session: "{session}"
+///             cookie "locale": locale
+///         }
+///     }
+/// }
+/// ```
+///
+/// # Return kinds
+/// - `StatusCode` returns the response status without reading the body.
+/// - `Headers` returns the response status and [`Headers`] without reading the body, for `HEAD` requests.
+/// - `String` returns the response body as text.
+/// - `Text` returns the response body as [`Text`], decoded with the charset from `Content-Type` and reporting the
+///   encoding used. Responses without a charset are decoded as UTF-8, or with the charset of a `charset = "..."`
+///   clause. Invalid sequences are replaced rather than failing.
+/// - `Bytes` returns the raw response body.
+/// - `ByteStream` streams the response body in chunks, failing up front on an error status (requires the `stream`
+///   feature).
+/// - `Download` writes the response body to the file named by a `save_to = <parameter>` clause one chunk at a time,
+///   returning a `Download` summary (requires the `download` feature). Add `create_dirs = true` to create missing
+///   parent directories. The partial file is removed if the download fails.
+/// - `Events` streams a `text/event-stream` body as server-sent `Event`s (requires the `stream` feature).
+/// - `Sse<T>` is like `Events`, but deserializes the data of each event from JSON into a `T` (requires the `stream`
+///   and `json` features).
+/// - `Json<T>` deserializes the response body from JSON. Add an `or_default` clause to return `T::default()` when the
+///   body is empty or only whitespace. Declaring the endpoint as `-> Json<T> at "data.items"` deserializes only the
+///   value at that dotted path, for responses wrapped in an envelope; see also [`Api::envelope`].
+/// - `Result<Json<T>, Json<E>>` deserializes the response body into `T` on a success status, and into `E` on an error
+///   status. The method returns [`ApiError<E>`](ApiError) instead of [`Error`], keeping the raw text of error bodies that
+///   aren't an `E`.
+/// - `Lines<T>` streams a newline-delimited JSON body, deserializing each line into a `T` (requires the `stream` and
+///   `json` features).
+/// - `Created<Json<T>>` returns a [`Created`] with the status, the `Location` header resolved against the request URL,
+///   and the body deserialized from JSON if there was one (requires the `json` feature).
+/// - `JsonBytes` keeps the JSON response body as bytes, to be deserialized with [`JsonBytes::parse`] into types that
+///   borrow from it (requires the `json` feature).
+/// - `Value` deserializes the response body into an untyped JSON [`Value`] (requires the `json` feature).
+/// - `MsgPack<T>` deserializes the response body from `MessagePack` (requires the `msgpack` feature).
+/// - `Cbor<T>` deserializes the response body from CBOR (requires the `cbor` feature).
+/// - `Yaml<T>` deserializes the response body from a single YAML document (requires the `yaml` feature).
+/// - `Csv<T>` deserializes each CSV record into a `T`, returning a `Vec<T>` (requires the `csv` feature). The first
+///   row is read as a header row unless the endpoint declares `has_headers = false`.
+///
+/// Any other return kind fails to compile with the list above.
+///
+/// # Progress
+/// `Bytes` and `Download` endpoints declared with a `with_progress` clause gain a trailing
+/// `progress: impl FnMut(u64, Option<u64>)` parameter. It's called after every chunk of the body with the number of
+/// bytes received so far and the total from `Content-Length`, if the response had one. The callback doesn't need to be
+/// `Send`.
+/// ```rust,ignore
+/// impl ExampleApi {
+///     api! {
+///         fn artifact(id: u32) -> Bytes {
+///             GET "https://example.com/artifacts/{id}"
+///             with_progress
+///         }
+///     }
+/// }
+///
+/// let bytes = api.artifact(1, |received, total| println!("{received} of {total:?} bytes")).await?;
+/// ```
+///
+/// # Post-processing
+/// A `map` clause runs a closure on the decoded response before it's returned. The closure can change the return type
+/// by declaring it, as in `map |raw| -> Clean { ... }`. Unless it's the last clause, end the closure with a `;`.
+/// ```rust,ignore
+/// impl ExampleApi {
+///     api! {
+///         fn users() -> Json<Vec<User>> {
+///             GET "https://example.com/users"
+///             map |mut users| { users.sort_by_key(|user| user.id); users }
+///         }
+///
+///         fn user_ids() -> Json<Vec<User>> {
+///             GET "https://example.com/users"
+///             map |users| -> Vec<u32> { users.into_iter().map(|user| user.id).collect() }
+///         }
+///     }
+/// }
+/// ```
+///
+/// # Validation
+/// A `validate` clause checks the decoded response with its [`Validate`] implementation before any `map` clause, and
+/// fails with [`Error::Invalid`] carrying its message if it's rejected, such as for a `200 OK` whose required list
+/// is empty. Endpoints without the clause don't require the trait.
+/// ```rust,ignore
+/// impl ExampleApi {
+///     api! {
+///         fn page(number: u32) -> Json<Page> {
+///             GET "https://example.com/pages/{number}"
+///             validate
+///         }
+///     }
+/// }
+/// ```
+///
+/// # Response examples
+/// `Json<T>` endpoints can declare example response bodies, one `example = "..."` per file, with paths relative to
+/// the crate root. Each block of endpoints with examples gets a `verify_examples()` function, which deserializes every
+/// example into its endpoint's response type without touching the network (requires the `examples` feature).
+/// ```rust,ignore
+/// impl ExampleApi {
+///     api! {
+///         fn todo(id: u32) -> Json<Todo> {
+///             GET "https://example.com/todos/{id}"
+///             example = "tests/examples/todo.json"
+///             example = "tests/examples/todo_completed.json"
+///         }
+///     }
+/// }
+///
+/// #[test]
+/// fn examples() {
+///     ExampleApi::verify_examples().unwrap();
+/// }
+/// ```
+///
+/// # Sharing endpoints between structs
+/// Endpoints can be written once as default methods of a trait with [`Api`] as a supertrait, and then shared by any
+/// number of structs with their own [`Api`] implementations. Leave off the visibility, as trait items can't have one.
+/// ```rust
+/// use api_client::{api, Api};
+/// use reqwest::{Client, RequestBuilder};
+///
+/// trait ExampleEndpoints: Api {
+///     api! {
+///         fn example() -> String {
+///             GET "https://example.com"
+///         }
+///     }
+/// }
+///
+/// api!(pub struct Production);
+///
+/// struct Sandbox(Client);
+///
+/// impl Api for Sandbox {
+///     fn client(&self) -> &Client {
+///         &self.0
+///     }
+///
+///     fn pre_request(&self, request: RequestBuilder) -> reqwest::Result<RequestBuilder> {
+///         Ok(request.header("x-sandbox", "true"))
+///     }
+/// }
+///
+/// impl ExampleEndpoints for Production {}
+/// impl ExampleEndpoints for Sandbox {}
+/// ```
+///
+/// The macro can also be used inside `impl Trait for Struct` blocks, as long as the trait declares the endpoints as
+/// native `async fn`s returning [`Result`]. Response examples generate an extra associated function, so they can't be
+/// used in either position.
+///
+/// Both rely on `async fn` in traits, which is why they need Rust 1.75, and `#[async_trait]` isn't supported as a way
+/// around it. The attribute rewrites the methods of a trait or impl before the `api!` inside it is expanded, so the
+/// generated methods are never boxed, and they can't match the boxed signatures it gives declared methods. Declare the
+/// shared endpoints in a trait of their own using native `async fn`s instead; a trait using `#[async_trait]` for its
+/// other methods may still contain `api!` default methods, as they're left alone.
+///
+/// # Mocking
+/// `trait Name for Struct` declares a trait with the signatures and documentation of its endpoints, and implements it
+/// for the struct by sending requests. Code written against the trait can then be tested with a fake implementation
+/// written by hand. The trait's methods return `impl Future`, which mocking libraries such as `mockall` can't generate
+/// implementations of. As in other traits, endpoints have no visibility, and can't have companion functions or
+/// response examples.
+/// ```rust
+/// use api_client::{api, Api};
+///
+/// api!(pub struct TodoApi, base = "https://example.com");
+///
+/// api!(pub trait Todos for TodoApi {
+///     fn title(id: u32) -> String {
+///         GET "/todos/{id}/title"
+///     }
+/// });
+///
+/// struct FakeTodos;
+///
+/// impl Todos for FakeTodos {
+///     async fn title(&self, id: u32) -> api_client::Result<String> {
+///         Ok(format!("todo {id}"))
+///     }
+/// }
+/// ```
+///
+/// # Response size limits
+/// `String`, `Text`, `Bytes`, `Json<T>`, `Value`, `MsgPack<T>`, `Cbor<T>`, `Yaml<T>` and `Csv<T>` endpoints can cap the
+/// size of the response body with a `limit = <bytes>` clause, optionally followed by a `KB`, `MB` or `GB` unit
+/// (multiples of 1024), such as `limit = 10 MB`. A response with a larger `Content-Length` fails without reading the
+/// body, and others fail with [`Error::BodyTooLarge`] as soon as the limit is passed. Endpoints without a `limit`
+/// clause use [`Api::body_limit`].
+///
+/// # Expected statuses
+/// A response with an error status (400 to 599) fails with [`Error::UnexpectedStatus`], carrying the status, URL and
+/// the start of the body, before the body is decoded. `StatusCode` and `Headers` endpoints return any status, and
+/// `Result<Json<T>, Json<E>>` endpoints decode error bodies into `E`.
+///
+/// An `expect` clause replaces these statuses with the ones an endpoint may respond with, as single statuses or
+/// inclusive ranges separated by `|`, such as `expect 202` or `expect 200..=204 | 207 | 404`. Any other status fails
+/// with [`Error::UnexpectedStatus`] for every return kind, which takes precedence over decoding the error body of a
+/// `Result<Json<T>, Json<E>>` endpoint.
+/// ```rust,ignore
+/// impl ExampleApi {
+///     api! {
+///         fn import(request: Json<Import>) -> Json<Job> {
+///             POST "https://example.com/imports"
+///             expect 202
+///         }
+///     }
+/// }
+/// ```
+///
+/// # Timeouts
+/// A `timeout = <duration>` clause overrides the client's timeout for one endpoint, covering everything from
+/// connecting until the response body is read. The duration is a literal followed by `ms`, `s` or `min`, such as
+/// `timeout = 30 s`, or an expression evaluating to a [`Duration`](std::time::Duration), ended with a `;` unless it's
+/// the last clause. A request that times out fails with [`Error::Transport`].
+///
+/// # Basic auth
+/// A `basic_auth user, password` clause sends HTTP basic auth credentials, where `user` is anything that implements
+/// [`Display`](std::fmt::Display) and `password` is an `Option` of one, like [`RequestBuilder::basic_auth`]. End it
+/// with a `;` or `,` unless it's the last clause or both are parameters. The credentials replace any `Authorization`
+/// header added by [`Api::pre_request`], so an endpoint can use basic auth in an otherwise token-authenticated API.
+/// ```rust,ignore
+/// impl ExampleApi {
+///     api! {
+///         fn purge(&self, user: &str, password: &str) -> StatusCode {
+///             POST "https://example.com/admin/purge"
+///             basic_auth user, Some(password);
+///         }
+///     }
+/// }
+/// ```
+///
+/// # Building requests without sending them
+/// A `request = name` clause also generates an async method called `name`, taking the same parameters, that returns
+/// the [`RequestBuilder`] the endpoint would send, with its URL, headers, body and [`Api::pre_request`] applied. It's an
+/// escape hatch for requests that need something the macro doesn't support, such as a one-off header, before sending
+/// them yourself. Call budgets are only charged by the endpoint method.
+/// ```rust,ignore
+/// impl ExampleApi {
+///     api! {
+///         fn todos(&self) -> Json<Vec<Todo>> {
+///             GET "https://example.com/todos"
+///             request = todos_request
+///         }
+///     }
+/// }
+///
+/// let response = api.todos_request().await?.header("x-trace", "1").send().await?;
+/// ```
+///
+/// Similarly, a `url = name` clause generates a method returning the endpoint's URL as a `String`, formatted and
+/// joined onto the base URL like the request's, for logging or handing links to other systems. `Query<T>` parameters
+/// are not included, as they're only serialized when the request is built.
+/// ```rust,ignore
+/// impl ExampleApi {
+///     api! {
+///         fn todo(&self, id: u32) -> Json<Todo> {
+///             GET "todos/{id}"
+///             url = todo_url
+///         }
+///     }
+/// }
+///
+/// assert_eq!(api.todo_url(1), "https://example.com/v2/todos/1");
+/// ```
+///
+/// Endpoints the macro can't express at all can be written by hand with [`send`], which takes the same steps as a
+/// generated method, from [`Api::default_headers`] to the caching hooks and middlewares, and decodes the response into
+/// any [`FromResponse`] type such as `String` or [`Json<T>`](Json). [`send_with`] takes [`SendOptions`] for the
+/// endpoint name and the `no_pre_request`, `auth =` and `context =` clauses. Both prepare and send requests with the
+/// functions generated methods call, so the two can't take different steps.
+/// ```rust,ignore
+/// impl ExampleApi {
+///     async fn rename(&self, id: u32, name: &Rename) -> api_client::Result<Todo> {
+///         let Json(todo) = api_client::send(self, Method::PATCH, &format!("todos/{id}"), Body::Json(name), &[]).await?;
+///         Ok(todo)
+///     }
+/// }
+/// ```
+///
+/// # Skipping `pre_request`
+/// A `no_pre_request` clause sends the request without calling [`Api::pre_request`] or [`Api::pre_request_async`], for
+/// endpoints that must not get what they add, such as a token endpoint that rejects an `Authorization` header.
+/// ```rust,ignore
+/// impl ExampleApi {
+///     api! {
+///         fn health() -> StatusCode {
+///             GET "https://example.com/health"
+///             no_pre_request
+///         }
+///     }
+/// }
+/// ```
+///
+/// # Per-endpoint authentication
+/// An `auth = <scheme>` clause passes the endpoint's request to [`Api::auth_for`] with the scheme's name instead of
+/// [`Api::pre_request`] and [`Api::pre_request_async`], for the few endpoints of an API that need other credentials,
+/// or none. Endpoints without it are prepared as usual.
+/// ```rust,ignore
+/// impl ExampleApi {
+///     api! {
+///         fn invoices() -> Json<Vec<Invoice>> {
+///             GET "invoices"
+///         }
+///
+///         fn rotate_keys() -> StatusCode {
+///             POST "admin/keys"
+///             auth = service
+///         }
+///
+///         fn status() -> StatusCode {
+///             GET "status"
+///             auth = none
+///         }
+///     }
+/// }
+/// ```
+///
+/// # Per-call context
+/// A `context = <parameter>` clause passes a parameter to [`PreRequestWith::pre_request_with`], so a single shared
+/// struct, and its connection pool, can prepare each call differently, such as with the credentials of the tenant it's
+/// made for.
+/// ```rust,ignore
+/// impl ExampleApi {
+///     api! {
+///         fn invoices(tenant: &Tenant) -> Json<Vec<Invoice>> {
+///             GET "https://example.com/invoices"
+///             context = tenant
+///         }
+///     }
+/// }
+/// ```
+///
+/// # Call budgets
+/// An endpoint can be charged against a [`Budget`] with a `budget = "name"` clause; see [`Api::budget`].
+///
+/// # Rate limiting
+/// Every endpoint awaits [`Api::acquire_permit`] right before sending its request, so all of a struct's endpoints can
+/// share a limit such as a `RateLimiter` (requires the `rate-limit` feature).
+///
+/// To limit how many requests are in flight at once instead, enable the `concurrency` feature and call
+/// `max_concurrency` on the [`ApiBuilder`], or return a semaphore from `Api::concurrency_permits`.
+///
+/// # Caching
+/// Before sending a request, endpoints ask [`Api::before_send`] for a [`CachedResponse`] to decode instead, and pass
+/// each response they receive to [`Api::after_receive`], with its body for return kinds that read it whole. Together
+/// they let responses be cached, replayed or deduplicated outside the macro.
+///
+/// # Send futures
+/// The futures of [`Api::pre_request_async`] and of the [`Middleware`] trait, which is declared with
+/// `#[async_trait(?Send)]`, don't need to be [`Send`], so they work on single-threaded runtimes and WebAssembly, but
+/// endpoints can't be awaited inside `tokio::spawn` on a multi-threaded runtime. The `send` feature requires them to be, so endpoints return [`Send`]
+/// futures. The feature changes what implementations must look like, so enable it in the final application rather than
+/// in a library:
+/// - Implementations of [`Middleware`] must use `#[async_trait]` rather than `#[async_trait(?Send)]`.
+/// - Structs implementing [`Api`] must be [`Sync`], such as by using a `Mutex` rather than a `RefCell`.
+/// - Traits sharing endpoints between structs must require `Api + Sync`.
+///
+/// # Advanced Usage (manually created struct and [Api] implementation)
+/// ```rust
+/// use api_client::{api, Api};
+/// use reqwest::{Client, RequestBuilder};
+///
+/// struct ExampleApi {
+///     client: Client,
+///     username: String,
+///     password: String
+/// }
+///
+/// impl Api for ExampleApi {
+///     fn client(&self) -> &Client {
+///         &self.client
+///     }
+///
+///     fn pre_request(&self, request: RequestBuilder) -> reqwest::Result<RequestBuilder> {
+///         Ok(request.basic_auth(&self.username, Some(&self.password)))
+///     }
+/// }
+///
+/// impl ExampleApi {
+///     api! {
+///         fn example() -> String {
+///            GET "https://example.com"
+///         }
+///     }
+/// }
+/// ```
+#[macro_export]
+macro_rules! api {
+    () => {};
+
+    (@output StatusCode) => { ::reqwest::StatusCode };
+    (@output Headers) => { $crate::Headers };
+    (@output String) => { String };
+    (@output Text) => { $crate::Text };
+    (@output Bytes) => { ::bytes::Bytes };
+    (@output Json<$res:ty>) => { $res };
+    (@output Value) => { $crate::Value };
+    (@output JsonBytes) => { $crate::JsonBytes };
+    (@output Created<$res:ty>) => { $crate::Created<$res> };
+    (@output ByteStream) => { $crate::ByteStream };
+    (@output Lines<$res:ty>) => { $crate::Lines<$res> };
+    (@output Events) => { $crate::Events };
+    (@output Download) => { $crate::Download };
+    (@output Sse<$res:ty>) => { $crate::Events<$res> };
+    (@output MsgPack<$res:ty>) => { $res };
+    (@output Cbor<$res:ty>) => { $res };
+    (@output Yaml<$res:ty>) => { $res };
+    (@output Csv<$res:ty>) => { ::std::vec::Vec<$res> };
+    (@output $($kind:tt)*) => {
+        compile_error!(concat!(
+            "unsupported return kind `", stringify!($($kind)*), "`; expected one of `StatusCode`, `Headers`, `String`, ",
+            "`Text`, `Bytes`, `Json<T>`, `Result<Json<T>, Json<E>>`, `Value`, `JsonBytes`, `Created<Json<T>>`, ",
+            "`ByteStream`, `Lines<T>`, `Events`, `Sse<T>`, `Download`, `MsgPack<T>`, `Cbor<T>`, `Yaml<T>` or `Csv<T>`",
+        ))
+    };
+
+    (@decode $api:ident $response:ident $clauses:tt StatusCode) => { $crate::api!(@from_response $response None; $crate::reqwest::StatusCode) };
+    (@decode $api:ident $response:ident $clauses:tt Headers) => { $crate::api!(@from_response $response None; $crate::Headers) };
+    (@decode $api:ident $response:ident [$($clauses:tt)*] String) => {
+        $crate::api!(@from_response $response $crate::api!(@limit $api; $($clauses)*); ::std::string::String)
+    };
+    (@decode $api:ident $response:ident [$($clauses:tt)*] Text) => {
+        $crate::Text::new($response, $crate::api!(@limit $api; $($clauses)*), $crate::api!(@charset $($clauses)*)).await
+    };
+    (@decode $api:ident $response:ident [$($clauses:tt)*] Bytes) => {
+        Ok(::bytes::Bytes::from(
+            $crate::decode::body($response, $crate::api!(@limit $api; $($clauses)*), $crate::api!(@progress $($clauses)*)).await?,
+        ))
+    };
+    (@decode $api:ident $response:ident [$($clauses:tt)*] Json<$res:ty>) => {
+        $crate::api!(
+            @json $response
+            ($crate::api!(@limit $api; $($clauses)*))
+            ($crate::api!(@envelope $api; $($clauses)*))
+            [] [];
+            $($clauses)*
+        )
+    };
+    (@decode $api:ident $response:ident [$($clauses:tt)*] Value) => {
+        $crate::api!(@from_response $response $crate::api!(@limit $api; $($clauses)*); $crate::Value)
+    };
+    (@decode $api:ident $response:ident $clauses:tt JsonBytes) => { $crate::api!(@from_response $response None; $crate::JsonBytes) };
+    (@decode $api:ident $response:ident $clauses:tt Created<$res:ty>) => { $crate::Created::new($response).await };
+    (@decode $api:ident $response:ident $clauses:tt ByteStream) => { $crate::api!(@from_response $response None; $crate::ByteStream) };
+    (@decode $api:ident $response:ident $clauses:tt Lines<$res:ty>) => { Ok($crate::Lines::new($response)) };
+    (@decode $api:ident $response:ident [$($clauses:tt)*] Download) => {
+        $crate::download::save(
+            $response,
+            $crate::api!(@save_to $($clauses)*),
+            $crate::api!(@create_dirs $($clauses)*),
+            $crate::api!(@progress $($clauses)*),
+        )
+        .await
+    };
+    (@decode $api:ident $response:ident $clauses:tt Events) => { Ok($crate::Events::new($response)) };
+    (@decode $api:ident $response:ident $clauses:tt Sse<$res:ty>) => { Ok($crate::Events::json($response)) };
+    (@decode $api:ident $response:ident [$($clauses:tt)*] MsgPack<$res:ty>) => {
+        $crate::decode::msgpack(&$crate::api!(@limited $api $response; $($clauses)*))
+    };
+    (@decode $api:ident $response:ident [$($clauses:tt)*] Cbor<$res:ty>) => {
+        $crate::decode::cbor(&$crate::api!(@limited $api $response; $($clauses)*))
+    };
+    (@decode $api:ident $response:ident [$($clauses:tt)*] Yaml<$res:ty>) => {
+        $crate::decode::yaml(&$crate::api!(@limited $api $response; $($clauses)*))
+    };
+    (@decode $api:ident $response:ident [$($clauses:tt)*] Csv<$res:ty>) => {
+        $crate::decode::csv(&$crate::api!(@limited $api $response; $($clauses)*), $crate::api!(@has_headers $($clauses)*))
+    };
+    // Unsupported kinds are reported by `@output`.
+    (@decode $api:ident $response:ident $clauses:tt $($kind:tt)*) => { ::core::unreachable!() };
+
+    (@limited $api:ident $response:ident; $($clauses:tt)*) => {
+        $crate::decode::body($response, $crate::api!(@limit $api; $($clauses)*), |_, _| {}).await?
+    };
+
+    (@from_response $response:ident $limit:expr; $ty:ty) => {
+        <$ty as $crate::FromResponse>::from_response($response, $limit).await
+    };
+
+    (@save_to) => { compile_error!("`-> Download` endpoints need a `save_to = <parameter>` clause") };
+    (@save_to (save_to $path:ident) $($clauses:tt)*) => { &$path };
+    (@save_to $clause:tt $($clauses:tt)*) => { $crate::api!(@save_to $($clauses)*) };
+
+    (@create_dirs) => { false };
+    (@create_dirs (create_dirs $create_dirs:literal) $($clauses:tt)*) => { $create_dirs };
+    (@create_dirs $clause:tt $($clauses:tt)*) => { $crate::api!(@create_dirs $($clauses)*) };
+
+    (@json $response:ident $limit:tt $path:tt [] [];) => { $crate::decode::json($response, $limit, $path, None).await };
+    (@json $response:ident $limit:tt $path:tt [] [$($default:tt)+];) => {
+        $crate::decode::json($response, $limit, $path, Some(::core::default::Default::default())).await
+    };
+    (@json $response:ident $limit:tt $path:tt [$err:ty] [];) => {
+        $crate::decode::json_or_error::<_, $err>($response, $limit, $path, None).await
+    };
+    (@json $response:ident $limit:tt $path:tt [$err:ty] [$($default:tt)+];) => {
+        $crate::decode::json_or_error::<_, $err>($response, $limit, $path, Some(::core::default::Default::default())).await
+    };
+    (@json $response:ident $limit:tt $path:tt [] $default:tt; (error_body $err:ty) $($clauses:tt)*) => {
+        $crate::api!(@json $response $limit $path [$err] $default; $($clauses)*)
+    };
+    (@json $response:ident $limit:tt $path:tt $err:tt []; (or_default) $($clauses:tt)*) => {
+        $crate::api!(@json $response $limit $path $err [or_default]; $($clauses)*)
+    };
+    (@json $response:ident $limit:tt $path:tt $err:tt $default:tt; $clause:tt $($clauses:tt)*) => {
+        $crate::api!(@json $response $limit $path $err $default; $($clauses)*)
+    };
+
+    (@envelope $api:ident;) => { $crate::Api::envelope($api) };
+    (@envelope $api:ident; (at $path:literal) $($clauses:tt)*) => { Some($path) };
+    (@envelope $api:ident; $clause:tt $($clauses:tt)*) => { $crate::api!(@envelope $api; $($clauses)*) };
+
+    (@limit $api:ident;) => { $crate::Api::body_limit($api) };
+    (@limit $api:ident; (limit $limit:tt) $($clauses:tt)*) => { Some($limit) };
+    (@limit $api:ident; $clause:tt $($clauses:tt)*) => { $crate::api!(@limit $api; $($clauses)*) };
+
+    (@returns [] $($kind:tt)*) => { $crate::api!(@output $($kind)*) };
+    (@returns [(map $map:tt $output:ty) $($clauses:tt)*] $($kind:tt)*) => { $output };
+    (@returns [$clause:tt $($clauses:tt)*] $($kind:tt)*) => { $crate::api!(@returns [$($clauses)*] $($kind)*) };
+
+    (@map $api:ident $response:ident [$($clauses:tt)*] [] $($kind:tt)*) => {
+        $crate::api!(@validate ($crate::api!(@decode $api $response [$($clauses)*] $($kind)*)); $($clauses)*)
+    };
+    (@map $api:ident $response:ident [$($clauses:tt)*] [(map $map:tt $($output:ty)?) $($rest:tt)*] $($kind:tt)*) => {{
+        let value: $crate::Result<$crate::api!(@output $($kind)*), $crate::api!(@error $($clauses)*)> =
+            $crate::api!(@validate ($crate::api!(@decode $api $response [$($clauses)*] $($kind)*)); $($clauses)*);
+        value.map($map)
+    }};
+    (@map $api:ident $response:ident $clauses:tt [$clause:tt $($rest:tt)*] $($kind:tt)*) => {
+        $crate::api!(@map $api $response $clauses [$($rest)*] $($kind)*)
+    };
+
+    (@validate ($value:expr);) => { $value };
+    (@validate ($value:expr); (validate) $($clauses:tt)*) => {
+        $value.and_then(|value| $crate::validate::check(value).map_err(::core::convert::Into::into))
+    };
+    (@validate ($value:expr); $clause:tt $($clauses:tt)*) => { $crate::api!(@validate ($value); $($clauses)*) };
+
+    (@error) => { $crate::Error };
+    (@error (error_body $err:ty) $($clauses:tt)*) => { $crate::ApiError<$err> };
+    (@error $clause:tt $($clauses:tt)*) => { $crate::api!(@error $($clauses)*) };
+
+    (@buffer $api:ident [$($clauses:tt)*] [$($kind:tt)*];) => { $crate::api!(@buffer_kind $api [$($clauses)*] $($kind)*) };
+    (@buffer $api:ident $clauses:tt $kind:tt; (progress $progress:ident) $($rest:tt)*) => { None };
+    (@buffer $api:ident $clauses:tt $kind:tt; $clause:tt $($rest:tt)*) => { $crate::api!(@buffer $api $clauses $kind; $($rest)*) };
+
+    (@buffer_kind $api:ident [$($clauses:tt)*] String) => { Some($crate::api!(@limit $api; $($clauses)*)) };
+    (@buffer_kind $api:ident [$($clauses:tt)*] Text) => { Some($crate::api!(@limit $api; $($clauses)*)) };
+    (@buffer_kind $api:ident [$($clauses:tt)*] Bytes) => { Some($crate::api!(@limit $api; $($clauses)*)) };
+    (@buffer_kind $api:ident [$($clauses:tt)*] Json<$res:ty>) => { Some($crate::api!(@limit $api; $($clauses)*)) };
+    (@buffer_kind $api:ident [$($clauses:tt)*] Value) => { Some($crate::api!(@limit $api; $($clauses)*)) };
+    (@buffer_kind $api:ident $clauses:tt JsonBytes) => { Some(None) };
+    (@buffer_kind $api:ident $clauses:tt Created<$res:ty>) => { Some(None) };
+    (@buffer_kind $api:ident [$($clauses:tt)*] MsgPack<$res:ty>) => { Some($crate::api!(@limit $api; $($clauses)*)) };
+    (@buffer_kind $api:ident [$($clauses:tt)*] Cbor<$res:ty>) => { Some($crate::api!(@limit $api; $($clauses)*)) };
+    (@buffer_kind $api:ident [$($clauses:tt)*] Yaml<$res:ty>) => { Some($crate::api!(@limit $api; $($clauses)*)) };
+    (@buffer_kind $api:ident [$($clauses:tt)*] Csv<$res:ty>) => { Some($crate::api!(@limit $api; $($clauses)*)) };
+    (@buffer_kind $api:ident $clauses:tt $($kind:tt)*) => { None };
+
+    (@progress) => { |_, _| {} };
+    (@progress (progress $progress:ident) $($clauses:tt)*) => { $progress };
+    (@progress $clause:tt $($clauses:tt)*) => { $crate::api!(@progress $($clauses)*) };
+
+    (@charset) => { "utf-8" };
+    (@charset (charset $charset:literal) $($clauses:tt)*) => { $charset };
+    (@charset $clause:tt $($clauses:tt)*) => { $crate::api!(@charset $($clauses)*) };
+
+    (@has_headers) => { true };
+    (@has_headers (has_headers $has_headers:literal) $($clauses:tt)*) => { $has_headers };
+    (@has_headers $clause:tt $($clauses:tt)*) => { $crate::api!(@has_headers $($clauses)*) };
+
+    (
+        $(#[$attr:meta])* $vis:vis struct $ident:ident {
+            $($(#[$field_attr:meta])* $field_vis:vis $field:ident: $field_ty:ty),* $(,)?
+        }
+        $($(#[$impl_attr:meta])* impl Api { $($items:tt)* })?
+    ) => {
+        $(#[$attr])*
+        $vis struct $ident {
+            client: ::reqwest::Client,
+            $($(#[$field_attr])* $field_vis $field: $field_ty),*
+        }
+
+        $($(#[$impl_attr])*)?
+        impl $crate::Api for $ident {
+            fn client(&self) -> &::reqwest::Client {
+                &self.client
+            }
+
+            $($($items)*)?
+        }
+
+        impl $ident {
+            /// Creates a client from its fields.
+            #[allow(dead_code)]
+            #[must_use]
+            $vis fn new($($field: $field_ty),*) -> Self {
+                $ident::with_client(::reqwest::Client::new(), $($field),*)
+            }
+
+            /// Creates a client from its fields that sends its requests with `client`, such as one shared with the
+            /// rest of the application.
+            #[allow(dead_code)]
+            #[must_use]
+            $vis fn with_client(client: ::reqwest::Client, $($field: $field_ty),*) -> Self {
+                $ident { client, $($field),* }
+            }
+        }
+    };
+
+    ($(#[$($attr:tt)*])* $vis:vis struct $ident:ident $(with $auth:ident)? { $($options:tt)* }) => {
+        $crate::api!(@options [[$(#[$($attr)*])*] [$($auth)?] []] $vis $ident [] [] [$($options)*]);
+    };
+
+    ($(#[$($attr:tt)*])* $vis:vis struct $ident:ident $(with $auth:ident)? $(, $($options:tt)*)?) => {
+        $crate::api!(@options [[$(#[$($attr)*])*] [$($auth)?] []] $vis $ident [] [] [$($($options)*)?]);
+    };
+
+    (@options $head:tt $vis:vis $ident:ident $old:tt [$($options:tt)*] [base = $base:literal $(, $($rest:tt)*)?]) => {
+        $crate::api!(@options $head $vis $ident [$base] [$($options)*] [$($($rest)*)?]);
+    };
+    (
+        @options $head:tt $vis:vis $ident:ident [$($base:literal)?] [$($options:tt)*]
+        [environments $($enum:ident)? { $($variants:tt)* } $(, $($rest:tt)*)?]
+    ) => {
+        $crate::api!(@environments $vis $ident [$($enum)? Environment] $($variants)*);
+        $crate::api!(@options $head $vis $ident [$($base)?] [$($options)*] [$($($rest)*)?] $($variants)*);
+    };
+    (@options $head:tt $vis:vis $ident:ident [] $options:tt $tokens:tt $(#[$($attr:tt)*])* $variant:ident = $url:literal $($variants:tt)*) => {
+        $crate::api!(@options $head $vis $ident [$url] $options $tokens);
+    };
+    (@options $head:tt $vis:vis $ident:ident $base:tt $options:tt $tokens:tt $($variants:tt)+) => {
+        $crate::api!(@options $head $vis $ident $base $options $tokens);
+    };
+    (@options [$attrs:tt $auth:tt $old:tt] $vis:vis $ident:ident $base:tt $options:tt [login = $login:ident $(, $($rest:tt)*)?]) => {
+        $crate::api!(@options [$attrs $auth [$login]] $vis $ident $base $options [$($($rest)*)?]);
+    };
+    (@options $head:tt $vis:vis $ident:ident $base:tt [$($options:tt)*] [$option:ident = $value:literal ms $(, $($rest:tt)*)?]) => {
+        $crate::api!(@options $head $vis $ident $base [$($options)* ($option (::std::time::Duration::from_millis($value)))] [$($($rest)*)?]);
+    };
+    (@options $head:tt $vis:vis $ident:ident $base:tt [$($options:tt)*] [$option:ident = $value:literal s $(, $($rest:tt)*)?]) => {
+        $crate::api!(@options $head $vis $ident $base [$($options)* ($option (::std::time::Duration::from_secs($value)))] [$($($rest)*)?]);
+    };
+    (@options $head:tt $vis:vis $ident:ident $base:tt [$($options:tt)*] [$option:ident = $value:literal min $(, $($rest:tt)*)?]) => {
+        $crate::api!(@options $head $vis $ident $base [$($options)* ($option (::std::time::Duration::from_secs($value * 60)))] [$($($rest)*)?]);
+    };
+    (@options $head:tt $vis:vis $ident:ident $base:tt [$($options:tt)*] [$option:ident = $value:expr $(, $($rest:tt)*)?]) => {
+        $crate::api!(@options $head $vis $ident $base [$($options)* ($option ($value))] [$($($rest)*)?]);
+    };
+    (@options $head:tt $vis:vis $ident:ident $base:tt [$($options:tt)*] [$option:ident $(, $($rest:tt)*)?]) => {
+        $crate::api!(@options $head $vis $ident $base [$($options)* $option] [$($($rest)*)?]);
+    };
+    (@options $head:tt $vis:vis $ident:ident [$($base:literal)?] [$($options:tt)*] []) => {
+        $crate::api!(@tuple $head $vis $ident [$($base)?] [$($options)*]);
+    };
+
+    (@tuple [[$($attr:tt)*] [$($auth:ident)?] [$($login:ident)?]] $vis:vis $ident:ident [$($base:literal)?] [$($option:tt)*]) => {
+        $crate::api!(@struct [] [$($attr)*] {
+            $vis struct $ident(::reqwest::Client, $crate::ApiConfig<$crate::api!(@auth_type $($auth)?)>);
+        });
+        $crate::api!(@auth_methods $vis $ident $($auth)?);
+        $crate::api!(@default $ident);
+
+        impl $crate::Api for $ident {
+            fn client(&self) -> &::reqwest::Client {
+                &self.0
+            }
+
+            fn base_url(&self) -> Option<&::reqwest::Url> {
+                self.1.base_url.as_ref()
+            }
+
+            fn default_headers(&self) -> ::reqwest::header::HeaderMap {
+                self.1.headers.clone()
+            }
+
+            fn default_query(&self) -> &[(::std::string::String, ::std::string::String)] {
+                &self.1.query
+            }
+
+            fn middlewares(&self) -> &[::std::sync::Arc<dyn $crate::Middleware>] {
+                &self.1.middlewares
+            }
+
+            $crate::__concurrency_permits!();
+
+            fn pre_request_async(
+                &self,
+                request: ::reqwest::RequestBuilder,
+            ) -> impl ::core::future::Future<Output = $crate::Result<::reqwest::RequestBuilder>> + $crate::MaybeSend {
+                $crate::Authenticate::authenticate(&self.1.auth, request)
+            }
+
+            fn reauthenticate(&self) -> impl ::core::future::Future<Output = $crate::Result<bool>> + $crate::MaybeSend {
+                $crate::api!(@reauthenticate self $($login)?)
+            }
+
+            fn new() -> Self where Self: Sized {
+                $ident::with_client($crate::api!(@client $($option)*))
+            }
+        }
+
+        impl $ident {
+            /// Creates a client that resolves relative endpoint URLs against `base_url` instead of the declared base
+            /// URL, such as a mock server.
+            #[allow(dead_code)]
+            #[must_use]
+            $vis fn with_base_url(base_url: ::reqwest::Url) -> Self {
+                $ident(
+                    $crate::api!(@client $($option)*),
+                    $crate::ApiConfig::new(Some($crate::url::base(base_url))),
+                )
+            }
+
+            /// Creates a client that sends its requests with `client`, such as one shared with the rest of the
+            /// application.
+            #[allow(dead_code)]
+            #[must_use]
+            $vis fn with_client(client: ::reqwest::Client) -> Self {
+                $ident(client, $crate::ApiConfig::new($crate::api!(@base $($base)?)))
+            }
+
+            /// Creates a client like [`Api::new`], returning an error instead of panicking if the HTTP client
+            /// can't be built.
+            ///
+            /// # Errors
+            /// Returns an error if the HTTP client can't be built, such as when TLS initialization fails.
+            #[allow(dead_code)]
+            $vis fn try_new() -> ::reqwest::Result<Self> {
+                $ident::from_builder(::reqwest::Client::builder())
+            }
+
+            /// Creates a client that sends its requests with the client built by `builder`, such as one with a
+            /// timeout, proxy or user agent.
+            ///
+            /// # Errors
+            /// Returns an error if `builder` fails to build the HTTP client.
+            #[allow(dead_code)]
+            $vis fn from_builder(builder: ::reqwest::ClientBuilder) -> ::reqwest::Result<Self> {
+                Ok($ident::with_client($crate::api!(@configure (builder) $($option)*).build()?))
+            }
+
+            /// Returns a builder to configure the client, base URL, default headers and middlewares of a client.
+            #[allow(dead_code)]
+            $vis fn builder() -> $crate::ApiBuilder<Self, $crate::api!(@auth_type $($auth)?)> {
+                $crate::ApiBuilder::new(
+                    |builder| $crate::api!(@configure (builder) $($option)*),
+                    $crate::api!(@base $($base)?),
+                    $ident,
+                )
+            }
+        }
+
+        impl ::core::convert::From<::reqwest::Client> for $ident {
+            fn from(client: ::reqwest::Client) -> Self {
+                $ident::with_client(client)
+            }
+        }
+    };
+
+    (@environments $vis:vis $ident:ident [$enum:ident $($default:ident)?] $($(#[$($attr:tt)*])* $variant:ident = $url:literal),* $(,)?) => {
+        #[doc = concat!("The environments [`", stringify!($ident), "`] can send its requests to.")]
+        #[derive(Clone, Debug, PartialEq, Eq, Hash)]
+        $vis enum $enum {
+            $(
+                $(#[$($attr)*])*
+                #[doc = concat!("`", $url, "`")]
+                $variant,
+            )*
+            /// A base URL chosen at runtime, such as a mock server.
+            Custom(::reqwest::Url),
+        }
+
+        impl $enum {
+            /// Returns the base URL of the environment.
+            #[allow(dead_code)]
+            #[must_use]
+            $vis fn base_url(&self) -> ::reqwest::Url {
+                match self {
+                    $($enum::$variant => $crate::url::parse($url),)*
+                    $enum::Custom(url) => url.clone(),
+                }
+            }
+        }
+
+        impl $ident {
+            /// Creates a client that resolves relative endpoint URLs against the base URL of `environment`.
+            #[allow(dead_code)]
+            #[must_use]
+            $vis fn with_environment(environment: $enum) -> Self {
+                $ident::with_base_url(environment.base_url())
+            }
+        }
+    };
+
+    (@auth_type) => { () };
+    (@auth_type bearer_auth) => { $crate::BearerAuth };
+    (@auth_type api_key) => { $crate::ApiKey };
+    (@auth_type basic_auth) => { $crate::BasicAuth };
+    (@auth_type client_credentials) => { ::core::option::Option<$crate::ClientCredentials> };
+    (@auth_type authorization_code) => { ::core::option::Option<$crate::AuthorizationCode> };
+    (@auth_type $auth:ident) => {
+        compile_error!(concat!(
+            "unknown authentication scheme `", stringify!($auth), "`; expected `bearer_auth`, `api_key`, `basic_auth`, ",
+            "`client_credentials` or `authorization_code`",
+        ))
+    };
+
+    (@auth_methods $vis:vis $ident:ident) => {};
+    (@auth_methods $vis:vis $ident:ident bearer_auth) => {
+        impl $ident {
+            /// Creates a client that sends `token` in an `Authorization: Bearer` header with every request.
+            #[allow(dead_code)]
+            #[must_use]
+            $vis fn with_token(token: impl ::core::convert::Into<$crate::Secret>) -> Self {
+                let api = <$ident as $crate::Api>::new();
+                api.1.auth.set_token(token);
+                api
+            }
+
+            /// Replaces the token sent with later requests, including by clones of this client.
+            #[allow(dead_code)]
+            $vis fn set_token(&self, token: impl ::core::convert::Into<$crate::Secret>) {
+                self.1.auth.set_token(token);
+            }
+        }
+    };
+    (@auth_methods $vis:vis $ident:ident api_key) => {
+        impl $ident {
+            /// Creates a client that sends `key` with every request.
+            #[allow(dead_code)]
+            #[must_use]
+            $vis fn with_api_key(key: $crate::ApiKey) -> Self {
+                let mut api = <$ident as $crate::Api>::new();
+                api.1.auth = key;
+                api
+            }
+        }
+    };
+    (@auth_methods $vis:vis $ident:ident basic_auth) => {
+        impl $ident {
+            /// Creates a client that sends `username` and `password` as HTTP basic auth with every request.
+            #[allow(dead_code)]
+            #[must_use]
+            $vis fn with_basic_auth(
+                username: impl ::core::convert::Into<::std::string::String>,
+                password: impl ::core::convert::Into<$crate::Secret>,
+            ) -> Self {
+                let mut api = <$ident as $crate::Api>::new();
+                api.1.auth = $crate::BasicAuth::new(username, password);
+                api
+            }
+        }
+    };
+    (@auth_methods $vis:vis $ident:ident client_credentials) => {
+        impl $ident {
+            /// Creates a client that sends access tokens fetched with `credentials` with every request.
+            #[allow(dead_code)]
+            #[must_use]
+            $vis fn with_client_credentials(credentials: $crate::ClientCredentials) -> Self {
+                let mut api = <$ident as $crate::Api>::new();
+                api.1.auth = ::core::option::Option::Some(credentials);
+                api
+            }
+        }
+    };
+    (@auth_methods $vis:vis $ident:ident authorization_code) => {
+        impl $ident {
+            /// Creates a client that sends access tokens of the user logged in with `auth` with every request.
+            #[allow(dead_code)]
+            #[must_use]
+            $vis fn with_authorization_code(auth: $crate::AuthorizationCode) -> Self {
+                let mut api = <$ident as $crate::Api>::new();
+                api.1.auth = ::core::option::Option::Some(auth);
+                api
+            }
+        }
+    };
+    (@auth_methods $vis:vis $ident:ident $auth:ident) => {};
+
+    (@base) => { ::core::option::Option::None };
+    (@base $base:literal) => { ::core::option::Option::Some($crate::url::parse($base)) };
+
+    (@struct [$($kept:tt)*] [] { $($item:tt)* }) => {
+        #[derive(Clone, Debug)]
+        $($kept)*
+        $($item)*
+    };
+    (@struct [$($kept:tt)*] [#[derive($($derive:tt)*)] $($attrs:tt)*] $item:tt) => {
+        $crate::api!(@derive [$($kept)*] [] [$($derive)*] [$($attrs)*] $item);
+    };
+    (@struct [$($kept:tt)*] [#[$($attr:tt)*] $($attrs:tt)*] $item:tt) => {
+        $crate::api!(@struct [$($kept)* #[$($attr)*]] [$($attrs)*] $item);
+    };
+
+    (@derive [$($kept:tt)*] [] [] [$($attrs:tt)*] $item:tt) => {
+        $crate::api!(@struct [$($kept)*] [$($attrs)*] $item);
+    };
+    (@derive [$($kept:tt)*] [$($derives:tt)+] [] [$($attrs:tt)*] $item:tt) => {
+        $crate::api!(@struct [$($kept)* #[derive($($derives)+)]] [$($attrs)*] $item);
+    };
+    (@derive $kept:tt $derives:tt [Clone $(, $($rest:tt)*)?] $attrs:tt $item:tt) => {
+        $crate::api!(@derive $kept $derives [$($($rest)*)?] $attrs $item);
+    };
+    (@derive $kept:tt $derives:tt [Debug $(, $($rest:tt)*)?] $attrs:tt $item:tt) => {
+        $crate::api!(@derive $kept $derives [$($($rest)*)?] $attrs $item);
+    };
+    (@derive $kept:tt $derives:tt [Default $(, $($rest:tt)*)?] $attrs:tt $item:tt) => {
+        $crate::api!(@derive $kept $derives [$($($rest)*)?] $attrs $item);
+    };
+    (@derive $kept:tt [$($derives:tt)*] [$derive:path $(, $($rest:tt)*)?] $attrs:tt $item:tt) => {
+        $crate::api!(@derive $kept [$($derives)* $derive,] [$($($rest)*)?] $attrs $item);
+    };
+
+    (@default $ident:ident) => {
+        impl ::core::default::Default for $ident {
+            fn default() -> Self {
+                <$ident as $crate::Api>::new()
+            }
+        }
+    };
+
+    (@reauthenticate $this:ident) => { $crate::Authenticate::reauthenticate(&$this.1.auth) };
+    (@reauthenticate $this:ident $login:ident) => {
+        async move {
+            let outcome = $this.$login().await?;
+            Ok($crate::LoginOutcome::succeeded(&outcome))
+        }
+    };
+
+    (@client) => { ::reqwest::Client::new() };
+    (@client $($option:tt)+) => {
+        $crate::api!(@configure (::reqwest::Client::builder()) $($option)+)
+            .build()
+            .expect("failed to build the HTTP client")
+    };
+
+    (@configure ($builder:expr)) => { $builder };
+    (@configure ($builder:expr) cookie_store $($options:tt)*) => {
+        $crate::api!(@configure ($crate::__cookie_store!($builder)) $($options)*)
+    };
+    (@configure ($builder:expr) ($option:ident ($value:expr)) $($options:tt)*) => {
+        $crate::api!(@configure ($builder.$option($value)) $($options)*)
+    };
+    (@configure ($builder:expr) $option:ident $($options:tt)*) => {
+        compile_error!(concat!("unknown struct option `", stringify!($option), "`; expected `cookie_store`"))
+    };
+
+    ($(#[$battr:meta])* $bvis:vis struct $builder:ident<$lt:lifetime> for $api:ty { $(#[$($attr:tt)*])* $vis:vis fn $ident:ident($($params:tt)*) -> $($rest:tt)* }) => {
+        $crate::api!(@builder_params [] [] [$($params)*] { [[$(#[$battr])*] $bvis $builder $lt $api] $(#[$($attr)*])* $vis fn $ident($($params)*) -> $($rest)* });
+    };
+
+    (@builder_params [$($required:tt)*] [$($optional:tt)*] [] { [$($builder:tt)*] $(#[$($attr:tt)*])* $vis:vis fn $ident:ident($($params:tt)*) -> $($rest:tt)* }) => {
+        $crate::api!(@endpoints [(builder $($builder)* [$($required)*] [$($optional)*])] $(#[$($attr)*])* $vis fn $ident(&api, $($params)*) -> $($rest)*);
+    };
+    (@builder_params $required:tt [$($optional:tt)*] [$name:ident: $ty:ty = $default:expr $(, $($rest:tt)*)?] $context:tt) => {
+        $crate::api!(@builder_params $required [$($optional)* ($name $ty)] [$($($rest)*)?] $context);
+    };
+    (@builder_params $required:tt [$($optional:tt)*] [$name:ident: Option<$ty:ty> $(, $($rest:tt)*)?] $context:tt) => {
+        $crate::api!(@builder_params $required [$($optional)* ($name $ty)] [$($($rest)*)?] $context);
+    };
+    (@builder_params [$($required:tt)*] $optional:tt [$name:ident: $ty:ty $(, $($rest:tt)*)?] $context:tt) => {
+        $crate::api!(@builder_params [$($required)* ($name $ty)] $optional [$($($rest)*)?] $context);
+    };
+
+    ($(#[$($attr:tt)*])* $vis:vis fn $($rest:tt)*) => {
+        $crate::api!(@endpoints [] $(#[$($attr)*])* $vis fn $($rest)*);
+    };
+
+    ($(#[$($attr:tt)*])* $vis:vis const $($rest:tt)*) => {
+        $crate::api!(@endpoints [] $(#[$($attr)*])* $vis const $($rest)*);
+    };
+
+    (group $($rest:tt)*) => {
+        $crate::api!(@endpoints [] group $($rest)*);
+    };
+
+    (impl $ty:ty { $($items:tt)* }) => {
+        $crate::api!(@impl [$ty] [] [] [$($items)*]);
+    };
+
+    ($(#[$attr:meta])* $vis:vis trait $trait:ident for $ty:ty { $($endpoints:tt)* }) => {
         $(#[$attr])*
+        $vis trait $trait {
+            $crate::api!(@endpoints [(signature)] $($endpoints)*);
+        }
+
+        impl $trait for $ty {
+            $crate::api! { $($endpoints)* }
+        }
+    };
+
+    (@impl [$ty:ty] [$($items:tt)*] [] []) => {
+        impl $ty {
+            $($items)*
+        }
+    };
+    (@impl [$ty:ty] [$($items:tt)*] [$($endpoints:tt)+] []) => {
+        impl $ty {
+            $($items)*
+            $crate::api! { $($endpoints)+ }
+        }
+    };
+    (@impl [$ty:ty] $items:tt [$($endpoints:tt)*] [group $prefix:tt $(($($params:tt)*))? { $($group:tt)* } $($rest:tt)*]) => {
+        $crate::api!(@impl [$ty] $items [$($endpoints)* group $prefix $(($($params)*))? { $($group)* }] [$($rest)*]);
+    };
+    (@impl [$ty:ty] $items:tt $endpoints:tt [$(#[$($attr:tt)*])* fn $ident:ident $($rest:tt)*]) => {
+        $crate::api!(@impl_fn [$ty] $items $endpoints [$(#[$($attr)*])* fn $ident] [$($rest)*]);
+    };
+    (@impl [$ty:ty] $items:tt $endpoints:tt [$(#[$($attr:tt)*])* pub $(($($scope:tt)*))? fn $ident:ident $($rest:tt)*]) => {
+        $crate::api!(@impl_fn [$ty] $items $endpoints [$(#[$($attr)*])* pub $(($($scope)*))? fn $ident] [$($rest)*]);
+    };
+    (@impl [$ty:ty] $items:tt [$($endpoints:tt)*] [$(#[$($attr:tt)*])* $vis:vis const $name:ident; $($rest:tt)*]) => {
+        $crate::api!(@impl [$ty] $items [$(#[$($attr)*])* $vis const $name; $($endpoints)*] [$($rest)*]);
+    };
+    (@impl [$ty:ty] [$($items:tt)*] $endpoints:tt [$token:tt $($rest:tt)*]) => {
+        $crate::api!(@impl [$ty] [$($items)* $token] $endpoints [$($rest)*]);
+    };
+
+    (@impl_fn [$ty:ty] $items:tt [$($endpoints:tt)*] [$($head:tt)*] [{ $method:tt $url:literal $($block:tt)* } $($rest:tt)*]) => {
+        $crate::api!(@impl [$ty] $items [$($endpoints)* $($head)* { $method $url $($block)* }] [$($rest)*]);
+    };
+    (@impl_fn [$ty:ty] [$($items:tt)*] $endpoints:tt [$($head:tt)*] [{ $($body:tt)* } $($rest:tt)*]) => {
+        $crate::api!(@impl [$ty] [$($items)* $($head)* { $($body)* }] $endpoints [$($rest)*]);
+    };
+    (@impl_fn [$ty:ty] $items:tt $endpoints:tt [$($head:tt)*] [$token:tt $($rest:tt)*]) => {
+        $crate::api!(@impl_fn [$ty] $items $endpoints [$($head)* $token] [$($rest)*]);
+    };
+
+    (@endpoints [(signature) $($endpoints:tt)*]) => {};
+    (@endpoints [(const $const:tt) $($endpoints:tt)*]) => {
+        $crate::api!(@endpoint_list $const [] $($endpoints)*);
+        $crate::api!(@examples [] $($endpoints)*);
+    };
+    (@endpoints [$($endpoints:tt)*]) => {
+        $crate::api!(@examples [] $($endpoints)*);
+    };
+
+    (@endpoints [] $(#[$($attr:tt)*])* $vis:vis const $name:ident; $($rest:tt)*) => {
+        $crate::api!(@endpoints [(const [[$(#[$($attr)*])*] [$vis] $name])] $($rest)*);
+    };
+    (@endpoints [(signature) $($endpoints:tt)*] $(#[$($attr:tt)*])* const $name:ident; $($rest:tt)*) => {
+        $(#[$($attr)*])*
+        const $name: &'static [$crate::EndpointInfo];
+        $crate::api!(@endpoints [(signature) $($endpoints)*] $($rest)*);
+    };
+
+    (@endpoint_list [[$($attr:tt)*] [$vis:vis] $name:ident] [$(([$(($($predicate:tt)*))*] $ident:ident $method:tt $url:tt [$($clauses:tt)*]))*]) => {
+        $($attr)*
+        #[allow(dead_code)]
+        $vis const $name: &'static [$crate::EndpointInfo] = &[$(
+            $(#[cfg($($predicate)*)])*
+            $crate::EndpointInfo::new(
+                stringify!($ident),
+                $crate::api!(@const_method $method),
+                $crate::api!(@doc_url $url),
+                $crate::api!(@header_names []; $($clauses)*),
+            ),
+        )*];
+    };
+    (@endpoint_list $const:tt $infos:tt ($ident:ident, $attrs:tt, $kind:ident $(<$res:ty>)?, [(endpoint $method:tt $url:tt) $($clauses:tt)*]) $($rest:tt)*) => {
+        $crate::api!(@endpoint_cfg $const $infos [] $attrs ($ident $method $url [$($clauses)*]) $($rest)*);
+    };
+
+    (@endpoint_cfg $const:tt $infos:tt [$($cfg:tt)*] [#[cfg($($predicate:tt)*)] $($attrs:tt)*] $info:tt $($rest:tt)*) => {
+        $crate::api!(@endpoint_cfg $const $infos [$($cfg)* ($($predicate)*)] [$($attrs)*] $info $($rest)*);
+    };
+    (@endpoint_cfg $const:tt $infos:tt $cfg:tt [#[$($attr:tt)*] $($attrs:tt)*] $info:tt $($rest:tt)*) => {
+        $crate::api!(@endpoint_cfg $const $infos $cfg [$($attrs)*] $info $($rest)*);
+    };
+    (@endpoint_cfg $const:tt [$($infos:tt)*] $cfg:tt [] ($($info:tt)*) $($rest:tt)*) => {
+        $crate::api!(@endpoint_list $const [$($infos)* ($cfg $($info)*)] $($rest)*);
+    };
+
+    (@endpoints $endpoints:tt group $prefix:literal $(($($params:tt)*))? { $($group:tt)* } $($rest:tt)*) => {
+        $crate::api!(@group [$prefix] [$($($params)*)?] [] [$($group)*] $endpoints $($rest)*);
+    };
+    (@endpoints $endpoints:tt group [$($prefix:literal)+] ($($params:tt)*) { $($group:tt)* } $($rest:tt)*) => {
+        $crate::api!(@group [$($prefix)+] [$($params)*] [] [$($group)*] $endpoints $($rest)*);
+    };
+
+    (@group $prefix:tt $params:tt [$($items:tt)*] [] $endpoints:tt $($rest:tt)*) => {
+        $crate::api!(@endpoints $endpoints $($items)* $($rest)*);
+    };
+    (@group [$($prefix:literal)+] [$($params:tt)*] [$($items:tt)*] [group $inner:literal $(($($innerparams:tt)*))? { $($group:tt)* } $($rest:tt)*] $($context:tt)*) => {
+        $crate::api!(@group [$($prefix)+] [$($params)*] [$($items)* group [$($prefix)+ $inner] ($($params)*, $($($innerparams)*)?) { $($group)* }] [$($rest)*] $($context)*);
+    };
+    (@group $prefix:tt $params:tt $items:tt [$(#[$($attr:tt)*])* $vis:vis fn $ident:ident $($rest:tt)*] $($context:tt)*) => {
+        $crate::api!(@group_fn $prefix $params $items [$(#[$($attr)*])* $vis fn $ident] [$($rest)*] $($context)*);
+    };
+    (@group $prefix:tt $params:tt $items:tt [$unexpected:tt $($rest:tt)*] $($context:tt)*) => {
+        compile_error!(concat!(
+            "unexpected `", stringify!($unexpected), "` in group; expected endpoints starting with `fn` or nested groups ",
+            "of the form `group \"prefix\" (name: Type, ...) { ... }`",
+        ));
+    };
+
+    (@group_fn $prefix:tt [$($params:tt)*] $items:tt [$($head:tt)*] [(&$this:ident $(, $($fnparams:tt)*)?) $($rest:tt)*] $($context:tt)*) => {
+        $crate::api!(@group_signature $prefix [$($params)*] $items [$($head)* (&$this, $($params)*, $($($fnparams)*)?)] [$($rest)*] $($context)*);
+    };
+    (@group_fn $prefix:tt [$($params:tt)*] $items:tt [$($head:tt)*] [($($fnparams:tt)*) $($rest:tt)*] $($context:tt)*) => {
+        $crate::api!(@group_signature $prefix [$($params)*] $items [$($head)* ($($params)*, $($fnparams)*)] [$($rest)*] $($context)*);
+    };
+    (@group_fn $prefix:tt $params:tt $items:tt [$($head:tt)*] [$token:tt $($rest:tt)*] $($context:tt)*) => {
+        $crate::api!(@group_fn $prefix $params $items [$($head)* $token] [$($rest)*] $($context)*);
+    };
+
+    (@group_signature [$($prefix:literal)+] $params:tt [$($items:tt)*] [$($head:tt)*] [{ $method:tt $url:literal $($block:tt)* } $($rest:tt)*] $($context:tt)*) => {
+        $crate::api!(@group [$($prefix)+] $params [$($items)* $($head)* { $method [$($prefix)+ $url] $($block)* }] [$($rest)*] $($context)*);
+    };
+    (@group_signature $prefix:tt $params:tt [$($items:tt)*] [$($head:tt)*] [{ $($block:tt)* } $($rest:tt)*] $($context:tt)*) => {
+        $crate::api!(@group $prefix $params [$($items)* $($head)* { $($block)* }] [$($rest)*] $($context)*);
+    };
+    (@group_signature $prefix:tt $params:tt $items:tt [$($head:tt)*] [$token:tt $($rest:tt)*] $($context:tt)*) => {
+        $crate::api!(@group_signature $prefix $params $items [$($head)* $token] [$($rest)*] $($context)*);
+    };
+
+    (@endpoints $endpoints:tt $(#[$($attr:tt)*])* $vis:vis fn $ident:ident < $($rest:tt)*) => {
+        $crate::api!(@generics $endpoints { $(#[$($attr)*])* $vis fn $ident } [<] $($rest)*);
+    };
+
+    (@endpoints [$($endpoints:tt)*] $(#[$($attr:tt)*])* $vis:vis fn $ident:ident $([$($generics:tt)*])? ($($params:tt)*) -> Json<$res:ty> at $path:literal $([where $($bounds:tt)*])? { $method:tt $url:tt $($block:tt)* } $($rest:tt)*) => {
+        $crate::api!(@clauses [(at $path)] [$($block)*] [$($endpoints)*] { $(#[$($attr)*])* $vis fn $ident [$($($generics)*)?] ($($params)*) -> Json<$res> [$(where $($bounds)*)?] { $method $url } } $($rest)*);
+    };
+
+    (@endpoints [$($endpoints:tt)*] $(#[$($attr:tt)*])* $vis:vis fn $ident:ident $([$($generics:tt)*])? ($($params:tt)*) -> Result<Json<$res:ty>, Json<$err:ty>> at $path:literal $([where $($bounds:tt)*])? { $method:tt $url:tt $($block:tt)* } $($rest:tt)*) => {
+        $crate::api!(@clauses [(error_body $err) (at $path)] [$($block)*] [$($endpoints)*] { $(#[$($attr)*])* $vis fn $ident [$($($generics)*)?] ($($params)*) -> Json<$res> [$(where $($bounds)*)?] { $method $url } } $($rest)*);
+    };
+
+    (@endpoints [$($endpoints:tt)*] $(#[$($attr:tt)*])* $vis:vis fn $ident:ident $([$($generics:tt)*])? ($($params:tt)*) -> Result<Json<$res:ty>, Json<$err:ty>> $([where $($bounds:tt)*])? { $method:tt $url:tt $($block:tt)* } $($rest:tt)*) => {
+        $crate::api!(@clauses [(error_body $err)] [$($block)*] [$($endpoints)*] { $(#[$($attr)*])* $vis fn $ident [$($($generics)*)?] ($($params)*) -> Json<$res> [$(where $($bounds)*)?] { $method $url } } $($rest)*);
+    };
+
+    (@endpoints [$($endpoints:tt)*] $(#[$($attr:tt)*])* $vis:vis fn $ident:ident $([$($generics:tt)*])? ($($params:tt)*) -> Created<Json<$res:ty>> $([where $($bounds:tt)*])? { $method:tt $url:tt $($block:tt)* } $($rest:tt)*) => {
+        $crate::api!(@clauses [] [$($block)*] [$($endpoints)*] { $(#[$($attr)*])* $vis fn $ident [$($($generics)*)?] ($($params)*) -> Created<$res> [$(where $($bounds)*)?] { $method $url } } $($rest)*);
+    };
+
+    (@endpoints [$($endpoints:tt)*] $(#[$($attr:tt)*])* $vis:vis fn $ident:ident $([$($generics:tt)*])? ($($params:tt)*) -> $kind:ident $(<$res:ty>)? $([where $($bounds:tt)*])? { $method:tt $url:tt $($block:tt)* } $($rest:tt)*) => {
+        $crate::api!(@clauses [] [$($block)*] [$($endpoints)*] { $(#[$($attr)*])* $vis fn $ident [$($($generics)*)?] ($($params)*) -> $kind $(<$res>)? [$(where $($bounds)*)?] { $method $url } } $($rest)*);
+    };
+    (@where $endpoints:tt { $($signature:tt)* } where $($rest:tt)*) => {
+        $crate::api!(@bounds $endpoints { $($signature)* } [] $($rest)*);
+    };
+    (@where $endpoints:tt { $(#[$($attr:tt)*])* $vis:vis fn $ident:ident $($signature:tt)* } { $($block:tt)* } $($rest:tt)*) => {
+        $crate::api!(@endpoints $endpoints $(#[$($attr)*])* $vis fn $ident);
+    };
+    (@where $endpoints:tt { $($signature:tt)* } $next:tt $($rest:tt)*) => {
+        $crate::api!(@where $endpoints { $($signature)* $next } $($rest)*);
+    };
+    (@bounds $endpoints:tt { $($signature:tt)* } [$($bounds:tt)*] { $($block:tt)* } $($rest:tt)*) => {
+        $crate::api!(@endpoints $endpoints $($signature)* [where $($bounds)*] { $($block)* } $($rest)*);
+    };
+    (@bounds $endpoints:tt $signature:tt [$($bounds:tt)*] $next:tt $($rest:tt)*) => {
+        $crate::api!(@bounds $endpoints $signature [$($bounds)* $next] $($rest)*);
+    };
+
+    (@generics $endpoints:tt { $($signature:tt)* } [$($generics:tt)*] ($($params:tt)*) -> $($rest:tt)*) => {
+        $crate::api!(@endpoints $endpoints $($signature)* [$($generics)*] ($($params)*) -> $($rest)*);
+    };
+    (@generics $endpoints:tt $signature:tt [$($generics:tt)*] $next:tt $($rest:tt)*) => {
+        $crate::api!(@generics $endpoints $signature [$($generics)* $next] $($rest)*);
+    };
+
+    (@endpoints $endpoints:tt $(#[$($attr:tt)*])* $vis:vis fn $ident:ident $([$($generics:tt)*])? ($($params:tt)*) -> $($rest:tt)*) => {
+        $crate::api!(@where $endpoints { $(#[$($attr)*])* $vis fn $ident $([$($generics)*])? ($($params)*) -> } $($rest)*);
+    };
+    (@endpoints $endpoints:tt $(#[$($attr:tt)*])* $vis:vis fn $ident:ident $($rest:tt)*) => {
+        compile_error!(concat!(
+            "could not parse endpoint `", stringify!($ident), "`; expected ",
+            "`fn ", stringify!($ident), "(name: Type, ...) -> Kind { METHOD \"url\" ... }`",
+        ));
+    };
+    (@endpoints $endpoints:tt $($rest:tt)*) => {
+        compile_error!(concat!("expected an endpoint declaration starting with `fn`, found `", stringify!($($rest)*), "`"));
+    };
+
+    (@clauses [$($clauses:tt)*] [] $($context:tt)*) => {
+        $crate::api!(@endpoint [$($clauses)*] $($context)*);
+    };
+    (@clauses $clauses:tt [, $($block:tt)*] $($context:tt)*) => {
+        $crate::api!(@clauses $clauses [$($block)*] $($context)*);
+    };
+    (@clauses [$($clauses:tt)*] [example = $example:literal $($block:tt)*] $($context:tt)*) => {
+        $crate::api!(@clauses [$($clauses)* (example $example)] [$($block)*] $($context)*);
+    };
+    (@clauses [$($clauses:tt)*] [budget = $budget:literal $($block:tt)*] $($context:tt)*) => {
+        $crate::api!(@clauses [$($clauses)* (budget $budget)] [$($block)*] $($context)*);
+    };
+    (@clauses [$($clauses:tt)*] [url = $name:ident $($block:tt)*] $($context:tt)*) => {
+        $crate::api!(@clauses [$($clauses)* (url_fn $name)] [$($block)*] $($context)*);
+    };
+    (@clauses [$($clauses:tt)*] [request = $name:ident $($block:tt)*] $($context:tt)*) => {
+        $crate::api!(@clauses [$($clauses)* (request_fn $name)] [$($block)*] $($context)*);
+    };
+    (@clauses [$($clauses:tt)*] [auth = $scheme:ident $($block:tt)*] $($context:tt)*) => {
+        $crate::api!(@clauses [$($clauses)* (auth $scheme)] [$($block)*] $($context)*);
+    };
+    (@clauses [$($clauses:tt)*] [context = $name:ident $($block:tt)*] $($context:tt)*) => {
+        $crate::api!(@clauses [$($clauses)* (context $name)] [$($block)*] $($context)*);
+    };
+    (@clauses [$($clauses:tt)*] [save_to = $path:ident $($block:tt)*] $($context:tt)*) => {
+        $crate::api!(@clauses [$($clauses)* (save_to $path)] [$($block)*] $($context)*);
+    };
+    (@clauses [$($clauses:tt)*] [create_dirs = $create_dirs:literal $($block:tt)*] $($context:tt)*) => {
+        $crate::api!(@clauses [$($clauses)* (create_dirs $create_dirs)] [$($block)*] $($context)*);
+    };
+    (@clauses [$($clauses:tt)*] [charset = $charset:literal $($block:tt)*] $($context:tt)*) => {
+        $crate::api!(@clauses [$($clauses)* (charset $charset)] [$($block)*] $($context)*);
+    };
+    (@clauses [$($clauses:tt)*] [limit = $limit:literal KB $($block:tt)*] $($context:tt)*) => {
+        $crate::api!(@clauses [$($clauses)* (limit ($limit * 1024))] [$($block)*] $($context)*);
+    };
+    (@clauses [$($clauses:tt)*] [limit = $limit:literal MB $($block:tt)*] $($context:tt)*) => {
+        $crate::api!(@clauses [$($clauses)* (limit ($limit * 1024 * 1024))] [$($block)*] $($context)*);
+    };
+    (@clauses [$($clauses:tt)*] [limit = $limit:literal GB $($block:tt)*] $($context:tt)*) => {
+        $crate::api!(@clauses [$($clauses)* (limit ($limit * 1024 * 1024 * 1024))] [$($block)*] $($context)*);
+    };
+    (@clauses [$($clauses:tt)*] [limit = $limit:literal $($block:tt)*] $($context:tt)*) => {
+        $crate::api!(@clauses [$($clauses)* (limit $limit)] [$($block)*] $($context)*);
+    };
+    (@clauses [$($clauses:tt)*] [timeout = $timeout:literal ms $($block:tt)*] $($context:tt)*) => {
+        $crate::api!(@clauses [$($clauses)* (timeout (::std::time::Duration::from_millis($timeout)))] [$($block)*] $($context)*);
+    };
+    (@clauses [$($clauses:tt)*] [timeout = $timeout:literal s $($block:tt)*] $($context:tt)*) => {
+        $crate::api!(@clauses [$($clauses)* (timeout (::std::time::Duration::from_secs($timeout)))] [$($block)*] $($context)*);
+    };
+    (@clauses [$($clauses:tt)*] [timeout = $timeout:literal min $($block:tt)*] $($context:tt)*) => {
+        $crate::api!(@clauses [$($clauses)* (timeout (::std::time::Duration::from_secs($timeout * 60)))] [$($block)*] $($context)*);
+    };
+    (@clauses [$($clauses:tt)*] [timeout = $timeout:expr; $($block:tt)*] $($context:tt)*) => {
+        $crate::api!(@clauses [$($clauses)* (timeout ($timeout))] [$($block)*] $($context)*);
+    };
+    (@clauses [$($clauses:tt)*] [timeout = $timeout:expr, $($block:tt)*] $($context:tt)*) => {
+        $crate::api!(@clauses [$($clauses)* (timeout ($timeout))] [$($block)*] $($context)*);
+    };
+    (@clauses [$($clauses:tt)*] [timeout = $timeout:expr] $($context:tt)*) => {
+        $crate::api!(@clauses [$($clauses)* (timeout ($timeout))] [] $($context)*);
+    };
+    (@clauses [$($clauses:tt)*] [map |mut $arg:ident $(: $input:ty)?| -> $output:ty { $($body:tt)* } $($block:tt)*] $($context:tt)*) => {
+        $crate::api!(@clauses [$($clauses)* (map (|mut $arg $(: $input)?| -> $output { $($body)* }) $output)] [$($block)*] $($context)*);
+    };
+    (@clauses [$($clauses:tt)*] [map |$arg:ident $(: $input:ty)?| -> $output:ty { $($body:tt)* } $($block:tt)*] $($context:tt)*) => {
+        $crate::api!(@clauses [$($clauses)* (map (|$arg $(: $input)?| -> $output { $($body)* }) $output)] [$($block)*] $($context)*);
+    };
+    (@clauses [$($clauses:tt)*] [map $map:expr; $($block:tt)*] $($context:tt)*) => {
+        $crate::api!(@clauses [$($clauses)* (map ($map))] [$($block)*] $($context)*);
+    };
+    (@clauses [$($clauses:tt)*] [map $map:expr, $($block:tt)*] $($context:tt)*) => {
+        $crate::api!(@clauses [$($clauses)* (map ($map))] [$($block)*] $($context)*);
+    };
+    (@clauses [$($clauses:tt)*] [map $map:expr] $($context:tt)*) => {
+        $crate::api!(@clauses [$($clauses)* (map ($map))] [] $($context)*);
+    };
+    (@clauses [$($clauses:tt)*] [query_style = $style:ident $($block:tt)*] $($context:tt)*) => {
+        $crate::api!(@clauses [$($clauses)* (query_style $style)] [$($block)*] $($context)*);
+    };
+    (@clauses [$($clauses:tt)*] [raw = $name:ident $($block:tt)*] $($context:tt)*) => {
+        $crate::api!(@clauses [$($clauses)* (raw $name)] [$($block)*] $($context)*);
+    };
+    (@clauses [$($clauses:tt)*] [basic_auth $user:expr, $password:expr; $($block:tt)*] $($context:tt)*) => {
+        $crate::api!(@clauses [$($clauses)* (basic_auth ($user) ($password))] [$($block)*] $($context)*);
+    };
+    (@clauses [$($clauses:tt)*] [basic_auth $user:expr, $password:expr, $($block:tt)*] $($context:tt)*) => {
+        $crate::api!(@clauses [$($clauses)* (basic_auth ($user) ($password))] [$($block)*] $($context)*);
+    };
+    (@clauses [$($clauses:tt)*] [basic_auth $user:expr, $password:expr] $($context:tt)*) => {
+        $crate::api!(@clauses [$($clauses)* (basic_auth ($user) ($password))] [] $($context)*);
+    };
+    (@clauses [$($clauses:tt)*] [basic_auth $user:ident, $password:ident $($block:tt)*] $($context:tt)*) => {
+        $crate::api!(@clauses [$($clauses)* (basic_auth ($user) ($password))] [$($block)*] $($context)*);
+    };
+    (@clauses [$($clauses:tt)*] [expect $from:literal ..= $to:literal | $($block:tt)*] $($context:tt)*) => {
+        $crate::api!(@clauses [$($clauses)* (expect ($from..=$to))] [expect $($block)*] $($context)*);
+    };
+    (@clauses [$($clauses:tt)*] [expect $status:literal | $($block:tt)*] $($context:tt)*) => {
+        $crate::api!(@clauses [$($clauses)* (expect ($status..=$status))] [expect $($block)*] $($context)*);
+    };
+    (@clauses [$($clauses:tt)*] [expect $from:literal ..= $to:literal $($block:tt)*] $($context:tt)*) => {
+        $crate::api!(@clauses [$($clauses)* (expect ($from..=$to))] [$($block)*] $($context)*);
+    };
+    (@clauses [$($clauses:tt)*] [expect $status:literal $($block:tt)*] $($context:tt)*) => {
+        $crate::api!(@clauses [$($clauses)* (expect ($status..=$status))] [$($block)*] $($context)*);
+    };
+    (@clauses [$($clauses:tt)*] [no_pre_request $($block:tt)*] $($context:tt)*) => {
+        $crate::api!(@clauses [$($clauses)* (no_pre_request)] [$($block)*] $($context)*);
+    };
+    (@clauses [$($clauses:tt)*] [validate $($block:tt)*] $($context:tt)*) => {
+        $crate::api!(@clauses [$($clauses)* (validate)] [$($block)*] $($context)*);
+    };
+    (@clauses [$($clauses:tt)*] [or_default $($block:tt)*] $($context:tt)*) => {
+        $crate::api!(@clauses [$($clauses)* (or_default)] [$($block)*] $($context)*);
+    };
+    (@clauses [$($clauses:tt)*] [has_headers = $has_headers:literal $($block:tt)*] $($context:tt)*) => {
+        $crate::api!(@clauses [$($clauses)* (has_headers $has_headers)] [$($block)*] $($context)*);
+    };
+    (@clauses [$($clauses:tt)*] [with_progress $($block:tt)*] [$($endpoints:tt)*] { $(#[$($attr:tt)*])* $vis:vis fn $ident:ident $generics:tt () -> $($signature:tt)* } $($rest:tt)*) => {
+        $crate::api!(@clauses [$($clauses)* (progress progress)] [$($block)*] [$($endpoints)*] { $(#[$($attr)*])* $vis fn $ident $generics (progress: impl FnMut(u64, Option<u64>)) -> $($signature)* } $($rest)*);
+    };
+    (@clauses [$($clauses:tt)*] [with_progress $($block:tt)*] [$($endpoints:tt)*] { $(#[$($attr:tt)*])* $vis:vis fn $ident:ident $generics:tt ($($params:tt)+) -> $($signature:tt)* } $($rest:tt)*) => {
+        $crate::api!(@clauses [$($clauses)* (progress progress)] [$($block)*] [$($endpoints)*] { $(#[$($attr)*])* $vis fn $ident $generics ($($params)+, progress: impl FnMut(u64, Option<u64>)) -> $($signature)* } $($rest)*);
+    };
+    (@clauses [$($clauses:tt)*] [? $key:tt: $value:literal $($block:tt)*] $($context:tt)*) => {
+        $crate::api!(@clauses [$($clauses)* (query $key $value)] [$($block)*] $($context)*);
+    };
+    (@clauses [$($clauses:tt)*] [? $key:tt: $value:ident $($block:tt)*] $($context:tt)*) => {
+        $crate::api!(@clauses [$($clauses)* (query $key $value)] [$($block)*] $($context)*);
+    };
+    (@clauses [$($clauses:tt)*] [? $value:ident $($block:tt)*] $($context:tt)*) => {
+        $crate::api!(@clauses [$($clauses)* (query $value $value)] [$($block)*] $($context)*);
+    };
+    (@clauses [$($clauses:tt)*] [cookie $name:tt: $value:literal $($block:tt)*] $($context:tt)*) => {
+        $crate::api!(@clauses [$($clauses)* (cookie $name $value)] [$($block)*] $($context)*);
+    };
+    (@clauses [$($clauses:tt)*] [cookie $name:tt: $value:expr; $($block:tt)*] $($context:tt)*) => {
+        $crate::api!(@clauses [$($clauses)* (cookie $name ($value))] [$($block)*] $($context)*);
+    };
+    (@clauses [$($clauses:tt)*] [cookie $name:tt: $value:expr, $($block:tt)*] $($context:tt)*) => {
+        $crate::api!(@clauses [$($clauses)* (cookie $name ($value))] [$($block)*] $($context)*);
+    };
+    (@clauses [$($clauses:tt)*] [cookie $name:tt: $value:expr] $($context:tt)*) => {
+        $crate::api!(@clauses [$($clauses)* (cookie $name ($value))] [] $($context)*);
+    };
+    (@clauses [$($clauses:tt)*] [cookie $name:tt: $value:ident $($block:tt)*] $($context:tt)*) => {
+        $crate::api!(@clauses [$($clauses)* (cookie $name ($value))] [$($block)*] $($context)*);
+    };
+    (@clauses [$($clauses:tt)*] [$headername:tt ? : $headervalue:expr; $($block:tt)*] $($context:tt)*) => {
+        $crate::api!(@clauses [$($clauses)* (optional_header $headername ($headervalue))] [$($block)*] $($context)*);
+    };
+    (@clauses [$($clauses:tt)*] [$headername:tt ? : $headervalue:expr, $($block:tt)*] $($context:tt)*) => {
+        $crate::api!(@clauses [$($clauses)* (optional_header $headername ($headervalue))] [$($block)*] $($context)*);
+    };
+    (@clauses [$($clauses:tt)*] [$headername:tt ? : $headervalue:expr] $($context:tt)*) => {
+        $crate::api!(@clauses [$($clauses)* (optional_header $headername ($headervalue))] [] $($context)*);
+    };
+    (@clauses [$($clauses:tt)*] [$headername:tt ? : $headervalue:ident $($block:tt)*] $($context:tt)*) => {
+        $crate::api!(@clauses [$($clauses)* (optional_header $headername ($headervalue))] [$($block)*] $($context)*);
+    };
+    (@clauses [$($clauses:tt)*] [$headername:tt: $headervalue:literal $($block:tt)*] $($context:tt)*) => {
+        $crate::api!(@clauses [$($clauses)* (header $headername $headervalue)] [$($block)*] $($context)*);
+    };
+    (@clauses [$($clauses:tt)*] [$headername:tt: $headervalue:expr; $($block:tt)*] $($context:tt)*) => {
+        $crate::api!(@clauses [$($clauses)* (header $headername ($headervalue))] [$($block)*] $($context)*);
+    };
+    (@clauses [$($clauses:tt)*] [$headername:tt: $headervalue:expr, $($block:tt)*] $($context:tt)*) => {
+        $crate::api!(@clauses [$($clauses)* (header $headername ($headervalue))] [$($block)*] $($context)*);
+    };
+    (@clauses [$($clauses:tt)*] [$headername:tt: $headervalue:expr] $($context:tt)*) => {
+        $crate::api!(@clauses [$($clauses)* (header $headername ($headervalue))] [] $($context)*);
+    };
+    (@clauses [$($clauses:tt)*] [$headername:tt: $headervalue:ident $($block:tt)*] $($context:tt)*) => {
+        $crate::api!(@clauses [$($clauses)* (header $headername ($headervalue))] [$($block)*] $($context)*);
+    };
+    (@clauses $clauses:tt [$unexpected:tt $($block:tt)*] $($context:tt)*) => {
+        compile_error!(concat!(
+            "unexpected `", stringify!($unexpected), "` in endpoint block; expected a header (`NAME: value`), ",
+            "a query parameter (`? key: value`), a cookie (`cookie name: value`) or an option such as `limit = 1 MB`",
+        ));
+    };
+
+    (@endpoint $clauses:tt $endpoints:tt { $(#[$($attr:tt)*])* $vis:vis fn $ident:ident $generics:tt (&$this:ident $(, $($params:tt)*)?) -> $($signature:tt)* } $($rest:tt)*) => {
+        $crate::api!(@params [] [] [$($($params)*)?] $clauses $endpoints { $(#[$($attr)*])* $vis fn $ident $generics [$this] -> $($signature)* } $($rest)*);
+    };
+    (@endpoint $clauses:tt $endpoints:tt { $(#[$($attr:tt)*])* $vis:vis fn $ident:ident $generics:tt ($($params:tt)*) -> $($signature:tt)* } $($rest:tt)*) => {
+        $crate::api!(@params [] [] [$($params)*] $clauses $endpoints { $(#[$($attr)*])* $vis fn $ident $generics [self] -> $($signature)* } $($rest)*);
+    };
+
+    (@params $params:tt $body:tt [] $($context:tt)*) => {
+        $crate::api!(@emit $params $body $($context)*);
+    };
+    (@params $params:tt $body:tt [, $($rest:tt)*] $($context:tt)*) => {
+        $crate::api!(@params $params $body [$($rest)*] $($context)*);
+    };
+    (@params [$($params:tt)*] [$($body:tt)*] [$name:ident: Json<$req:ty> $(, $($rest:tt)*)?] $($context:tt)*) => {
+        $crate::api!(@params [$($params)* $name: &$req,] [$($body)* (json $name)] [$($($rest)*)?] $($context)*);
+    };
+    (@params [$($params:tt)*] [$($body:tt)*] [$name:ident: Form<$req:ty> $(, $($rest:tt)*)?] $($context:tt)*) => {
+        $crate::api!(@params [$($params)* $name: &$req,] [$($body)* (form $name)] [$($($rest)*)?] $($context)*);
+    };
+    (@params [$($params:tt)*] [$($body:tt)*] [$name:ident: Multipart $(, $($rest:tt)*)?] $($context:tt)*) => {
+        $crate::api!(@params [$($params)* $name: $crate::reqwest::multipart::Form,] [$($body)* (multipart $name)] [$($($rest)*)?] $($context)*);
+    };
+    (@params [$($params:tt)*] [$($body:tt)*] [$name:ident: Query<$query:ty> $(, $($rest:tt)*)?] $($context:tt)*) => {
+        $crate::api!(@params [$($params)* $name: &$query,] [$($body)* (query $name)] [$($($rest)*)?] $($context)*);
+    };
+    (@params [$($params:tt)*] [$($body:tt)*] [$name:ident: $ty:ty = $default:expr $(, $($rest:tt)*)?] $($context:tt)*) => {
+        $crate::api!(@params [$($params)* $name: impl ::core::convert::Into<::core::option::Option<$ty>>,] [$($body)* (default $name ($default) $ty) (param $name)] [$($($rest)*)?] $($context)*);
+    };
+    (@params [$($params:tt)*] [$($body:tt)*] [$name:ident: $ty:ty $(, $($rest:tt)*)?] $($context:tt)*) => {
+        $crate::api!(@params [$($params)* $name: $ty,] [$($body)* (param $name)] [$($($rest)*)?] $($context)*);
+    };
+    (@params $params:tt $body:tt [$($unexpected:tt)*] $($context:tt)*) => {
+        compile_error!(concat!(
+            "expected endpoint parameters of the form `name: Type`, found `", stringify!($($unexpected)*), "`; ",
+            "request bodies are declared as `name: Json<T>`, `name: Form<T>` or `name: Multipart`, and query strings as ",
+            "`name: Query<T>`",
+        ));
+    };
+
+    (@emit $params:tt $body:tt $clauses:tt [(builder $($builder:tt)*)] $signature:tt) => {
+        $crate::api!(@builder [$($builder)*] $params $body $clauses $signature);
+    };
+    (@emit [$($params:tt)*] $body:tt [$($clauses:tt)*] [(signature) $($endpoints:tt)*] { $(#[$($attr:tt)*])* $vis:vis fn $ident:ident [$($generics:tt)*] [$this:ident] -> $kind:ident $(<$res:ty>)? [$($bounds:tt)*] { $method:tt $url:tt } } $($rest:tt)*) => {
+        $(#[$($attr)*])*
+        #[doc = ""]
+        #[doc = $crate::api!(@doc $method $url []; $($clauses)*)]
+        fn $ident $($generics)* (&$this, $($params)*) -> impl ::core::future::Future<Output = $crate::Result<$crate::api!(@returns [$($clauses)*] $kind $(<$res>)?), $crate::api!(@error $($clauses)*)>> + $crate::MaybeSend $($bounds)*;
+        $crate::api!(@endpoints [(signature) $($endpoints)*] $($rest)*);
+    };
+    (@emit [$($params:tt)*] $body:tt [$($clauses:tt)*] [$($endpoints:tt)*] { $(#[$($attr:tt)*])* $vis:vis fn $ident:ident [$($generics:tt)*] [$this:ident] -> $kind:ident $(<$res:ty>)? [$($bounds:tt)*] { $method:tt $url:tt } } $($rest:tt)*) => {
+        $(#[$($attr)*])*
+        #[doc = ""]
+        #[doc = $crate::api!(@doc $method $url []; $($clauses)*)]
         #[inline]
-        $vis async fn $ident(&self, request: &$req, $($name: $ty),*) -> ::reqwest::Result<String> {
-            use $crate::Api as _;
-            self.pre_request(self.client().request($crate::reqwest::Method::$method, format!($url).as_str()))?
-                $(.header($headername, format!($headervalue).as_str()))*
-                .json(request)
+        $vis async fn $ident $($generics)* (&$this, $($params)*) -> $crate::Result<$crate::api!(@returns [$($clauses)*] $kind $(<$res>)?), $crate::api!(@error $($clauses)*)> $($bounds)* {
+            $crate::api!(@request $ident $this $body [$($clauses)*] $method $url $kind $(<$res>)?)
+        }
+        $crate::api!(@companions $ident [$(#[$($attr)*])*] [$vis] [$($generics)*] [$this] [$($params)*] $body [$($clauses)*] [$($bounds)*] $method $url [$kind $(<$res>)?]; $($clauses)*);
+        $crate::api!(@endpoints [$($endpoints)* ($ident, [$(#[$($attr)*])*], $kind $(<$res>)?, [(endpoint $method $url) $($clauses)*])] $($rest)*);
+    };
+
+    (@builder [[$($battr:tt)*] $bvis:vis $builder:ident $lt:lifetime $api:ty [$(($required:ident $requiredty:ty))*] [$(($optional:ident $optionalty:ty))*]] $params:tt $body:tt [$($clauses:tt)*] { $(#[$($attr:tt)*])* $vis:vis fn $ident:ident [] [$this:ident] -> $kind:ident $(<$res:ty>)? [] { $method:tt $url:tt } }) => {
+        $($battr)*
+        #[must_use = "the request is only sent by `send`"]
+        $bvis struct $builder<$lt> {
+            api: &$lt $api,
+            $($required: $requiredty,)*
+            $($optional: ::core::option::Option<$optionalty>,)*
+        }
+
+        impl $api {
+            $(#[$($attr)*])*
+            #[doc = ""]
+            #[doc = concat!("Returns a [`", stringify!($builder), "`] to set optional parameters on before sending the request.")]
+            #[inline]
+            $vis fn $ident<$lt>(&$lt self, $($required: $requiredty),*) -> $builder<$lt> {
+                $builder {
+                    api: self,
+                    $($required,)*
+                    $($optional: None,)*
+                }
+            }
+        }
+
+        impl<$lt> $builder<$lt> {
+            $(
+                #[doc = concat!("Sets `", stringify!($optional), "`.")]
+                #[inline]
+                $vis fn $optional(mut self, $optional: $optionalty) -> Self {
+                    self.$optional = Some($optional);
+                    self
+                }
+            )*
+
+            /// Sends the request.
+            #[doc = ""]
+            #[doc = $crate::api!(@doc $method $url []; $($clauses)*)]
+            #[inline]
+            $vis async fn send(self) -> $crate::Result<$crate::api!(@returns [$($clauses)*] $kind $(<$res>)?), $crate::api!(@error $($clauses)*)> {
+                let $builder { api: $this, $($required,)* $($optional,)* } = self;
+                $crate::api!(@request $ident $this $body [$($clauses)*] $method $url $kind $(<$res>)?)
+            }
+        }
+    };
+
+    (@request $ident:ident $this:ident [$($body:tt)*] [$($clauses:tt)*] $method:tt $url:tt $($kind:tt)*) => {{
+        use $crate::Api as _;
+        $crate::api!(@unused [$method $url $($clauses)*]; $($body)*);
+        let result: $crate::Result<_, $crate::api!(@error $($clauses)*)> = async {
+            $crate::api!(@defaults $($body)*);
+            $crate::api!(@budget $this; $($clauses)*);
+            let builder = $crate::api!(@build $ident $this [$($body)*] [$($clauses)*] $method $url $($kind)*);
+            let buffer = $crate::api!(@buffer $this [$($clauses)*] [$($kind)*]; $($clauses)*);
+            let response = $crate::api!(
+                @exchange [
+                    $this,
+                    (stringify!($ident), $crate::api!(@doc_url $url), $crate::api!(@header_names []; $($clauses)*)),
+                ]
+                [builder, buffer]
+                $crate::api!(@context_fn $this; $($clauses)*);
+                $($clauses)*
+            )
+            .await?;
+            let response = $crate::api!(@expect response; [$($kind)*] []; $($clauses)*);
+            $crate::api!(@map $this response [$($clauses)*] [$($clauses)*] $($kind)*)
+        }
+        .await;
+        if let Err(error) = &result {
+            $crate::Failure::report(error, |error| $this.on_error(stringify!($ident), error));
+        }
+        result
+    }};
+
+    (@unused $tokens:tt;) => {};
+    (@unused [$($tokens:tt)*]; (param $name:ident) $($body:tt)*) => {
+        const _: () = assert!(
+            false $(|| $crate::api!(@mentions $name $tokens))*,
+            concat!("parameter `", stringify!($name), "` is never used; prefix it with `_` if that's intended"),
+        );
+        $crate::api!(@unused [$($tokens)*]; $($body)*);
+    };
+    (@unused $tokens:tt; $entry:tt $($body:tt)*) => { $crate::api!(@unused $tokens; $($body)*); };
+
+    (@mentions $name:ident ($($tokens:tt)*)) => { false $(|| $crate::api!(@mentions $name $tokens))* };
+    (@mentions $name:ident [$($tokens:tt)*]) => { false $(|| $crate::api!(@mentions $name $tokens))* };
+    (@mentions $name:ident {$($tokens:tt)*}) => { false $(|| $crate::api!(@mentions $name $tokens))* };
+    (@mentions $name:ident $token:tt) => { $crate::url::mentions(stringify!($name), stringify!($token)) };
+
+    (@build $ident:ident $this:ident [$($body:tt)*] [$($clauses:tt)*] $method:tt $url:tt $($kind:tt)*) => {{
+        use $crate::Api as _;
+        let url = $crate::api!(@resolve $this [$($body)*] [$($clauses)*] $url);
+        let method = $crate::api!(@method $method);
+        let defaults = $this.default_headers();
+        let builder = $this.client().request(method.clone(), url.as_str()).headers(defaults.clone());
+        let builder = $crate::api!(@query builder; $crate::api!(@query_style $($clauses)*); $($clauses)*);
+        let builder = $crate::api!(
+            @prepare $this [$ident $url method] $crate::api!(@accept defaults builder; [$($kind)*]; $($clauses)*);
+            [$($clauses)*]
+            $($clauses)*
+        );
+        let builder = $crate::api!(@query_params builder; $($body)*);
+        let builder = $crate::query::defaults(builder, $this.default_query())?;
+        let builder = $crate::api!(@body []; $($body)*).apply(builder);
+        let builder = $crate::api!(@headers defaults builder; $($clauses)*);
+        let builder = $crate::api!(@cookies builder; []; $($clauses)*);
+        let builder = $crate::api!(@basic_auth builder; $($clauses)*);
+        $crate::api!(@timeout builder; $($clauses)*)
+    }};
+
+    (@resolve $this:ident [$($body:tt)*] [$($clauses:tt)*] $url:tt) => {
+        $crate::url::resolve(
+            $crate::Api::base_url($this),
+            $crate::url::encode(&$crate::api!(@url $url [$($clauses)*]; $($body)*)),
+        )
+    };
+
+    (@companions $ident:ident $attrs:tt $vis:tt $generics:tt $this:tt $params:tt $body:tt $clauses:tt $bounds:tt $method:tt $url:tt [$($kind:tt)*];) => {};
+    (@companions $ident:ident $attrs:tt [$vis:vis] [$($generics:tt)*] [$this:ident] [$($params:tt)*] [$($body:tt)*] [$($clauses:tt)*] [$($bounds:tt)*] $method:tt $url:tt [$($kind:tt)*]; (request_fn $name:ident) $($rest:tt)*) => {
+        $crate::api!(@cfg [] $attrs {
+            #[doc = concat!("Builds the request sent by [`", stringify!($ident), "`](Self::", stringify!($ident), ") without sending it.")]
+            #[allow(unused_variables)]
+            #[inline]
+            $vis async fn $name $($generics)* (&$this, $($params)*) -> $crate::Result<$crate::reqwest::RequestBuilder> $($bounds)* {
+                $crate::api!(@defaults $($body)*);
+                Ok($crate::api!(@build $ident $this [$($body)*] [$($clauses)*] $method $url $($kind)*))
+            }
+        });
+        $crate::api!(@companions $ident $attrs [$vis] [$($generics)*] [$this] [$($params)*] [$($body)*] [$($clauses)*] [$($bounds)*] $method $url [$($kind)*]; $($rest)*);
+    };
+    (@companions $ident:ident $attrs:tt [$vis:vis] [$($generics:tt)*] [$this:ident] [$($params:tt)*] [$($body:tt)*] [$($clauses:tt)*] [$($bounds:tt)*] $method:tt $url:tt $kind:tt; (url_fn $name:ident) $($rest:tt)*) => {
+        $crate::api!(@cfg [] $attrs {
+            #[doc = concat!("Returns the URL requested by [`", stringify!($ident), "`](Self::", stringify!($ident), ") without sending a request.")]
+            #[allow(unused_variables)]
+            #[inline]
+            #[must_use]
+            $vis fn $name $($generics)* (&$this, $($params)*) -> ::std::string::String $($bounds)* {
+                $crate::api!(@defaults $($body)*);
+                let url = $crate::api!(@resolve $this [$($body)*] [$($clauses)*] $url);
+                let builder = $crate::Api::client($this).get(url.as_str());
+                let builder = $crate::api!(@query builder; $crate::api!(@query_style $($clauses)*); $($clauses)*);
+                $crate::query::url(builder, url)
+            }
+        });
+        $crate::api!(@companions $ident $attrs [$vis] [$($generics)*] [$this] [$($params)*] [$($body)*] [$($clauses)*] [$($bounds)*] $method $url $kind; $($rest)*);
+    };
+    (@companions $ident:ident $attrs:tt $vis:tt $generics:tt $this:tt $params:tt $body:tt $clauses:tt $bounds:tt $method:tt $url:tt $kind:tt; $clause:tt $($rest:tt)*) => {
+        $crate::api!(@companions $ident $attrs $vis $generics $this $params $body $clauses $bounds $method $url $kind; $($rest)*);
+    };
+
+    (@expect $response:expr; [StatusCode] [];) => { $response };
+    (@expect $response:expr; [Headers] [];) => { $response };
+    (@expect $response:expr; [(error_body)] [];) => { $response };
+    (@expect $response:expr; $kind:tt [];) => { $crate::decode::expect_status($response, &[100..=399]).await? };
+    (@expect $response:expr; $kind:tt [$($range:tt)+];) => { $crate::decode::expect_status($response, &[$($range),+]).await? };
+    (@expect $response:expr; $kind:tt [$($range:tt)*]; (expect $expected:tt) $($clauses:tt)*) => {
+        $crate::api!(@expect $response; $kind [$($range)* $expected]; $($clauses)*)
+    };
+    (@expect $response:expr; $kind:tt $ranges:tt; (error_body $err:ty) $($clauses:tt)*) => {
+        $crate::api!(@expect $response; [(error_body)] $ranges; $($clauses)*)
+    };
+    (@expect $response:expr; $kind:tt $ranges:tt; $clause:tt $($clauses:tt)*) => {
+        $crate::api!(@expect $response; $kind $ranges; $($clauses)*)
+    };
+
+    (@prepare $this:ident [$ident:ident $url:tt $method:ident] $builder:expr; [$($endpoint:tt)*]) => {{
+        let endpoint = $crate::EndpointInfo::new(stringify!($ident), $method, $crate::api!(@doc_url $url), $crate::api!(@header_names []; $($endpoint)*));
+        $crate::request::prepare($this, &endpoint, &$crate::api!(@context_fn $this; $($endpoint)*), $builder).await?
+    }};
+    (@prepare $this:ident $endpoint:tt $builder:expr; $all:tt (no_pre_request) $($clauses:tt)*) => { $builder };
+    (@prepare $this:ident $endpoint:tt $builder:expr; $all:tt (auth $scheme:ident) $($clauses:tt)*) => {
+        $this.auth_for(stringify!($scheme), $builder).await?
+    };
+    (@prepare $this:ident $endpoint:tt $builder:expr; $all:tt $clause:tt $($clauses:tt)*) => {
+        $crate::api!(@prepare $this $endpoint $builder; $all $($clauses)*)
+    };
+
+    (@exchange [$($endpoint:tt)*] [$($args:tt)*] $context:expr;) => {
+        $crate::request::exchange($($endpoint)* &$context, $($args)*)
+    };
+    (@exchange [$($endpoint:tt)*] [$($args:tt)*] $context:expr; (no_pre_request) $($clauses:tt)*) => {
+        $crate::request::exchange_once($($endpoint)* $($args)*)
+    };
+    (@exchange [$($endpoint:tt)*] [$($args:tt)*] $context:expr; (auth $scheme:ident) $($clauses:tt)*) => {
+        $crate::request::exchange_once($($endpoint)* $($args)*)
+    };
+    (@exchange $endpoint:tt $args:tt $context:expr; $clause:tt $($clauses:tt)*) => {
+        $crate::api!(@exchange $endpoint $args $context; $($clauses)*)
+    };
+
+    (@context_fn $this:ident; $($clauses:tt)*) => {
+        |builder: $crate::reqwest::RequestBuilder| -> $crate::Result<$crate::reqwest::RequestBuilder> {
+            ::core::result::Result::Ok($crate::api!(@context $this builder; $($clauses)*))
+        }
+    };
+
+    (@context $this:ident $builder:ident;) => { $builder };
+    (@context $this:ident $builder:ident; (context $context:ident) $($clauses:tt)*) => {
+        $crate::PreRequestWith::pre_request_with($this, $context, $builder)?
+    };
+    (@context $this:ident $builder:ident; $clause:tt $($clauses:tt)*) => { $crate::api!(@context $this $builder; $($clauses)*) };
+
+    (@defaults) => {};
+    (@defaults (default $name:ident ($default:expr) $ty:ty) $($body:tt)*) => {
+        let $name: $ty = ::core::convert::Into::<::core::option::Option<$ty>>::into($name).unwrap_or_else(|| $default);
+        $crate::api!(@defaults $($body)*);
+    };
+    (@defaults $entry:tt $($body:tt)*) => { $crate::api!(@defaults $($body)*); };
+
+    (@body [];) => { $crate::Body::<()>::None };
+    (@body [Multipart $request:ident];) => { $crate::Body::<()>::Multipart($request) };
+    (@body [$kind:ident $request:ident];) => { $crate::Body::$kind($request) };
+    (@body $found:tt; (param $name:ident) $($body:tt)*) => { $crate::api!(@body $found; $($body)*) };
+    (@body $found:tt; (query $name:ident) $($body:tt)*) => { $crate::api!(@body $found; $($body)*) };
+    (@body $found:tt; (default $($entry:tt)*) $($body:tt)*) => { $crate::api!(@body $found; $($body)*) };
+    (@body []; (json $request:ident) $($body:tt)*) => { $crate::api!(@body [Json $request]; $($body)*) };
+    (@body []; (form $request:ident) $($body:tt)*) => { $crate::api!(@body [Form $request]; $($body)*) };
+    (@body []; (multipart $request:ident) $($body:tt)*) => { $crate::api!(@body [Multipart $request]; $($body)*) };
+    (@body $found:tt; ($kind:ident $request:ident) $($body:tt)*) => {
+        compile_error!(concat!("`", stringify!($request), "` is a second request body; an endpoint can only send one"))
+    };
+
+    (@query_params $builder:expr;) => { $builder };
+    (@query_params $builder:expr; (query $query:ident) $($body:tt)*) => {
+        $crate::api!(@query_params $builder.query($query); $($body)*)
+    };
+    (@query_params $builder:expr; $entry:tt $($body:tt)*) => { $crate::api!(@query_params $builder; $($body)*) };
+
+    (@headers $defaults:ident $builder:expr;) => { $builder };
+    (@headers $defaults:ident $builder:expr; (header CONTENT_TYPE $headervalue:tt) $($clauses:tt)*) => {
+        $crate::api!(@headers $defaults $crate::api!(@content_type $builder; $headervalue)?; $($clauses)*)
+    };
+    (@headers $defaults:ident $builder:expr; (header "content-type" $headervalue:tt) $($clauses:tt)*) => {
+        $crate::api!(@headers $defaults $crate::api!(@content_type $builder; $headervalue)?; $($clauses)*)
+    };
+    (@headers $defaults:ident $builder:expr; (header "Content-Type" $headervalue:tt) $($clauses:tt)*) => {
+        $crate::api!(@headers $defaults $crate::api!(@content_type $builder; $headervalue)?; $($clauses)*)
+    };
+    (@headers $defaults:ident $builder:expr; (optional_header CONTENT_TYPE ($headervalue:expr)) $($clauses:tt)*) => {
+        $crate::api!(@optional_content_type $defaults $builder; $headervalue; $($clauses)*)
+    };
+    (@headers $defaults:ident $builder:expr; (optional_header "content-type" ($headervalue:expr)) $($clauses:tt)*) => {
+        $crate::api!(@optional_content_type $defaults $builder; $headervalue; $($clauses)*)
+    };
+    (@headers $defaults:ident $builder:expr; (optional_header "Content-Type" ($headervalue:expr)) $($clauses:tt)*) => {
+        $crate::api!(@optional_content_type $defaults $builder; $headervalue; $($clauses)*)
+    };
+    (@headers $defaults:ident $builder:expr; (header $headername:tt $headervalue:literal) $($clauses:tt)*) => {
+        $crate::api!(@headers $defaults $crate::header::add($builder, &$defaults, $crate::api!(@header_name $headername), format!($headervalue).as_str())?; $($clauses)*)
+    };
+    (@headers $defaults:ident $builder:expr; (header $headername:tt ($headervalue:expr)) $($clauses:tt)*) => {
+        $crate::api!(@headers $defaults $crate::header::add($builder, &$defaults, $crate::api!(@header_name $headername), $headervalue)?; $($clauses)*)
+    };
+    (@headers $defaults:ident $builder:expr; (optional_header $headername:tt ($headervalue:expr)) $($clauses:tt)*) => {
+        $crate::api!(@headers $defaults match $headervalue {
+            Some(value) => $crate::header::add($builder, &$defaults, $crate::api!(@header_name $headername), value)?,
+            None => $builder,
+        }; $($clauses)*)
+    };
+    (@headers $defaults:ident $builder:expr; $clause:tt $($clauses:tt)*) => {
+        $crate::api!(@headers $defaults $builder; $($clauses)*)
+    };
+
+    (@cookies $builder:expr; [];) => { $builder };
+    (@cookies $builder:expr; [$(($name:tt $value:tt))+];) => {{
+        let mut cookie = ::std::string::String::new();
+        $($crate::cookie::push(&mut cookie, $crate::api!(@cookie_name $name), $crate::api!(@cookie_value $value));)+
+        $builder.header($crate::reqwest::header::COOKIE, cookie)
+    }};
+    (@cookies $builder:expr; [$($cookies:tt)*]; (cookie $name:tt $value:tt) $($clauses:tt)*) => {
+        $crate::api!(@cookies $builder; [$($cookies)* ($name $value)]; $($clauses)*)
+    };
+    (@cookies $builder:expr; $cookies:tt; $clause:tt $($clauses:tt)*) => {
+        $crate::api!(@cookies $builder; $cookies; $($clauses)*)
+    };
+
+    (@cookie_name $name:literal) => { $name };
+    (@cookie_name $name:ident) => { stringify!($name) };
+
+    (@cookie_value $value:literal) => { format_args!($value) };
+    (@cookie_value ($value:expr)) => { $value };
+
+    (@basic_auth $builder:expr;) => { $builder };
+    (@basic_auth $builder:expr; (basic_auth ($user:expr) ($password:expr)) $($clauses:tt)*) => {
+        $crate::api!(@basic_auth $crate::header::basic_auth($builder, $user, $password)?; $($clauses)*)
+    };
+    (@basic_auth $builder:expr; $clause:tt $($clauses:tt)*) => {
+        $crate::api!(@basic_auth $builder; $($clauses)*)
+    };
+
+    (@accept $defaults:ident $builder:expr; $kind:tt;) => {
+        $crate::header::add($builder, &$defaults, $crate::reqwest::header::ACCEPT, $crate::api!(@accept_type $kind))?
+    };
+    (@accept $defaults:ident $builder:expr; $kind:tt; (header ACCEPT $headervalue:tt) $($clauses:tt)*) => { $builder };
+    (@accept $defaults:ident $builder:expr; $kind:tt; (header "accept" $headervalue:tt) $($clauses:tt)*) => { $builder };
+    (@accept $defaults:ident $builder:expr; $kind:tt; (header "Accept" $headervalue:tt) $($clauses:tt)*) => { $builder };
+    (@accept $defaults:ident $builder:expr; $kind:tt; (optional_header ACCEPT $headervalue:tt) $($clauses:tt)*) => { $builder };
+    (@accept $defaults:ident $builder:expr; $kind:tt; (optional_header "accept" $headervalue:tt) $($clauses:tt)*) => { $builder };
+    (@accept $defaults:ident $builder:expr; $kind:tt; (optional_header "Accept" $headervalue:tt) $($clauses:tt)*) => { $builder };
+    (@accept $defaults:ident $builder:expr; $kind:tt; $clause:tt $($clauses:tt)*) => { $crate::api!(@accept $defaults $builder; $kind; $($clauses)*) };
+
+    (@accept_type [Json $($res:tt)*]) => { "application/json" };
+    (@accept_type [Value]) => { "application/json" };
+    (@accept_type [JsonBytes]) => { "application/json" };
+    (@accept_type [Created $($res:tt)*]) => { "application/json" };
+    (@accept_type [Lines $($res:tt)*]) => { "application/x-ndjson" };
+    (@accept_type [Events]) => { "text/event-stream" };
+    (@accept_type [Sse $($res:tt)*]) => { "text/event-stream" };
+    (@accept_type [MsgPack $($res:tt)*]) => { "application/msgpack" };
+    (@accept_type [Cbor $($res:tt)*]) => { "application/cbor" };
+    (@accept_type [Yaml $($res:tt)*]) => { "application/yaml" };
+    (@accept_type [Csv $($res:tt)*]) => { "text/csv" };
+    (@accept_type [String]) => { "text/plain, */*;q=0.8" };
+    (@accept_type [Text]) => { "text/plain, */*;q=0.8" };
+    (@accept_type $kind:tt) => { "*/*" };
+
+    (@content_type $builder:expr; $headervalue:literal) => {
+        $crate::header::replace($builder, $crate::reqwest::header::CONTENT_TYPE, format!($headervalue).as_str())
+    };
+    (@content_type $builder:expr; ($headervalue:expr)) => {
+        $crate::header::replace($builder, $crate::reqwest::header::CONTENT_TYPE, $headervalue)
+    };
+
+    (@optional_content_type $defaults:ident $builder:expr; $headervalue:expr; $($clauses:tt)*) => {
+        $crate::api!(@headers $defaults match $headervalue {
+            Some(value) => $crate::header::replace($builder, $crate::reqwest::header::CONTENT_TYPE, value)?,
+            None => $builder,
+        }; $($clauses)*)
+    };
+
+    (@timeout $builder:expr;) => { $builder };
+    (@timeout $builder:expr; (timeout ($timeout:expr)) $($clauses:tt)*) => { $builder.timeout($timeout) };
+    (@timeout $builder:expr; $clause:tt $($clauses:tt)*) => { $crate::api!(@timeout $builder; $($clauses)*) };
+
+    (@method $method:ident) => { $crate::reqwest::Method::$method };
+    (@method { $method:expr }) => { $method };
+    (@method $method:literal) => {{
+        const _: () = assert!($crate::method::is_valid($method), concat!("invalid HTTP method `", $method, "`"));
+        $crate::method::parse($method)
+    }};
+
+    (@doc $method:tt $url:tt [$($headers:tt)*];) => {
+        concat!("`", $crate::api!(@doc_token $method), " ", $crate::api!(@doc_url $url), "`", $($headers)*)
+    };
+    (@doc $method:tt $url:tt [$($headers:tt)*]; (header $headername:tt $headervalue:tt) $($clauses:tt)*) => {
+        $crate::api!(@doc $method $url [$($headers)* "\n- `", $crate::api!(@doc_token $headername), ": ", $crate::api!(@doc_token $headervalue), "`",]; $($clauses)*)
+    };
+    (@doc $method:tt $url:tt [$($headers:tt)*]; (optional_header $headername:tt $headervalue:tt) $($clauses:tt)*) => {
+        $crate::api!(@doc $method $url [$($headers)* "\n- `", $crate::api!(@doc_token $headername), "?: ", $crate::api!(@doc_token $headervalue), "`",]; $($clauses)*)
+    };
+    (@doc $method:tt $url:tt [$($headers:tt)*]; (cookie $name:tt $value:tt) $($clauses:tt)*) => {
+        $crate::api!(@doc $method $url [$($headers)* "\n- `cookie ", $crate::api!(@doc_token $name), ": ", $crate::api!(@doc_token $value), "`",]; $($clauses)*)
+    };
+    (@doc $method:tt $url:tt $headers:tt; $clause:tt $($clauses:tt)*) => {
+        $crate::api!(@doc $method $url $headers; $($clauses)*)
+    };
+
+    (@doc_url [$($url:literal)+]) => { concat!($($url),+) };
+    (@doc_url $url:literal) => { $url };
+
+    (@doc_token $token:literal) => { $token };
+    (@doc_token { $token:expr }) => { concat!("{", stringify!($token), "}") };
+    (@doc_token ($token:expr)) => { stringify!($token) };
+    (@doc_token $token:tt) => { stringify!($token) };
+
+    (@header_names [$($names:tt)*];) => { &[$($crate::api!(@doc_token $names)),*] };
+    (@header_names [$($names:tt)*]; (header $headername:tt $headervalue:tt) $($clauses:tt)*) => {
+        $crate::api!(@header_names [$($names)* $headername]; $($clauses)*)
+    };
+    (@header_names [$($names:tt)*]; (optional_header $headername:tt $headervalue:tt) $($clauses:tt)*) => {
+        $crate::api!(@header_names [$($names)* $headername]; $($clauses)*)
+    };
+    (@header_names $names:tt; $clause:tt $($clauses:tt)*) => { $crate::api!(@header_names $names; $($clauses)*) };
+
+    (@const_method $method:ident) => { $crate::reqwest::Method::$method };
+    (@const_method { $method:expr }) => { $method };
+    (@const_method $method:literal) => {
+        compile_error!(concat!(
+            "the method `", $method, "` can't be listed in an endpoint constant; declare it as an identifier such as ",
+            "`GET`, or as a constant expression in braces",
+        ))
+    };
+
+    (@header_name $headername:literal) => { $crate::header::name($headername)? };
+    (@header_name $headername:tt) => { $headername };
+
+    (@url $url:tt [$($clauses:tt)*];) => { $crate::api!(@raw $url; $($clauses)*) };
+    (@url $url:tt [$($clauses:tt)*]; (param $name:ident) $($body:tt)*) => {{
+        #[allow(unused_variables)]
+        let $name = $crate::url::Param(&$name);
+        $crate::api!(@url $url [$($clauses)*]; $($body)*)
+    }};
+    (@url $url:tt $clauses:tt; $entry:tt $($body:tt)*) => { $crate::api!(@url $url $clauses; $($body)*) };
+
+    (@raw [$($url:literal)+];) => { [$(format!($url)),+].concat() };
+    (@raw $url:tt;) => { format!($url) };
+    (@raw $url:tt; (raw $name:ident) $($clauses:tt)*) => {{
+        #[allow(unused_variables)]
+        let $name = $name.0;
+        $crate::api!(@raw $url; $($clauses)*)
+    }};
+    (@raw $url:tt; $clause:tt $($clauses:tt)*) => { $crate::api!(@raw $url; $($clauses)*) };
+
+    (@query $url:expr; $style:expr;) => { $url };
+    (@query $url:expr; $style:expr; (query $key:tt $value:literal) $($clauses:tt)*) => {
+        $crate::api!(@query $crate::query::append($url, $crate::api!(@key $key), &format!($value), $style); $style; $($clauses)*)
+    };
+    (@query $url:expr; $style:expr; (query $key:tt $value:ident) $($clauses:tt)*) => {
+        $crate::api!(@query $crate::query::append($url, $crate::api!(@key $key), &$value, $style); $style; $($clauses)*)
+    };
+    (@query $url:expr; $style:expr; $clause:tt $($clauses:tt)*) => {
+        $crate::api!(@query $url; $style; $($clauses)*)
+    };
+
+    (@query_style) => { $crate::QueryStyle::Repeat };
+    (@query_style (query_style $style:ident) $($clauses:tt)*) => { $crate::QueryStyle::$style };
+    (@query_style $clause:tt $($clauses:tt)*) => { $crate::api!(@query_style $($clauses)*) };
+
+    (@key $key:ident) => { stringify!($key) };
+    (@key $key:literal) => { $key };
+
+    (@budget $api:ident;) => {};
+    (@budget $api:ident; (budget $budget:literal) $($clauses:tt)*) => {
+        if let Some(budget) = $crate::Api::budget($api, $budget) {
+            budget.acquire().await?;
+        }
+        $crate::api!(@budget $api; $($clauses)*);
+    };
+    (@budget $api:ident; $clause:tt $($clauses:tt)*) => {
+        $crate::api!(@budget $api; $($clauses)*);
+    };
+
+    (@examples []) => {};
+
+    (@examples [$(($ident:ident, $attrs:tt, $res:ty, [$($example:literal)+]))+]) => {
+        /// Checks that every response example declared in this block deserializes into its endpoint's response type.
+        ///
+        /// Example paths are resolved relative to the crate root.
+        ///
+        /// # Errors
+        /// Returns every example that could not be read or deserialized.
+        #[allow(dead_code)]
+        pub fn verify_examples() -> ::core::result::Result<(), ::std::vec::Vec<$crate::examples::ExampleError>> {
+            let mut errors = ::std::vec::Vec::new();
+            $($crate::api!(@cfg [] $attrs {{
+                $crate::examples::verify::<$res>(&mut errors, stringify!($ident), env!("CARGO_MANIFEST_DIR"), &[$($example),+]);
+            }});)+
+            if errors.is_empty() {
+                Ok(())
+            } else {
+                Err(errors)
+            }
+        }
+    };
+
+    (@examples [$($examples:tt)*] ($ident:ident, $attrs:tt, $kind:ident $(<$res:ty>)?, [$($example:literal)*] [(example $next:literal) $($clauses:tt)*]) $($rest:tt)*) => {
+        $crate::api!(@examples [$($examples)*] ($ident, $attrs, $kind $(<$res>)?, [$($example)* $next] [$($clauses)*]) $($rest)*);
+    };
+
+    (@examples [$($examples:tt)*] ($ident:ident, $attrs:tt, $kind:ident $(<$res:ty>)?, [$($example:literal)*] [$clause:tt $($clauses:tt)*]) $($rest:tt)*) => {
+        $crate::api!(@examples [$($examples)*] ($ident, $attrs, $kind $(<$res>)?, [$($example)*] [$($clauses)*]) $($rest)*);
+    };
+
+    (@examples [$($examples:tt)*] ($ident:ident, $attrs:tt, $kind:ident $(<$res:ty>)?, [] []) $($rest:tt)*) => {
+        $crate::api!(@examples [$($examples)*] $($rest)*);
+    };
+
+    (@examples [$($examples:tt)*] ($ident:ident, $attrs:tt, Json<$res:ty>, [$($example:literal)+] []) $($rest:tt)*) => {
+        $crate::api!(@examples [$($examples)* ($ident, $attrs, $res, [$($example)+])] $($rest)*);
+    };
+
+    (@examples [$($examples:tt)*] ($ident:ident, $attrs:tt, $kind:ident $(<$res:ty>)?, [$($example:literal)+] []) $($rest:tt)*) => {
+        compile_error!(concat!("response examples on `", stringify!($ident), "` are only supported for Json<T> return kinds"));
+    };
+
+    (@examples [$($examples:tt)*] ($ident:ident, $attrs:tt, $kind:ident $(<$res:ty>)?, [$($clauses:tt)*]) $($rest:tt)*) => {
+        $crate::api!(@examples [$($examples)*] ($ident, $attrs, $kind $(<$res>)?, [] [$($clauses)*]) $($rest)*);
+    };
+
+    (@cfg [$($cfg:tt)*] [#[cfg($($predicate:tt)*)] $($attrs:tt)*] { $($body:tt)* }) => {
+        $crate::api!(@cfg [$($cfg)* ($($predicate)*)] [$($attrs)*] { $($body)* })
+    };
+    (@cfg $cfg:tt [#[$($attr:tt)*] $($attrs:tt)*] { $($body:tt)* }) => {
+        $crate::api!(@cfg $cfg [$($attrs)*] { $($body)* })
+    };
+    (@cfg [$(($($predicate:tt)*))*] [] { $($body:tt)* }) => {
+        $(#[cfg($($predicate)*)])*
+        $($body)*
+    };
+
+    ($($tokens:tt)*) => {
+        compile_error!(concat!(
+            "expected `struct Name`, `struct Name, base = \"url\"`, `struct Name { option = value }`, `struct Name with scheme`, ",
+            "`struct Name { fields }`, ",
+            "`trait Name for Struct { endpoints }` or endpoint declarations starting with `fn`, `group` or `impl`, found `",
+            stringify!($($tokens)*), "`",
+        ));
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    #![allow(unused)]
+
+    #[cfg(feature = "json")]
+    use example::{CreateTodo, JsonPlaceholder, Todo, UpdateTodo};
+
+    use self::headers::HeaderTest;
+
+    /// A minimal HTTP/1.1 server so tests can run without network access.
+    mod server {
+        use std::{fmt::Write as _, time::Duration};
+
+        use tokio::{
+            io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader},
+            net::TcpListener,
+            task::JoinHandle,
+        };
+
+        /// A request received by the server.
+        #[derive(Debug)]
+        pub struct Request {
+            pub method: String,
+            pub path: String,
+            pub headers: Vec<(String, String)>,
+            pub body: Vec<u8>,
+        }
+
+        impl Request {
+            pub fn header(&self, name: &str) -> Option<&str> {
+                self.headers
+                    .iter()
+                    .find(|(key, _)| key.eq_ignore_ascii_case(name))
+                    .map(|(_, value)| value.as_str())
+            }
+        }
+
+        /// A canned response sent back by the server.
+        pub struct Response {
+            status: u16,
+            headers: Vec<(String, String)>,
+            body: Vec<Vec<u8>>,
+            length: Option<usize>,
+            unframed: bool,
+        }
+
+        impl Response {
+            pub fn new(status: u16) -> Self {
+                Response {
+                    status,
+                    headers: Vec::new(),
+                    body: Vec::new(),
+                    length: None,
+                    unframed: false,
+                }
+            }
+
+            pub fn header(mut self, name: &str, value: &str) -> Self {
+                self.headers.push((name.to_string(), value.to_string()));
+                self
+            }
+
+            pub fn body(mut self, body: impl Into<Vec<u8>>) -> Self {
+                self.body = vec![body.into()];
+                self
+            }
+
+            /// Sends the body in separate writes, pausing between them so the client sees separate chunks.
+            pub fn body_parts<T: Into<Vec<u8>>>(
+                mut self,
+                parts: impl IntoIterator<Item = T>,
+            ) -> Self {
+                self.body = parts.into_iter().map(Into::into).collect();
+                self
+            }
+
+            /// Leaves out the `content-length` header, so the body ends when the connection closes.
+            pub fn unframed(mut self) -> Self {
+                self.unframed = true;
+                self
+            }
+
+            /// Overrides the `content-length` header, such as to simulate a truncated body.
+            pub fn length(mut self, length: usize) -> Self {
+                self.length = Some(length);
+                self
+            }
+        }
+
+        /// Serves `responses` in order, one per connection.
+        ///
+        /// Returns the base url of the server and a handle resolving to the received requests.
+        pub async fn serve(responses: Vec<Response>) -> (String, JoinHandle<Vec<Request>>) {
+            let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let url = format!("http://{}", listener.local_addr().unwrap());
+
+            let handle = tokio::spawn(async move {
+                let mut requests = Vec::new();
+                for response in responses {
+                    let (stream, _) = listener.accept().await.unwrap();
+                    let mut stream = BufReader::new(stream);
+
+                    let mut line = String::new();
+                    stream.read_line(&mut line).await.unwrap();
+                    let mut parts = line.split_whitespace();
+                    let method = parts.next().unwrap().to_string();
+                    let path = parts.next().unwrap().to_string();
+
+                    let mut headers = Vec::new();
+                    loop {
+                        line.clear();
+                        stream.read_line(&mut line).await.unwrap();
+                        let line = line.trim_end();
+                        if line.is_empty() {
+                            break;
+                        }
+                        let (name, value) = line.split_once(':').unwrap();
+                        headers.push((name.trim().to_string(), value.trim().to_string()));
+                    }
+
+                    let mut request = Request {
+                        method,
+                        path,
+                        headers,
+                        body: Vec::new(),
+                    };
+                    let length = request
+                        .header("content-length")
+                        .map_or(0, |length| length.parse().unwrap());
+                    request.body.resize(length, 0);
+                    stream.read_exact(&mut request.body).await.unwrap();
+
+                    let mut head =
+                        format!("HTTP/1.1 {} Test\r\nconnection: close\r\n", response.status);
+                    if !response.unframed {
+                        write!(
+                            head,
+                            "content-length: {}\r\n",
+                            response.length.unwrap_or_else(|| response
+                                .body
+                                .iter()
+                                .map(Vec::len)
+                                .sum())
+                        )
+                        .unwrap();
+                    }
+                    for (name, value) in &response.headers {
+                        write!(head, "{name}: {value}\r\n").unwrap();
+                    }
+                    head.push_str("\r\n");
+                    stream.write_all(head.as_bytes()).await.unwrap();
+                    for (index, part) in response.body.iter().enumerate() {
+                        if index > 0 {
+                            tokio::time::sleep(Duration::from_millis(20)).await;
+                        }
+                        stream.write_all(part).await.unwrap();
+                        stream.flush().await.unwrap();
+                    }
+
+                    requests.push(request);
+                }
+                requests
+            });
+
+            (url, handle)
+        }
+    }
+
+    #[cfg(feature = "json")]
+    mod example {
+        use crate::{api, Api};
+
+        pub use models::*;
+
+        mod models {
+            use serde::{Deserialize, Serialize};
+
+            #[derive(Debug, PartialEq, Eq, Deserialize, Serialize)]
+            pub struct Todo {
+                #[serde(rename = "userId")]
+                pub user_id: u32,
+                pub id: u32,
+                pub title: String,
+                pub completed: bool,
+            }
+
+            #[derive(Debug, Serialize)]
+            pub struct CreateTodo {
+                #[serde(rename = "userId")]
+                pub user_id: u32,
+                pub title: String,
+                pub completed: bool,
+            }
+
+            #[derive(Debug, Default, Serialize)]
+            pub struct UpdateTodo {
+                #[serde(rename = "userId", skip_serializing_if = "Option::is_none")]
+                pub user_id: Option<u32>,
+                #[serde(skip_serializing_if = "Option::is_none")]
+                pub title: Option<String>,
+                #[serde(skip_serializing_if = "Option::is_none")]
+                pub completed: Option<bool>,
+            }
+        }
+
+        api!(pub struct JsonPlaceholder);
+
+        const BASE_URL: &str = "https://jsonplaceholder.typicode.com";
+
+        impl JsonPlaceholder {
+            pub fn new() -> Self {
+                Api::new()
+            }
+
+            api! {
+                pub fn todos() -> Json<Vec<Todo>> {
+                    GET "{BASE_URL}/todos"
+                }
+
+                pub fn todo(id: u32) -> Json<Todo> {
+                    GET "{BASE_URL}/todos/{id}"
+                }
+
+                pub fn create_todo(request: Json<CreateTodo>) -> Json<Todo> {
+                    POST "{BASE_URL}/todos"
+                }
+
+                pub fn replace_todo(request: Json<Todo>, id: u32) -> Json<Todo> {
+                    PUT "{BASE_URL}/todos/{id}"
+                }
+
+                pub fn update_todo(request: Json<UpdateTodo>, id: u32) -> Json<Todo> {
+                    PATCH "{BASE_URL}/todos/{id}"
+                }
+
+                pub fn delete_todo(id: u32) -> StatusCode {
+                    DELETE "{BASE_URL}/todos/{id}"
+                }
+            }
+        }
+    }
+
+    #[cfg(feature = "json")]
+    #[tokio::test]
+    async fn json_placeholder() {
+        let api = JsonPlaceholder::new();
+
+        let all_todos = api.todos().await.unwrap();
+        let todo_1 = api.todo(1).await.unwrap();
+        assert_eq!(&all_todos[0], &todo_1);
+
+        let new_todo = api
+            .create_todo(&CreateTodo {
+                user_id: 1,
+                title: "test".to_string(),
+                completed: false,
+            })
+            .await
+            .unwrap();
+        assert_eq!(new_todo.id as usize, all_todos.len() + 1);
+
+        let replaced_todo = api
+            .replace_todo(
+                &Todo {
+                    title: "test".to_string(),
+                    completed: true,
+                    ..todo_1
+                },
+                1,
+            )
+            .await
+            .unwrap();
+        assert_eq!(replaced_todo.title, "test");
+        assert!(replaced_todo.completed);
+
+        let updated_todo = api
+            .update_todo(
+                &UpdateTodo {
+                    title: Some("test".to_string()),
+                    completed: Some(true),
+                    ..Default::default()
+                },
+                1,
+            )
+            .await
+            .unwrap();
+        assert_eq!(updated_todo.title, "test");
+        assert!(updated_todo.completed);
+
+        assert!(api.delete_todo(1).await.unwrap().is_success());
+    }
+
+    mod headers {
+        use reqwest::header::USER_AGENT;
+
+        use crate::{api, Api};
+
+        api!(pub struct HeaderTest);
+
+        const BASE_URL: &str = "https://ifconfig.me";
+
+        impl HeaderTest {
+            #[inline]
+            pub fn new() -> Self {
+                Api::new()
+            }
+
+            api! {
+                pub fn get_ua(ua: &str) -> String {
+                    GET "{BASE_URL}/ua"
+                    USER_AGENT: "{ua}"
+                    "X-REQUEST-ID": "0"
+                }
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn example_header() {
+        let api = HeaderTest::new();
+
+        assert_eq!(
+            api.get_ua("Api-client 0.1").await.unwrap(),
+            "Api-client 0.1"
+        );
+    }
+
+    #[cfg(feature = "json")]
+    mod echo {
+        use std::collections::HashMap;
+
+        use reqwest::header::USER_AGENT;
+
+        use crate::{api, Api};
+
+        api!(pub struct Echo);
+
+        const BASE_URL: &str = "http://postman-echo.com";
+
+        #[derive(Debug, serde::Deserialize)]
+        pub struct Response {
+            pub args: HashMap<String, String>,
+            pub headers: HashMap<String, String>,
+            pub url: String,
+        }
+
+        impl Echo {
+            #[inline]
+            pub fn new() -> Self {
+                Api::new()
+            }
+
+            api! {
+                pub fn echo() -> Json<Response> {
+                    GET "{BASE_URL}/get"
+                    USER_AGENT: "api-client/1.0"
+                    "x-request-id": "42"
+                }
+            }
+        }
+
+        #[tokio::test]
+        async fn echo_test() {
+            let api = Echo::new();
+
+            let response = api.echo().await.unwrap();
+
+            assert_eq!(
+                response.headers.get("user-agent").map(String::as_str),
+                Some("api-client/1.0")
+            );
+            assert_eq!(
+                response.headers.get("x-request-id").map(String::as_str),
+                Some("42")
+            );
+        }
+    }
+
+    #[cfg(feature = "msgpack")]
+    mod msgpack {
+        use serde::{Deserialize, Serialize};
+
+        use super::server::{self, Response};
+        use crate::{api, Api, Error};
+
+        #[derive(Debug, PartialEq, Eq, Deserialize, Serialize)]
+        pub struct Point {
+            pub x: i32,
+            pub y: i32,
+        }
+
+        api!(pub struct MsgPackTest);
+
+        impl MsgPackTest {
+            api! {
+                pub fn point(base: &str) -> MsgPack<Point> {
+                    GET "{base}/point"
+                }
+            }
+        }
+
+        #[tokio::test]
+        async fn decodes_response() {
+            let point = Point { x: 1, y: -2 };
+            let body = rmp_serde::to_vec(&point).unwrap();
+            let (base, requests) = server::serve(vec![Response::new(200)
+                .header("content-type", "application/msgpack")
+                .body(body)])
+            .await;
+
+            let api = MsgPackTest::new();
+            assert_eq!(api.point(&base).await.unwrap(), point);
+            assert_eq!(requests.await.unwrap()[0].path, "/point");
+        }
+
+        #[tokio::test]
+        async fn decode_error() {
+            let (base, _) = server::serve(vec![Response::new(200).body("nope")]).await;
+
+            let api = MsgPackTest::new();
+            match api.point(&base).await {
+                Err(Error::Decode { length, .. }) => assert_eq!(length, 4),
+                other => panic!("expected a decode error, got {other:?}"),
+            }
+        }
+    }
+
+    #[cfg(all(feature = "cbor", feature = "json"))]
+    mod cbor {
+        use serde::{Deserialize, Serialize};
+
+        use super::server::{self, Response};
+        use crate::{api, Api, Error};
+
+        #[derive(Debug, PartialEq, Deserialize, Serialize)]
+        pub struct Reading {
+            pub sensor: String,
+            pub value: f64,
+        }
+
+        #[derive(Debug, Serialize)]
+        pub struct Query {
+            pub sensor: String,
+        }
+
+        api!(pub struct CborTest);
+
+        impl CborTest {
+            api! {
+                pub fn reading(request: Json<Query>, base: &str) -> Cbor<Reading> {
+                    POST "{base}/readings"
+                }
+            }
+        }
+
+        #[tokio::test]
+        async fn decodes_response() {
+            let reading = Reading {
+                sensor: "temperature".to_string(),
+                value: 21.5,
+            };
+            let mut body = Vec::new();
+            ciborium::ser::into_writer(&reading, &mut body).unwrap();
+            let (base, requests) = server::serve(vec![Response::new(200)
+                .header("content-type", "application/cbor")
+                .body(body)])
+            .await;
+
+            let api = CborTest::new();
+            let query = Query {
+                sensor: "temperature".to_string(),
+            };
+            assert_eq!(api.reading(&query, &base).await.unwrap(), reading);
+            assert_eq!(
+                requests.await.unwrap()[0].body,
+                br#"{"sensor":"temperature"}"#
+            );
+        }
+
+        #[tokio::test]
+        async fn decode_error() {
+            let (base, _) = server::serve(vec![Response::new(200).body(vec![0xff])]).await;
+
+            let api = CborTest::new();
+            let query = Query {
+                sensor: "temperature".to_string(),
+            };
+            match api.reading(&query, &base).await {
+                Err(Error::Decode { length, .. }) => assert_eq!(length, 1),
+                other => panic!("expected a decode error, got {other:?}"),
+            }
+        }
+    }
+
+    #[cfg(all(feature = "examples", feature = "json"))]
+    mod examples {
+        use super::example::Todo;
+        use crate::{api, Api};
+
+        api!(pub struct ValidExamples);
+
+        impl ValidExamples {
+            api! {
+                pub fn todos() -> Json<Vec<Todo>> {
+                    GET "https://example.com/todos"
+                    example = "tests/examples/todos.json"
+                }
+
+                pub fn todo(id: u32) -> Json<Todo> {
+                    GET "https://example.com/todos/{id}"
+                    example = "tests/examples/todo.json"
+                }
+
+                pub fn delete_todo(id: u32) -> StatusCode {
+                    DELETE "https://example.com/todos/{id}"
+                }
+
+                #[cfg(any())]
+                pub fn archived() -> Json<Archived> {
+                    GET "https://example.com/archived"
+                    example = "tests/examples/todo_invalid.json"
+                }
+            }
+        }
+
+        api!(pub struct InvalidExamples);
+
+        impl InvalidExamples {
+            api! {
+                pub fn todo(id: u32) -> Json<Todo> {
+                    GET "https://example.com/todos/{id}"
+                    example = "tests/examples/todo.json"
+                    example = "tests/examples/todo_invalid.json"
+                    example = "tests/examples/missing.json"
+                }
+            }
+        }
+
+        #[test]
+        fn valid_examples() {
+            ValidExamples::verify_examples().unwrap();
+        }
+
+        #[test]
+        fn invalid_examples() {
+            let errors = InvalidExamples::verify_examples().unwrap_err();
+            assert_eq!(errors.len(), 2);
+
+            assert_eq!(errors[0].endpoint, "todo");
+            assert_eq!(errors[0].file, "tests/examples/todo_invalid.json");
+            assert_eq!(errors[0].path, "id");
+
+            assert_eq!(errors[1].file, "tests/examples/missing.json");
+            assert!(errors[1].source.is::<std::io::Error>());
+        }
+    }
+
+    mod budget {
+        use std::{sync::Arc, time::Duration};
+
+        use reqwest::Client;
+
+        use super::server::{self, Response};
+        use crate::{api, Api, Budget, BudgetPolicy, BudgetStore, Error, MemoryBudgetStore};
+
+        struct Metered {
+            client: Client,
+            reports: Budget,
+        }
+
+        impl Api for Metered {
+            fn client(&self) -> &Client {
+                &self.client
+            }
+
+            fn budget(&self, name: &str) -> Option<&Budget> {
+                (name == "reports").then_some(&self.reports)
+            }
+        }
+
+        impl Metered {
+            fn new(reports: Budget) -> Self {
+                Metered {
+                    client: Client::new(),
+                    reports,
+                }
+            }
+
+            api! {
+                fn report(base: &str) -> StatusCode {
+                    GET "{base}/report"
+                    budget = "reports"
+                }
+
+                fn free(base: &str) -> StatusCode {
+                    GET "{base}/free"
+                }
+            }
+        }
+
+        #[tokio::test]
+        async fn exhausted_budget_errors() {
+            let (base, requests) = server::serve(vec![
+                Response::new(200),
+                Response::new(200),
+                Response::new(200),
+            ])
+            .await;
+            let api = Metered::new(Budget::new("reports", 2, Duration::from_secs(3600)));
+
+            api.report(&base).await.unwrap();
+            api.report(&base).await.unwrap();
+            assert_eq!(api.reports.used().await, 2);
+            assert_eq!(api.reports.remaining().await, 0);
+
+            match api.report(&base).await {
+                Err(Error::BudgetExhausted { budget, limit, .. }) => {
+                    assert_eq!(budget, "reports");
+                    assert_eq!(limit, 2);
+                }
+                other => panic!("expected an exhausted budget, got {other:?}"),
+            }
+
+            api.free(&base).await.unwrap();
+            let paths: Vec<_> = requests
+                .await
+                .unwrap()
+                .into_iter()
+                .map(|request| request.path)
+                .collect();
+            assert_eq!(paths, ["/report", "/report", "/free"]);
+        }
+
+        #[tokio::test]
+        async fn warn_policy_continues() {
+            let (base, _) = server::serve(vec![Response::new(200), Response::new(200)]).await;
+            let api = Metered::new(
+                Budget::new("reports", 1, Duration::from_secs(3600)).policy(BudgetPolicy::Warn),
+            );
+
+            api.report(&base).await.unwrap();
+            api.report(&base).await.unwrap();
+            assert_eq!(api.reports.used().await, 2);
+        }
+
+        #[tokio::test]
+        async fn store_survives_restart() {
+            let store = Arc::new(MemoryBudgetStore::new());
+
+            let budget = Budget::new("reports", 2, Duration::from_secs(3600)).store(store.clone());
+            budget.acquire().await.unwrap();
+            budget.acquire().await.unwrap();
+            drop(budget);
+
+            let budget = Budget::new("reports", 2, Duration::from_secs(3600)).store(store.clone());
+            assert_eq!(budget.used().await, 2);
+            assert!(matches!(
+                budget.acquire().await,
+                Err(Error::BudgetExhausted { .. })
+            ));
+            assert!(store.load("other").await.is_empty());
+        }
+
+        #[tokio::test]
+        async fn window_expires_calls() {
+            let budget = Budget::new("reports", 1, Duration::from_millis(10));
+            budget.acquire().await.unwrap();
+            tokio::time::sleep(Duration::from_millis(20)).await;
+            assert_eq!(budget.used().await, 0);
+            budget.acquire().await.unwrap();
+        }
+
+        #[cfg(feature = "json")]
+        #[tokio::test]
+        async fn file_store_keeps_budgets_apart() {
+            use crate::FileBudgetStore;
+
+            let dir =
+                std::env::temp_dir().join(format!("api-client-budget-{}", std::process::id()));
+            std::fs::create_dir_all(&dir).unwrap();
+            let path = dir.join("budgets.json");
+            let store = Arc::new(FileBudgetStore::new(&path));
+            assert!(store.load("reports").await.is_empty());
+
+            let reports = Budget::new("reports", 2, Duration::from_secs(3600)).store(store.clone());
+            let exports = Budget::new("exports", 2, Duration::from_secs(3600)).store(store.clone());
+            let (first, second) = tokio::join!(reports.acquire(), exports.acquire());
+            first.unwrap();
+            second.unwrap();
+            reports.acquire().await.unwrap();
+
+            let reports = Budget::new("reports", 2, Duration::from_secs(3600))
+                .store(FileBudgetStore::new(&path));
+            let exports = Budget::new("exports", 2, Duration::from_secs(3600))
+                .store(FileBudgetStore::new(&path));
+            assert_eq!(reports.used().await, 2);
+            assert_eq!(exports.used().await, 1);
+
+            std::fs::remove_dir_all(&dir).unwrap();
+        }
+    }
+
+    #[cfg(feature = "yaml")]
+    mod yaml {
+        use serde::Deserialize;
+
+        use super::server::{self, Response};
+        use crate::{api, Api, Error};
+
+        #[derive(Debug, PartialEq, Eq, Deserialize)]
+        pub struct Config {
+            pub name: String,
+            pub replicas: u32,
+        }
+
+        api!(pub struct YamlTest);
+
+        impl YamlTest {
+            api! {
+                pub fn config(base: &str) -> Yaml<Config> {
+                    GET "{base}/config"
+                }
+
+                pub fn small_config(base: &str) -> Yaml<Config> {
+                    GET "{base}/config"
+                    limit = 8
+                }
+            }
+        }
+
+        #[tokio::test]
+        async fn decodes_response() {
+            let (base, _) = server::serve(vec![Response::new(200)
+                .header("content-type", "application/yaml")
+                .body("name: web\nreplicas: 3\n")])
+            .await;
+
+            let api = YamlTest::new();
+            assert_eq!(
+                api.config(&base).await.unwrap(),
+                Config {
+                    name: "web".to_string(),
+                    replicas: 3
+                }
+            );
+        }
+
+        #[tokio::test]
+        async fn decode_error_has_location() {
+            let (base, _) =
+                server::serve(vec![Response::new(200).body("name: web\nreplicas: many\n")]).await;
+
+            let api = YamlTest::new();
+            match api.config(&base).await {
+                Err(Error::Decode { source, .. }) => {
+                    let source = source.downcast::<serde_yaml::Error>().unwrap();
+                    assert_eq!(source.location().unwrap().line(), 2);
+                }
+                other => panic!("expected a decode error, got {other:?}"),
+            }
+        }
+
+        #[tokio::test]
+        async fn limited() {
+            let (base, _) = server::serve(vec![Response::new(200)
+                .body("name: web\nreplicas: 3\n")
+                .unframed()])
+            .await;
+
+            let api = YamlTest::new();
+            assert!(matches!(
+                api.small_config(&base).await,
+                Err(Error::BodyTooLarge { limit: 8, .. })
+            ));
+        }
+    }
+
+    #[cfg(feature = "csv")]
+    mod csv {
+        use serde::Deserialize;
+
+        use super::server::{self, Response};
+        use crate::{api, Api, Error};
+
+        #[derive(Debug, PartialEq, Eq, Deserialize)]
+        pub struct Sale {
+            pub region: String,
+            pub total: u32,
+        }
+
+        api!(pub struct CsvTest);
+
+        impl CsvTest {
+            api! {
+                pub fn report(base: &str) -> Csv<Sale> {
+                    GET "{base}/report.csv"
+                }
+
+                pub fn raw_report(base: &str) -> Csv<(String, u32)> {
+                    GET "{base}/raw.csv"
+                    has_headers = false
+                }
+
+                pub fn small_report(base: &str) -> Csv<Sale> {
+                    GET "{base}/report.csv"
+                    limit = 1 KB
+                }
+            }
+        }
+
+        #[tokio::test]
+        async fn decodes_records() {
+            let (base, _) = server::serve(vec![
+                Response::new(200).body("total,region\n10,north\n20,south\n"),
+                Response::new(200).body("north,10\nsouth,20\n"),
+            ])
+            .await;
+
+            let api = CsvTest::new();
+            assert_eq!(
+                api.report(&base).await.unwrap(),
+                [
+                    Sale {
+                        region: "north".to_string(),
+                        total: 10
+                    },
+                    Sale {
+                        region: "south".to_string(),
+                        total: 20
+                    }
+                ]
+            );
+            assert_eq!(
+                api.raw_report(&base).await.unwrap(),
+                [("north".to_string(), 10), ("south".to_string(), 20)]
+            );
+        }
+
+        #[tokio::test]
+        async fn decode_error_has_record() {
+            let (base, _) = server::serve(vec![
+                Response::new(200).body("region,total\nnorth,10\nsouth,lots\n")
+            ])
+            .await;
+
+            let api = CsvTest::new();
+            match api.report(&base).await {
+                Err(Error::Decode { source, .. }) => {
+                    let source = source.downcast::<csv_crate::Error>().unwrap();
+                    assert_eq!(source.position().unwrap().record(), 2);
+                }
+                other => panic!("expected a decode error, got {other:?}"),
+            }
+        }
+
+        #[tokio::test]
+        async fn limited() {
+            let (base, _) = server::serve(vec![
+                Response::new(200).body(format!("region,total\n{}", "north,10\n".repeat(200)))
+            ])
+            .await;
+
+            let api = CsvTest::new();
+            assert!(matches!(
+                api.small_report(&base).await,
+                Err(Error::BodyTooLarge { limit: 1024, .. })
+            ));
+        }
+    }
+
+    mod mocking {
+        use super::server::{self, Response};
+        use crate::api;
+
+        api!(pub struct MockTest);
+
+        api!(
+            /// Reads todos.
+            pub trait Todos for MockTest {
+                /// Returns the title of a todo.
+                fn title(base: &str, id: u32) -> String {
+                    GET "{base}/todos/{id}/title"
+                }
+
+                fn done(&self, base: &str, id: u32) -> StatusCode {
+                    PUT "{base}/todos/{id}/done"
+                }
+            }
+        );
+
+        struct FakeTodos;
+
+        impl Todos for FakeTodos {
+            async fn title(&self, _base: &str, id: u32) -> crate::Result<String> {
+                Ok(format!("todo {id}"))
+            }
+
+            async fn done(&self, _base: &str, _id: u32) -> crate::Result<reqwest::StatusCode> {
+                Ok(reqwest::StatusCode::NO_CONTENT)
+            }
+        }
+
+        async fn first_title(todos: &impl Todos, base: &str) -> String {
+            todos.title(base, 1).await.unwrap()
+        }
+
+        #[tokio::test]
+        async fn fake_and_real() {
+            assert_eq!(first_title(&FakeTodos, "").await, "todo 1");
+
+            let (base, requests) = server::serve(vec![Response::new(200).body("Milk")]).await;
+            assert_eq!(first_title(&MockTest::default(), &base).await, "Milk");
+            assert_eq!(requests.await.unwrap()[0].path, "/todos/1/title");
+        }
+    }
+
+    mod endpoint_list {
+        use reqwest::{header::USER_AGENT, Method};
+
+        use crate::{api, EndpointInfo};
+
+        api!(pub struct EndpointListTest, base = "https://example.com");
+
+        impl EndpointListTest {
+            api! {
+                pub const ENDPOINTS;
+
+                fn todo(id: u32, etag: Option<&str>) -> String {
+                    GET "/todos/{id}"
+                    "x-request-id": "{id}"
+                    USER_AGENT: "example"
+                    "if-none-match"?: etag
+                }
+
+                #[cfg(any())]
+                fn hidden() -> StatusCode {
+                    DELETE "/everything"
+                }
+
+                group "/lists/{list}" (list: u32) {
+                    fn item(id: u32) -> StatusCode {
+                        { Method::PATCH } "/items/{id}"
+                    }
+                }
+            }
+        }
+
+        api!(pub trait Notes for EndpointListTest {
+            const NOTE_ENDPOINTS;
+
+            fn note(id: u32) -> String {
+                GET "/notes/{id}"
+            }
+        });
+
+        fn summary(endpoints: &[EndpointInfo]) -> Vec<(&str, Method, &str, &[&str])> {
+            endpoints
+                .iter()
+                .map(|endpoint| {
+                    (
+                        endpoint.name,
+                        endpoint.method.clone(),
+                        endpoint.url_template,
+                        endpoint.headers,
+                    )
+                })
+                .collect()
+        }
+
+        #[test]
+        fn listed() {
+            assert_eq!(
+                summary(EndpointListTest::ENDPOINTS),
+                [
+                    (
+                        "todo",
+                        Method::GET,
+                        "/todos/{id}",
+                        &["x-request-id", "USER_AGENT", "if-none-match"][..],
+                    ),
+                    ("item", Method::PATCH, "/lists/{list}/items/{id}", &[][..]),
+                ]
+            );
+            assert_eq!(
+                summary(<EndpointListTest as Notes>::NOTE_ENDPOINTS),
+                [("note", Method::GET, "/notes/{id}", &[][..])]
+            );
+        }
+    }
+
+    mod pointers {
+        use std::sync::Arc;
+
+        use super::server::{self, Response};
+        use crate::{api, Api};
+
+        trait Endpoints: Api {
+            api! {
+                fn todo(base: &str) -> StatusCode {
+                    GET "{base}/todo"
+                }
+            }
+        }
+
+        impl<T: Api> Endpoints for T {}
+
+        api!(pub struct PointerTest, base = "http://127.0.0.1:1/v2");
+
+        fn base_url(api: impl Api) -> String {
+            api.base_url().unwrap().to_string()
+        }
+
+        #[tokio::test]
+        async fn forwarded() {
+            let (base, requests) =
+                server::serve(vec![Response::new(200), Response::new(200)]).await;
+
+            let api = PointerTest::builder()
+                .default_header("x-tenant", "acme")
+                .build()
+                .unwrap();
+            assert_eq!(base_url(&api), "http://127.0.0.1:1/v2/");
+
+            let api = Arc::new(api);
+            api.clone().todo(&base).await.unwrap();
+            Box::new(api).todo(&base).await.unwrap();
+            for request in requests.await.unwrap() {
+                assert_eq!(request.header("x-tenant"), Some("acme"));
+            }
+        }
+    }
+
+    mod traits {
+        use reqwest::{Client, RequestBuilder};
+
+        use super::server::{self, Response};
+        use crate::{api, Api};
+
+        trait StatusEndpoints: Api + Sync {
+            api! {
+                fn status(base: &str) -> StatusCode {
+                    GET "{base}/status"
+                }
+            }
+        }
+
+        trait Declared {
+            async fn echo(&self, base: &str, message: &str) -> crate::Result<String>;
+        }
+
+        #[cfg_attr(feature = "send", async_trait::async_trait)]
+        #[cfg_attr(not(feature = "send"), async_trait::async_trait(?Send))]
+        trait Mixed: Api + Sync {
+            async fn base(&self) -> String;
+
+            api! {
+                fn mixed(base: &str) -> StatusCode {
+                    GET "{base}/mixed"
+                }
+            }
+        }
+
+        api!(struct Production);
+
+        struct Sandbox(Client);
+
+        impl Api for Sandbox {
+            fn client(&self) -> &Client {
+                &self.0
+            }
+
+            fn pre_request(&self, request: RequestBuilder) -> reqwest::Result<RequestBuilder> {
+                Ok(request.header("x-sandbox", "true"))
+            }
+        }
+
+        impl StatusEndpoints for Production {}
+        impl StatusEndpoints for Sandbox {}
+
+        #[cfg_attr(feature = "send", async_trait::async_trait)]
+        #[cfg_attr(not(feature = "send"), async_trait::async_trait(?Send))]
+        impl Mixed for Production {
+            async fn base(&self) -> String {
+                String::from("boxed")
+            }
+        }
+
+        impl Declared for Production {
+            api! {
+                fn echo(base: &str, message: &str) -> String {
+                    GET "{base}/echo/{message}"
+                }
+            }
+        }
+
+        #[tokio::test]
+        async fn default_methods() {
+            let (base, requests) =
+                server::serve(vec![Response::new(200), Response::new(204)]).await;
+
+            let production = Production::new();
+            let sandbox = Sandbox(Client::new());
+            assert_eq!(production.status(&base).await.unwrap(), 200);
+            assert_eq!(sandbox.status(&base).await.unwrap(), 204);
+
+            let requests = requests.await.unwrap();
+            assert_eq!(requests[0].header("x-sandbox"), None);
+            assert_eq!(requests[1].header("x-sandbox"), Some("true"));
+        }
+
+        #[tokio::test]
+        async fn trait_impl() {
+            let (base, requests) = server::serve(vec![Response::new(200).body("hello")]).await;
+
+            let production = Production::new();
+            assert_eq!(production.echo(&base, "hello").await.unwrap(), "hello");
+            assert_eq!(requests.await.unwrap()[0].path, "/echo/hello");
+        }
+
+        #[tokio::test]
+        async fn async_trait_default_methods() {
+            let (base, requests) = server::serve(vec![Response::new(204)]).await;
+
+            let production = Production::new();
+            assert_eq!(production.base().await, "boxed");
+            assert_eq!(production.mixed(&base).await.unwrap(), 204);
+            assert_eq!(requests.await.unwrap()[0].path, "/mixed");
+        }
+    }
+
+    #[cfg(feature = "json")]
+    mod value {
+        use super::server::{self, Response};
+        use crate::{api, Api};
+
+        api!(pub struct ValueTest);
+
+        impl ValueTest {
+            api! {
+                pub fn anything(base: &str) -> Value {
+                    GET "{base}/anything"
+                }
+            }
+        }
+
+        #[tokio::test]
+        async fn untyped_json() {
+            let (base, _) = server::serve(vec![Response::new(200)
+                .header("content-type", "application/json")
+                .body(r#"{"id":1,"tags":["a","b"]}"#)])
+            .await;
+
+            let api = ValueTest::new();
+            let value = api.anything(&base).await.unwrap();
+            assert_eq!(value["id"], 1);
+            assert_eq!(value["tags"][1], "b");
+        }
+    }
+
+    mod head {
+        use super::server::{self, Response};
+        use crate::{api, Api};
+
+        api!(pub struct HeadTest);
+
+        impl HeadTest {
+            api! {
+                pub fn probe(base: &str, id: u32) -> Headers {
+                    HEAD "{base}/objects/{id}"
+                }
+            }
+        }
+
+        #[tokio::test]
+        async fn status_and_headers() {
+            let (base, requests) = server::serve(vec![Response::new(200)
+                .header("etag", "\"abc\"")
+                .length(1024)])
+            .await;
+
+            let api = HeadTest::new();
+            let headers = api.probe(&base, 7).await.unwrap();
+            assert_eq!(headers.status, 200);
+            assert_eq!(headers.headers["etag"], "\"abc\"");
+            assert_eq!(headers.headers["content-length"], "1024");
+
+            let requests = requests.await.unwrap();
+            assert_eq!(requests[0].method, "HEAD");
+            assert_eq!(requests[0].path, "/objects/7");
+        }
+    }
+
+    #[cfg(feature = "json")]
+    mod created {
+        use serde::{Deserialize, Serialize};
+
+        use super::server::{self, Response};
+        use crate::{api, Api, Error};
+
+        #[derive(Serialize)]
+        pub struct NewWidget {
+            name: String,
+        }
+
+        #[derive(Debug, Deserialize, PartialEq)]
+        pub struct Widget {
+            id: u32,
+        }
+
+        api!(pub struct CreatedTest);
+
+        impl CreatedTest {
+            api! {
+                pub fn create(request: Json<NewWidget>, base: &str) -> Created<Json<Widget>> {
+                    POST "{base}/v1/widgets"
+                }
+            }
+        }
+
+        fn new_widget() -> NewWidget {
+            NewWidget {
+                name: "gear".to_string(),
+            }
+        }
+
+        #[tokio::test]
+        async fn relative_location_and_body() {
+            let (base, _) = server::serve(vec![Response::new(201)
+                .header("location", "widgets/7")
+                .body(r#"{"id":7}"#)])
+            .await;
+
+            let api = CreatedTest::new();
+            let created = api.create(&new_widget(), &base).await.unwrap();
+            assert_eq!(created.status, 201);
+            assert_eq!(
+                created.location.unwrap().as_str(),
+                format!("{base}/v1/widgets/7")
+            );
+            assert_eq!(created.body, Some(Widget { id: 7 }));
+        }
+
+        #[tokio::test]
+        async fn absolute_location_without_body() {
+            let (base, _) = server::serve(vec![
+                Response::new(201).header("location", "https://example.com/widgets/7")
+            ])
+            .await;
+
+            let api = CreatedTest::new();
+            let created = api.create(&new_widget(), &base).await.unwrap();
+            assert_eq!(
+                created.location.unwrap().as_str(),
+                "https://example.com/widgets/7"
+            );
+            assert_eq!(created.body, None);
+        }
+
+        #[tokio::test]
+        async fn error_status() {
+            let (base, _) = server::serve(vec![Response::new(409).body("conflict")]).await;
+
+            let api = CreatedTest::new();
+            let error = api.create(&new_widget(), &base).await.unwrap_err();
+            assert!(
+                matches!(&error, Error::UnexpectedStatus { .. }),
+                "{error:?}"
+            );
+            assert_eq!(error.body(), Some("conflict"));
+        }
+    }
+
+    mod text {
+        use super::server::{self, Response};
+        use crate::{api, Api, Text};
+
+        api!(pub struct TextTest);
+
+        impl TextTest {
+            api! {
+                pub fn utf8(base: &str) -> Text {
+                    GET "{base}/utf8"
+                }
+
+                pub fn legacy(base: &str) -> Text {
+                    GET "{base}/legacy"
+                    charset = "iso-8859-1"
+                }
+            }
+        }
+
+        #[tokio::test]
+        async fn content_type_charset() {
+            let (base, _) = server::serve(vec![Response::new(200)
+                .header("content-type", "text/plain; charset=\"ISO-8859-1\"")
+                .body(b"caf\xe9".to_vec())])
+            .await;
+
+            let api = TextTest::new();
+            let text = api.utf8(&base).await.unwrap();
+            assert_eq!(text.text, "café");
+            assert_eq!(text.encoding, "windows-1252");
+        }
+
+        #[tokio::test]
+        async fn fallback_charset() {
+            let (base, _) = server::serve(vec![
+                Response::new(200)
+                    .header("content-type", "text/xml")
+                    .body(b"caf\xe9".to_vec()),
+                Response::new(200)
+                    .header("content-type", "text/xml")
+                    .body(b"caf\xe9".to_vec()),
+            ])
+            .await;
+
+            let api = TextTest::new();
+            assert_eq!(
+                api.legacy(&base).await.unwrap(),
+                Text {
+                    text: "café".to_string(),
+                    encoding: "windows-1252",
+                }
+            );
+            assert_eq!(
+                api.utf8(&base).await.unwrap(),
+                Text {
+                    text: "caf\u{fffd}".to_string(),
+                    encoding: "UTF-8",
+                }
+            );
+        }
+    }
+
+    #[cfg(feature = "json")]
+    mod json_bytes {
+        use std::borrow::Cow;
+
+        use serde::Deserialize;
+
+        use super::server::{self, Response};
+        use crate::{api, Api, Error};
+
+        #[derive(Debug, Deserialize)]
+        pub struct Item<'a> {
+            name: &'a str,
+            #[serde(borrow)]
+            note: Cow<'a, str>,
+        }
+
+        api!(pub struct JsonBytesTest);
+
+        impl JsonBytesTest {
+            api! {
+                pub fn items(base: &str) -> JsonBytes {
+                    GET "{base}/items"
+                }
+            }
+        }
+
+        #[tokio::test]
+        async fn borrowed_parse() {
+            let (base, _) = server::serve(vec![Response::new(200)
+                .body(r#"[{"name":"gear","note":"plain"},{"name":"bolt","note":"esc\"aped"}]"#)])
+            .await;
+
+            let api = JsonBytesTest::new();
+            let body = api.items(&base).await.unwrap();
+            let items: Vec<Item> = body.parse().unwrap();
+            assert_eq!(items[1].name, "bolt");
+            assert!(matches!(items[0].note, Cow::Borrowed("plain")));
+            assert_eq!(items[1].note, "esc\"aped");
+            assert!(matches!(body.parse::<Item>(), Err(Error::Decode { .. })));
+        }
+    }
+
+    #[cfg(feature = "json")]
+    mod generics {
+        use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+        use super::server::{self, Response};
+        use crate::{api, Api};
+
+        #[derive(Debug, Deserialize, PartialEq, Serialize)]
+        pub struct Todo {
+            id: u32,
+        }
+
+        api!(pub struct GenericTest);
+
+        impl GenericTest {
+            api! {
+                pub fn resource<T: DeserializeOwned>(base: &str, path: &str) -> Json<T> {
+                    GET "{base}/{path}"
+                }
+
+                pub fn tagged<T: std::fmt::Display>(base: &str, tag: T) -> StatusCode {
+                    GET "{base}/tags/{tag}"
+                }
+            }
+        }
+
+        api!(pub struct BoundTest);
+
+        impl BoundTest {
+            api! {
+                pub fn echo<T>(base: &str, request: Json<T>) -> Json<T>
+                where
+                    T: Serialize + DeserializeOwned,
+                {
+                    POST "{base}/echo"
+                }
+
+                pub fn describe<T>(base: &str) -> String
+                where
+                    T: DeserializeOwned + std::fmt::Debug,
+                {
+                    GET "{base}/describe"
+                    map |body| -> String { format!("{:?}", serde_json::from_str::<T>(&body).unwrap()) }
+                }
+            }
+        }
+
+        #[derive(Serialize)]
+        pub struct CreateNote<'a> {
+            title: &'a str,
+        }
+
+        api!(pub struct LifetimeTest);
+
+        impl LifetimeTest {
+            api! {
+                pub fn create<'a>(base: &str, request: Json<CreateNote<'a>>, tag: &'a str) -> StatusCode {
+                    POST "{base}/notes"
+                    "x-tag": tag
+                }
+            }
+        }
+
+        #[tokio::test]
+        async fn turbofish() {
+            let (base, requests) = server::serve(vec![
+                Response::new(200).body(r#"{"id":1}"#),
+                Response::new(200).body("[1,2]"),
+                Response::new(204),
+            ])
+            .await;
+
+            let api = GenericTest::new();
+            assert_eq!(
+                api.resource::<Todo>(&base, "todo").await.unwrap(),
+                Todo { id: 1 }
+            );
+            let ids: Vec<u32> = api.resource(&base, "ids").await.unwrap();
+            assert_eq!(ids, [1, 2]);
+            assert_eq!(api.tagged(&base, 7).await.unwrap(), 204);
+
+            let requests = requests.await.unwrap();
+            assert_eq!(requests[0].path, "/todo");
+            assert_eq!(requests[2].path, "/tags/7");
+        }
+
+        #[tokio::test]
+        async fn where_clause() {
+            let (base, requests) = server::serve(vec![
+                Response::new(200).body(r#"{"id":3}"#),
+                Response::new(200).body(r#"{"id":4}"#),
+            ])
+            .await;
+
+            let api = BoundTest::new();
+            let todo = api.echo(&base, &Todo { id: 3 }).await.unwrap();
+            assert_eq!(todo, Todo { id: 3 });
+            assert_eq!(api.describe::<Todo>(&base).await.unwrap(), "Todo { id: 4 }");
+
+            assert_eq!(requests.await.unwrap()[0].body, br#"{"id":3}"#);
+        }
+
+        #[tokio::test]
+        async fn lifetimes() {
+            let (base, requests) = server::serve(vec![Response::new(201)]).await;
+
+            let title = String::from("groceries");
+            let tag = String::from("home");
+            let api = LifetimeTest::new();
+            let request = CreateNote { title: &title };
+            assert_eq!(api.create(&base, &request, &tag).await.unwrap(), 201);
+
+            let requests = requests.await.unwrap();
+            assert_eq!(requests[0].body, br#"{"title":"groceries"}"#);
+            assert_eq!(requests[0].header("x-tag"), Some("home"));
+        }
+    }
+
+    #[cfg(feature = "json")]
+    mod or_default {
+        use super::server::{self, Response};
+        use crate::{api, Api, Error};
+
+        api!(pub struct OrDefaultTest);
+
+        impl OrDefaultTest {
+            api! {
+                pub fn items(base: &str) -> Json<Vec<u32>> {
+                    GET "{base}/items"
+                    or_default
+                }
+            }
+        }
+
+        #[tokio::test]
+        async fn empty_body() {
+            let (base, _) = server::serve(vec![
+                Response::new(200),
+                Response::new(200).body(" \n"),
+                Response::new(200).body("[1,2]"),
+            ])
+            .await;
+
+            let api = OrDefaultTest::new();
+            assert_eq!(api.items(&base).await.unwrap(), Vec::<u32>::new());
+            assert_eq!(api.items(&base).await.unwrap(), Vec::<u32>::new());
+            assert_eq!(api.items(&base).await.unwrap(), [1, 2]);
+        }
+
+        #[tokio::test]
+        async fn malformed_body() {
+            let (base, _) = server::serve(vec![Response::new(200).body("[1,")]).await;
+
+            let api = OrDefaultTest::new();
+            assert!(matches!(
+                api.items(&base).await,
+                Err(Error::Decode { length: 3, .. })
+            ));
+        }
+    }
+
+    #[cfg(feature = "json")]
+    mod limit {
+        use reqwest::Client;
+
+        use super::server::{self, Response};
+        use crate::{api, Api, Error};
+
+        pub struct LimitTest(Client);
+
+        impl Api for LimitTest {
+            fn client(&self) -> &Client {
+                &self.0
+            }
+
+            fn body_limit(&self) -> Option<u64> {
+                Some(8)
+            }
+        }
+
+        impl LimitTest {
+            api! {
+                pub fn text(base: &str) -> String {
+                    GET "{base}/text"
+                }
+
+                pub fn bytes(base: &str) -> Bytes {
+                    GET "{base}/bytes"
+                    limit = 1 KB
+                }
+
+                pub fn json(base: &str) -> Json<Vec<u32>> {
+                    GET "{base}/json"
+                    limit = 4
+                }
+
+                pub fn value(base: &str) -> Value {
+                    GET "{base}/value"
+                }
+            }
+        }
+
+        #[tokio::test]
+        async fn within_limit() {
+            let (base, _) = server::serve(vec![
+                Response::new(200).body("12345678"),
+                Response::new(200).body(vec![0; 1024]),
+                Response::new(200).body("[1]"),
+            ])
+            .await;
+
+            let api = LimitTest(Client::new());
+            assert_eq!(api.text(&base).await.unwrap(), "12345678");
+            assert_eq!(api.bytes(&base).await.unwrap().len(), 1024);
+            assert_eq!(api.json(&base).await.unwrap(), [1]);
+        }
+
+        #[tokio::test]
+        async fn content_length_too_large() {
+            let (base, _) = server::serve(vec![
+                Response::new(200).body("123456789"),
+                Response::new(200).body(vec![0; 1025]),
+            ])
+            .await;
+
+            let api = LimitTest(Client::new());
+            assert!(matches!(
+                api.text(&base).await,
+                Err(Error::BodyTooLarge {
+                    limit: 8,
+                    received: 9
+                })
+            ));
+            assert!(matches!(
+                api.bytes(&base).await,
+                Err(Error::BodyTooLarge {
+                    limit: 1024,
+                    received: 1025
+                })
+            ));
+        }
+
+        #[tokio::test]
+        async fn streamed_body_too_large() {
+            let (base, _) = server::serve(vec![Response::new(200)
+                .unframed()
+                .body_parts(["[1,", "2]"])])
+            .await;
+
+            let api = LimitTest(Client::new());
+            assert!(matches!(
+                api.json(&base).await,
+                Err(Error::BodyTooLarge {
+                    limit: 4,
+                    received: 5
+                })
+            ));
+        }
+
+        #[tokio::test]
+        async fn value_too_large() {
+            let (base, _) = server::serve(vec![Response::new(200).body("[1,2,3,4]")]).await;
+
+            let api = LimitTest(Client::new());
+            assert!(matches!(
+                api.value(&base).await,
+                Err(Error::BodyTooLarge {
+                    limit: 8,
+                    received: 9
+                })
+            ));
+        }
+    }
+
+    #[cfg(feature = "json")]
+    mod envelope {
+        use reqwest::Client;
+        use serde::Deserialize;
+
+        use super::server::{self, Response};
+        use crate::{api, Api, Error};
+
+        #[derive(Debug, Deserialize, PartialEq)]
+        pub struct User {
+            id: u32,
+        }
+
+        pub struct EnvelopeTest(Client);
+
+        impl Api for EnvelopeTest {
+            fn client(&self) -> &Client {
+                &self.0
+            }
+
+            fn envelope(&self) -> Option<&str> {
+                Some("data")
+            }
+        }
+
+        impl EnvelopeTest {
+            api! {
+                pub fn user(base: &str) -> Json<User> {
+                    GET "{base}/user"
+                }
+
+                pub fn first_user(base: &str) -> Json<User> at "result.users.0" {
+                    GET "{base}/users"
+                }
+
+                pub fn raw(base: &str) -> Json<User> at "" {
+                    GET "{base}/raw"
+                }
+            }
+        }
+
+        #[tokio::test]
+        async fn unwraps_path() {
+            let (base, _) = server::serve(vec![
+                Response::new(200).body(r#"{"data":{"id":1},"meta":{}}"#),
+                Response::new(200).body(r#"{"result":{"users":[{"id":2},{"id":3}]}}"#),
+                Response::new(200).body(r#"{"id":4}"#),
+            ])
+            .await;
+
+            let api = EnvelopeTest(Client::new());
+            assert_eq!(api.user(&base).await.unwrap(), User { id: 1 });
+            assert_eq!(api.first_user(&base).await.unwrap(), User { id: 2 });
+            assert_eq!(api.raw(&base).await.unwrap(), User { id: 4 });
+        }
+
+        #[tokio::test]
+        async fn missing_path() {
+            let (base, _) =
+                server::serve(vec![Response::new(200).body(r#"{"result":{"users":[]}}"#)]).await;
+
+            let api = EnvelopeTest(Client::new());
+            match api.first_user(&base).await {
+                Err(Error::Decode { source, .. }) => assert_eq!(
+                    source.to_string(),
+                    "response body has no value at `result.users.0`"
+                ),
+                other => panic!("unexpected result: {other:?}"),
+            }
+        }
+    }
+
+    #[cfg(feature = "json")]
+    mod map {
+        use serde::Deserialize;
+
+        use super::server::{self, Response};
+        use crate::{api, Api};
+
+        #[derive(Debug, Deserialize)]
+        pub struct User {
+            id: u32,
+        }
+
+        api!(pub struct MapTest);
+
+        impl MapTest {
+            api! {
+                pub fn sorted(base: &str) -> Json<Vec<User>> {
+                    GET "{base}/users"
+                    map |mut users| {
+                        users.sort_by_key(|user: &User| user.id);
+                        users
+                    };
+                    "x-sorted": "true"
+                }
+
+                pub fn ids(base: &str) -> Json<Vec<User>> {
+                    GET "{base}/users"
+                    map |users| -> Vec<u32> { users.into_iter().map(|user| user.id).collect() }
+                }
+
+                pub fn status(base: &str) -> StatusCode {
+                    GET "{base}/users"
+                    map |status| -> bool { status.is_success() }
+                }
+            }
+        }
+
+        #[tokio::test]
+        async fn same_type() {
+            let (base, requests) =
+                server::serve(vec![Response::new(200).body(r#"[{"id":2},{"id":1}]"#)]).await;
+
+            let api = MapTest::new();
+            let users = api.sorted(&base).await.unwrap();
+            assert_eq!(users.iter().map(|user| user.id).collect::<Vec<_>>(), [1, 2]);
+            assert_eq!(requests.await.unwrap()[0].header("x-sorted"), Some("true"));
+        }
+
+        #[tokio::test]
+        async fn new_type() {
+            let (base, _) = server::serve(vec![
+                Response::new(200).body(r#"[{"id":2},{"id":1}]"#),
+                Response::new(204),
+            ])
+            .await;
+
+            let api = MapTest::new();
+            assert_eq!(api.ids(&base).await.unwrap(), [2, 1]);
+            assert!(api.status(&base).await.unwrap());
+        }
+    }
+
+    #[cfg(feature = "json")]
+    mod query {
+        use serde::Serialize;
+
+        use super::server::{self, Response};
+        use crate::{api, Api};
+
+        #[derive(Serialize)]
+        pub struct Search<'a> {
+            q: &'a str,
+            page: Option<u32>,
+        }
+
+        #[derive(Serialize)]
+        pub struct Filter {
+            done: bool,
+        }
+
+        api!(pub struct QueryTest);
+
+        impl QueryTest {
+            api! {
+                pub fn search(base: &str, query: Query<Search<'_>>) -> StatusCode {
+                    GET "{base}/search"
+                }
+
+                pub fn filter(request: Json<Filter>, query: Query<Search<'_>>, base: &str) -> StatusCode {
+                    POST "{base}/filter"
+                }
+
+                pub fn inline(base: &str, term: &str, page: u32) -> StatusCode {
+                    GET "{base}/inline?fixed=1"
+                    ? q: "{term}"
+                    ? tag: "x"
+                    ? tag: ""
+                    "x-test": "{term}"
+                    ? "page[number]": "{page}"
+                }
+
+                pub fn list(base: &str, status: Option<&str>, limit: Option<u32>) -> StatusCode {
+                    GET "{base}/list"
+                    ? status
+                    ? "page[size]": limit
+                }
+
+                pub fn repeated(base: &str, ids: &[&str]) -> StatusCode {
+                    GET "{base}/repeated#top"
+                    ? id: ids
+                }
+
+                pub fn comma(base: &str, ids: Vec<&str>) -> StatusCode {
+                    GET "{base}/comma"
+                    ? id: ids
+                    query_style = Comma
+                }
+
+                pub fn brackets(base: &str, ids: [u32; 2]) -> StatusCode {
+                    GET "{base}/brackets"
+                    query_style = Brackets
+                    ? id: ids
+                }
+            }
+        }
+
+        #[tokio::test]
+        async fn serializes_query() {
+            let (base, requests) =
+                server::serve(vec![Response::new(200), Response::new(200)]).await;
+
+            let api = QueryTest::new();
+            api.search(
+                &base,
+                &Search {
+                    q: "a&b c",
+                    page: None,
+                },
+            )
+            .await
+            .unwrap();
+            api.search(
+                &base,
+                &Search {
+                    q: "x",
+                    page: Some(2),
+                },
+            )
+            .await
+            .unwrap();
+
+            let requests = requests.await.unwrap();
+            assert_eq!(requests[0].path, "/search?q=a%26b+c");
+            assert_eq!(requests[1].path, "/search?q=x&page=2");
+        }
+
+        #[tokio::test]
+        async fn inline() {
+            let (base, requests) = server::serve(vec![Response::new(200)]).await;
+
+            let api = QueryTest::new();
+            api.inline(&base, "a b", 2).await.unwrap();
+
+            let requests = requests.await.unwrap();
+            assert_eq!(
+                requests[0].path,
+                "/inline?fixed=1&q=a+b&tag=x&tag=&page%5Bnumber%5D=2"
+            );
+            assert_eq!(requests[0].header("x-test"), Some("a b"));
+        }
+
+        #[tokio::test]
+        async fn optional() {
+            let (base, requests) =
+                server::serve(vec![Response::new(200), Response::new(200)]).await;
+
+            let api = QueryTest::new();
+            api.list(&base, Some("open"), None).await.unwrap();
+            api.list(&base, None, Some(10)).await.unwrap();
+
+            let requests = requests.await.unwrap();
+            assert_eq!(requests[0].path, "/list?status=open");
+            assert_eq!(requests[1].path, "/list?page%5Bsize%5D=10");
+        }
+
+        #[tokio::test]
+        async fn sequences() {
+            let (base, requests) = server::serve(vec![
+                Response::new(200),
+                Response::new(200),
+                Response::new(200),
+                Response::new(200),
+            ])
+            .await;
+
+            let api = QueryTest::new();
+            api.repeated(&base, &["1", "2"]).await.unwrap();
+            api.repeated(&base, &[]).await.unwrap();
+            api.comma(&base, vec!["a", "b"]).await.unwrap();
+            api.brackets(&base, [1, 2]).await.unwrap();
+
+            let requests = requests.await.unwrap();
+            assert_eq!(requests[0].path, "/repeated?id=1&id=2");
+            assert_eq!(requests[1].path, "/repeated");
+            assert_eq!(requests[2].path, "/comma?id=a%2Cb");
+            assert_eq!(requests[3].path, "/brackets?id%5B%5D=1&id%5B%5D=2");
+        }
+
+        #[tokio::test]
+        async fn with_body() {
+            let (base, requests) = server::serve(vec![Response::new(200)]).await;
+
+            let api = QueryTest::new();
+            api.filter(
+                &Filter { done: true },
+                &Search { q: "x", page: None },
+                &base,
+            )
+            .await
+            .unwrap();
+
+            let requests = requests.await.unwrap();
+            assert_eq!(requests[0].path, "/filter?q=x");
+            assert_eq!(requests[0].body, br#"{"done":true}"#);
+        }
+    }
+
+    mod header_values {
+        use reqwest::{
+            header::{HeaderValue, ACCEPT, AUTHORIZATION, IF_MATCH},
+            Client,
+        };
+
+        use super::server::{self, Response};
+        use crate::{api, Api, Error};
+
+        pub struct HeaderValueTest {
+            client: Client,
+            token: String,
+        }
+
+        impl Api for HeaderValueTest {
+            fn client(&self) -> &Client {
+                &self.client
+            }
+        }
+
+        impl HeaderValueTest {
+            fn token(&self) -> String {
+                format!("Bearer {}", self.token)
+            }
+
+            api! {
+                pub fn get(&self, base: &str, id: u32, accept: HeaderValue) -> StatusCode {
+                    GET "{base}/headers"
+                    AUTHORIZATION: self.token();
+                    "x-id": "{id}"
+                    "x-computed": (id * 2).to_string();
+                    ACCEPT: accept
+                }
+
+                pub fn optional(base: &str, etag: Option<&str>, user: Option<u32>) -> StatusCode {
+                    GET "{base}/headers"
+                    IF_MATCH?: etag
+                    "x-user"?: user.map(|user| user.to_string());
+                    "x-fixed": "1"
+                }
+
+                pub fn invalid(base: &str) -> StatusCode {
+                    GET "{base}/headers"
+                    "x api": "value"
+                }
+            }
+        }
+
+        #[tokio::test]
+        async fn optional() {
+            let (base, requests) =
+                server::serve(vec![Response::new(200), Response::new(200)]).await;
+
+            let api = HeaderValueTest {
+                client: Client::new(),
+                token: "secret".to_string(),
+            };
+            api.optional(&base, Some("\"abc\""), None).await.unwrap();
+            api.optional(&base, None, Some(7)).await.unwrap();
+
+            let requests = requests.await.unwrap();
+            assert_eq!(requests[0].header("if-match"), Some("\"abc\""));
+            assert_eq!(requests[0].header("x-user"), None);
+            assert_eq!(requests[1].header("if-match"), None);
+            assert_eq!(requests[1].header("x-user"), Some("7"));
+            assert_eq!(requests[1].header("x-fixed"), Some("1"));
+        }
+
+        #[tokio::test]
+        async fn invalid_name() {
+            let api = HeaderValueTest {
+                client: Client::new(),
+                token: "secret".to_string(),
+            };
+            match api.invalid("http://127.0.0.1:9").await {
+                Err(error @ Error::InvalidHeaderName { .. }) => {
+                    assert_eq!(error.to_string(), "invalid header name `x api`");
+                }
+                other => panic!("unexpected result: {other:?}"),
+            }
+        }
+
+        #[tokio::test]
+        async fn expressions() {
+            let (base, requests) = server::serve(vec![Response::new(200)]).await;
+
+            let api = HeaderValueTest {
+                client: Client::new(),
+                token: "secret".to_string(),
+            };
+            api.get(&base, 21, HeaderValue::from_static("text/plain"))
+                .await
+                .unwrap();
+
+            let requests = requests.await.unwrap();
+            assert_eq!(requests[0].header("authorization"), Some("Bearer secret"));
+            assert_eq!(requests[0].header("x-id"), Some("21"));
+            assert_eq!(requests[0].header("x-computed"), Some("42"));
+            assert_eq!(requests[0].header("accept"), Some("text/plain"));
+        }
+    }
+
+    mod base_url {
+        use super::server::{self, Response};
+        use crate::{api, Api};
+
+        api!(pub struct BaseUrlTest, base = "https://example.invalid/v2");
+
+        impl BaseUrlTest {
+            api! {
+                pub fn user(id: u32) -> String {
+                    GET "/users/{id}"
+                }
+
+                pub fn search(term: &str) -> String {
+                    GET "search"
+                    ? q: "{term}"
+                }
+
+                pub fn absolute(base: &str) -> String {
+                    GET "{base}/absolute"
+                }
+            }
+        }
+
+        #[test]
+        fn declared_base() {
+            let api = BaseUrlTest::new();
+            assert_eq!(
+                api.base_url().unwrap().as_str(),
+                "https://example.invalid/v2/"
+            );
+        }
+
+        #[tokio::test]
+        async fn runtime_base() {
+            let (base, requests) = server::serve(vec![
+                Response::new(200).body("user"),
+                Response::new(200).body("search"),
+                Response::new(200).body("absolute"),
+            ])
+            .await;
+
+            let api = BaseUrlTest::with_base_url(format!("{base}/mock").parse().unwrap());
+            assert_eq!(api.user(7).await.unwrap(), "user");
+            assert_eq!(api.search("a b").await.unwrap(), "search");
+            assert_eq!(api.absolute(&base).await.unwrap(), "absolute");
+
+            let requests = requests.await.unwrap();
+            assert_eq!(requests[0].path, "/mock/users/7");
+            assert_eq!(requests[1].path, "/mock/search?q=a+b");
+            assert_eq!(requests[2].path, "/absolute");
+        }
+    }
+
+    mod path_params {
+        use super::server::{self, Response};
+        use crate::{api, Api};
+
+        api!(pub struct PathTest);
+
+        impl PathTest {
+            api! {
+                pub fn get(base: &str, id: &str, tag: &str) -> StatusCode {
+                    GET "{base}/items/{id}?tag={tag}"
+                }
+
+                pub fn nested(base: &str, path: &str, id: u32) -> StatusCode {
+                    GET "{base}/files/{path}/{id:03}"
+                    raw = path
+                }
+
+                pub fn delete(base: &str, id: &str, _force: bool) -> StatusCode {
+                    DELETE "{base}/items/{id}"
+                }
+            }
+        }
+
+        #[tokio::test]
+        async fn encodes_segments() {
+            let (base, requests) = server::serve(vec![
+                Response::new(200),
+                Response::new(200),
+                Response::new(200),
+            ])
+            .await;
+
+            let api = PathTest::new();
+            api.get(&base, "a/b c?d", "x&y=z#").await.unwrap();
+            api.nested(&base, "a/b", 7).await.unwrap();
+            api.delete(&base, "a b", true).await.unwrap();
+
+            let requests = requests.await.unwrap();
+            assert_eq!(requests[0].path, "/items/a%2Fb%20c%3Fd?tag=x%26y%3Dz%23");
+            assert_eq!(requests[1].path, "/files/a/b/007");
+            assert_eq!(requests[2].path, "/items/a%20b");
+        }
+
+        #[test]
+        fn encode() {
+            use crate::url::{encode, Param};
+
+            assert_eq!(
+                encode(&format!("{}/{}", Param(&"http://host/v1"), Param(&"a b"))),
+                "http://host/v1/a%20b"
+            );
+            assert_eq!(
+                encode(&format!("http://{}/{}", Param(&"host:80"), Param(&"a/b"))),
+                "http://host:80/a%2Fb"
+            );
+            assert_eq!(encode(&format!("users/{}", Param(&"a?b"))), "users/a%3Fb");
+            assert_eq!(encode("plain/url"), "plain/url");
+        }
+
+        #[test]
+        fn mentions() {
+            use crate::url::mentions;
+
+            assert!(mentions("id", "\"{base}/items/{id}\""));
+            assert!(mentions("id", "\"{base}/items/{id:03}\""));
+            assert!(mentions("token", "(header \"x-token\" (token.as_str()))"));
+            assert!(mentions("r#type", "(query type r#type)"));
+            assert!(mentions("_force", "\"{base}/items\""));
+            assert!(!mentions("id", "\"{base}/items/{idd}\""));
+            assert!(!mentions("id", "\"{base}/items/{user_id}\""));
+            assert!(!mentions("force", "\"{base}/force/{id}\""));
+            assert!(!mentions("id", "(header \"x-request-id\" (\"1\"))"));
+            assert!(!mentions("id", "\"{base}/items/{{id}}\""));
+            assert!(!mentions("id", "r#\"{base}/\"id\"\"#"));
+            assert!(mentions("id", "r#\"{base}/\"{id}\"\"#"));
+            assert!(mentions("id", "\"{base}/\\\"{id}\""));
+        }
+
+        #[test]
+        fn escapes_markers() {
+            use crate::url::{encode, Param};
+
+            assert_eq!(
+                encode(&format!("users/{}/x", Param(&"a\u{fdd1}/../admin"))),
+                "users/a%EF%B7%91%2F..%2Fadmin/x"
+            );
+            assert_eq!(
+                encode(&format!("users/{}", Param(&"\u{fdd0}\u{fdd2}0"))),
+                "users/%EF%B7%90%EF%B7%920"
+            );
+        }
+    }
+
+    mod methods {
+        use super::server::{self, Response};
+        use crate::{api, Api};
+
+        api!(pub struct MethodTest);
+
+        impl MethodTest {
+            api! {
+                pub fn propfind(base: &str) -> StatusCode {
+                    "PROPFIND" "{base}/dav"
+                    "depth": "1"
+                }
+
+                pub fn ingest(method: reqwest::Method, base: &str) -> StatusCode {
+                    {method} "{base}/ingest"
+                }
+            }
+        }
+
+        #[tokio::test]
+        async fn extension_method() {
+            let (base, requests) = server::serve(vec![Response::new(207)]).await;
+
+            let api = MethodTest::new();
+            assert_eq!(api.propfind(&base).await.unwrap(), 207);
+            assert_eq!(requests.await.unwrap()[0].method, "PROPFIND");
+        }
+
+        #[tokio::test]
+        async fn runtime_method() {
+            let (base, requests) =
+                server::serve(vec![Response::new(200), Response::new(200)]).await;
+
+            let api = MethodTest::new();
+            api.ingest(reqwest::Method::PUT, &base).await.unwrap();
+            api.ingest(reqwest::Method::POST, &base).await.unwrap();
+
+            let requests = requests.await.unwrap();
+            assert_eq!(requests[0].method, "PUT");
+            assert_eq!(requests[1].method, "POST");
+        }
+
+        #[test]
+        fn validation() {
+            use crate::method::is_valid;
+
+            assert!(is_valid("PURGE"));
+            assert!(is_valid("M-SEARCH"));
+            assert!(!is_valid(""));
+            assert!(!is_valid("BAD METHOD"));
+        }
+    }
+
+    mod builder {
+        use super::server::{self, Response};
+        use crate::{api, Api};
+
+        api!(pub struct BuilderTest);
+
+        api! {
+            /// Builds a search request.
+            pub struct Search<'a> for BuilderTest {
+                /// Searches the todos.
+                pub fn search(base: &'a str, term: &'a str, status: Option<&'a str>, limit: Option<u32>) -> StatusCode {
+                    GET "{base}/search"
+                    ? term
+                    ? status
+                    ? limit
+                }
+            }
+        }
+
+        #[tokio::test]
+        async fn optional_parameters() {
+            let (base, requests) =
+                server::serve(vec![Response::new(200), Response::new(200)]).await;
+
+            let api = BuilderTest::new();
+            assert_eq!(
+                api.search(&base, "milk")
+                    .status("open")
+                    .limit(3)
+                    .send()
+                    .await
+                    .unwrap(),
+                200
+            );
+            api.search(&base, "eggs").send().await.unwrap();
+
+            let requests = requests.await.unwrap();
+            assert_eq!(requests[0].path, "/search?term=milk&status=open&limit=3");
+            assert_eq!(requests[1].path, "/search?term=eggs");
+        }
+    }
+
+    mod defaults {
+        use super::server::{self, Response};
+        use crate::{api, Api};
+
+        api!(pub struct DefaultTest);
+
+        impl DefaultTest {
+            api! {
+                pub fn list(base: &str, page_size: u32 = 100, sort: &str = "name") -> StatusCode {
+                    GET "{base}/todos"
+                    ? page_size
+                    ? sort
+                }
+            }
+        }
+
+        api! {
+            pub struct List<'a> for DefaultTest {
+                pub fn paged(base: &'a str, page_size: u32 = 100) -> StatusCode {
+                    GET "{base}/todos"
+                    ? page_size
+                }
+            }
+        }
+
+        #[tokio::test]
+        async fn default_values() {
+            let (base, requests) = server::serve(vec![
+                Response::new(200),
+                Response::new(200),
+                Response::new(200),
+                Response::new(200),
+            ])
+            .await;
+
+            let api = DefaultTest::new();
+            api.list(&base, None, None).await.unwrap();
+            api.list(&base, 25, "due").await.unwrap();
+            api.paged(&base).send().await.unwrap();
+            api.paged(&base).page_size(5).send().await.unwrap();
+
+            let requests = requests.await.unwrap();
+            assert_eq!(requests[0].path, "/todos?page_size=100&sort=name");
+            assert_eq!(requests[1].path, "/todos?page_size=25&sort=due");
+            assert_eq!(requests[2].path, "/todos?page_size=100");
+            assert_eq!(requests[3].path, "/todos?page_size=5");
+        }
+    }
+
+    #[cfg(feature = "json")]
+    mod accept {
+        use reqwest::header::{HeaderValue, ACCEPT};
+
+        use super::server::{self, Response};
+        use crate::{api, Api};
+
+        api!(pub struct AcceptTest);
+
+        impl AcceptTest {
+            api! {
+                pub fn json(base: &str) -> Json<u32> {
+                    GET "{base}/json"
+                }
+
+                pub fn value(base: &str) -> Value {
+                    GET "{base}/value"
+                }
+
+                pub fn text(base: &str) -> String {
+                    GET "{base}/text"
+                }
+
+                pub fn bytes(base: &str) -> Bytes {
+                    GET "{base}/bytes"
+                }
+
+                pub fn status(base: &str) -> StatusCode {
+                    GET "{base}/status"
+                }
+
+                pub fn explicit(base: &str) -> Json<u32> {
+                    GET "{base}/explicit"
+                    ACCEPT: HeaderValue::from_static("application/vnd.example+json")
+                }
+
+                #[cfg(feature = "msgpack")]
+                pub fn msgpack(base: &str) -> MsgPack<u32> {
+                    GET "{base}/msgpack"
+                }
+            }
+        }
+
+        #[tokio::test]
+        async fn by_kind() {
+            let (base, requests) = server::serve(vec![
+                Response::new(200).body("1"),
+                Response::new(200).body("1"),
+                Response::new(200).body("1"),
+                Response::new(200).body("1"),
+                Response::new(200),
+                Response::new(200).body("1"),
+            ])
+            .await;
+
+            let api = AcceptTest::new();
+            api.json(&base).await.unwrap();
+            api.value(&base).await.unwrap();
+            api.text(&base).await.unwrap();
+            api.bytes(&base).await.unwrap();
+            api.status(&base).await.unwrap();
+            api.explicit(&base).await.unwrap();
+
+            let accept: Vec<_> = requests
+                .await
+                .unwrap()
+                .iter()
+                .map(|request| request.header("accept").unwrap().to_string())
+                .collect();
+            assert_eq!(
+                accept,
+                [
+                    "application/json",
+                    "application/json",
+                    "text/plain, */*;q=0.8",
+                    "*/*",
+                    "*/*",
+                    "application/vnd.example+json",
+                ]
+            );
+        }
+
+        #[cfg(feature = "msgpack")]
+        #[tokio::test]
+        async fn msgpack() {
+            let (base, requests) = server::serve(vec![Response::new(200).body([1])]).await;
+
+            AcceptTest::new().msgpack(&base).await.unwrap();
+            assert_eq!(
+                requests.await.unwrap()[0].header("accept"),
+                Some("application/msgpack")
+            );
+        }
+    }
+
+    mod no_pre_request {
+        use reqwest::{header::AUTHORIZATION, Client, RequestBuilder};
+
+        use super::server::{self, Response};
+        use crate::{api, Api};
+
+        struct AuthTest(Client);
+
+        impl Api for AuthTest {
+            fn client(&self) -> &Client {
+                &self.0
+            }
+
+            fn pre_request(&self, request: RequestBuilder) -> reqwest::Result<RequestBuilder> {
+                Ok(request.bearer_auth("token"))
+            }
+        }
+
+        impl AuthTest {
+            api! {
+                fn token(base: &str) -> StatusCode {
+                    POST "{base}/token"
+                    no_pre_request
+                }
+
+                fn me(base: &str) -> StatusCode {
+                    GET "{base}/me"
+                }
+            }
+        }
+
+        #[tokio::test]
+        async fn skipped() {
+            let (base, requests) =
+                server::serve(vec![Response::new(200), Response::new(200)]).await;
+
+            let api = AuthTest(Client::new());
+            api.token(&base).await.unwrap();
+            api.me(&base).await.unwrap();
+
+            let requests = requests.await.unwrap();
+            assert_eq!(requests[0].header(AUTHORIZATION.as_str()), None);
+            assert_eq!(
+                requests[1].header(AUTHORIZATION.as_str()),
+                Some("Bearer token")
+            );
+        }
+    }
+
+    mod request_fn {
+        use reqwest::header::AUTHORIZATION;
+
+        use super::server::{self, Response};
+        use crate::{api, Api};
+
+        api!(pub struct RequestFnTest);
+
+        impl RequestFnTest {
+            api! {
+                pub fn todo(base: &str, id: u32, page: u32 = 1) -> String {
+                    GET "{base}/todos/{id}?page={page}"
+                    "x-request-id": "{id}"
+                    request = todo_request
+                }
+            }
+        }
+
+        #[tokio::test]
+        async fn builds_without_sending() {
+            let (base, requests) = server::serve(vec![Response::new(200).body("sent")]).await;
+
+            let api = RequestFnTest::new();
+            let request = api
+                .todo_request(&base, 1, None)
+                .await
+                .unwrap()
+                .bearer_auth("token")
+                .build()
+                .unwrap();
+            assert_eq!(request.url().as_str(), format!("{base}/todos/1?page=1"));
+            assert_eq!(request.headers()["x-request-id"], "1");
+
+            let response = api
+                .todo_request(&base, 2, 3)
+                .await
+                .unwrap()
+                .bearer_auth("token")
                 .send()
-                .await?
-                .text()
                 .await
+                .unwrap();
+            assert_eq!(response.text().await.unwrap(), "sent");
+
+            let requests = requests.await.unwrap();
+            assert_eq!(requests[0].path, "/todos/2?page=3");
+            assert_eq!(
+                requests[0].header(AUTHORIZATION.as_str()),
+                Some("Bearer token")
+            );
+        }
+    }
+
+    mod expect {
+        use reqwest::StatusCode;
+
+        use super::server::{self, Response};
+        use crate::{api, Api, Error};
+
+        api!(pub struct ExpectTest);
+
+        impl ExpectTest {
+            api! {
+                pub fn accepted(base: &str) -> StatusCode {
+                    POST "{base}/imports"
+                    expect 202
+                }
+
+                pub fn batch(base: &str) -> String {
+                    POST "{base}/batch"
+                    expect 200..=201 | 207,
+                    "x-request-id": "1"
+                }
+            }
+        }
+
+        #[tokio::test]
+        async fn statuses() {
+            let page = "<html>".repeat(200);
+            let (base, _) = server::serve(vec![
+                Response::new(202),
+                Response::new(207).body("multi"),
+                Response::new(200).body("ok"),
+                Response::new(500).body(page.as_str()),
+            ])
+            .await;
+
+            let api = ExpectTest::new();
+            assert_eq!(api.accepted(&base).await.unwrap(), StatusCode::ACCEPTED);
+            assert_eq!(api.batch(&base).await.unwrap(), "multi");
+            match api.accepted(&base).await {
+                Err(Error::UnexpectedStatus {
+                    status, url, body, ..
+                }) => {
+                    assert_eq!(status, StatusCode::OK);
+                    assert_eq!(url.unwrap().path(), "/imports");
+                    assert_eq!(body, "ok");
+                }
+                other => panic!("expected an unexpected status error, got {other:?}"),
+            }
+            match api.batch(&base).await {
+                Err(Error::UnexpectedStatus { status, body, .. }) => {
+                    assert_eq!(status, StatusCode::INTERNAL_SERVER_ERROR);
+                    assert_eq!(body, page[..512]);
+                }
+                other => panic!("expected an unexpected status error, got {other:?}"),
+            }
+        }
+    }
+
+    mod impl_block {
+        use super::server::{self, Response};
+        use crate::{api, Api};
+
+        const PREFIX: &str = "v2";
+
+        api!(pub struct ImplTest);
+
+        api! {
+            impl ImplTest {
+                /// The first todo's id.
+                const FIRST: u32 = 1;
+
+                pub fn todo(base: &str, id: u32) -> String {
+                    GET "{base}/{PREFIX}/todos/{id}"
+                }
+
+                #[must_use]
+                pub fn label(&self, id: u32) -> String {
+                    format!("todo {id}")
+                }
+
+                pub async fn first(&self, base: &str) -> crate::Result<String> {
+                    self.todo(base, Self::FIRST).await
+                }
+
+                group "{base}/{PREFIX}" (base: &str) {
+                    pub fn health() -> StatusCode {
+                        GET "/health"
+                    }
+                }
+            }
+        }
+
+        #[tokio::test]
+        async fn passes_items_through() {
+            let (base, requests) =
+                server::serve(vec![Response::new(200).body("one"), Response::new(204)]).await;
+
+            let api = ImplTest::new();
+            assert_eq!(api.label(1), "todo 1");
+            assert_eq!(api.first(&base).await.unwrap(), "one");
+            assert_eq!(api.health(&base).await.unwrap().as_u16(), 204);
+
+            let requests = requests.await.unwrap();
+            assert_eq!(requests[0].path, "/v2/todos/1");
+            assert_eq!(requests[1].path, "/v2/health");
+        }
+    }
+
+    mod groups {
+        use super::server::{self, Response};
+        use crate::{api, Api};
+
+        api!(pub struct GroupTest);
+
+        impl GroupTest {
+            api! {
+                pub fn health(base: &str) -> StatusCode {
+                    GET "{base}/health"
+                }
+
+                group "{base}/projects/{project}" (base: &str, project: &str) {
+                    pub fn project() -> StatusCode {
+                        GET ""
+                    }
+
+                    pub fn env(&self, env: &str) -> StatusCode {
+                        DELETE "/envs/{env}"
+                    }
+
+                    group "/members/{member}" (member: u32) {
+                        pub fn member(role: Option<&str>) -> StatusCode {
+                            GET ""
+                            ? role
+                        }
+                    }
+                }
+            }
+        }
+
+        #[tokio::test]
+        async fn prefixed() {
+            let (base, requests) = server::serve(vec![
+                Response::new(200),
+                Response::new(200),
+                Response::new(200),
+                Response::new(200),
+            ])
+            .await;
+
+            let api = GroupTest::new();
+            api.health(&base).await.unwrap();
+            api.project(&base, "a b").await.unwrap();
+            api.env(&base, "p", "prod").await.unwrap();
+            api.member(&base, "p", 7, Some("admin")).await.unwrap();
+
+            let requests: Vec<_> = requests
+                .await
+                .unwrap()
+                .into_iter()
+                .map(|request| format!("{} {}", request.method, request.path))
+                .collect();
+            assert_eq!(
+                requests,
+                [
+                    "GET /health",
+                    "GET /projects/a%20b",
+                    "DELETE /projects/p/envs/prod",
+                    "GET /projects/p/members/7?role=admin",
+                ]
+            );
+        }
+    }
+
+    mod url_fn {
+        use crate::api;
+
+        api!(pub struct UrlFnTest, base = "https://example.com/v2/");
+
+        impl UrlFnTest {
+            api! {
+                pub fn todo(&self, id: &str, page: u32 = 1) -> String {
+                    GET "todos/{id}?page={page}"
+                    url = todo_url,
+                    request = todo_request
+                }
+
+                pub fn search(&self, q: Option<&str>) -> String {
+                    GET "search"
+                    ? q
+                    url = search_url
+                }
+            }
+        }
+
+        #[tokio::test]
+        async fn formats_without_sending() {
+            let api = UrlFnTest::with_base_url("http://127.0.0.1:1/mock/".parse().unwrap());
+            assert_eq!(
+                api.todo_url("a/b c", None),
+                "http://127.0.0.1:1/mock/todos/a%2Fb%20c?page=1"
+            );
+            assert_eq!(
+                api.todo_request("1", 2)
+                    .await
+                    .unwrap()
+                    .build()
+                    .unwrap()
+                    .url()
+                    .as_str(),
+                api.todo_url("1", 2)
+            );
+            assert_eq!(
+                api.search_url(Some("milk & eggs")),
+                "http://127.0.0.1:1/mock/search?q=milk+%26+eggs"
+            );
+            assert_eq!(api.search_url(None), "http://127.0.0.1:1/mock/search");
+        }
+    }
+
+    mod with_client {
+        use reqwest::{header::USER_AGENT, Client};
+
+        use super::server::{self, Response};
+        use crate::{api, Api};
+
+        api!(pub struct SharedTest);
+        api!(pub struct SharedBaseTest, base = "http://127.0.0.1:1/v2");
+
+        impl SharedTest {
+            api! {
+                pub fn todo(base: &str) -> StatusCode {
+                    GET "{base}/todo"
+                }
+            }
+        }
+
+        #[tokio::test]
+        async fn uses_client() {
+            let (base, requests) = server::serve(vec![Response::new(200)]).await;
+
+            let client = Client::builder().user_agent("shared/1").build().unwrap();
+            SharedTest::with_client(client.clone())
+                .todo(&base)
+                .await
+                .unwrap();
+            assert_eq!(
+                requests.await.unwrap()[0].header(USER_AGENT.as_str()),
+                Some("shared/1")
+            );
+
+            let api = SharedBaseTest::from(client);
+            assert_eq!(api.base_url().unwrap().as_str(), "http://127.0.0.1:1/v2/");
+        }
+
+        #[tokio::test]
+        async fn from_builder() {
+            let (base, requests) = server::serve(vec![Response::new(200)]).await;
+
+            SharedTest::from_builder(Client::builder().user_agent("built/1"))
+                .unwrap()
+                .todo(&base)
+                .await
+                .unwrap();
+            assert_eq!(
+                requests.await.unwrap()[0].header(USER_AGENT.as_str()),
+                Some("built/1")
+            );
+
+            let api = SharedBaseTest::try_new().unwrap();
+            assert_eq!(api.base_url().unwrap().as_str(), "http://127.0.0.1:1/v2/");
+        }
+    }
+
+    mod api_builder {
+        use reqwest::Client;
+
+        use super::server::{self, Response};
+        use crate::{api, Api, Error};
+
+        api!(pub struct BuilderTest, base = "http://127.0.0.1:1/v2");
+
+        impl BuilderTest {
+            api! {
+                pub fn todo() -> StatusCode {
+                    GET "/todo"
+                }
+            }
+        }
+
+        #[tokio::test]
+        async fn builder() {
+            let (base, requests) = server::serve(vec![Response::new(200)]).await;
+
+            let api = BuilderTest::builder()
+                .client(Client::new())
+                .base_url(format!("{base}/v1"))
+                .default_header("x-tenant", "acme")
+                .build()
+                .unwrap();
+            api.todo().await.unwrap();
+            let requests = requests.await.unwrap();
+            assert_eq!(requests[0].path, "/v1/todo");
+            assert_eq!(requests[0].header("x-tenant"), Some("acme"));
+
+            let api = BuilderTest::builder().build().unwrap();
+            assert_eq!(api.base_url().unwrap().as_str(), "http://127.0.0.1:1/v2/");
+            assert!(api.default_headers().is_empty());
+        }
+
+        #[test]
+        fn invalid() {
+            let error = BuilderTest::builder()
+                .base_url("staging")
+                .build()
+                .unwrap_err();
+            assert!(matches!(error, Error::InvalidBaseUrl { url } if url == "staging"));
+
+            let error = BuilderTest::builder()
+                .default_header("x-tenant", "a\nb")
+                .default_header("x tenant", "acme")
+                .build()
+                .unwrap_err();
+            assert!(matches!(error, Error::InvalidHeaderValue { name } if name == "x-tenant"));
+        }
+    }
+
+    mod derives {
+        use crate::{api, Api};
+
+        api!(
+            /// A client with its own derives.
+            #[derive(Clone, Debug)]
+            #[allow(dead_code)]
+            pub struct DeriveTest
+        );
+        api!(#[derive(Default)] pub struct DeriveBaseTest, base = "http://127.0.0.1:1/v2");
+
+        #[test]
+        fn derives() {
+            let api = DeriveTest::default().clone();
+            assert!(format!("{api:?}").starts_with("DeriveTest("));
+
+            let api = DeriveBaseTest::default().clone();
+            assert_eq!(api.base_url().unwrap().as_str(), "http://127.0.0.1:1/v2/");
+        }
+    }
+
+    mod fields {
+        use reqwest::{header::AUTHORIZATION, Client, RequestBuilder, Url};
+
+        use super::server::{self, Response};
+        use crate::{api, Api};
+
+        api!(
+            pub struct FieldTest {
+                token: String,
+                base: Url,
+            }
+
+            impl Api {
+                fn base_url(&self) -> Option<&Url> {
+                    Some(&self.base)
+                }
+
+                fn pre_request(&self, request: RequestBuilder) -> reqwest::Result<RequestBuilder> {
+                    Ok(request.bearer_auth(&self.token))
+                }
+            }
+        );
+
+        api!(
+            pub struct AsyncFieldTest {
+                token: String,
+            }
+
+            impl Api {
+                async fn pre_request_async(
+                    &self,
+                    request: RequestBuilder,
+                ) -> crate::Result<RequestBuilder> {
+                    Ok(request.bearer_auth(&self.token))
+                }
+            }
+        );
+
+        impl FieldTest {
+            api! {
+                pub fn todo() -> StatusCode {
+                    GET "/todo"
+                }
+            }
+        }
+
+        impl AsyncFieldTest {
+            api! {
+                pub fn todo(base: &str) -> StatusCode {
+                    GET "{base}/todo"
+                }
+            }
+        }
+
+        #[tokio::test]
+        async fn fields() {
+            let (base, requests) = server::serve(vec![Response::new(200)]).await;
+
+            let api = FieldTest::new(String::from("secret"), Url::parse(&base).unwrap());
+            api.todo().await.unwrap();
+            let requests = requests.await.unwrap();
+            assert_eq!(requests[0].path, "/todo");
+            assert_eq!(
+                requests[0].header(AUTHORIZATION.as_str()),
+                Some("Bearer secret")
+            );
+
+            let (base, requests) = server::serve(vec![Response::new(200)]).await;
+
+            AsyncFieldTest::with_client(Client::new(), String::from("other"))
+                .todo(&base)
+                .await
+                .unwrap();
+            assert_eq!(
+                requests.await.unwrap()[0].header(AUTHORIZATION.as_str()),
+                Some("Bearer other")
+            );
+        }
+    }
+
+    mod middlewares {
+        use std::sync::{Arc, Mutex};
+
+        use reqwest::{header::HeaderValue, Client, Request, Response as HttpResponse};
+
+        use super::server::{self, Response};
+        use crate::{api, Api, Error, Execute, Middleware, Next};
+
+        struct Tag(&'static str, Arc<Mutex<Vec<String>>>);
+
+        #[cfg_attr(feature = "send", async_trait::async_trait)]
+        #[cfg_attr(not(feature = "send"), async_trait::async_trait(?Send))]
+        impl Middleware for Tag {
+            async fn handle(
+                &self,
+                mut request: Request,
+                next: Next<'_>,
+            ) -> crate::Result<HttpResponse> {
+                request
+                    .headers_mut()
+                    .append("x-tag", HeaderValue::from_static(self.0));
+                let response = next.run(request).await?;
+                self.1
+                    .lock()
+                    .unwrap()
+                    .push(format!("{} {}", self.0, response.status().as_u16()));
+                Ok(response)
+            }
+        }
+
+        struct MiddlewareTest {
+            client: Client,
+            middlewares: Vec<Arc<dyn Middleware>>,
+        }
+
+        impl Api for MiddlewareTest {
+            fn client(&self) -> &Client {
+                &self.client
+            }
+
+            fn middlewares(&self) -> &[Arc<dyn Middleware>] {
+                &self.middlewares
+            }
+        }
+
+        impl MiddlewareTest {
+            api! {
+                pub fn todo(base: &str) -> StatusCode {
+                    GET "{base}/todo"
+                }
+            }
+        }
+
+        #[tokio::test]
+        async fn chain() {
+            let (base, requests) = server::serve(vec![Response::new(204)]).await;
+
+            let seen = Arc::new(Mutex::new(Vec::new()));
+            let api = MiddlewareTest {
+                client: Client::new(),
+                middlewares: vec![
+                    Arc::new(Tag("outer", seen.clone())),
+                    Arc::new(Tag("inner", seen.clone())),
+                ],
+            };
+            api.todo(&base).await.unwrap();
+
+            let tags: Vec<_> = requests.await.unwrap()[0]
+                .headers
+                .iter()
+                .filter(|(name, _)| name == "x-tag")
+                .map(|(_, value)| value.clone())
+                .collect();
+            assert_eq!(tags, ["outer", "inner"]);
+            assert_eq!(*seen.lock().unwrap(), ["inner 204", "outer 204"]);
+        }
+
+        #[tokio::test]
+        async fn execute() {
+            let (base, requests) = server::serve(vec![Response::new(204)]).await;
+
+            let other = Client::new();
+            let api = MiddlewareTest {
+                client: Client::new(),
+                middlewares: vec![Arc::new(Execute::new(move |request| {
+                    let other = other.clone();
+                    async move { other.execute(request).await }
+                }))],
+            };
+            api.todo(&base).await.unwrap();
+            assert_eq!(requests.await.unwrap()[0].path, "/todo");
+
+            let api = MiddlewareTest {
+                client: Client::new(),
+                middlewares: vec![Arc::new(Execute::new(|_| async {
+                    Err::<HttpResponse, _>("offline")
+                }))],
+            };
+            let error = api.todo(&base).await.unwrap_err();
+            assert!(matches!(error, Error::Service(error) if error.to_string() == "offline"));
+        }
+    }
+
+    #[cfg(feature = "json")]
+    mod default_headers {
+        use reqwest::{
+            header::{HeaderMap, HeaderValue, ACCEPT, ACCEPT_LANGUAGE},
+            Client,
+        };
+
+        use super::server::{self, Response};
+        use crate::{api, Api};
+
+        struct TenantTest {
+            client: Client,
+            tenant: &'static str,
+        }
+
+        impl Api for TenantTest {
+            fn client(&self) -> &Client {
+                &self.client
+            }
+
+            fn default_headers(&self) -> HeaderMap {
+                let mut headers = HeaderMap::new();
+                headers.insert("x-tenant", HeaderValue::from_static(self.tenant));
+                headers.insert(ACCEPT_LANGUAGE, HeaderValue::from_static("en"));
+                headers.insert(ACCEPT, HeaderValue::from_static("application/xml"));
+                headers
+            }
+        }
+
+        impl TenantTest {
+            api! {
+                fn todos(base: &str) -> String {
+                    GET "{base}/todos"
+                }
+
+                fn localized(base: &str, language: Option<&str>) -> Json<u32> {
+                    GET "{base}/localized"
+                    ACCEPT_LANGUAGE?: language
+                    "x-request-id": "1"
+                }
+            }
+        }
+
+        #[tokio::test]
+        async fn applied_and_overridden() {
+            let (base, requests) = server::serve(vec![
+                Response::new(200),
+                Response::new(200).body("1"),
+                Response::new(200).body("1"),
+            ])
+            .await;
+
+            let api = TenantTest {
+                client: Client::new(),
+                tenant: "acme",
+            };
+            api.todos(&base).await.unwrap();
+            api.localized(&base, Some("fr")).await.unwrap();
+            api.localized(&base, None).await.unwrap();
+
+            let requests = requests.await.unwrap();
+            let values = |index: usize, name: &str| -> Vec<&str> {
+                requests[index]
+                    .headers
+                    .iter()
+                    .filter(|(key, _)| key.eq_ignore_ascii_case(name))
+                    .map(|(_, value)| value.as_str())
+                    .collect()
+            };
+            assert_eq!(values(0, "x-tenant"), ["acme"]);
+            assert_eq!(values(0, "accept-language"), ["en"]);
+            assert_eq!(values(0, "accept"), ["text/plain, */*;q=0.8"]);
+            assert_eq!(values(1, "accept-language"), ["fr"]);
+            assert_eq!(values(1, "accept"), ["application/json"]);
+            assert_eq!(values(1, "x-request-id"), ["1"]);
+            assert_eq!(values(2, "accept-language"), ["en"]);
+        }
+    }
+
+    mod default_query {
+        use serde::Serialize;
+
+        use super::server::{self, Response};
+        use crate::api;
+
+        api!(pub struct DefaultQueryTest);
+
+        #[derive(Serialize)]
+        struct Format {
+            format: &'static str,
+        }
+
+        impl DefaultQueryTest {
+            api! {
+                fn todos(base: &str) -> StatusCode {
+                    GET "{base}/todos"
+                }
+
+                fn search(base: &str) -> StatusCode {
+                    GET "{base}/search?q=milk"
+                    ? format: "xml"
+                }
+
+                fn export(base: &str, format: Query<Format>) -> StatusCode {
+                    GET "{base}/export"
+                }
+            }
+        }
+
+        #[tokio::test]
+        async fn appended_once() {
+            let (base, requests) = server::serve(vec![
+                Response::new(200),
+                Response::new(200),
+                Response::new(200),
+            ])
+            .await;
+            let api = DefaultQueryTest::builder()
+                .default_query("api_key", "a b")
+                .default_query("format", "json")
+                .build()
+                .unwrap();
+            api.todos(&base).await.unwrap();
+            api.search(&base).await.unwrap();
+            api.export(&base, &Format { format: "csv" }).await.unwrap();
+
+            let paths = requests
+                .await
+                .unwrap()
+                .into_iter()
+                .map(|request| request.path)
+                .collect::<Vec<_>>();
+            assert_eq!(
+                paths,
+                [
+                    "/todos?api_key=a+b&format=json",
+                    "/search?q=milk&format=xml&api_key=a+b",
+                    "/export?format=csv&api_key=a+b",
+                ]
+            );
+        }
+    }
+
+    mod pre_request_for {
+        use std::sync::Mutex;
+
+        use reqwest::{Client, Method, RequestBuilder};
+
+        use crate::{api, Api, EndpointInfo};
+
+        struct SigningTest {
+            client: Client,
+            endpoints: Mutex<Vec<String>>,
+        }
+
+        impl Api for SigningTest {
+            fn client(&self) -> &Client {
+                &self.client
+            }
+
+            fn pre_request_for(
+                &self,
+                endpoint: &EndpointInfo,
+                request: RequestBuilder,
+            ) -> reqwest::Result<RequestBuilder> {
+                self.endpoints.lock().unwrap().push(format!(
+                    "{} {} {}",
+                    endpoint.name, endpoint.method, endpoint.url_template
+                ));
+                Ok(request)
+            }
+        }
+
+        impl SigningTest {
+            api! {
+                fn todo(&self, id: u32) -> String {
+                    GET "http://127.0.0.1:1/todos/{id}"
+                    url = todo_url
+                    request = todo_request
+                }
+
+                group "http://127.0.0.1:1/projects/{project}" (project: &str) {
+                    fn ingest(method: Method) -> StatusCode {
+                        {method} "/ingest"
+                        request = ingest_request
+                    }
+                }
+            }
+        }
+
+        #[tokio::test]
+        async fn endpoint_info() {
+            let api = SigningTest {
+                client: Client::new(),
+                endpoints: Mutex::new(Vec::new()),
+            };
+            api.todo_request(1).await.unwrap();
+            api.ingest_request("a", Method::PUT).await.unwrap();
+            assert_eq!(
+                *api.endpoints.lock().unwrap(),
+                [
+                    "todo GET http://127.0.0.1:1/todos/{id}",
+                    "ingest PUT http://127.0.0.1:1/projects/{project}/ingest",
+                ]
+            );
+        }
+    }
+
+    mod pre_request_async {
+        use std::sync::atomic::{AtomicU32, Ordering};
+
+        use reqwest::{header::AUTHORIZATION, Client, RequestBuilder};
+
+        use super::server::{self, Response};
+        use crate::{api, Api};
+
+        struct RefreshTest {
+            client: Client,
+            refreshes: AtomicU32,
+        }
+
+        impl Api for RefreshTest {
+            fn client(&self) -> &Client {
+                &self.client
+            }
+
+            async fn pre_request_async(
+                &self,
+                request: RequestBuilder,
+            ) -> crate::Result<RequestBuilder> {
+                tokio::task::yield_now().await;
+                let refreshes = self.refreshes.fetch_add(1, Ordering::SeqCst) + 1;
+                Ok(request.bearer_auth(format!("token-{refreshes}")))
+            }
+        }
+
+        impl RefreshTest {
+            api! {
+                fn me(base: &str) -> StatusCode {
+                    GET "{base}/me"
+                }
+
+                fn token(base: &str) -> StatusCode {
+                    POST "{base}/token"
+                    no_pre_request
+                }
+            }
+        }
+
+        #[tokio::test]
+        async fn awaited() {
+            let (base, requests) = server::serve(vec![
+                Response::new(200),
+                Response::new(200),
+                Response::new(200),
+            ])
+            .await;
+
+            let api = RefreshTest {
+                client: Client::new(),
+                refreshes: AtomicU32::new(0),
+            };
+            api.me(&base).await.unwrap();
+            api.token(&base).await.unwrap();
+            api.me(&base).await.unwrap();
+
+            let requests = requests.await.unwrap();
+            assert_eq!(
+                requests[0].header(AUTHORIZATION.as_str()),
+                Some("Bearer token-1")
+            );
+            assert_eq!(requests[1].header(AUTHORIZATION.as_str()), None);
+            assert_eq!(
+                requests[2].header(AUTHORIZATION.as_str()),
+                Some("Bearer token-2")
+            );
+        }
+    }
+
+    #[cfg(feature = "json")]
+    mod on_error {
+        use std::sync::Mutex;
+
+        use reqwest::Client;
+        use serde::Deserialize;
+
+        use super::server::{self, Response};
+        use crate::{api, Api, Error};
+
+        #[derive(Debug, Deserialize)]
+        pub struct Problem {
+            detail: String,
+        }
+
+        struct ReportTest {
+            client: Client,
+            failures: Mutex<Vec<String>>,
+        }
+
+        impl Api for ReportTest {
+            fn client(&self) -> &Client {
+                &self.client
+            }
+
+            fn on_error(&self, endpoint: &str, error: &Error) {
+                let kind = match error {
+                    Error::Transport(_) => "transport",
+                    Error::Decode { .. } => "decode",
+                    Error::UnexpectedStatus { .. } => "status",
+                    _ => "other",
+                };
+                self.failures
+                    .lock()
+                    .unwrap()
+                    .push(format!("{endpoint}: {kind}"));
+            }
+        }
+
+        impl ReportTest {
+            api! {
+                fn count(base: &str) -> Json<u32> {
+                    GET "{base}/count"
+                }
+
+                fn accepted(base: &str) -> StatusCode {
+                    POST "{base}/jobs"
+                    expect 202
+                }
+
+                fn problem(base: &str) -> Result<Json<u32>, Json<Problem>> {
+                    GET "{base}/problem"
+                }
+            }
+        }
+
+        #[tokio::test]
+        async fn reports_failures() {
+            let (base, _) = server::serve(vec![
+                Response::new(200).body("1"),
+                Response::new(200).body("one"),
+                Response::new(200),
+                Response::new(404).body(r#"{"detail": "missing"}"#),
+            ])
+            .await;
+
+            let api = ReportTest {
+                client: Client::new(),
+                failures: Mutex::new(Vec::new()),
+            };
+            assert_eq!(api.count(&base).await.unwrap(), 1);
+            assert!(api.count(&base).await.is_err());
+            assert!(api.accepted(&base).await.is_err());
+            match api.problem(&base).await {
+                Err(crate::ApiError::Api { body, .. }) => assert_eq!(body.detail, "missing"),
+                other => panic!("expected an API error, got {other:?}"),
+            }
+            assert!(api.count("http://127.0.0.1:9").await.is_err());
+
+            assert_eq!(
+                *api.failures.lock().unwrap(),
+                [
+                    "count: decode",
+                    "accepted: status",
+                    "problem: status",
+                    "count: transport"
+                ]
+            );
+        }
+    }
+
+    mod basic_auth {
+        use reqwest::{header::AUTHORIZATION, Client, RequestBuilder};
+
+        use super::server::{self, Response};
+        use crate::{api, Api};
+
+        struct AdminTest(Client);
+
+        impl Api for AdminTest {
+            fn client(&self) -> &Client {
+                &self.0
+            }
+
+            fn pre_request(&self, request: RequestBuilder) -> reqwest::Result<RequestBuilder> {
+                Ok(request.bearer_auth("token"))
+            }
+        }
+
+        impl AdminTest {
+            api! {
+                fn purge(base: &str, user: &str, password: &str) -> StatusCode {
+                    POST "{base}/admin/purge"
+                    basic_auth user, Some(password);
+                    "x-request-id": "1"
+                }
+
+                fn ping(base: &str, user: &str) -> StatusCode {
+                    GET "{base}/admin/ping"
+                    basic_auth user, None::<&str>
+                }
+            }
+        }
+
+        #[tokio::test]
+        async fn replaces_pre_request() {
+            let (base, requests) =
+                server::serve(vec![Response::new(200), Response::new(200)]).await;
+
+            let api = AdminTest(Client::new());
+            api.purge(&base, "admin", "pässword").await.unwrap();
+            api.ping(&base, "admin").await.unwrap();
+
+            let requests = requests.await.unwrap();
+            let authorization = |index: usize| -> Vec<&str> {
+                requests[index]
+                    .headers
+                    .iter()
+                    .filter(|(name, _)| name.eq_ignore_ascii_case(AUTHORIZATION.as_str()))
+                    .map(|(_, value)| value.as_str())
+                    .collect()
+            };
+            assert_eq!(authorization(0), ["Basic YWRtaW46cMOkc3N3b3Jk"]);
+            assert_eq!(authorization(1), ["Basic YWRtaW46"]);
+        }
+    }
+
+    mod cookies {
+        use super::server::{self, Response};
+        use crate::{api, Api};
+
+        api!(pub struct CookieTest);
+
+        impl CookieTest {
+            api! {
+                pub fn dashboard(base: &str, session: &str, locale: &str) -> StatusCode {
+                    GET "{base}/dashboard"
+                    cookie session: "s-{session}"
+                    "x-request-id": "1",
+                    cookie "locale": locale,
+                    cookie theme: String::from("dark")
+                }
+
+                pub fn plain(base: &str) -> StatusCode {
+                    GET "{base}/plain"
+                }
+            }
+        }
+
+        #[tokio::test]
+        async fn single_header() {
+            let (base, requests) =
+                server::serve(vec![Response::new(200), Response::new(200)]).await;
+
+            let api = CookieTest::new();
+            api.dashboard(&base, "a b;c", "en-GB").await.unwrap();
+            api.plain(&base).await.unwrap();
+
+            let cookies: Vec<Vec<_>> = requests
+                .await
+                .unwrap()
+                .iter()
+                .map(|request| {
+                    request
+                        .headers
+                        .iter()
+                        .filter(|(name, _)| name.eq_ignore_ascii_case("cookie"))
+                        .map(|(_, value)| value.clone())
+                        .collect()
+                })
+                .collect();
+            assert_eq!(
+                cookies[0],
+                ["session=s-a%20b%3Bc; locale=en-GB; theme=dark"]
+            );
+            assert!(cookies[1].is_empty());
+        }
+    }
+
+    #[cfg(feature = "cookies")]
+    mod cookie_store {
+        use super::server::{self, Response};
+        use crate::api;
+
+        api!(pub struct CookieStoreTest, cookie_store);
+
+        impl CookieStoreTest {
+            api! {
+                pub fn visit(base: &str) -> StatusCode {
+                    GET "{base}/visit"
+                }
+            }
+        }
+
+        #[tokio::test]
+        async fn round_trip() {
+            let (base, requests) = server::serve(vec![
+                Response::new(200).header("set-cookie", "visitor=abc; Path=/"),
+                Response::new(200),
+            ])
+            .await;
+
+            let api = CookieStoreTest::try_new().unwrap();
+            api.visit(&base).await.unwrap();
+            api.visit(&base).await.unwrap();
+
+            let requests = requests.await.unwrap();
+            assert_eq!(requests[0].header("cookie"), None);
+            assert_eq!(requests[1].header("cookie"), Some("visitor=abc"));
+        }
+    }
+
+    #[cfg(all(feature = "json", feature = "multipart"))]
+    mod content_type {
+        use reqwest::header::CONTENT_TYPE;
+        use serde::Serialize;
+
+        use super::server::{self, Response};
+        use crate::{api, Api, Error};
+
+        #[derive(Serialize)]
+        pub struct Note {
+            title: &'static str,
+        }
+
+        api!(pub struct ContentTypeTest);
+
+        impl ContentTypeTest {
+            api! {
+                pub fn json(base: &str, note: Json<Note>) -> StatusCode {
+                    POST "{base}/json"
+                    CONTENT_TYPE: "application/vnd.example+json"
+                }
+
+                pub fn form(base: &str, note: Form<Note>, content_type: Option<&str>) -> StatusCode {
+                    POST "{base}/form"
+                    "content-type"?: content_type
+                }
+
+                pub fn multipart(base: &str, form: Multipart, version: u32) -> StatusCode {
+                    POST "{base}/multipart"
+                    "Content-Type": "multipart/related; version={version}"
+                }
+
+                pub fn invalid(base: &str) -> StatusCode {
+                    POST "{base}/invalid"
+                    CONTENT_TYPE: String::from("text/plain\n");
+                }
+            }
+        }
+
+        #[tokio::test]
+        async fn declared_wins() {
+            let (base, requests) = server::serve(vec![
+                Response::new(200),
+                Response::new(200),
+                Response::new(200),
+                Response::new(200),
+            ])
+            .await;
+
+            let api = ContentTypeTest::new();
+            let note = Note { title: "a" };
+            api.json(&base, &note).await.unwrap();
+            api.form(&base, &note, Some("application/x-example-form"))
+                .await
+                .unwrap();
+            api.form(&base, &note, None).await.unwrap();
+            api.multipart(&base, reqwest::multipart::Form::new().text("a", "b"), 2)
+                .await
+                .unwrap();
+
+            let content_types: Vec<Vec<_>> = requests
+                .await
+                .unwrap()
+                .iter()
+                .map(|request| {
+                    request
+                        .headers
+                        .iter()
+                        .filter(|(name, _)| name.eq_ignore_ascii_case("content-type"))
+                        .map(|(_, value)| value.clone())
+                        .collect()
+                })
+                .collect();
+            assert_eq!(content_types[0], ["application/vnd.example+json"]);
+            assert_eq!(content_types[1], ["application/x-example-form"]);
+            assert_eq!(content_types[2], ["application/x-www-form-urlencoded"]);
+            assert_eq!(content_types[3], ["multipart/related; version=2"]);
+        }
+
+        #[tokio::test]
+        async fn invalid_value() {
+            let api = ContentTypeTest::new();
+            match api.invalid("http://127.0.0.1:9").await {
+                Err(Error::InvalidHeaderValue { name }) => assert_eq!(name, "content-type"),
+                other => panic!("expected an invalid header value error, got {other:?}"),
+            }
+        }
+    }
+
+    #[cfg(all(feature = "json", feature = "multipart"))]
+    mod bodies {
+        use serde::{Deserialize, Serialize};
+
+        use super::server::{self, Response};
+        use crate::{api, Api};
+
+        #[derive(Debug, Deserialize, PartialEq, Serialize)]
+        pub struct Login {
+            user: String,
+        }
+
+        api!(pub struct BodyTest);
+
+        impl BodyTest {
+            api! {
+                /// Logs in.
+                pub fn login(base: &str, form: Form<Login>) -> Bytes {
+                    POST "{base}/login"
+                }
+
+                pub fn upload(base: &str, form: Multipart) -> Json<Login> {
+                    POST "{base}/upload"
+                }
+
+                pub fn echo(base: &str, query: Query<Login>, body: Json<Login>) -> Json<Login> {
+                    POST "{base}/echo"
+                }
+            }
+        }
+
+        #[tokio::test]
+        async fn combinations() {
+            let (base, requests) = server::serve(vec![
+                Response::new(200).body("token"),
+                Response::new(200).body(r#"{"user":"b"}"#),
+                Response::new(200).body(r#"{"user":"c"}"#),
+            ])
+            .await;
+
+            let api = BodyTest::new();
+            let login = Login {
+                user: "a".to_string(),
+            };
+            assert_eq!(api.login(&base, &login).await.unwrap(), "token");
+            let form = reqwest::multipart::Form::new().text("user", "b");
+            assert_eq!(api.upload(&base, form).await.unwrap().user, "b");
+            assert_eq!(api.echo(&base, &login, &login).await.unwrap().user, "c");
+
+            let requests = requests.await.unwrap();
+            assert_eq!(requests[0].body, b"user=a");
+            assert!(requests[1]
+                .header("content-type")
+                .unwrap()
+                .starts_with("multipart/form-data"));
+            assert_eq!(requests[2].path, "/echo?user=a");
+            assert_eq!(requests[2].body, br#"{"user":"a"}"#);
+        }
+    }
+
+    mod cfg_endpoints {
+        use super::server::{self, Response};
+        use crate::{api, Api};
+
+        api!(pub struct CfgTest);
+
+        impl CfgTest {
+            api! {
+                /// Decodes the todo.
+                #[cfg(feature = "json")]
+                pub fn todo(base: &str) -> Json<super::example::Todo> {
+                    GET "{base}/todo"
+                }
+
+                /// Returns the todo as text.
+                #[cfg(not(feature = "json"))]
+                pub fn todo(base: &str) -> String {
+                    GET "{base}/todo"
+                }
+
+                #[cfg(any())]
+                pub fn removed() -> Json<Missing> {
+                    GET "https://example.com/removed"
+                }
+
+                #[cfg_attr(all(), doc = "Checks the todo exists.")]
+                pub fn exists(base: &str) -> StatusCode {
+                    HEAD "{base}/todo"
+                }
+            }
+        }
+
+        #[tokio::test]
+        async fn gated() {
+            let body = r#"{"userId":1,"id":2,"title":"gated","completed":false}"#;
+            let (base, _) =
+                server::serve(vec![Response::new(200).body(body), Response::new(200)]).await;
+
+            let api = CfgTest::new();
+            let todo = api.todo(&base).await.unwrap();
+            #[cfg(feature = "json")]
+            assert_eq!(todo.title, "gated");
+            #[cfg(not(feature = "json"))]
+            assert_eq!(todo, body);
+            assert_eq!(api.exists(&base).await.unwrap(), 200);
+        }
+    }
+
+    mod timeout {
+        use std::time::Duration;
+
+        use super::server::{self, Response};
+        use crate::{api, Api, Error};
+
+        api!(pub struct TimeoutTest);
+
+        impl TimeoutTest {
+            api! {
+                pub fn slow(base: &str) -> Bytes {
+                    GET "{base}/slow"
+                    timeout = 5 ms
+                }
+
+                pub fn search(base: &str, timeout: Duration) -> Bytes {
+                    GET "{base}/search"
+                    timeout = timeout
+                }
+            }
+        }
+
+        #[tokio::test]
+        async fn per_endpoint() {
+            let api = TimeoutTest::new();
+            let (base, _) =
+                server::serve(vec![Response::new(200).body_parts(["a", "b", "c"])]).await;
+            assert!(matches!(
+                api.slow(&base).await,
+                Err(Error::Transport(error)) if error.is_timeout()
+            ));
+
+            let (base, _) =
+                server::serve(vec![Response::new(200).body_parts(["a", "b", "c"])]).await;
+            assert_eq!(
+                api.search(&base, Duration::from_secs(5)).await.unwrap(),
+                "abc"
+            );
+        }
+    }
+
+    mod trailing_commas {
+        use reqwest::header::USER_AGENT;
+
+        use super::server::{self, Response};
+        use crate::{api, Api};
+
+        api!(pub struct CommaTest);
+
+        impl CommaTest {
+            api! {
+                pub fn tagged(base: &str, tag: &str,) -> StatusCode {
+                    GET "{base}/tags/{tag}",
+                    USER_AGENT: "comma-test",
+                    "x-tag": tag.to_uppercase(),
+                    ? page: "1",
+                }
+
+                pub fn tracked(base: &str,) -> Bytes {
+                    GET "{base}/tracked"
+                    with_progress
+                }
+            }
+        }
+
+        #[tokio::test]
+        async fn tolerated() {
+            let (base, requests) =
+                server::serve(vec![Response::new(200), Response::new(200).body("ok")]).await;
+
+            let api = CommaTest::new();
+            assert_eq!(api.tagged(&base, "a").await.unwrap(), 200);
+            assert_eq!(api.tracked(&base, |_, _| {}).await.unwrap(), "ok");
+
+            let requests = requests.await.unwrap();
+            assert_eq!(requests[0].path, "/tags/a?page=1");
+            assert_eq!(requests[0].header("user-agent"), Some("comma-test"));
+            assert_eq!(requests[0].header("x-tag"), Some("A"));
+        }
+    }
+
+    #[cfg(feature = "json")]
+    mod typed_errors {
+        use serde::Deserialize;
+
+        use super::server::{self, Response};
+        use crate::{api, Api, ApiError, Error};
+
+        #[derive(Debug, Deserialize, PartialEq)]
+        pub struct Widget {
+            id: u32,
+        }
+
+        #[derive(Debug, Deserialize, PartialEq)]
+        pub struct Problem {
+            code: String,
+        }
+
+        api!(pub struct TypedErrorTest);
+
+        impl TypedErrorTest {
+            api! {
+                pub fn widget(base: &str, id: u32) -> Result<Json<Widget>, Json<Problem>> {
+                    GET "{base}/widgets/{id}"
+                }
+            }
+        }
+
+        #[tokio::test]
+        async fn success() {
+            let (base, _) = server::serve(vec![Response::new(200).body(r#"{"id":1}"#)]).await;
+
+            let api = TypedErrorTest::new();
+            assert_eq!(api.widget(&base, 1).await.unwrap(), Widget { id: 1 });
+        }
+
+        #[tokio::test]
+        async fn error_body() {
+            let (base, _) =
+                server::serve(vec![Response::new(404).body(r#"{"code":"not_found"}"#)]).await;
+
+            let api = TypedErrorTest::new();
+            match api.widget(&base, 1).await {
+                Err(ApiError::Api { status, body }) => {
+                    assert_eq!(status, 404);
+                    assert_eq!(body.code, "not_found");
+                }
+                other => panic!("unexpected result: {other:?}"),
+            }
+        }
+
+        #[tokio::test]
+        async fn unparsed_error_body() {
+            let (base, _) =
+                server::serve(vec![Response::new(502).body("<html>Bad Gateway</html>")]).await;
+
+            let api = TypedErrorTest::new();
+            match api.widget(&base, 1).await {
+                Err(ApiError::Unparsed { status, body }) => {
+                    assert_eq!(status, 502);
+                    assert_eq!(body, "<html>Bad Gateway</html>");
+                }
+                other => panic!("unexpected result: {other:?}"),
+            }
+        }
+
+        #[tokio::test]
+        async fn transport_error() {
+            let api = TypedErrorTest::new();
+            assert!(matches!(
+                api.widget("http://127.0.0.1:9", 1).await,
+                Err(ApiError::Other(Error::Transport(_)))
+            ));
+        }
+    }
+
+    #[cfg(feature = "stream")]
+    mod byte_stream {
+        use std::{future::poll_fn, pin::Pin};
+
+        use futures_core::Stream;
+
+        use super::server::{self, Response};
+        use crate::{api, Api, ByteStream};
+
+        api!(pub struct StreamTest);
+
+        impl StreamTest {
+            api! {
+                pub fn artifact(base: &str, id: u32) -> ByteStream {
+                    GET "{base}/artifacts/{id}"
+                }
+            }
+        }
+
+        async fn collect(mut stream: ByteStream) -> Vec<u8> {
+            let mut body = Vec::new();
+            while let Some(chunk) = poll_fn(|cx| Pin::new(&mut stream).poll_next(cx)).await {
+                body.extend_from_slice(&chunk.unwrap());
+            }
+            body
+        }
+
+        #[tokio::test]
+        async fn streams_body() {
+            let (base, _) = server::serve(vec![Response::new(200).body(vec![7; 100_000])]).await;
+
+            let stream = {
+                let api = StreamTest::new();
+                api.artifact(&base, 1).await.unwrap()
+            };
+            let body = tokio::spawn(collect(stream)).await.unwrap();
+            assert_eq!(body, vec![7; 100_000]);
+        }
+
+        #[tokio::test]
+        async fn checks_status() {
+            let (base, _) = server::serve(vec![Response::new(404).body("missing")]).await;
+
+            let api = StreamTest::new();
+            let error = api.artifact(&base, 1).await.unwrap_err();
+            assert!(
+                matches!(&error, crate::Error::UnexpectedStatus { .. }),
+                "{error:?}"
+            );
+            assert_eq!(error.status(), Some(reqwest::StatusCode::NOT_FOUND));
+            assert_eq!(error.body(), Some("missing"));
+        }
+    }
+
+    #[cfg(all(feature = "stream", feature = "json"))]
+    mod lines {
+        use std::{future::poll_fn, pin::Pin};
+
+        use futures_core::Stream;
+        use serde::Deserialize;
+
+        use super::server::{self, Response};
+        use crate::{api, Api, Error, Lines};
+
+        #[derive(Debug, PartialEq, Eq, Deserialize)]
+        pub struct Event {
+            pub id: u32,
+            pub kind: String,
+        }
+
+        api!(pub struct LinesTest);
+
+        impl LinesTest {
+            api! {
+                pub fn events(base: &str) -> Lines<Event> {
+                    GET "{base}/events"
+                }
+            }
+        }
+
+        async fn collect(mut lines: Lines<Event>) -> Vec<crate::Result<Event>> {
+            let mut items = Vec::new();
+            while let Some(item) = poll_fn(|cx| Pin::new(&mut lines).poll_next(cx)).await {
+                items.push(item);
+            }
+            items
+        }
+
+        fn event(id: u32, kind: &str) -> Event {
+            Event {
+                id,
+                kind: kind.to_string(),
+            }
+        }
+
+        #[tokio::test]
+        async fn lines_split_across_chunks() {
+            let (base, _) = server::serve(vec![Response::new(200).body_parts([
+                "{\"id\":1,\"kind\":\"cre",
+                "ate\"}\n{\"id\":2,",
+                "\"kind\":\"update\"}\r\n\n",
+                "{\"id\":3,\"kind\":\"delete\"}",
+            ])])
+            .await;
+
+            let api = LinesTest::new();
+            let items: Vec<_> = collect(api.events(&base).await.unwrap())
+                .await
+                .into_iter()
+                .map(Result::unwrap)
+                .collect();
+            assert_eq!(
+                items,
+                [event(1, "create"), event(2, "update"), event(3, "delete")]
+            );
+        }
+
+        #[tokio::test]
+        async fn invalid_line() {
+            let (base, _) = server::serve(vec![Response::new(200).body(
+                "{\"id\":1,\"kind\":\"create\"}\nnot json\n{\"id\":3,\"kind\":\"delete\"}\n",
+            )])
+            .await;
+
+            let api = LinesTest::new();
+            let items = collect(api.events(&base).await.unwrap()).await;
+            assert_eq!(items.len(), 3);
+            assert!(matches!(items[1], Err(Error::Decode { length: 9, .. })));
+            assert_eq!(items[2].as_ref().unwrap(), &event(3, "delete"));
+        }
+
+        #[tokio::test]
+        async fn many_lines_in_one_chunk() {
+            let body = (0..20_000)
+                .map(|id| format!("{{\"id\":{id},\"kind\":\"create\"}}"))
+                .collect::<Vec<_>>()
+                .join("\n");
+            let (base, _) = server::serve(vec![Response::new(200).body(body)]).await;
+
+            let api = LinesTest::new();
+            let items = collect(api.events(&base).await.unwrap()).await;
+            assert_eq!(items.len(), 20_000);
+            assert_eq!(items[19_999].as_ref().unwrap(), &event(19_999, "create"));
+        }
+
+        #[tokio::test]
+        async fn error_status() {
+            let (base, _) = server::serve(vec![Response::new(503).body("unavailable")]).await;
+
+            let api = LinesTest::new();
+            let error = api.events(&base).await.err().unwrap();
+            assert!(
+                matches!(&error, Error::UnexpectedStatus { .. }),
+                "{error:?}"
+            );
+            assert_eq!(error.body(), Some("unavailable"));
+        }
+    }
+
+    #[cfg(all(feature = "stream", feature = "json"))]
+    mod events {
+        use std::{future::poll_fn, pin::Pin, time::Duration};
+
+        use futures_core::Stream;
+        use serde::Deserialize;
+
+        use super::server::{self, Response};
+        use crate::{api, Api, Event, Events};
+
+        #[derive(Debug, PartialEq, Eq, Deserialize)]
+        pub struct Delta {
+            pub text: String,
+        }
+
+        api!(pub struct EventsTest);
+
+        impl EventsTest {
+            api! {
+                pub fn events(base: &str) -> Events {
+                    GET "{base}/events"
+                }
+
+                pub fn deltas(base: &str) -> Sse<Delta> {
+                    GET "{base}/deltas"
+                }
+            }
+        }
+
+        async fn collect<T>(mut events: Events<T>) -> Vec<crate::Result<Event<T>>> {
+            let mut items = Vec::new();
+            while let Some(item) = poll_fn(|cx| Pin::new(&mut events).poll_next(cx)).await {
+                items.push(item);
+            }
+            items
+        }
+
+        #[tokio::test]
+        async fn parses_events() {
+            let (base, _) = server::serve(vec![Response::new(200)
+                .header("content-type", "text/event-stream")
+                .body_parts([
+                    ": connected\n\nretry: 3000\nid: 1\nevent: greeting\nda",
+                    "ta: hello\r",
+                    "\ndata:world\r\n\r\n:\n\n",
+                    "data: second\n\nid: 2\ndata: never finished\n",
+                ])])
+            .await;
+
+            let api = EventsTest::new();
+            let events: Vec<_> = collect(api.events(&base).await.unwrap())
+                .await
+                .into_iter()
+                .map(Result::unwrap)
+                .collect();
+            assert_eq!(
+                events,
+                [
+                    Event {
+                        id: Some("1".to_string()),
+                        event: Some("greeting".to_string()),
+                        data: "hello\nworld".to_string(),
+                        retry: Some(Duration::from_secs(3)),
+                    },
+                    Event {
+                        id: Some("1".to_string()),
+                        event: None,
+                        data: "second".to_string(),
+                        retry: None,
+                    }
+                ]
+            );
+        }
+
+        #[tokio::test]
+        async fn typed_events() {
+            let (base, _) = server::serve(vec![Response::new(200)
+                .header("content-type", "text/event-stream")
+                .body_parts(["data: {\"text\":", "\"hi\"}\n\ndata: oops\n\n"])])
+            .await;
+
+            let api = EventsTest::new();
+            let events = collect(api.deltas(&base).await.unwrap()).await;
+            assert_eq!(
+                events[0].as_ref().unwrap().data,
+                Delta {
+                    text: "hi".to_string()
+                }
+            );
+            assert!(matches!(events[1], Err(crate::Error::Decode { .. })));
+        }
+
+        #[tokio::test]
+        async fn long_event_across_chunks() {
+            let parts = std::iter::once("data: ".to_string())
+                .chain((0..32).map(|_| "x".repeat(4096)))
+                .chain(["\r".to_string(), "\n\r\n".to_string()]);
+            let (base, _) = server::serve(vec![Response::new(200)
+                .header("content-type", "text/event-stream")
+                .body_parts(parts)])
+            .await;
+
+            let api = EventsTest::new();
+            let events = collect(api.events(&base).await.unwrap()).await;
+            assert_eq!(events.len(), 1);
+            assert_eq!(events[0].as_ref().unwrap().data, "x".repeat(32 * 4096));
+        }
+    }
+
+    #[cfg(feature = "auth")]
+    mod auth_state {
+        use std::{cell::Cell, time::Duration};
+
+        use crate::AuthState;
+
+        #[tokio::test]
+        async fn refreshes_once() {
+            let state = AuthState::new();
+            let refreshes = Cell::new(0);
+            let refresh = || async {
+                refreshes.set(refreshes.get() + 1);
+                tokio::time::sleep(Duration::from_millis(20)).await;
+                Ok::<_, ()>(refreshes.get())
+            };
+
+            let tokens = tokio::join!(
+                state.get_or_refresh(refresh),
+                state.get_or_refresh(refresh),
+                state.get_or_refresh(refresh),
+            );
+            assert_eq!(tokens, (Ok(1), Ok(1), Ok(1)));
+            assert_eq!(refreshes.get(), 1);
+
+            state.invalidate().await;
+            assert_eq!(state.get().await, None);
+            assert_eq!(state.get_or_refresh(refresh).await, Ok(2));
+            assert_eq!(
+                state.get_or_refresh_if(|token| *token < 3, refresh).await,
+                Ok(3)
+            );
+        }
+
+        #[tokio::test]
+        async fn retries_failed_refresh() {
+            let state = AuthState::with(1);
+            assert_eq!(
+                state
+                    .get_or_refresh_if(|_| true, || async { Err("offline") })
+                    .await,
+                Err("offline")
+            );
+            assert_eq!(state.get().await, Some(1));
+            assert_eq!(
+                state
+                    .get_or_refresh_if(|token| *token < 2, || async { Ok::<_, ()>(2) })
+                    .await,
+                Ok(2)
+            );
+        }
+    }
+
+    #[cfg(feature = "rate-limit")]
+    mod rate_limit {
+        use std::time::{Duration, Instant};
+
+        use reqwest::Client;
+
+        use super::server::{self, Response};
+        use crate::{api, Api, RateLimiter};
+
+        struct RateLimitTest {
+            client: Client,
+            limiter: RateLimiter,
+        }
+
+        impl Api for RateLimitTest {
+            fn client(&self) -> &Client {
+                &self.client
+            }
+
+            async fn acquire_permit(&self) {
+                self.limiter.acquire().await;
+            }
+        }
+
+        impl RateLimitTest {
+            api! {
+                fn todo(base: &str) -> StatusCode {
+                    GET "{base}/todo"
+                    no_pre_request
+                }
+            }
+        }
+
+        #[tokio::test]
+        async fn delays_requests() {
+            let (base, requests) = server::serve(vec![
+                Response::new(200),
+                Response::new(200),
+                Response::new(200),
+                Response::new(200),
+            ])
+            .await;
+            let api = RateLimitTest {
+                client: Client::new(),
+                limiter: RateLimiter::new(2, Duration::from_millis(200)),
+            };
+
+            let start = Instant::now();
+            api.todo(&base).await.unwrap();
+            api.todo(&base).await.unwrap();
+            assert!(start.elapsed() < Duration::from_millis(200));
+            api.todo(&base).await.unwrap();
+            api.todo(&base).await.unwrap();
+            assert!(start.elapsed() >= Duration::from_millis(200));
+            assert_eq!(requests.await.unwrap().len(), 4);
+        }
+    }
+
+    #[cfg(feature = "concurrency")]
+    mod concurrency {
+        use std::{
+            sync::{
+                atomic::{AtomicUsize, Ordering},
+                Arc,
+            },
+            time::Duration,
+        };
+
+        use reqwest::{Request, Response as HttpResponse};
+
+        use super::server::{self, Response};
+        use crate::{api, Api, Middleware, Next};
+
+        /// Records the most requests in flight at once.
+        #[derive(Default)]
+        struct InFlight {
+            current: AtomicUsize,
+            max: AtomicUsize,
+        }
+
+        #[cfg_attr(feature = "send", async_trait::async_trait)]
+        #[cfg_attr(not(feature = "send"), async_trait::async_trait(?Send))]
+        impl Middleware for Arc<InFlight> {
+            async fn handle(
+                &self,
+                request: Request,
+                next: Next<'_>,
+            ) -> crate::Result<HttpResponse> {
+                let current = self.current.fetch_add(1, Ordering::SeqCst) + 1;
+                self.max.fetch_max(current, Ordering::SeqCst);
+                tokio::time::sleep(Duration::from_millis(20)).await;
+                let response = next.run(request).await;
+                self.current.fetch_sub(1, Ordering::SeqCst);
+                response
+            }
+        }
+
+        api!(pub struct ConcurrencyTest);
+
+        impl ConcurrencyTest {
+            api! {
+                fn todo(base: &str) -> String {
+                    GET "{base}/todo"
+                }
+            }
+        }
+
+        #[tokio::test]
+        async fn limited() {
+            let (base, requests) = server::serve(
+                (0..5)
+                    .map(|_| Response::new(200).body_parts(["a", "b"]))
+                    .collect(),
+            )
+            .await;
+            let in_flight = Arc::new(InFlight::default());
+            let api = ConcurrencyTest::builder()
+                .max_concurrency(2)
+                .middleware(in_flight.clone())
+                .build()
+                .unwrap();
+
+            let todos = tokio::join!(
+                api.todo(&base),
+                api.todo(&base),
+                api.todo(&base),
+                api.todo(&base),
+                api.todo(&base),
+            );
+            assert_eq!(todos.4.unwrap(), "ab");
+            assert_eq!(in_flight.max.load(Ordering::SeqCst), 2);
+            assert_eq!(requests.await.unwrap().len(), 5);
+
+            let permits = api.concurrency_permits().unwrap();
+            let held = permits.acquire_many(2).await.unwrap();
+            let waiting = tokio::time::timeout(Duration::from_millis(20), api.todo(&base)).await;
+            assert!(waiting.is_err());
+            drop(held);
+            assert_eq!(permits.available_permits(), 2);
+        }
+
+        #[test]
+        fn zero_rejected() {
+            assert!(matches!(
+                ConcurrencyTest::builder().max_concurrency(0).build(),
+                Err(crate::Error::Config { .. })
+            ));
+        }
+    }
+
+    #[cfg(feature = "json")]
+    mod caching {
+        use std::{collections::HashMap, sync::Mutex};
+
+        use reqwest::{Client, Request};
+
+        use super::server::{self, Response};
+        use crate::{api, Api, CachedResponse, EndpointInfo, ResponseSummary};
+
+        struct CacheTest {
+            client: Client,
+            cache: Mutex<HashMap<String, Vec<u8>>>,
+            received: Mutex<Vec<String>>,
+        }
+
+        impl Api for CacheTest {
+            fn client(&self) -> &Client {
+                &self.client
+            }
+
+            async fn before_send(
+                &self,
+                _endpoint: &EndpointInfo,
+                request: &Request,
+            ) -> Option<CachedResponse> {
+                let body = self
+                    .cache
+                    .lock()
+                    .unwrap()
+                    .get(request.url().as_str())?
+                    .clone();
+                Some(CachedResponse::new(body))
+            }
+
+            async fn after_receive(&self, endpoint: &EndpointInfo, response: &ResponseSummary<'_>) {
+                let mut received = endpoint.name.to_string();
+                if let Some(body) = response.body {
+                    self.cache
+                        .lock()
+                        .unwrap()
+                        .insert(response.url.to_string(), body.to_vec());
+                    received.push(' ');
+                    received.push_str(&String::from_utf8_lossy(body));
+                }
+                self.received.lock().unwrap().push(received);
+            }
+        }
+
+        impl CacheTest {
+            api! {
+                fn todo(base: &str, id: u32) -> Json<u32> {
+                    GET "{base}/todos/{id}"
+                }
+
+                fn status(base: &str) -> StatusCode {
+                    GET "{base}/status"
+                }
+            }
+        }
+
+        #[tokio::test]
+        async fn short_circuits() {
+            let (base, requests) = server::serve(vec![
+                Response::new(200).body("1"),
+                Response::new(200).body("2"),
+                Response::new(204),
+            ])
+            .await;
+            let api = CacheTest {
+                client: Client::new(),
+                cache: Mutex::default(),
+                received: Mutex::default(),
+            };
+
+            assert_eq!(api.todo(&base, 1).await.unwrap(), 1);
+            assert_eq!(api.todo(&base, 1).await.unwrap(), 1);
+            assert_eq!(api.todo(&base, 2).await.unwrap(), 2);
+            assert_eq!(
+                api.status(&base).await.unwrap(),
+                reqwest::StatusCode::NO_CONTENT
+            );
+
+            assert_eq!(requests.await.unwrap().len(), 3);
+            assert_eq!(
+                *api.received.lock().unwrap(),
+                ["todo 1", "todo 2", "status"]
+            );
+        }
+    }
+
+    #[cfg(feature = "tower")]
+    mod tower {
+        use std::{
+            future::{ready, Ready},
+            task::{Context, Poll},
+        };
+
+        use reqwest::{Client, Request, Response as HttpResponse};
+        use tower_service::Service;
+
+        use super::server::{self, Response};
+        use crate::{api, Error};
+
+        api!(pub struct TowerTest);
+
+        impl TowerTest {
+            api! {
+                pub fn todo(base: &str) -> StatusCode {
+                    GET "{base}/todo"
+                }
+            }
+        }
+
+        #[derive(Clone)]
+        struct Unavailable;
+
+        impl Service<Request> for Unavailable {
+            type Response = HttpResponse;
+            type Error = std::io::Error;
+            type Future = Ready<Result<HttpResponse, std::io::Error>>;
+
+            fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+                Poll::Ready(Ok(()))
+            }
+
+            fn call(&mut self, _request: Request) -> Self::Future {
+                ready(Err(std::io::Error::other("overloaded")))
+            }
+        }
+
+        #[tokio::test]
+        async fn service() {
+            let (base, requests) = server::serve(vec![Response::new(204)]).await;
+
+            let api = TowerTest::builder().service(Client::new()).build().unwrap();
+            assert_eq!(api.todo(&base).await.unwrap().as_u16(), 204);
+            assert_eq!(requests.await.unwrap()[0].path, "/todo");
+
+            let api = TowerTest::builder().service(Unavailable).build().unwrap();
+            let error = api.todo("http://127.0.0.1:1").await.unwrap_err();
+            assert!(matches!(error, Error::Service(error) if error.to_string() == "overloaded"));
+        }
+    }
+
+    #[cfg(all(feature = "send", feature = "json"))]
+    mod send {
+        use std::sync::Arc;
+
+        use reqwest::{Request, Response as HttpResponse};
+
+        use super::server::{self, Response};
+        use crate::{api, Middleware, Next};
+
+        api!(pub struct SendTest);
+
+        impl SendTest {
+            api! {
+                pub fn todo(base: &str) -> Json<u32> {
+                    GET "{base}/todo"
+                }
+            }
+        }
+
+        struct Pass;
+
+        #[async_trait::async_trait]
+        impl Middleware for Pass {
+            async fn handle(
+                &self,
+                request: Request,
+                next: Next<'_>,
+            ) -> crate::Result<HttpResponse> {
+                next.run(request).await
+            }
+        }
+
+        #[tokio::test(flavor = "multi_thread")]
+        async fn spawned() {
+            let (base, _requests) = server::serve(vec![Response::new(200)
+                .header("content-type", "application/json")
+                .body("7")])
+            .await;
+
+            let api = Arc::new(SendTest::builder().middleware(Pass).build().unwrap());
+            let task = tokio::spawn(async move { api.todo(&base).await });
+            assert_eq!(task.await.unwrap().unwrap(), 7);
+        }
+    }
+
+    #[cfg(feature = "download")]
+    mod download {
+        use std::path::{Path, PathBuf};
+
+        use super::server::{self, Response};
+        use crate::{api, Api, Download, Error};
+
+        api!(pub struct DownloadTest);
+
+        impl DownloadTest {
+            api! {
+                pub fn artifact(base: &str, id: u32, dest: &Path) -> Download {
+                    GET "{base}/artifacts/{id}"
+                    save_to = dest
+                    create_dirs = true
+                }
+
+                pub fn tracked_artifact(base: &str, id: u32, dest: &Path) -> Download {
+                    GET "{base}/artifacts/{id}"
+                    save_to = dest
+                    create_dirs = true
+                    with_progress
+                }
+            }
+        }
+
+        fn temp_dir(name: &str) -> PathBuf {
+            std::env::temp_dir().join(format!("api-client-{}-{name}", std::process::id()))
+        }
+
+        #[tokio::test]
+        async fn writes_file() {
+            let (base, _) = server::serve(vec![Response::new(200)
+                .header("content-type", "application/octet-stream")
+                .body_parts([vec![1; 50_000], vec![2; 50_000]])])
+            .await;
+            let dir = temp_dir("writes-file");
+            let dest = dir.join("nested/artifact.bin");
+
+            let api = DownloadTest::new();
+            let download = api.artifact(&base, 1, &dest).await.unwrap();
+            assert_eq!(
+                download,
+                Download {
+                    path: dest.clone(),
+                    bytes: 100_000,
+                    content_type: Some("application/octet-stream".to_string()),
+                }
+            );
+
+            let contents = std::fs::read(&dest).unwrap();
+            assert_eq!(contents.len(), 100_000);
+            assert_eq!(contents[99_999], 2);
+            std::fs::remove_dir_all(dir).unwrap();
+        }
+
+        #[tokio::test]
+        async fn removes_partial_file() {
+            let (base, _) =
+                server::serve(vec![Response::new(200).length(100_000).body(vec![1; 10])]).await;
+            let dir = temp_dir("removes-partial-file");
+            let dest = dir.join("artifact.bin");
+
+            let api = DownloadTest::new();
+            assert!(matches!(
+                api.artifact(&base, 1, &dest).await,
+                Err(Error::Transport(_))
+            ));
+            assert!(!dest.exists());
+            std::fs::remove_dir_all(dir).unwrap();
+        }
+
+        #[tokio::test]
+        async fn reports_progress() {
+            let (base, _) = server::serve(vec![
+                Response::new(200).body_parts([vec![1; 50_000], vec![2; 50_000]])
+            ])
+            .await;
+            let dir = temp_dir("reports-progress");
+            let dest = dir.join("artifact.bin");
+
+            let api = DownloadTest::new();
+            let mut reports = Vec::new();
+            api.tracked_artifact(&base, 1, &dest, |received, total| {
+                reports.push((received, total));
+            })
+            .await
+            .unwrap();
+            assert_eq!(reports.last(), Some(&(100_000, Some(100_000))));
+            assert!(reports.windows(2).all(|pair| pair[0].0 < pair[1].0));
+            std::fs::remove_dir_all(dir).unwrap();
+        }
+    }
+
+    mod progress {
+        use std::{cell::RefCell, rc::Rc};
+
+        use super::server::{self, Response};
+        use crate::{api, Api};
+
+        api!(pub struct ProgressTest);
+
+        impl ProgressTest {
+            api! {
+                pub fn artifact(base: &str, id: u32) -> Bytes {
+                    GET "{base}/artifacts/{id}"
+                    with_progress
+                }
+
+                pub fn latest() -> Bytes {
+                    GET "http://127.0.0.1:9/artifacts/latest"
+                    with_progress
+                }
+            }
+        }
+
+        #[tokio::test]
+        async fn reports_each_chunk() {
+            let (base, _) =
+                server::serve(vec![Response::new(200).body_parts(["abc", "def", "gh"])]).await;
+
+            let api = ProgressTest::new();
+            let reports = Rc::new(RefCell::new(Vec::new()));
+            let recorded = Rc::clone(&reports);
+            let bytes = api
+                .artifact(&base, 1, move |received, total| {
+                    recorded.borrow_mut().push((received, total));
+                })
+                .await
+                .unwrap();
+            assert_eq!(&bytes[..], b"abcdefgh");
+
+            let reports = reports.borrow();
+            assert_eq!(reports.last(), Some(&(8, Some(8))));
+            assert!(reports.len() > 1);
+        }
+    }
+
+    #[cfg(feature = "json")]
+    mod validation {
+        use serde::Deserialize;
+
+        use super::server::{self, Response};
+        use crate::{api, Api, ApiError, Error, Validate};
+
+        #[derive(Debug, Deserialize)]
+        struct Page {
+            items: Vec<u32>,
+        }
+
+        #[derive(Debug, Deserialize)]
+        struct Problem {
+            code: u32,
+        }
+
+        impl Validate for Page {
+            fn validate(&self) -> Result<(), String> {
+                if self.items.is_empty() {
+                    return Err("page has no items".to_string());
+                }
+                Ok(())
+            }
+        }
+
+        api!(pub struct ValidationTest);
+
+        impl ValidationTest {
+            api! {
+                fn page(base: &str) -> Json<Page> {
+                    GET "{base}/page"
+                    validate
+                }
+
+                fn count(base: &str) -> Result<Json<Page>, Json<Problem>> {
+                    GET "{base}/page"
+                    validate
+                    map |page| -> usize { page.items.len() }
+                }
+
+                fn unchecked(base: &str) -> Json<Page> {
+                    GET "{base}/page"
+                }
+            }
+        }
+
+        #[tokio::test]
+        async fn rejects_invalid() {
+            let (base, _) = server::serve(vec![
+                Response::new(200).body(r#"{"items":[1]}"#),
+                Response::new(200).body(r#"{"items":[]}"#),
+                Response::new(200).body(r#"{"items":[]}"#),
+                Response::new(200).body(r#"{"items":[]}"#),
+            ])
+            .await;
+            let api = ValidationTest::new();
+
+            assert_eq!(api.page(&base).await.unwrap().items, [1]);
+            assert!(matches!(
+                api.page(&base).await,
+                Err(Error::Invalid { message }) if message == "page has no items"
+            ));
+            assert!(matches!(
+                api.count(&base).await,
+                Err(ApiError::Other(Error::Invalid { .. }))
+            ));
+            assert!(api.unchecked(&base).await.unwrap().items.is_empty());
+        }
+    }
+
+    mod context {
+        use reqwest::RequestBuilder;
+
+        use super::server::{self, Response};
+        use crate::{api, Api, PreRequestWith};
+
+        struct Tenant {
+            id: &'static str,
+        }
+
+        api!(pub struct ContextTest);
+
+        impl PreRequestWith<Tenant> for ContextTest {
+            fn pre_request_with(
+                &self,
+                tenant: &Tenant,
+                request: RequestBuilder,
+            ) -> reqwest::Result<RequestBuilder> {
+                Ok(request.header("x-tenant", tenant.id))
+            }
+        }
+
+        impl PreRequestWith<str> for ContextTest {
+            fn pre_request_with(
+                &self,
+                token: &str,
+                request: RequestBuilder,
+            ) -> reqwest::Result<RequestBuilder> {
+                Ok(request.bearer_auth(token))
+            }
+        }
+
+        impl ContextTest {
+            api! {
+                fn invoices(base: &str, tenant: &Tenant) -> StatusCode {
+                    GET "{base}/invoices"
+                    context = tenant
+                }
+
+                fn profile(token: &str, base: &str) -> StatusCode {
+                    GET "{base}/profile"
+                    context = token
+                }
+            }
+        }
+
+        #[tokio::test]
+        async fn forwarded() {
+            let (base, requests) = server::serve(vec![
+                Response::new(200),
+                Response::new(200),
+                Response::new(200),
+            ])
+            .await;
+            let api = ContextTest::new();
+            api.invoices(&base, &Tenant { id: "acme" }).await.unwrap();
+            api.invoices(&base, &Tenant { id: "globex" }).await.unwrap();
+            api.profile("secret", &base).await.unwrap();
+
+            let requests = requests.await.unwrap();
+            assert_eq!(requests[0].header("x-tenant"), Some("acme"));
+            assert_eq!(requests[1].header("x-tenant"), Some("globex"));
+            assert_eq!(requests[2].header("authorization"), Some("Bearer secret"));
+        }
+    }
+
+    mod client_options {
+        use super::server::{self, Response};
+        use crate::{api, Api};
+
+        api!(pub struct OptionsTest {
+            timeout = 10 s,
+            connect_timeout = 500 ms,
+            user_agent = concat!("options-test/", env!("CARGO_PKG_VERSION")),
+        });
+
+        api!(pub struct CommaTest, base = "http://localhost/", pool_idle_timeout = 1 min);
+
+        api!(pub struct InvalidTest { user_agent = "bad\nagent" });
+
+        impl OptionsTest {
+            api! {
+                pub fn agent(base: &str) -> StatusCode {
+                    GET "{base}/agent"
+                }
+            }
+        }
+
+        #[tokio::test]
+        async fn builder_settings() {
+            let (base, requests) = server::serve(vec![Response::new(200)]).await;
+
+            OptionsTest::new().agent(&base).await.unwrap();
+
+            let requests = requests.await.unwrap();
+            let agent = requests[0]
+                .headers
+                .iter()
+                .find(|(name, _)| name.eq_ignore_ascii_case("user-agent"))
+                .map(|(_, value)| value.as_str());
+            assert_eq!(
+                agent,
+                Some(concat!("options-test/", env!("CARGO_PKG_VERSION")))
+            );
+        }
+
+        #[test]
+        fn base_with_settings() {
+            let api = CommaTest::new();
+            assert_eq!(api.base_url().unwrap().as_str(), "http://localhost/");
+        }
+
+        #[test]
+        fn invalid_settings() {
+            assert!(InvalidTest::try_new().is_err());
+        }
+    }
+
+    #[cfg(feature = "json")]
+    mod send_helper {
+        use reqwest::{
+            header::{HeaderName, HeaderValue},
+            Client, Method, RequestBuilder, StatusCode,
+        };
+        use serde::{Deserialize, Serialize};
+
+        use super::server::{self, Response};
+        use crate::{api, Api, Body, Json, PreRequestWith, SendOptions};
+
+        #[derive(Serialize)]
+        pub struct Patch {
+            done: bool,
+        }
+
+        #[derive(Debug, Deserialize, PartialEq)]
+        pub struct Todo {
+            id: u32,
+            done: bool,
+        }
+
+        api!(pub struct SendTest);
+
+        #[tokio::test]
+        async fn hand_written() {
+            let (base, requests) = server::serve(vec![Response::new(200)
+                .header("content-type", "application/json")
+                .body(r#"{"id":3,"done":true}"#)])
+            .await;
+
+            let api = SendTest::builder()
+                .base_url(&base)
+                .default_header("x-tenant", "a")
+                .default_query("key", "k")
+                .build()
+                .unwrap();
+            let Json(todo): Json<Todo> = crate::send(
+                &api,
+                Method::PATCH,
+                "/todos/3",
+                Body::Json(&Patch { done: true }),
+                &[(
+                    HeaderName::from_static("x-tenant"),
+                    HeaderValue::from_static("b"),
+                )],
+            )
+            .await
+            .unwrap();
+            assert_eq!(todo, Todo { id: 3, done: true });
+
+            let requests = requests.await.unwrap();
+            assert_eq!(requests[0].method, "PATCH");
+            assert_eq!(requests[0].path, "/todos/3?key=k");
+            assert_eq!(requests[0].header("accept"), Some("application/json"));
+            assert_eq!(requests[0].header("x-tenant"), Some("b"));
+            assert_eq!(requests[0].body, br#"{"done":true}"#);
+        }
+
+        pub struct OptionsTest(Client);
+
+        impl Api for OptionsTest {
+            fn client(&self) -> &Client {
+                &self.0
+            }
+
+            fn pre_request(&self, request: RequestBuilder) -> reqwest::Result<RequestBuilder> {
+                Ok(request.header("x-hook", "pre"))
+            }
+
+            async fn auth_for(
+                &self,
+                scheme: &str,
+                request: RequestBuilder,
+            ) -> crate::Result<RequestBuilder> {
+                Ok(request.bearer_auth(scheme))
+            }
+
+            fn sign(&self, request: &mut reqwest::Request) -> crate::Result<()> {
+                request
+                    .headers_mut()
+                    .insert("x-signature", HeaderValue::from_static("signed"));
+                Ok(())
+            }
+        }
+
+        impl PreRequestWith<str> for OptionsTest {
+            fn pre_request_with(
+                &self,
+                tenant: &str,
+                request: RequestBuilder,
+            ) -> reqwest::Result<RequestBuilder> {
+                Ok(request.header("x-tenant", tenant))
+            }
+        }
+
+        #[tokio::test]
+        async fn with_options() {
+            let (base, requests) =
+                server::serve((0..3).map(|_| Response::new(204)).collect()).await;
+
+            let api = OptionsTest(Client::new());
+            let tenant = "acme";
+            let options = SendOptions::new()
+                .name("tenant_todo")
+                .context(|request| api.pre_request_with(tenant, request));
+            let status: StatusCode = crate::send_with(
+                &api,
+                options,
+                Method::GET,
+                &format!("{base}/todos/1"),
+                Body::<()>::None,
+                &[],
+            )
+            .await
+            .unwrap();
+            assert_eq!(status, StatusCode::NO_CONTENT);
+            for options in [
+                SendOptions::new().auth("admin"),
+                SendOptions::new().no_pre_request(),
+            ] {
+                let _: StatusCode = crate::send_with(
+                    &api,
+                    options,
+                    Method::GET,
+                    &format!("{base}/todos/1"),
+                    Body::<()>::None,
+                    &[],
+                )
+                .await
+                .unwrap();
+            }
+
+            let requests = requests.await.unwrap();
+            assert_eq!(requests[0].header("x-hook"), Some("pre"));
+            assert_eq!(requests[0].header("x-tenant"), Some("acme"));
+            assert_eq!(requests[1].header("x-hook"), None);
+            assert_eq!(requests[1].header("authorization"), Some("Bearer admin"));
+            assert_eq!(requests[2].header("x-hook"), None);
+            assert_eq!(requests[2].header("authorization"), None);
+            assert!(requests
+                .iter()
+                .all(|request| request.header("x-signature") == Some("signed")));
+        }
+    }
+
+    mod environments {
+        use super::server::{self, Response};
+        use crate::{api, Api};
+
+        api!(pub struct EnvironmentTest, environments {
+            /// The production host.
+            Prod = "http://prod.invalid/v1",
+            Staging = "http://staging.invalid/v1",
+        });
+
+        api!(pub struct NamedTest {
+            timeout = 5 s,
+            environments NamedEnvironment { Eu = "http://eu.invalid" },
+            base = "http://base.invalid",
+        });
+
+        impl EnvironmentTest {
+            api! {
+                pub fn todo(id: u32) -> StatusCode {
+                    GET "todos/{id}"
+                }
+            }
+        }
+
+        #[test]
+        fn default_environment() {
+            assert_eq!(
+                EnvironmentTest::new().base_url().unwrap().as_str(),
+                "http://prod.invalid/v1/"
+            );
+            assert_eq!(
+                EnvironmentTest::with_environment(Environment::Staging)
+                    .base_url()
+                    .unwrap()
+                    .as_str(),
+                "http://staging.invalid/v1/"
+            );
+            assert_eq!(
+                NamedTest::new().base_url().unwrap().as_str(),
+                "http://base.invalid/"
+            );
+            assert_eq!(
+                NamedEnvironment::Eu.base_url().as_str(),
+                "http://eu.invalid/"
+            );
+        }
+
+        #[tokio::test]
+        async fn custom_environment() {
+            let (base, requests) = server::serve(vec![Response::new(200)]).await;
+
+            let api = EnvironmentTest::with_environment(Environment::Custom(
+                format!("{base}/v2").parse().unwrap(),
+            ));
+            api.todo(3).await.unwrap();
+
+            assert_eq!(requests.await.unwrap()[0].path, "/v2/todos/3");
+        }
+    }
+
+    mod bearer_auth {
+        use super::server::{self, Response};
+        use crate::{api, Api, BearerAuth};
+
+        api!(pub struct BearerTest with bearer_auth);
+
+        impl BearerTest {
+            api! {
+                pub fn me(base: &str) -> StatusCode {
+                    GET "{base}/me"
+                }
+
+                pub fn health(base: &str) -> StatusCode {
+                    GET "{base}/health"
+                    no_pre_request
+                }
+            }
+        }
+
+        #[tokio::test]
+        async fn token_header() {
+            let (base, requests) = server::serve(vec![
+                Response::new(200),
+                Response::new(200),
+                Response::new(200),
+                Response::new(200),
+            ])
+            .await;
+
+            let api = BearerTest::with_token("first");
+            api.me(&base).await.unwrap();
+            api.clone().set_token("second");
+            api.me(&base).await.unwrap();
+            api.health(&base).await.unwrap();
+            BearerTest::new().me(&base).await.unwrap();
+
+            let requests = requests.await.unwrap();
+            assert_eq!(requests[0].header("authorization"), Some("Bearer first"));
+            assert_eq!(requests[1].header("authorization"), Some("Bearer second"));
+            assert_eq!(requests[2].header("authorization"), None);
+            assert_eq!(requests[3].header("authorization"), None);
+        }
+
+        #[test]
+        fn redacted() {
+            let api = BearerTest::builder()
+                .auth(BearerAuth::new("hunter2"))
+                .build()
+                .unwrap();
+            assert!(!format!("{api:?}").contains("hunter2"));
+        }
+    }
+
+    mod api_key {
+        use super::server::{self, Response};
+        use crate::{api, ApiKey};
+
+        api!(pub struct KeyTest with api_key);
+
+        impl KeyTest {
+            api! {
+                pub fn search(base: &str, term: &str, api_key: Option<&str>) -> StatusCode {
+                    GET "{base}/search"
+                    ? term
+                    ? api_key
+                }
+            }
+        }
+
+        #[tokio::test]
+        async fn placement() {
+            let (base, requests) = server::serve(vec![
+                Response::new(200),
+                Response::new(200),
+                Response::new(200),
+            ])
+            .await;
+
+            let header = KeyTest::with_api_key(ApiKey::header("x-api-key", "secret"));
+            header.search(&base, "a", None).await.unwrap();
+            let query = KeyTest::with_api_key(ApiKey::query("api_key", "secret"));
+            query.search(&base, "b", None).await.unwrap();
+            query.search(&base, "c", Some("override")).await.unwrap();
+
+            let requests = requests.await.unwrap();
+            assert_eq!(requests[0].header("x-api-key"), Some("secret"));
+            assert_eq!(requests[0].path, "/search?term=a");
+            assert_eq!(requests[1].header("x-api-key"), None);
+            assert_eq!(requests[1].path, "/search?term=b&api_key=secret");
+            assert_eq!(requests[2].path, "/search?term=c&api_key=override");
+        }
+
+        #[test]
+        fn redacted() {
+            let api = KeyTest::with_api_key(ApiKey::query("api_key", "hunter2"));
+            assert!(!format!("{api:?}").contains("hunter2"));
+        }
+    }
+
+    mod basic_auth_struct {
+        use super::server::{self, Response};
+        use crate::api;
+
+        api!(pub struct BasicTest with basic_auth);
+
+        impl BasicTest {
+            api! {
+                pub fn me(base: &str) -> StatusCode {
+                    GET "{base}/me"
+                }
+
+                pub fn admin(base: &str) -> StatusCode {
+                    GET "{base}/admin"
+                    basic_auth "root", Some("toor")
+                }
+            }
+        }
+
+        #[tokio::test]
+        async fn credentials() {
+            let (base, requests) =
+                server::serve(vec![Response::new(200), Response::new(200)]).await;
+
+            let api = BasicTest::with_basic_auth("admin", "hunter2");
+            assert!(!format!("{api:?}").contains("hunter2"));
+            api.me(&base).await.unwrap();
+            api.admin(&base).await.unwrap();
+
+            let requests = requests.await.unwrap();
+            assert_eq!(
+                requests[0].header("authorization"),
+                Some("Basic YWRtaW46aHVudGVyMg==")
+            );
+            assert_eq!(
+                requests[1].header("authorization"),
+                Some("Basic cm9vdDp0b29y")
+            );
+        }
+    }
+
+    #[cfg(feature = "oauth2")]
+    mod client_credentials {
+        use std::time::Duration;
+
+        use super::server::{self, Response};
+        use crate::{api, ClientCredentials, Error};
+
+        api!(pub struct OAuthTest with client_credentials);
+
+        impl OAuthTest {
+            api! {
+                pub fn me(base: &str) -> StatusCode {
+                    GET "{base}/me"
+                }
+            }
+        }
+
+        fn token(value: &str, expires_in: u64) -> Response {
+            Response::new(200)
+                .header("content-type", "application/json")
+                .body(format!(
+                    r#"{{"access_token":"{value}","token_type":"Bearer","expires_in":{expires_in}}}"#
+                ))
+        }
+
+        #[tokio::test]
+        async fn cached_and_refreshed() {
+            let (base, requests) = server::serve(vec![
+                token("first", 3600),
+                Response::new(200),
+                Response::new(200),
+                token("second", 10),
+                Response::new(200),
+                token("third", 3600),
+                Response::new(200),
+            ])
+            .await;
+
+            let credentials = ClientCredentials::new(format!("{base}/token"), "id", "secret")
+                .scope("read write")
+                .margin(Duration::from_secs(60));
+            let api = OAuthTest::with_client_credentials(credentials.clone());
+            let (first, second) = tokio::join!(api.me(&base), api.me(&base));
+            first.unwrap();
+            second.unwrap();
+            credentials.invalidate().await;
+            api.me(&base).await.unwrap();
+            // Expires within the margin, so it's refreshed before the next request.
+            api.me(&base).await.unwrap();
+            assert!(!format!("{api:?}").contains("second"));
+
+            let requests = requests.await.unwrap();
+            assert_eq!(requests[0].path, "/token");
+            assert_eq!(
+                String::from_utf8_lossy(&requests[0].body),
+                "grant_type=client_credentials&client_id=id&client_secret=secret&scope=read+write"
+            );
+            assert_eq!(requests[1].header("authorization"), Some("Bearer first"));
+            assert_eq!(requests[2].header("authorization"), Some("Bearer first"));
+            assert_eq!(requests[3].path, "/token");
+            assert_eq!(requests[4].header("authorization"), Some("Bearer second"));
+            assert_eq!(requests[5].path, "/token");
+            assert_eq!(requests[6].header("authorization"), Some("Bearer third"));
+        }
+
+        #[tokio::test]
+        async fn token_error() {
+            let (base, _requests) =
+                server::serve(vec![Response::new(401).body("invalid_client")]).await;
+
+            let credentials = ClientCredentials::new(format!("{base}/token"), "id", "secret");
+            let error = credentials.token().await.unwrap_err();
+            assert!(matches!(
+                error,
+                Error::UnexpectedStatus { status, .. } if status == 401
+            ));
+        }
+    }
+
+    mod reauthenticate {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        use reqwest::{Client, RequestBuilder};
+
+        use super::server::{self, Response};
+        use crate::{api, Api, Error, PreRequestWith};
+
+        struct ReauthApi {
+            client: Client,
+            logins: AtomicUsize,
+        }
+
+        impl Api for ReauthApi {
+            fn client(&self) -> &Client {
+                &self.client
+            }
+
+            async fn pre_request_async(
+                &self,
+                request: RequestBuilder,
+            ) -> crate::Result<RequestBuilder> {
+                let token = format!("token-{}", self.logins.load(Ordering::SeqCst));
+                Ok(request.bearer_auth(token))
+            }
+
+            async fn reauthenticate(&self) -> crate::Result<bool> {
+                self.logins.fetch_add(1, Ordering::SeqCst);
+                Ok(true)
+            }
+        }
+
+        impl ReauthApi {
+            fn new() -> Self {
+                ReauthApi {
+                    client: Client::new(),
+                    logins: AtomicUsize::new(0),
+                }
+            }
+
+            api! {
+                pub fn status(base: &str) -> StatusCode {
+                    GET "{base}/me"
+                }
+
+                pub fn unauthenticated(base: &str) -> StatusCode {
+                    GET "{base}/me"
+                    no_pre_request
+                }
+
+                pub fn post(base: &str, body: Form<[(&str, &str)]>) -> String {
+                    POST "{base}/echo"
+                }
+            }
+        }
+
+        #[cfg(feature = "multipart")]
+        impl ReauthApi {
+            api! {
+                pub fn upload(base: &str, form: Multipart) -> StatusCode {
+                    POST "{base}/upload"
+                }
+            }
+        }
+
+        #[tokio::test]
+        async fn retried_once() {
+            let (base, requests) = server::serve(vec![
+                Response::new(401),
+                Response::new(200),
+                Response::new(401),
+                Response::new(401),
+            ])
+            .await;
+
+            let api = ReauthApi::new();
+            assert_eq!(api.status(&base).await.unwrap(), 200);
+            assert_eq!(api.status(&base).await.unwrap(), 401);
+            assert_eq!(api.logins.load(Ordering::SeqCst), 2);
+
+            let requests = requests.await.unwrap();
+            let tokens: Vec<_> = requests
+                .iter()
+                .map(|request| request.header("authorization").unwrap())
+                .collect();
+            assert_eq!(
+                tokens,
+                [
+                    "Bearer token-0",
+                    "Bearer token-1",
+                    "Bearer token-1",
+                    "Bearer token-2"
+                ]
+            );
+        }
+
+        #[tokio::test]
+        async fn body_resent() {
+            let (base, requests) =
+                server::serve(vec![Response::new(401), Response::new(200).body("ok")]).await;
+
+            let api = ReauthApi::new();
+            assert_eq!(api.post(&base, &[("a", "b")]).await.unwrap(), "ok");
+
+            let requests = requests.await.unwrap();
+            assert_eq!(requests.len(), 2);
+            assert_eq!(requests[1].body, b"a=b");
+        }
+
+        #[tokio::test]
+        async fn skipped_without_pre_request() {
+            let (base, _requests) = server::serve(vec![Response::new(401)]).await;
+
+            let api = ReauthApi::new();
+            assert_eq!(api.unauthenticated(&base).await.unwrap(), 401);
+            assert_eq!(api.logins.load(Ordering::SeqCst), 0);
         }
-        api!($($rest)*);
-    };
 
-    ($(#[$attr:meta])* $vis:vis fn $ident:ident(request: Json<$req:ty>$(, $name:ident: $ty:ty)*) -> Bytes { $method:tt $url:literal $($headername:tt: $headervalue:expr)* } $($rest:tt)*) => {
-        $(#[$attr])*
-        #[inline]
-        $vis async fn $ident(&self, request: &$req, $($name: $ty),*) -> ::reqwest::Result<::bytes::Bytes> {
-            use $crate::Api as _;
-            self.pre_request(self.client().request($crate::reqwest::Method::$method, format!($url).as_str()))?
-                $(.header($headername, format!($headervalue).as_str()))*
-                .json(request)
-                .send()
-                .await?
-                .bytes()
-                .await
+        struct SyncReauthApi {
+            client: Client,
+            logins: AtomicUsize,
         }
-        api!($($rest)*);
-    };
 
-    ($(#[$attr:meta])* $vis:vis fn $ident:ident(request: Json<$req:ty>$(, $name:ident: $ty:ty)*) -> Json<$res:ty> { $method:tt $url:literal $($headername:tt: $headervalue:expr)* } $($rest:tt)*) => {
-        $(#[$attr])*
-        #[inline]
-        $vis async fn $ident(&self, request: &$req, $($name: $ty),*) -> ::reqwest::Result<$res> {
-            use $crate::Api as _;
-            self.pre_request(self.client().request($crate::reqwest::Method::$method, format!($url).as_str()))?
-                $(.header($headername, format!($headervalue).as_str()))*
-                .json(request)
-                .send()
-                .await?
-                .json()
-                .await
+        impl Api for SyncReauthApi {
+            fn client(&self) -> &Client {
+                &self.client
+            }
+
+            fn pre_request(&self, request: RequestBuilder) -> reqwest::Result<RequestBuilder> {
+                let token = format!("token-{}", self.logins.load(Ordering::SeqCst));
+                Ok(request.bearer_auth(token))
+            }
+
+            async fn reauthenticate(&self) -> crate::Result<bool> {
+                self.logins.fetch_add(1, Ordering::SeqCst);
+                Ok(true)
+            }
         }
-        api!($($rest)*);
-    };
 
-    ($(#[$attr:meta])* $vis:vis fn $ident:ident(request: Form<$req:ty>$(, $name:ident: $ty:ty)*) -> StatusCode { $method:tt $url:literal $($headername:tt: $headervalue:expr)* } $($rest:tt)*) => {
-        $(#[$attr])*
-        #[inline]
-        $vis async fn $ident(&self, request: &$req, $($name: $ty),*) -> ::reqwest::Result<::reqwest::StatusCode> {
-            use $crate::Api as _;
-            self.pre_request(self.client().request($crate::reqwest::Method::$method, format!($url).as_str()))?
-                $(.header($headername, format!($headervalue).as_str()))*
-                .form(request)
-                .send()
-                .await
-                .map(|res| res.status())
+        impl PreRequestWith<str> for SyncReauthApi {
+            fn pre_request_with(
+                &self,
+                tenant: &str,
+                request: RequestBuilder,
+            ) -> reqwest::Result<RequestBuilder> {
+                Ok(request.header("x-tenant", tenant))
+            }
         }
-        api!($($rest)*);
-    };
 
-    ($(#[$attr:meta])* $vis:vis fn $ident:ident(request: Form<$req:ty>$(, $name:ident: $ty:ty)*) -> String { $method:tt $url:literal $($headername:tt: $headervalue:expr)* } $($rest:tt)*) => {
-        $(#[$attr])*
-        #[inline]
-        $vis async fn $ident(&self, request: &$req, $($name: $ty),*) -> ::reqwest::Result<String> {
-            use $crate::Api as _;
-            self.pre_request(self.client().request($crate::reqwest::Method::$method, format!($url).as_str()))?
-                $(.header($headername, format!($headervalue).as_str()))*
-                .form(request)
-                .send()
-                .await?
-                .text()
-                .await
+        impl SyncReauthApi {
+            api! {
+                pub fn status(base: &str, tenant: &str) -> StatusCode {
+                    GET "{base}/me"
+                    context = tenant
+                }
+            }
         }
-        api!($($rest)*);
-    };
 
-    ($(#[$attr:meta])* $vis:vis fn $ident:ident(request: Form<$req:ty>$(, $name:ident: $ty:ty)*) -> Bytes { $method:tt $url:literal $($headername:tt: $headervalue:expr)* } $($rest:tt)*) => {
-        $(#[$attr])*
-        #[inline]
-        $vis async fn $ident(&self, request: &$req, $($name: $ty),*) -> ::reqwest::Result<::bytes::Bytes> {
-            use $crate::Api as _;
-            self.pre_request(self.client().request($crate::reqwest::Method::$method, format!($url).as_str()))?
-                $(.header($headername, format!($headervalue).as_str()))*
-                .form(request)
-                .send()
-                .await?
-                .bytes()
-                .await
+        #[tokio::test]
+        async fn retried_with_sync_pre_request() {
+            let (base, requests) =
+                server::serve(vec![Response::new(401), Response::new(200)]).await;
+
+            let api = SyncReauthApi {
+                client: Client::new(),
+                logins: AtomicUsize::new(0),
+            };
+            assert_eq!(api.status(&base, "acme").await.unwrap(), 200);
+
+            let requests = requests.await.unwrap();
+            assert_eq!(requests[0].header("authorization"), Some("Bearer token-0"));
+            assert_eq!(requests[1].header("authorization"), Some("Bearer token-1"));
+            let tenants = requests[1]
+                .headers
+                .iter()
+                .filter(|(name, _)| name == "x-tenant")
+                .count();
+            assert_eq!(tenants, 1);
         }
-        api!($($rest)*);
-    };
 
-    ($(#[$attr:meta])* $vis:vis fn $ident:ident(request: Form<$req:ty>$(, $name:ident: $ty:ty)*) -> Json<$res:ty> { $method:tt $url:literal $($headername:tt: $headervalue:expr)* } $($rest:tt)*) => {
-        $(#[$attr])*
-        #[inline]
-        $vis async fn $ident(&self, request: &$req, $($name: $ty),*) -> ::reqwest::Result<$res> {
-            use $crate::Api as _;
-            self.pre_request(self.client().request($crate::reqwest::Method::$method, format!($url).as_str()))?
-                $(.header($headername, format!($headervalue).as_str()))*
-                .form(request)
-                .send()
-                .await?
-                .json()
+        #[cfg(feature = "multipart")]
+        #[tokio::test]
+        async fn streamed_body_not_retried() {
+            let (base, _requests) = server::serve(vec![Response::new(401)]).await;
+
+            let api = ReauthApi::new();
+            let form = reqwest::multipart::Form::new().text("a", "b");
+            let error = api.upload(&base, form).await.unwrap_err();
+            assert!(matches!(error, Error::NotRetryable));
+            assert_eq!(api.logins.load(Ordering::SeqCst), 1);
+        }
+    }
+
+    #[cfg(feature = "jwt")]
+    mod jwt {
+        use std::{
+            sync::atomic::{AtomicUsize, Ordering},
+            time::{Duration, SystemTime, UNIX_EPOCH},
+        };
+
+        use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+
+        use crate::{Jwt, JwtCache};
+
+        fn token(claims: &str) -> String {
+            format!(
+                "{}.{}.signature",
+                URL_SAFE_NO_PAD.encode(r#"{"alg":"HS256","typ":"JWT"}"#),
+                URL_SAFE_NO_PAD.encode(claims)
+            )
+        }
+
+        fn expiring_in(seconds: u64) -> String {
+            let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap();
+            token(&format!(
+                r#"{{"sub":"user","exp":{}}}"#,
+                now.as_secs() + seconds
+            ))
+        }
+
+        #[test]
+        fn expiry() {
+            let jwt = Jwt::new(token(r#"{"sub":"user","exp":1700000000}"#));
+            assert_eq!(
+                jwt.expires(),
+                Some(UNIX_EPOCH + Duration::from_secs(1_700_000_000))
+            );
+            assert!(jwt.is_expired(Duration::ZERO));
+
+            let jwt = Jwt::new(expiring_in(120));
+            assert!(!jwt.is_expired(Duration::from_secs(60)));
+            assert!(jwt.is_expired(Duration::from_secs(180)));
+        }
+
+        #[test]
+        fn without_expiry() {
+            for token in [
+                token(r#"{"sub":"user"}"#),
+                "opaque".to_string(),
+                "a.!!!.c".to_string(),
+            ] {
+                let jwt = Jwt::new(token);
+                assert_eq!(jwt.expires(), None);
+                assert!(!jwt.is_expired(Duration::from_secs(3600)));
+            }
+        }
+
+        #[tokio::test]
+        async fn refreshed_before_expiry() {
+            let cache = JwtCache::new().leeway(Duration::from_secs(60));
+            assert!(cache.is_expired(Duration::ZERO).await);
+
+            let refreshes = AtomicUsize::new(0);
+            let refresh = |expires_in| {
+                let refreshes = &refreshes;
+                move || async move {
+                    refreshes.fetch_add(1, Ordering::SeqCst);
+                    crate::Result::Ok(expiring_in(expires_in))
+                }
+            };
+
+            cache.get_or_refresh(refresh(30)).await.unwrap();
+            cache.get_or_refresh(refresh(3600)).await.unwrap();
+            let jwt = cache.get_or_refresh(refresh(3600)).await.unwrap();
+            assert_eq!(refreshes.load(Ordering::SeqCst), 2);
+            assert!(!jwt.is_expired(Duration::from_secs(60)));
+
+            cache.set(token(r#"{"sub":"user"}"#)).await;
+            cache.get_or_refresh(refresh(3600)).await.unwrap();
+            assert_eq!(refreshes.load(Ordering::SeqCst), 2);
+
+            cache.invalidate().await;
+            cache.get_or_refresh(refresh(3600)).await.unwrap();
+            assert_eq!(refreshes.load(Ordering::SeqCst), 3);
+        }
+    }
+
+    mod sign {
+        use reqwest::{Client, Request};
+
+        use super::server::{self, Response};
+        use crate::{api, Api, Error};
+
+        struct SignedApi {
+            client: Client,
+        }
+
+        impl Api for SignedApi {
+            fn client(&self) -> &Client {
+                &self.client
+            }
+
+            fn sign(&self, request: &mut Request) -> crate::Result<()> {
+                let body = request
+                    .body()
+                    .and_then(reqwest::Body::as_bytes)
+                    .unwrap_or_default();
+                let signature = format!(
+                    "{} {} {}",
+                    request.method(),
+                    request.url().path(),
+                    String::from_utf8_lossy(body)
+                );
+                if signature.contains("reject") {
+                    return Err(Error::Service("can't sign".into()));
+                }
+                request
+                    .headers_mut()
+                    .insert("x-signature", signature.parse().unwrap());
+                Ok(())
+            }
+        }
+
+        impl SignedApi {
+            api! {
+                pub fn create(base: &str, order: Form<[(&str, &str)]>) -> StatusCode {
+                    POST "{base}/orders"
+                    no_pre_request
+                }
+            }
+        }
+
+        #[tokio::test]
+        async fn signature_covers_body() {
+            let (base, requests) = server::serve(vec![Response::new(200)]).await;
+
+            let api = SignedApi {
+                client: Client::new(),
+            };
+            api.create(&base, &[("side", "buy"), ("qty", "2")])
                 .await
+                .unwrap();
+
+            let requests = requests.await.unwrap();
+            assert_eq!(
+                requests[0].header("x-signature"),
+                Some("POST /orders side=buy&qty=2")
+            );
         }
-        api!($($rest)*);
-    };
 
-    ($(#[$attr:meta])* $vis:vis fn $ident:ident($($name:ident: $ty:ty),*) -> StatusCode { $method:tt $url:literal $($headername:tt: $headervalue:expr)* } $($rest:tt)*) => {
-        $(#[$attr])*
-        #[inline]
-        $vis async fn $ident(&self, $($name: $ty),*) -> ::reqwest::Result<::reqwest::StatusCode> {
-            use $crate::Api as _;
-            self.pre_request(self.client().request($crate::reqwest::Method::$method, format!($url).as_str()))?
-                $(.header($headername, format!($headervalue).as_str()))*
-                .send()
-                .await
-                .map(|res| res.status())
+        #[tokio::test]
+        async fn error_aborts_request() {
+            let (base, requests) = server::serve(vec![]).await;
+
+            let api = SignedApi {
+                client: Client::new(),
+            };
+            let error = api.create(&base, &[("side", "reject")]).await.unwrap_err();
+            assert!(matches!(error, Error::Service(_)));
+            assert!(requests.await.unwrap().is_empty());
+        }
+    }
+
+    #[cfg(feature = "sigv4")]
+    mod sigv4 {
+        use std::time::{Duration, UNIX_EPOCH};
+
+        use reqwest::{Client, Method, Request};
+
+        use crate::{AwsCredentials, SigV4};
+
+        fn signer(service: &str) -> SigV4 {
+            let credentials =
+                AwsCredentials::new("AKIDEXAMPLE", "wJalrXUtnFEMI/K7MDENG+bPxRfiCYEXAMPLEKEY");
+            SigV4::new("us-east-1", service, credentials).content_sha256(false)
+        }
+
+        fn sign(signer: &SigV4, mut request: Request) -> Request {
+            // 2015-08-30T12:36:00Z, the time of the AWS test suite.
+            let time = UNIX_EPOCH + Duration::from_secs(1_440_938_160);
+            signer.sign_at(&mut request, time).unwrap();
+            request
+        }
+
+        fn signature(request: &Request) -> &str {
+            let authorization = request.headers()["authorization"].to_str().unwrap();
+            authorization.rsplit("Signature=").next().unwrap()
+        }
+
+        #[test]
+        fn get_vanilla() {
+            let request = Client::new()
+                .get("https://example.amazonaws.com/")
+                .build()
+                .unwrap();
+            let request = sign(&signer("service"), request);
+            assert_eq!(request.headers()["x-amz-date"], "20150830T123600Z");
+            assert_eq!(
+                request.headers()["authorization"],
+                "AWS4-HMAC-SHA256 Credential=AKIDEXAMPLE/20150830/us-east-1/service/aws4_request, \
+                 SignedHeaders=host;x-amz-date, \
+                 Signature=5fa00fa31553b73ebf1942676e86291e8372ff2a2260956d9b8aae1d763fbf31"
+            );
+            assert!(request.headers()["authorization"].is_sensitive());
+        }
+
+        #[test]
+        fn post_vanilla() {
+            let request = Client::new()
+                .post("https://example.amazonaws.com/")
+                .build()
+                .unwrap();
+            let request = sign(&signer("service"), request);
+            assert_eq!(
+                signature(&request),
+                "5da7c1a2acd57cee7505fc6676e4e544621c30862966e37dddb68e92efbe5d6b"
+            );
+        }
+
+        #[test]
+        fn post_form_body() {
+            let request = Client::new()
+                .post("https://example.amazonaws.com/")
+                .header("content-type", "application/x-www-form-urlencoded")
+                .body("Param1=value1")
+                .build()
+                .unwrap();
+            let request = sign(&signer("service"), request);
+            assert_eq!(
+                signature(&request),
+                "ff11897932ad3f4e8b18135d722051e5ac45fc38421b1da7b9d196a0fe09473a"
+            );
+        }
+
+        #[test]
+        fn query_order() {
+            let request = Client::new()
+                .get("https://example.amazonaws.com/?Param2=value2&Param1=value1")
+                .build()
+                .unwrap();
+            let request = sign(&signer("service"), request);
+            assert_eq!(
+                signature(&request),
+                "b97d918cfa904a5beff61c982a1b6f458b799221646efd99d3219ec94cdf2500"
+            );
+        }
+
+        #[test]
+        fn plus_in_query() {
+            let signed = |url: &str| {
+                let request = sign(&signer("service"), Client::new().get(url).build().unwrap());
+                signature(&request).to_owned()
+            };
+            let plus = signed("https://example.amazonaws.com/?q=a+b");
+            assert_eq!(plus, signed("https://example.amazonaws.com/?q=a%2Bb"));
+            assert_ne!(plus, signed("https://example.amazonaws.com/?q=a%20b"));
+        }
+
+        #[test]
+        fn iam_list_users() {
+            let request = Client::new()
+                .get("https://iam.amazonaws.com/?Action=ListUsers&Version=2010-05-08")
+                .header(
+                    "content-type",
+                    "application/x-www-form-urlencoded; charset=utf-8",
+                )
+                .build()
+                .unwrap();
+            let request = sign(&signer("iam"), request);
+            assert_eq!(
+                signature(&request),
+                "5d672d79c15b13162d9279b0855cfba6789a8edb4c82c400e06b5924a6f2b5d7"
+            );
+        }
+
+        #[test]
+        fn content_sha256_and_session_token() {
+            let credentials = AwsCredentials::new("AKIDEXAMPLE", "secret").session_token("token");
+            let signer = SigV4::new("us-east-1", "s3", move || Ok(credentials.clone()));
+
+            let request = Client::new()
+                .request(Method::PUT, "https://bucket.s3.amazonaws.com/a%20b.txt")
+                .body("hello")
+                .build()
+                .unwrap();
+            let request = sign(&signer, request);
+            assert_eq!(
+                request.headers()["x-amz-content-sha256"],
+                "2cf24dba5fb0a30e26e83b2ac5b9e29e1b161e5c1fa7425e73043362938b9824"
+            );
+            assert_eq!(request.headers()["x-amz-security-token"], "token");
+            assert!(request.headers()["authorization"]
+                .to_str()
+                .unwrap()
+                .contains(
+                    "SignedHeaders=host;x-amz-content-sha256;x-amz-date;x-amz-security-token,"
+                ));
+
+            let form = reqwest::multipart::Form::new().text("a", "b");
+            let request = Client::new()
+                .post("https://bucket.s3.amazonaws.com/")
+                .multipart(form)
+                .build()
+                .unwrap();
+            let request = sign(&signer, request);
+            assert_eq!(
+                request.headers()["x-amz-content-sha256"],
+                "UNSIGNED-PAYLOAD"
+            );
+        }
+    }
+
+    #[cfg(feature = "digest")]
+    mod digest {
+        use super::server::{self, Response};
+        use crate::{api, digest::Challenge, DigestAuth};
+
+        fn response(header: &str) -> &str {
+            let start = header.find("response=\"").unwrap() + 10;
+            &header[start..start + header[start..].find('"').unwrap()]
+        }
+
+        #[test]
+        fn rfc_7616_example() {
+            let challenge = |algorithm| {
+                Challenge::parse(&format!(
+                    r#"Digest realm="http-auth@example.org", qop="auth, auth-int", algorithm={algorithm},
+                    nonce="7ypf/xlj9XXwfDPEoM4URrv/xwf94BcCAzFZH4GiTo0v",
+                    opaque="FQhe/qaU925kfnzjCev0ciny7QMkPqMAFRtzCUYo5tdS""#
+                ))
+                .unwrap()
+            };
+            let cnonce = "f2/wE4q74E6zIJEtWaHKaf5wv/H5QzzpXusqGemxURZJ";
+
+            let header = challenge("MD5").authorization(
+                "Mufasa",
+                "Circle of Life",
+                "GET",
+                "/dir/index.html",
+                cnonce,
+            );
+            assert_eq!(response(&header), "8ca523f5e9506fed4657c9700eebdbec");
+            assert!(header.contains("nc=00000001"));
+            assert!(header.contains(r#"opaque="FQhe/qaU925kfnzjCev0ciny7QMkPqMAFRtzCUYo5tdS""#));
+
+            let header = challenge("SHA-256").authorization(
+                "Mufasa",
+                "Circle of Life",
+                "GET",
+                "/dir/index.html",
+                cnonce,
+            );
+            assert_eq!(
+                response(&header),
+                "753927fa0e85d155564e2e272a28d1802ca10daf4496794697cf8db5856cb6c1"
+            );
+        }
+
+        #[test]
+        fn rfc_2617_example() {
+            let mut challenge = Challenge::parse(
+                r#"Digest realm="testrealm@host.com", qop="auth,auth-int", nonce="dcd98b7102dd2f0e8b11d0f600bfb0c093", opaque="5ccc069c403ebaf9f0171e9517f40e41""#,
+            )
+            .unwrap();
+            let header = challenge.authorization(
+                "Mufasa",
+                "Circle Of Life",
+                "GET",
+                "/dir/index.html",
+                "0a4f113b",
+            );
+            assert_eq!(response(&header), "6629fae49393a05397450978507c4ef1");
+        }
+
+        #[test]
+        fn unsupported_challenges() {
+            assert!(Challenge::parse(r#"Basic realm="example""#).is_none());
+            assert!(
+                Challenge::parse(r#"Digest realm="a", nonce="b", algorithm=SHA-512-256"#).is_none()
+            );
+            assert!(Challenge::parse(r#"Digest realm="a", nonce="b", qop="auth-int""#).is_none());
+        }
+
+        api!(pub struct DigestTest);
+
+        impl DigestTest {
+            api! {
+                pub fn status(base: &str) -> StatusCode {
+                    GET "{base}/status?verbose=true"
+                }
+
+                pub fn users(base: &str) -> StatusCode {
+                    GET "{base}/admin/users"
+                }
+
+                pub fn cpu(base: &str) -> StatusCode {
+                    GET "{base}/metrics/cpu"
+                }
+            }
+        }
+
+        #[tokio::test]
+        async fn handshake_then_preemptive() {
+            let challenge = Response::new(401).header(
+                "www-authenticate",
+                r#"Digest realm="appliance", qop="auth", nonce="abc123", algorithm=MD5"#,
+            );
+            let (base, requests) =
+                server::serve(vec![challenge, Response::new(200), Response::new(200)]).await;
+
+            let api = DigestTest::builder()
+                .middleware(DigestAuth::new("admin", "secret"))
+                .build()
+                .unwrap();
+            assert_eq!(api.status(&base).await.unwrap(), 200);
+            assert_eq!(api.status(&base).await.unwrap(), 200);
+
+            let requests = requests.await.unwrap();
+            assert_eq!(requests.len(), 3);
+            assert_eq!(requests[0].header("authorization"), None);
+            let first = requests[1].header("authorization").unwrap();
+            assert!(first.starts_with(
+                r#"Digest username="admin", realm="appliance", uri="/status?verbose=true""#
+            ));
+            assert!(first.contains("nc=00000001"));
+            assert!(requests[2]
+                .header("authorization")
+                .unwrap()
+                .contains("nc=00000002"));
+        }
+
+        #[tokio::test]
+        async fn challenges_kept_per_realm() {
+            let challenge = |realm: &str| {
+                Response::new(401).header(
+                    "www-authenticate",
+                    &format!(r#"Digest realm="{realm}", qop="auth", nonce="{realm}-nonce""#),
+                )
+            };
+            let (base, requests) = server::serve(vec![
+                challenge("admin"),
+                Response::new(200),
+                challenge("metrics"),
+                Response::new(200),
+                Response::new(200),
+                Response::new(200),
+            ])
+            .await;
+
+            let api = DigestTest::builder()
+                .middleware(DigestAuth::new("admin", "secret"))
+                .build()
+                .unwrap();
+            assert_eq!(api.users(&base).await.unwrap(), 200);
+            assert_eq!(api.cpu(&base).await.unwrap(), 200);
+            assert_eq!(api.users(&base).await.unwrap(), 200);
+            assert_eq!(api.cpu(&base).await.unwrap(), 200);
+
+            let requests = requests.await.unwrap();
+            assert_eq!(requests.len(), 6);
+            // The metrics directory isn't in the admin realm's protection space.
+            assert_eq!(requests[2].header("authorization"), None);
+            let admin = requests[4].header("authorization").unwrap();
+            assert!(admin.contains(r#"realm="admin""#));
+            assert!(admin.contains("nc=00000002"));
+            let metrics = requests[5].header("authorization").unwrap();
+            assert!(metrics.contains(r#"realm="metrics""#));
+            assert!(metrics.contains("nc=00000002"));
+        }
+    }
+
+    #[cfg(feature = "cookies")]
+    mod session_login {
+        use super::server::{self, Response};
+        use crate::api;
+
+        api!(pub struct SessionTest, cookie_store, login = login);
+
+        impl SessionTest {
+            api! {
+                pub fn login() -> StatusCode {
+                    POST "login"
+                    no_pre_request
+                }
+
+                pub fn orders() -> StatusCode {
+                    GET "orders"
+                }
+            }
+        }
+
+        #[tokio::test]
+        async fn login_after_unauthorized() {
+            let (base, requests) = server::serve(vec![
+                Response::new(401),
+                Response::new(204).header("set-cookie", "session=abc; HttpOnly"),
+                Response::new(200),
+                Response::new(401),
+                Response::new(401),
+            ])
+            .await;
+
+            let api = SessionTest::builder().base_url(&base).build().unwrap();
+            assert_eq!(api.orders().await.unwrap(), 200);
+            // A rejected login isn't followed by a retry.
+            assert_eq!(api.orders().await.unwrap(), 401);
+
+            let requests = requests.await.unwrap();
+            let paths: Vec<_> = requests
+                .iter()
+                .map(|request| (request.method.as_str(), request.path.as_str()))
+                .collect();
+            assert_eq!(
+                paths,
+                [
+                    ("GET", "/orders"),
+                    ("POST", "/login"),
+                    ("GET", "/orders"),
+                    ("GET", "/orders"),
+                    ("POST", "/login")
+                ]
+            );
+            assert_eq!(requests[0].header("cookie"), None);
+            assert_eq!(requests[2].header("cookie"), Some("session=abc"));
+        }
+
+        #[cfg(feature = "cookie-store")]
+        #[tokio::test]
+        async fn saved_between_runs() {
+            use crate::CookieFile;
+
+            let (base, requests) = server::serve(vec![
+                Response::new(200).header("set-cookie", "session=abc; HttpOnly"),
+                Response::new(200),
+            ])
+            .await;
+            let path = std::env::temp_dir()
+                .join(format!("api-client-cookies-{}.json", std::process::id()));
+
+            let cookies = CookieFile::load(&path).await.unwrap();
+            let api = SessionTest::builder()
+                .base_url(&base)
+                .cookie_file(&cookies)
+                .build()
+                .unwrap();
+            api.login().await.unwrap();
+            cookies.save().await.unwrap();
+
+            let cookies = CookieFile::load(&path).await.unwrap();
+            let api = SessionTest::builder()
+                .base_url(&base)
+                .cookie_file(&cookies)
+                .build()
+                .unwrap();
+            api.orders().await.unwrap();
+            std::fs::remove_file(&path).unwrap();
+
+            let requests = requests.await.unwrap();
+            assert_eq!(requests[1].header("cookie"), Some("session=abc"));
+        }
+
+        #[cfg(feature = "cookie-store")]
+        #[tokio::test]
+        async fn cookie_file_not_with_client() {
+            use crate::{CookieFile, Error};
+
+            let path = std::env::temp_dir().join(format!(
+                "api-client-cookies-client-{}.json",
+                std::process::id()
+            ));
+            let cookies = CookieFile::load(&path).await.unwrap();
+            let error = SessionTest::builder()
+                .client(reqwest::Client::new())
+                .cookie_file(&cookies)
+                .build()
+                .unwrap_err();
+            assert!(matches!(error, Error::Config { .. }), "{error:?}");
+        }
+    }
+
+    #[cfg(feature = "auth")]
+    mod csrf {
+        use super::server::{self, Response};
+        use crate::{api, CsrfToken};
+
+        api!(pub struct CsrfTest);
+
+        impl CsrfTest {
+            api! {
+                pub fn list(base: &str) -> StatusCode {
+                    GET "{base}/items"
+                }
+
+                pub fn create(base: &str) -> String {
+                    POST "{base}/items"
+                }
+            }
+        }
+
+        fn api(csrf: CsrfToken) -> CsrfTest {
+            CsrfTest::builder().middleware(csrf).build().unwrap()
+        }
+
+        #[tokio::test]
+        async fn fetched_once_for_mutating_requests() {
+            let (base, requests) = server::serve(vec![
+                Response::new(200),
+                Response::new(204).header("x-csrf-token", "first"),
+                Response::new(201).body("created"),
+                Response::new(201).body("created"),
+            ])
+            .await;
+
+            let api = api(CsrfToken::header("/csrf", "x-csrf-token"));
+            assert_eq!(api.list(&base).await.unwrap(), 200);
+            assert_eq!(api.create(&base).await.unwrap(), "created");
+            assert_eq!(api.create(&base).await.unwrap(), "created");
+
+            let requests = requests.await.unwrap();
+            let sent: Vec<_> = requests
+                .iter()
+                .map(|request| (request.path.as_str(), request.header("x-csrf-token")))
+                .collect();
+            assert_eq!(
+                sent,
+                [
+                    ("/items", None),
+                    ("/csrf", None),
+                    ("/items", Some("first")),
+                    ("/items", Some("first"))
+                ]
+            );
+        }
+
+        #[cfg(feature = "json")]
+        #[tokio::test]
+        async fn refreshed_when_expired() {
+            let token = |value: &str| {
+                Response::new(200)
+                    .header("content-type", "application/json")
+                    .body(format!(r#"{{"token":"{value}"}}"#))
+            };
+            let (base, requests) = server::serve(vec![
+                token("first"),
+                Response::new(403).body(r#"{"error":"CSRF token expired"}"#),
+                token("second"),
+                Response::new(201).body("created"),
+                Response::new(403).body("forbidden"),
+            ])
+            .await;
+
+            let api = api(CsrfToken::json("/csrf", "token").send_as("x-xsrf-token"));
+            assert_eq!(api.create(&base).await.unwrap(), "created");
+            let error = api.create(&base).await.unwrap_err();
+            assert_eq!(error.status(), Some(reqwest::StatusCode::FORBIDDEN));
+            assert_eq!(error.body(), Some("forbidden"));
+
+            let requests = requests.await.unwrap();
+            let tokens: Vec<_> = requests
+                .iter()
+                .filter(|request| request.path == "/items")
+                .map(|request| request.header("x-xsrf-token"))
+                .collect();
+            assert_eq!(tokens, [Some("first"), Some("second"), Some("second")]);
+        }
+
+        #[tokio::test]
+        async fn missing_token() {
+            let (base, _) = server::serve(vec![Response::new(204)]).await;
+
+            let api = api(CsrfToken::header("/csrf", "x-csrf-token"));
+            let error = api.create(&base).await.unwrap_err();
+            assert!(matches!(error, crate::Error::Protocol { .. }), "{error:?}");
+        }
+    }
+
+    mod auth_for {
+        use reqwest::{Client, RequestBuilder};
+
+        use super::server::{self, Response};
+        use crate::{api, Api, Error};
+
+        struct TenantApi {
+            client: Client,
+        }
+
+        impl Api for TenantApi {
+            fn client(&self) -> &Client {
+                &self.client
+            }
+
+            async fn pre_request_async(
+                &self,
+                request: RequestBuilder,
+            ) -> crate::Result<RequestBuilder> {
+                Ok(request.bearer_auth("tenant"))
+            }
+
+            async fn auth_for(
+                &self,
+                scheme: &str,
+                request: RequestBuilder,
+            ) -> crate::Result<RequestBuilder> {
+                match scheme {
+                    "service" => Ok(request.bearer_auth("service")),
+                    "none" => Ok(request),
+                    _ => Err(Error::Service(format!("unknown scheme `{scheme}`").into())),
+                }
+            }
+        }
+
+        impl TenantApi {
+            api! {
+                pub fn invoices(base: &str) -> StatusCode {
+                    GET "{base}/invoices"
+                }
+
+                pub fn rotate_keys(base: &str) -> StatusCode {
+                    POST "{base}/admin/keys"
+                    auth = service
+                }
+
+                pub fn status(base: &str) -> StatusCode {
+                    GET "{base}/status"
+                    auth = none
+                }
+
+                pub fn legacy(base: &str) -> StatusCode {
+                    GET "{base}/legacy"
+                    auth = legacy
+                }
+            }
         }
-        api!($($rest)*);
-    };
 
-    ($(#[$attr:meta])* $vis:vis fn $ident:ident($($name:ident: $ty:ty),*) -> String { $method:tt $url:literal $($headername:tt: $headervalue:expr)* } $($rest:tt)*) => {
-        $(#[$attr])*
-        #[inline]
-        $vis async fn $ident(&self, $($name: $ty),*) -> ::reqwest::Result<String> {
-            use $crate::Api as _;
-            self.pre_request(self.client().request($crate::reqwest::Method::$method, format!($url).as_str()))?
-                $(.header($headername, format!($headervalue).as_str()))*
-                .send()
-                .await?
-                .text()
-                .await
-        }
-        api!($($rest)*);
-    };
+        #[tokio::test]
+        async fn scheme_per_endpoint() {
+            let (base, requests) = server::serve(vec![
+                Response::new(200),
+                Response::new(200),
+                Response::new(200),
+            ])
+            .await;
 
-    ($(#[$attr:meta])* $vis:vis fn $ident:ident($($name:ident: $ty:ty),*) -> Bytes { $method:tt $url:literal $($headername:tt: $headervalue:expr)* } $($rest:tt)*) => {
-        $(#[$attr])*
-        #[inline]
-        $vis async fn $ident(&self, $($name: $ty),*) -> ::reqwest::Result<::bytes::Bytes> {
-            use $crate::Api as _;
-            self.pre_request(self.client().request($crate::reqwest::Method::$method, format!($url).as_str()))?
-                $(.header($headername, format!($headervalue).as_str()))*
-                .send()
-                .await?
-                .bytes()
-                .await
-        }
-        api!($($rest)*);
-    };
+            let api = TenantApi {
+                client: Client::new(),
+            };
+            api.invoices(&base).await.unwrap();
+            api.rotate_keys(&base).await.unwrap();
+            api.status(&base).await.unwrap();
+            assert!(matches!(api.legacy(&base).await, Err(Error::Service(_))));
 
-    ($(#[$attr:meta])* $vis:vis fn $ident:ident($($name:ident: $ty:ty),*) -> Json<$res:ty> { $method:ident $url:literal $($headername:tt: $headervalue:expr)* } $($rest:tt)*) => {
-        $(#[$attr])*
-        #[inline]
-        $vis async fn $ident(&self, $($name: $ty),*) -> ::reqwest::Result<$res> {
-            use $crate::Api as _;
-            self.pre_request(self.client().request($crate::reqwest::Method::$method, format!($url).as_str()))?
-                $(.header($headername, format!($headervalue).as_str()))*
-                .send()
-                .await?
-                .json()
-                .await
+            let requests = requests.await.unwrap();
+            let authorization: Vec<_> = requests
+                .iter()
+                .map(|request| request.header("authorization"))
+                .collect();
+            assert_eq!(
+                authorization,
+                [Some("Bearer tenant"), Some("Bearer service"), None]
+            );
         }
-        api!($($rest)*);
-    };
-}
+    }
 
-#[cfg(test)]
-mod tests {
-    #![allow(unused)]
+    mod token_store {
+        use std::time::{Duration, SystemTime};
 
-    use example::{CreateTodo, JsonPlaceholder, Todo, UpdateTodo};
+        use super::server::{self, Response};
+        use crate::{api, BearerAuth, MemoryTokenStore, Secret, StoredToken, TokenStore};
 
-    use self::headers::HeaderTest;
+        api!(pub struct StoreTest with bearer_auth);
 
-    mod example {
-        use crate::{api, Api};
+        impl StoreTest {
+            api! {
+                pub fn me(base: &str) -> StatusCode {
+                    GET "{base}/me"
+                }
+            }
+        }
 
-        pub use models::*;
+        #[tokio::test]
+        async fn bearer_loads_and_saves() {
+            let store = std::sync::Arc::new(MemoryTokenStore::new());
+            store.save(&StoredToken::new("stored")).await.unwrap();
+            let auth = BearerAuth::default().store(store.clone());
+            let api = StoreTest::builder().auth(auth.clone()).build().unwrap();
+            let (base, requests) =
+                server::serve(vec![Response::new(200), Response::new(200)]).await;
 
-        mod models {
-            use serde::{Deserialize, Serialize};
+            api.me(&base).await.unwrap();
+            auth.save_token("saved").await.unwrap();
+            api.me(&base).await.unwrap();
 
-            #[derive(Debug, PartialEq, Eq, Deserialize, Serialize)]
-            pub struct Todo {
-                #[serde(rename = "userId")]
-                pub user_id: u32,
-                pub id: u32,
-                pub title: String,
-                pub completed: bool,
-            }
+            let requests = requests.await.unwrap();
+            assert_eq!(requests[0].header("authorization"), Some("Bearer stored"));
+            assert_eq!(requests[1].header("authorization"), Some("Bearer saved"));
+            assert_eq!(
+                store.load().await.unwrap().access_token,
+                Secret::new("saved")
+            );
+        }
 
-            #[derive(Debug, Serialize)]
-            pub struct CreateTodo {
-                #[serde(rename = "userId")]
-                pub user_id: u32,
-                pub title: String,
-                pub completed: bool,
+        /// A store that takes a while to load, so requests can overlap the load.
+        struct SlowStore;
+
+        #[cfg_attr(feature = "send", async_trait::async_trait)]
+        #[cfg_attr(not(feature = "send"), async_trait::async_trait(?Send))]
+        impl TokenStore for SlowStore {
+            async fn load(&self) -> Option<StoredToken> {
+                tokio::time::sleep(Duration::from_millis(50)).await;
+                Some(StoredToken::new("stored"))
             }
 
-            #[derive(Debug, Default, Serialize)]
-            pub struct UpdateTodo {
-                #[serde(rename = "userId", skip_serializing_if = "Option::is_none")]
-                pub user_id: Option<u32>,
-                #[serde(skip_serializing_if = "Option::is_none")]
-                pub title: Option<String>,
-                #[serde(skip_serializing_if = "Option::is_none")]
-                pub completed: Option<bool>,
+            async fn save(&self, _token: &StoredToken) -> crate::Result<()> {
+                Ok(())
             }
         }
 
-        api!(pub struct JsonPlaceholder);
+        #[tokio::test]
+        async fn concurrent_first_requests_wait_for_load() {
+            let auth = BearerAuth::default().store(SlowStore);
+            let api = StoreTest::builder().auth(auth).build().unwrap();
+            let (base, requests) =
+                server::serve(vec![Response::new(200), Response::new(200)]).await;
 
-        const BASE_URL: &str = "https://jsonplaceholder.typicode.com";
+            let (first, second) = tokio::join!(api.me(&base), api.me(&base));
+            first.unwrap();
+            second.unwrap();
 
-        impl JsonPlaceholder {
-            pub fn new() -> Self {
-                Api::new()
+            for request in requests.await.unwrap() {
+                assert_eq!(request.header("authorization"), Some("Bearer stored"));
             }
+        }
 
-            api! {
-                pub fn todos() -> Json<Vec<Todo>> {
-                    GET "{BASE_URL}/todos"
-                }
+        #[tokio::test]
+        async fn expired_not_loaded() {
+            let store = MemoryTokenStore::new();
+            let mut token = StoredToken::new("stale");
+            token.expires_at = Some(SystemTime::now() - Duration::from_secs(1));
+            store.save(&token).await.unwrap();
+            let auth = BearerAuth::default().store(store);
+            let api = StoreTest::builder().auth(auth).build().unwrap();
+            let (base, requests) = server::serve(vec![Response::new(200)]).await;
 
-                pub fn todo(id: u32) -> Json<Todo> {
-                    GET "{BASE_URL}/todos/{id}"
-                }
+            api.me(&base).await.unwrap();
 
-                pub fn create_todo(request: Json<CreateTodo>) -> Json<Todo> {
-                    POST "{BASE_URL}/todos"
-                }
+            assert_eq!(requests.await.unwrap()[0].header("authorization"), None);
+        }
 
-                pub fn replace_todo(request: Json<Todo>, id: u32) -> Json<Todo> {
-                    PUT "{BASE_URL}/todos/{id}"
-                }
+        #[cfg(feature = "json")]
+        #[tokio::test]
+        async fn file_roundtrip_and_concurrent_saves() {
+            use crate::FileTokenStore;
 
-                pub fn update_todo(request: Json<UpdateTodo>, id: u32) -> Json<Todo> {
-                    PATCH "{BASE_URL}/todos/{id}"
+            let dir = std::env::temp_dir().join(format!("api-client-token-{}", std::process::id()));
+            std::fs::create_dir_all(&dir).unwrap();
+            let path = dir.join("token.json");
+            let store = FileTokenStore::new(&path);
+            assert_eq!(store.load().await, None);
+
+            let expires_at = SystemTime::UNIX_EPOCH + Duration::from_secs(4_000_000_000);
+            let token = StoredToken {
+                access_token: Secret::new("access"),
+                refresh_token: Some(Secret::new("refresh")),
+                expires_at: Some(expires_at),
+            };
+            store.save(&token).await.unwrap();
+            assert_eq!(store.load().await, Some(token));
+
+            std::thread::scope(|scope| {
+                for i in 0..16 {
+                    let store = &store;
+                    scope.spawn(move || {
+                        let runtime = tokio::runtime::Builder::new_current_thread()
+                            .build()
+                            .unwrap();
+                        let token = StoredToken::new(format!("token-{i}"));
+                        runtime.block_on(store.save(&token)).unwrap();
+                    });
                 }
+            });
+            let loaded = store.load().await.unwrap();
+            assert!(loaded.access_token.expose().starts_with("token-"));
+            assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 1);
 
-                pub fn delete_todo(id: u32) -> StatusCode {
-                    DELETE "{BASE_URL}/todos/{id}"
+            #[cfg(unix)]
+            {
+                use std::os::unix::fs::PermissionsExt;
+                let mode = std::fs::metadata(&path).unwrap().permissions().mode();
+                assert_eq!(mode & 0o777, 0o600);
+            }
+            std::fs::remove_dir_all(&dir).unwrap();
+        }
+
+        #[cfg(feature = "keyring")]
+        #[tokio::test]
+        async fn keyring_roundtrip() {
+            let store = crate::KeyringTokenStore::new("api-client-test", "user").unwrap();
+            assert_eq!(store.load().await, None);
+
+            let token = StoredToken {
+                access_token: Secret::new("access"),
+                refresh_token: Some(Secret::new("refresh")),
+                expires_at: Some(SystemTime::UNIX_EPOCH + Duration::from_secs(4_000_000_000)),
+            };
+            store.save(&token).await.unwrap();
+            assert_eq!(store.load().await, Some(token));
+        }
+
+        #[cfg(feature = "oauth2")]
+        #[tokio::test]
+        async fn client_credentials_load_and_save() {
+            use crate::ClientCredentials;
+
+            api!(pub struct OAuthStoreTest with client_credentials);
+
+            impl OAuthStoreTest {
+                api! {
+                    pub fn me(base: &str) -> StatusCode {
+                        GET "{base}/me"
+                    }
                 }
             }
+
+            let store = std::sync::Arc::new(MemoryTokenStore::new());
+            store.save(&StoredToken::new("stored")).await.unwrap();
+            let (base, requests) = server::serve(vec![
+                Response::new(401),
+                Response::new(200)
+                    .header("content-type", "application/json")
+                    .body(r#"{"access_token":"fetched","expires_in":3600}"#),
+                Response::new(200),
+            ])
+            .await;
+            let credentials = ClientCredentials::new(format!("{base}/token"), "id", "secret")
+                .store(store.clone());
+            let api = OAuthStoreTest::with_client_credentials(credentials);
+
+            api.me(&base).await.unwrap();
+
+            let requests = requests.await.unwrap();
+            assert_eq!(requests[0].header("authorization"), Some("Bearer stored"));
+            assert_eq!(requests[1].path, "/token");
+            assert_eq!(requests[2].header("authorization"), Some("Bearer fetched"));
+            let saved = store.load().await.unwrap();
+            assert_eq!(saved.access_token, Secret::new("fetched"));
+            assert!(saved.expires_at.is_some());
         }
     }
 
-    #[tokio::test]
-    async fn json_placeholder() {
-        let api = JsonPlaceholder::new();
+    #[cfg(feature = "oauth2-pkce")]
+    mod pkce {
+        use std::{sync::Arc, time::Duration};
 
-        let all_todos = api.todos().await.unwrap();
-        let todo_1 = api.todo(1).await.unwrap();
-        assert_eq!(&all_todos[0], &todo_1);
+        use super::server::{self, Response};
+        use crate::{
+            api, AuthorizationCode, Error, MemoryTokenStore, Pkce, RedirectListener, Secret,
+            StoredToken, TokenStore,
+        };
 
-        let new_todo = api
-            .create_todo(&CreateTodo {
-                user_id: 1,
-                title: "test".to_string(),
-                completed: false,
-            })
-            .await
-            .unwrap();
-        assert_eq!(new_todo.id as usize, all_todos.len() + 1);
+        api!(pub struct PkceTest with authorization_code);
 
-        let replaced_todo = api
-            .replace_todo(
-                &Todo {
-                    title: "test".to_string(),
-                    completed: true,
-                    ..todo_1
-                },
-                1,
+        impl PkceTest {
+            api! {
+                pub fn me(base: &str) -> StatusCode {
+                    GET "{base}/me"
+                }
+            }
+        }
+
+        fn token(access: &str, refresh: Option<&str>, expires_in: u64) -> Response {
+            let refresh = refresh.map_or(String::new(), |refresh| {
+                format!(r#","refresh_token":"{refresh}""#)
+            });
+            Response::new(200)
+                .header("content-type", "application/json")
+                .body(format!(
+                    r#"{{"access_token":"{access}"{refresh},"expires_in":{expires_in}}}"#
+                ))
+        }
+
+        #[test]
+        fn challenge() {
+            // RFC 7636, appendix B.
+            let pkce = Pkce::from_verifier("dBjftJeZ4CVP-mB92K27uhbUJU1p1r_wW1gFWFOEjXk");
+            assert_eq!(
+                pkce.challenge(),
+                "E9Melhoa2OwvFrEMTJguCHaoeK1t8URWbuGJSstw-cM"
+            );
+            let random = Pkce::new().unwrap();
+            assert_eq!(random.verifier().expose().len(), 43);
+            assert_ne!(
+                random.verifier().expose(),
+                Pkce::new().unwrap().verifier().expose()
+            );
+        }
+
+        #[test]
+        fn authorize_url() {
+            let auth = AuthorizationCode::new(
+                "https://auth.example.com/authorize?audience=api",
+                "https://auth.example.com/token",
+                "cli",
             )
-            .await
-            .unwrap();
-        assert_eq!(replaced_todo.title, "test");
-        assert!(replaced_todo.completed);
+            .scope("read offline_access");
+            let pkce = Pkce::from_verifier("verifier");
+            let url = auth
+                .authorize_url(&pkce, "http://127.0.0.1:8000/callback", "xyz")
+                .unwrap();
+            assert_eq!(
+                url.as_str(),
+                format!(
+                    "https://auth.example.com/authorize?audience=api&response_type=code&client_id=cli\
+                     &redirect_uri=http%3A%2F%2F127.0.0.1%3A8000%2Fcallback&scope=read+offline_access&state=xyz\
+                     &code_challenge={}&code_challenge_method=S256",
+                    pkce.challenge()
+                )
+            );
+        }
 
-        let updated_todo = api
-            .update_todo(
-                &UpdateTodo {
-                    title: Some("test".to_string()),
-                    completed: Some(true),
-                    ..Default::default()
-                },
-                1,
+        #[tokio::test]
+        async fn listener() {
+            let listener = RedirectListener::bind(0).await.unwrap();
+            let redirect_uri = listener.redirect_uri();
+            let browser = tokio::spawn(async move {
+                // A connection opened ahead of time and left idle is dropped rather than waited on.
+                let address = redirect_uri
+                    .trim_start_matches("http://")
+                    .trim_end_matches("/callback")
+                    .to_string();
+                let _idle = tokio::net::TcpStream::connect(address).await.unwrap();
+                let favicon = redirect_uri.replace("/callback", "/favicon.ico");
+                let favicon = reqwest::get(favicon).await.unwrap().status();
+                let similar = reqwest::get(format!("{redirect_uri}foo?state=xyz&code=abc"))
+                    .await
+                    .unwrap()
+                    .status();
+                let response = reqwest::get(format!("{redirect_uri}?state=wrong&code=abc"))
+                    .await
+                    .unwrap();
+                (favicon, similar, response.status())
+            });
+            let error = listener.receive("xyz").await.unwrap_err();
+            assert!(matches!(error, Error::Protocol { .. }), "{error:?}");
+            let (favicon, similar, callback) = browser.await.unwrap();
+            assert_eq!(favicon, 404);
+            assert_eq!(similar, 404);
+            assert_eq!(callback, 400);
+        }
+
+        #[tokio::test]
+        async fn login_refresh_and_store() {
+            let (base, requests) = server::serve(vec![
+                token("first", Some("refresh-1"), 0),
+                token("second", Some("refresh-2"), 3600),
+                Response::new(200),
+                Response::new(401),
+                token("third", None, 3600),
+                Response::new(200),
+            ])
+            .await;
+            let store = Arc::new(MemoryTokenStore::new());
+            let auth = AuthorizationCode::new(
+                "https://auth.example.com/authorize",
+                format!("{base}/token"),
+                "cli",
             )
+            .margin(Duration::ZERO)
+            .store(store.clone());
+
+            let mut challenge = None;
+            auth.login(|url| {
+                let param = |name: &str| {
+                    url.query_pairs()
+                        .find(|(key, _)| key == name)
+                        .unwrap()
+                        .1
+                        .into_owned()
+                };
+                let redirect = format!(
+                    "{}?code=the-code&state={}",
+                    param("redirect_uri"),
+                    param("state")
+                );
+                challenge = Some(param("code_challenge"));
+                tokio::spawn(reqwest::get(redirect));
+            })
             .await
             .unwrap();
-        assert_eq!(updated_todo.title, "test");
-        assert!(updated_todo.completed);
 
-        assert!(api.delete_todo(1).await.unwrap().is_success());
+            let api = PkceTest::with_authorization_code(auth);
+            // The first access token has already expired, so it's refreshed before the request.
+            api.me(&base).await.unwrap();
+            // The rejected token is refreshed, keeping the refresh token the server didn't rotate.
+            api.me(&base).await.unwrap();
+
+            let requests = requests.await.unwrap();
+            let exchange = String::from_utf8_lossy(&requests[0].body).into_owned();
+            assert!(exchange.starts_with(
+                "grant_type=authorization_code&code=the-code&redirect_uri=http%3A%2F%2F127.0.0.1%3A"
+            ));
+            assert_eq!(
+                Pkce::from_verifier(exchange.split("code_verifier=").nth(1).unwrap()).challenge(),
+                challenge.unwrap()
+            );
+            assert_eq!(
+                String::from_utf8_lossy(&requests[1].body),
+                "grant_type=refresh_token&refresh_token=refresh-1&client_id=cli"
+            );
+            assert_eq!(requests[2].header("authorization"), Some("Bearer second"));
+            assert_eq!(
+                String::from_utf8_lossy(&requests[4].body),
+                "grant_type=refresh_token&refresh_token=refresh-2&client_id=cli"
+            );
+            assert_eq!(requests[5].header("authorization"), Some("Bearer third"));
+            let stored = store.load().await.unwrap();
+            assert_eq!(stored.access_token, Secret::new("third"));
+            assert_eq!(stored.refresh_token, Some(Secret::new("refresh-2")));
+        }
+
+        #[tokio::test]
+        async fn loaded_from_store() {
+            let store = MemoryTokenStore::new();
+            store.save(&StoredToken::new("stored")).await.unwrap();
+            let auth = AuthorizationCode::new("https://auth.example.com/authorize", "", "cli")
+                .store(store);
+            assert_eq!(auth.token().await.unwrap(), Secret::new("stored"));
+
+            let auth = AuthorizationCode::new("https://auth.example.com/authorize", "", "cli");
+            assert!(matches!(auth.token().await, Err(Error::LoginRequired)));
+        }
     }
 
-    mod headers {
-        use reqwest::header::USER_AGENT;
+    #[cfg(any(feature = "rustls-tls", feature = "native-tls"))]
+    mod mutual_tls {
+        use std::{io::BufReader, sync::Arc};
 
-        use crate::{api, Api};
+        use reqwest::{Certificate, Client, Identity};
+        use tokio::{
+            io::{AsyncReadExt, AsyncWriteExt},
+            net::TcpListener,
+            task::JoinHandle,
+        };
+        use tokio_rustls::{
+            rustls::{server::AllowAnyAuthenticatedClient, RootCertStore, ServerConfig},
+            TlsAcceptor,
+        };
 
-        api!(pub struct HeaderTest);
+        use crate::{api, Error};
 
-        const BASE_URL: &str = "https://ifconfig.me";
+        const CA: &str = include_str!("../tests/tls/ca.pem");
+        const SERVER: &str = include_str!("../tests/tls/server.pem");
+        const SERVER_KEY: &str = include_str!("../tests/tls/server.key");
+        const CLIENT: &str = include_str!("../tests/tls/client.pem");
+        const CLIENT_KEY: &str = include_str!("../tests/tls/client.key");
 
-        impl HeaderTest {
-            #[inline]
-            pub fn new() -> Self {
-                Api::new()
-            }
+        api!(pub struct MutualTlsTest);
 
+        impl MutualTlsTest {
             api! {
-                pub fn get_ua(ua: &str) -> String {
-                    GET "{BASE_URL}/ua"
-                    USER_AGENT: "{ua}"
-                    "X-REQUEST-ID": "0"
+                pub fn hello() -> String {
+                    GET "hello"
                 }
             }
         }
-    }
 
-    #[tokio::test]
-    async fn example_header() {
-        let api = HeaderTest::new();
+        /// Serves `connections` connections over TLS, requiring a client certificate issued by the test CA.
+        ///
+        /// Returns the base url of the server and a handle resolving to the number of completed handshakes.
+        async fn serve(connections: usize) -> (String, JoinHandle<usize>) {
+            let certificates = |pem: &str| {
+                rustls_pemfile::certs(&mut BufReader::new(pem.as_bytes()))
+                    .unwrap()
+                    .into_iter()
+                    .map(tokio_rustls::rustls::Certificate)
+                    .collect::<Vec<_>>()
+            };
+            let mut roots = RootCertStore::empty();
+            for certificate in certificates(CA) {
+                roots.add(&certificate).unwrap();
+            }
+            let key =
+                rustls_pemfile::pkcs8_private_keys(&mut BufReader::new(SERVER_KEY.as_bytes()))
+                    .unwrap()
+                    .remove(0);
+            let config = ServerConfig::builder()
+                .with_safe_defaults()
+                .with_client_cert_verifier(AllowAnyAuthenticatedClient::new(roots).boxed())
+                .with_single_cert(certificates(SERVER), tokio_rustls::rustls::PrivateKey(key))
+                .unwrap();
+            let acceptor = TlsAcceptor::from(Arc::new(config));
 
-        assert_eq!(
-            api.get_ua("Api-client 0.1").await.unwrap(),
-            "Api-client 0.1"
-        );
-    }
+            let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+            let url = format!("https://{}/", listener.local_addr().unwrap());
+            let handle = tokio::spawn(async move {
+                let mut handshakes = 0;
+                for _ in 0..connections {
+                    let (stream, _) = listener.accept().await.unwrap();
+                    let Ok(mut stream) = acceptor.accept(stream).await else {
+                        continue;
+                    };
+                    handshakes += 1;
+                    let mut request = Vec::new();
+                    let mut buffer = [0; 1024];
+                    while !request.windows(4).any(|window| window == b"\r\n\r\n") {
+                        let read = stream.read(&mut buffer).await.unwrap();
+                        if read == 0 {
+                            break;
+                        }
+                        request.extend_from_slice(&buffer[..read]);
+                    }
+                    stream
+                        .write_all(b"HTTP/1.1 200 OK\r\nconnection: close\r\ncontent-length: 5\r\n\r\nhello")
+                        .await
+                        .unwrap();
+                    stream.shutdown().await.unwrap();
+                }
+                handshakes
+            });
+            (url, handle)
+        }
 
-    mod echo {
-        use std::collections::HashMap;
+        #[cfg(feature = "rustls-tls")]
+        fn identity() -> Identity {
+            Identity::from_pem(format!("{CLIENT}{CLIENT_KEY}").as_bytes()).unwrap()
+        }
 
-        use reqwest::header::USER_AGENT;
+        #[cfg(not(feature = "rustls-tls"))]
+        fn identity() -> Identity {
+            Identity::from_pkcs8_pem(CLIENT.as_bytes(), CLIENT_KEY.as_bytes()).unwrap()
+        }
 
-        use crate::{api, Api};
+        fn ca() -> Certificate {
+            Certificate::from_pem(CA.as_bytes()).unwrap()
+        }
 
-        api!(pub struct Echo);
+        #[tokio::test]
+        async fn client_certificate() {
+            let (base, handshakes) = serve(2).await;
+            let api = MutualTlsTest::builder()
+                .base_url(&base)
+                .identity(identity())
+                .add_root_certificate(ca())
+                .build()
+                .unwrap();
+            assert_eq!(api.hello().await.unwrap(), "hello");
 
-        const BASE_URL: &str = "http://postman-echo.com";
+            let anonymous = MutualTlsTest::builder()
+                .base_url(&base)
+                .add_root_certificate(ca())
+                .build()
+                .unwrap();
+            let error = anonymous.hello().await.unwrap_err();
+            assert!(matches!(error, Error::Transport(_)), "{error:?}");
+            assert_eq!(handshakes.await.unwrap(), 1);
+        }
 
-        #[derive(Debug, serde::Deserialize)]
-        pub struct Response {
-            pub args: HashMap<String, String>,
-            pub headers: HashMap<String, String>,
-            pub url: String,
+        #[test]
+        fn not_with_client() {
+            let error = MutualTlsTest::builder()
+                .client(Client::new())
+                .identity(identity())
+                .build()
+                .unwrap_err();
+            assert!(matches!(error, Error::Config { .. }), "{error:?}");
         }
+    }
 
-        impl Echo {
-            #[inline]
-            pub fn new() -> Self {
-                Api::new()
-            }
+    #[cfg(feature = "json")]
+    mod error_details {
+        use reqwest::StatusCode;
+        use serde::Deserialize;
 
+        use super::server::{self, Response};
+        use crate::{api, Api, Error, Json};
+
+        #[derive(Debug, Deserialize)]
+        pub struct Item {
+            #[allow(dead_code)]
+            id: u32,
+        }
+
+        api!(pub struct DetailsTest);
+
+        impl DetailsTest {
             api! {
-                pub fn echo() -> Json<Response> {
-                    GET "{BASE_URL}/get"
-                    USER_AGENT: "api-client/1.0"
-                    "x-request-id": "42"
+                pub fn item(base: &str) -> Json<Item> {
+                    GET "{base}/item"
+                    expect 200
+                }
+
+                pub fn page(base: &str) -> String {
+                    GET "{base}/page"
                 }
             }
         }
 
         #[tokio::test]
-        async fn echo_test() {
-            let api = Echo::new();
+        async fn status_url_and_body() {
+            let page = "<p>".repeat(300);
+            let (base, _) = server::serve(vec![
+                Response::new(503).body("maintenance"),
+                Response::new(200).body(page.as_str()),
+                Response::new(502).body("bad gateway"),
+                Response::new(302).body("moved"),
+            ])
+            .await;
+            let api = DetailsTest::new();
 
-            let response = api.echo().await.unwrap();
+            let error = api.item(&base).await.unwrap_err();
+            assert_eq!(error.status(), Some(StatusCode::SERVICE_UNAVAILABLE));
+            assert_eq!(error.url().unwrap().path(), "/item");
+            assert_eq!(error.body(), Some("maintenance"));
 
-            assert_eq!(
-                response.headers.get("user-agent").map(String::as_str),
-                Some("api-client/1.0")
+            let error = api.item(&base).await.unwrap_err();
+            assert!(
+                matches!(&error, Error::Decode { length: 900, .. }),
+                "{error:?}"
             );
-            assert_eq!(
-                response.headers.get("x-request-id").map(String::as_str),
-                Some("42")
+            assert_eq!(error.status(), None);
+            assert_eq!(error.body(), Some(&page[..512]));
+
+            let error = api.page(&base).await.unwrap_err();
+            assert!(
+                matches!(&error, Error::UnexpectedStatus { .. }),
+                "{error:?}"
             );
+            assert_eq!(error.status(), Some(StatusCode::BAD_GATEWAY));
+            assert_eq!(error.url().unwrap().path(), "/page");
+            assert_eq!(error.body(), Some("bad gateway"));
+            assert_eq!(api.page(&base).await.unwrap(), "moved");
+        }
+
+        #[tokio::test]
+        async fn transport() {
+            let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+            let base = format!("http://{}", listener.local_addr().unwrap());
+            drop(listener);
+
+            let error = DetailsTest::new().item(&base).await.unwrap_err();
+            assert!(matches!(error, Error::Transport(_)), "{error:?}");
+            assert_eq!(error.url().unwrap().path(), "/item");
+            assert_eq!(error.body(), None);
         }
     }
 }
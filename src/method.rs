@@ -0,0 +1,49 @@
+//! HTTP method helpers used internally by the [`api`](crate::api) macro.
+
+use reqwest::Method;
+
+/// Returns whether `method` is a valid HTTP method name, a non-empty sequence of token characters.
+#[must_use]
+pub const fn is_valid(method: &str) -> bool {
+    let bytes = method.as_bytes();
+    if bytes.is_empty() {
+        return false;
+    }
+
+    let mut index = 0;
+    while index < bytes.len() {
+        if !matches!(
+            bytes[index],
+            b'!' | b'#'
+                | b'$'
+                | b'%'
+                | b'&'
+                | b'\''
+                | b'*'
+                | b'+'
+                | b'-'
+                | b'.'
+                | b'^'
+                | b'_'
+                | b'`'
+                | b'|'
+                | b'~'
+                | b'0'..=b'9'
+                | b'a'..=b'z'
+                | b'A'..=b'Z'
+        ) {
+            return false;
+        }
+        index += 1;
+    }
+    true
+}
+
+/// Builds a method declared as a string literal, such as `"PROPFIND"`.
+///
+/// # Panics
+/// Panics if `method` is not a valid method name, which the macro has already ruled out at compile time.
+#[must_use]
+pub fn parse(method: &'static str) -> Method {
+    Method::from_bytes(method.as_bytes()).expect("invalid HTTP method")
+}
@@ -0,0 +1,308 @@
+//! Middleware that requests sent by the [`api`](crate::api) macro pass through.
+
+use std::{error::Error as StdError, future::Future, sync::Arc};
+
+use reqwest::{header::AUTHORIZATION, Client, Request, RequestBuilder, Response, StatusCode};
+
+use crate::{decode, Api, CachedResponse, EndpointInfo, Error, MaybeSend, ResponseSummary, Result};
+
+#[cfg(feature = "concurrency")]
+pub use tokio::sync::Semaphore;
+
+/// Handles requests on their way to the server and their responses on the way back, such as to log, retry or sign
+/// them, returned by [`Api::middlewares`].
+///
+/// Each middleware passes the request on to the rest of the chain with [`Next::run`], and may change the request
+/// before and the response after, call it more than once, or not call it at all. Middlewares must be [`Send`] and
+/// [`Sync`], so the structs holding them are too.
+///
+/// ```rust
+/// use std::sync::Arc;
+///
+/// use api_client::{api, Api, Middleware, Next};
+/// use reqwest::{Client, Request, Response};
+///
+/// struct Logger;
+///
+#[cfg_attr(feature = "send", doc = "#[async_trait::async_trait]")]
+#[cfg_attr(not(feature = "send"), doc = "#[async_trait::async_trait(?Send)]")]
+/// impl Middleware for Logger {
+///     async fn handle(&self, request: Request, next: Next<'_>) -> api_client::Result<Response> {
+///         let url = request.url().clone();
+///         let response = next.run(request).await?;
+///         log::info!("{url}: {}", response.status());
+///         Ok(response)
+///     }
+/// }
+///
+/// struct ExampleApi {
+///     client: Client,
+///     middlewares: Vec<Arc<dyn Middleware>>,
+/// }
+///
+/// impl Api for ExampleApi {
+///     fn client(&self) -> &Client {
+///         &self.client
+///     }
+///
+///     fn middlewares(&self) -> &[Arc<dyn Middleware>] {
+///         &self.middlewares
+///     }
+/// }
+///
+/// impl ExampleApi {
+///     api! {
+///         fn example() -> String {
+///             GET "https://example.com"
+///         }
+///     }
+/// }
+///
+/// let api = ExampleApi { client: Client::new(), middlewares: vec![Arc::new(Logger)] };
+/// ```
+#[cfg_attr(feature = "send", async_trait::async_trait)]
+#[cfg_attr(not(feature = "send"), async_trait::async_trait(?Send))]
+pub trait Middleware: Send + Sync {
+    /// Handles `request`, passing it on to the rest of the chain with `next`.
+    ///
+    /// # Errors
+    /// Implementations may return an error instead of a response, such as the one returned by `next`.
+    async fn handle(&self, request: Request, next: Next<'_>) -> Result<Response>;
+}
+
+/// The rest of a middleware chain, ending with the [`Client`] sending the request.
+#[derive(Clone, Copy)]
+pub struct Next<'a> {
+    /// The client that sends the request after the last middleware.
+    client: &'a Client,
+    /// The middlewares after the current one.
+    middlewares: &'a [Arc<dyn Middleware>],
+}
+
+impl Next<'_> {
+    /// Passes `request` to the next middleware, or sends it if there are none left.
+    ///
+    /// # Errors
+    /// Returns the error of the next middleware, or [`Error::Transport`] if sending the
+    /// request fails.
+    pub async fn run(self, request: Request) -> Result<Response> {
+        match self.middlewares.split_first() {
+            Some((middleware, middlewares)) => {
+                let next = Next {
+                    client: self.client,
+                    middlewares,
+                };
+                middleware.handle(request, next).await
+            }
+            None => Ok(self.client.execute(request).await?),
+        }
+    }
+}
+
+/// A [`Middleware`] that sends requests with a function instead of the [`Api::client`], such as the `execute` method
+/// of another HTTP client or a wrapper around reqwest like `reqwest-middleware`'s `ClientWithMiddleware`.
+///
+/// Being the last stop, it ignores any middleware after it. Errors of reqwest itself are returned as
+/// [`Error::Transport`], and any others as [`Error::Service`].
+///
+/// ```rust,ignore
+/// let client = ClientBuilder::new(Client::new())
+///     .with(RetryTransientMiddleware::new_with_policy(policy))
+///     .build();
+/// let api = ExampleApi::builder()
+///     .middleware(Execute::new(move |request| {
+///         let client = client.clone();
+///         async move { client.execute(request).await }
+///     }))
+///     .build()?;
+/// ```
+#[derive(Clone, Debug)]
+pub struct Execute<F> {
+    /// Sends a request.
+    execute: F,
+}
+
+impl<F> Execute<F> {
+    /// Creates a middleware that sends requests with `execute`.
+    #[must_use]
+    pub fn new(execute: F) -> Self {
+        Execute { execute }
+    }
+}
+
+#[cfg_attr(feature = "send", async_trait::async_trait)]
+#[cfg_attr(not(feature = "send"), async_trait::async_trait(?Send))]
+impl<F, Fut, E> Middleware for Execute<F>
+where
+    F: Fn(Request) -> Fut + Send + Sync,
+    Fut: Future<Output = std::result::Result<Response, E>> + MaybeSend,
+    E: Into<Box<dyn StdError + Send + Sync>>,
+{
+    async fn handle(&self, request: Request, _next: Next<'_>) -> Result<Response> {
+        (self.execute)(request).await.map_err(service_error)
+    }
+}
+
+/// Converts an error returned by a service sending requests in place of the client, keeping errors of reqwest itself
+/// as [`Error::Transport`].
+pub(crate) fn service_error(error: impl Into<Box<dyn StdError + Send + Sync>>) -> Error {
+    match error.into().downcast::<reqwest::Error>() {
+        Ok(error) => Error::Transport(*error),
+        Err(error) => Error::Service(error),
+    }
+}
+
+/// Waits for a permit from the [`Api::concurrency_permits`] of `api`, if it has any, held until it's dropped.
+#[cfg(feature = "concurrency")]
+pub async fn permit<A: Api + ?Sized>(api: &A) -> Option<tokio::sync::SemaphorePermit<'_>> {
+    match api.concurrency_permits() {
+        Some(semaphore) => semaphore.acquire().await.ok(),
+        None => None,
+    }
+}
+
+/// Does nothing without the `concurrency` feature.
+#[cfg(not(feature = "concurrency"))]
+#[allow(clippy::unused_async)]
+pub async fn permit<A: Api + ?Sized>(_api: &A) {}
+
+/// Sends the request built by `builder` through [`Api::sign`] and the [`Api::middlewares`] of `api`.
+///
+/// # Errors
+/// Returns an error if the request can't be built or signed, or the error of the middleware chain.
+pub async fn send<A: Api + ?Sized>(api: &A, builder: RequestBuilder) -> Result<Response> {
+    execute(api, builder.build()?).await
+}
+
+/// Signs `request` with [`Api::sign`] and sends it through the [`Api::middlewares`] of `api`.
+async fn execute<A: Api + ?Sized>(api: &A, mut request: Request) -> Result<Response> {
+    api.sign(&mut request)?;
+    Next {
+        client: api.client(),
+        middlewares: api.middlewares(),
+    }
+    .run(request)
+    .await
+}
+
+/// Sends the request built by `builder` after [`Api::acquire_permit`], unless [`Api::before_send`] returns a response
+/// for it, and passes the response received to [`Api::after_receive`].
+///
+/// The [`EndpointInfo`] passed to the hooks is made of the request's method and the given `name`, `url_template` and
+/// `headers`.
+///
+/// With a `buffer`, the body of a successful response is read up front, up to the limit it holds, so the hook sees
+/// it.
+///
+/// # Errors
+/// Returns an error if the request can't be built or signed, the error of the middleware chain, or an error reading
+/// the body.
+pub async fn exchange<A: Api + ?Sized>(
+    api: &A,
+    name: &'static str,
+    url_template: &'static str,
+    headers: &'static [&'static str],
+    builder: RequestBuilder,
+    buffer: Option<Option<u64>>,
+) -> Result<Response> {
+    let request = builder.build()?;
+    let endpoint = &EndpointInfo::new(name, request.method().clone(), url_template, headers);
+    if let Some(cached) = api.before_send(endpoint, &request).await {
+        return Ok(cached.into_response(request.url().clone()));
+    }
+
+    api.acquire_permit().await;
+    let response = execute(api, request).await?;
+    receive(api, endpoint, response, buffer).await
+}
+
+/// Like [`exchange`], but passes a `401 Unauthorized` response to [`Api::reauthenticate`] and, if it returns `true`,
+/// sends the request once more without its `Authorization` header. The headers are prepared again by
+/// [`request::prepare`](crate::request::prepare), which first prepared them, and replace those of the first attempt.
+///
+/// `context` applies the endpoint's [`PreRequestWith`](crate::PreRequestWith) context, if it has one.
+///
+/// It's kept apart from [`exchange`] so endpoints skipping [`Api::pre_request_async`] can be called from it.
+///
+/// # Errors
+/// Returns the errors of [`exchange`], the preparation steps and [`Api::reauthenticate`], or [`Error::NotRetryable`]
+/// if the request should be retried but its body is a stream.
+pub async fn exchange_reauthenticating<A: Api + ?Sized>(
+    api: &A,
+    name: &'static str,
+    url_template: &'static str,
+    headers: &'static [&'static str],
+    builder: RequestBuilder,
+    buffer: Option<Option<u64>>,
+    context: impl Fn(RequestBuilder) -> Result<RequestBuilder>,
+) -> Result<Response> {
+    let request = builder.build()?;
+    let endpoint = &EndpointInfo::new(name, request.method().clone(), url_template, headers);
+    if let Some(cached) = api.before_send(endpoint, &request).await {
+        return Ok(cached.into_response(request.url().clone()));
+    }
+
+    api.acquire_permit().await;
+    let retry = request.try_clone();
+    let mut response = execute(api, request).await?;
+    if response.status() == StatusCode::UNAUTHORIZED && api.reauthenticate().await? {
+        let mut retry = retry.ok_or(Error::NotRetryable)?;
+        retry.headers_mut().remove(AUTHORIZATION);
+        let blank = Request::new(retry.method().clone(), retry.url().clone());
+        let builder = RequestBuilder::from_parts(api.client().clone(), blank);
+        let prepared = crate::request::prepare(api, endpoint, &context, builder)
+            .await?
+            .build()?;
+        for name in prepared.headers().keys() {
+            retry.headers_mut().remove(name);
+        }
+        for (name, value) in prepared.headers() {
+            retry.headers_mut().append(name, value.clone());
+        }
+        api.acquire_permit().await;
+        response = execute(api, retry).await?;
+    }
+    receive(api, endpoint, response, buffer).await
+}
+
+/// Passes `response` to [`Api::after_receive`], reading its body up front first if there is a `buffer` and it's
+/// successful.
+#[allow(clippy::option_option)]
+async fn receive<A: Api + ?Sized>(
+    api: &A,
+    endpoint: &EndpointInfo,
+    response: Response,
+    buffer: Option<Option<u64>>,
+) -> Result<Response> {
+    let limit = match buffer {
+        Some(limit) if response.status().is_success() => limit,
+        _ => {
+            let summary = ResponseSummary {
+                status: response.status(),
+                headers: response.headers(),
+                url: response.url(),
+                body: None,
+            };
+            api.after_receive(endpoint, &summary).await;
+            return Ok(response);
+        }
+    };
+
+    let status = response.status();
+    let headers = response.headers().clone();
+    let url = response.url().clone();
+    let body = decode::body(response, limit, |_, _| {}).await?;
+    let summary = ResponseSummary {
+        status,
+        headers: &headers,
+        url: &url,
+        body: Some(&body),
+    };
+    api.after_receive(endpoint, &summary).await;
+    Ok(CachedResponse {
+        status,
+        headers,
+        body,
+    }
+    .into_response(url))
+}
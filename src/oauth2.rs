@@ -0,0 +1,242 @@
+//! OAuth 2.0 access tokens fetched with the client credentials grant.
+
+use std::{
+    sync::Arc,
+    time::{Duration, Instant, SystemTime},
+};
+
+use reqwest::{Client, RequestBuilder};
+use tokio::sync::OnceCell;
+
+use crate::{
+    credentials::bearer, decode, token_store::SharedStore, AuthState, Authenticate, Error, Result,
+    Secret, StoredToken, TokenStore, Value,
+};
+
+/// An access token and when it expires.
+#[derive(Clone, Debug)]
+struct Token {
+    /// The access token.
+    value: Secret,
+    /// When the token expires, if the server said so.
+    expires: Option<Instant>,
+}
+
+impl From<StoredToken> for Token {
+    fn from(stored: StoredToken) -> Self {
+        Token {
+            value: stored.access_token,
+            expires: stored.expires_at.map(|expires_at| {
+                Instant::now()
+                    + expires_at
+                        .duration_since(SystemTime::now())
+                        .unwrap_or_default()
+            }),
+        }
+    }
+}
+
+impl From<&Token> for StoredToken {
+    fn from(token: &Token) -> Self {
+        StoredToken {
+            expires_at: token.expires.map(|expires| {
+                SystemTime::now() + expires.saturating_duration_since(Instant::now())
+            }),
+            ..StoredToken::new(token.value.clone())
+        }
+    }
+}
+
+/// Fetches OAuth 2.0 access tokens with the client credentials grant and sends them as bearer tokens, used by structs
+/// declared `with client_credentials` (requires the `oauth2` feature).
+///
+/// The token is fetched by the first request and cached until `margin` before the `expires_in` the server returned,
+/// 30 seconds by default, so clock skew and slow requests don't send an expired token. Concurrent requests needing a
+/// new token wait for a single fetch. Tokens without an `expires_in` are kept until [`ClientCredentials::invalidate`]
+/// is called, or the server responds `401 Unauthorized`, after which the request is retried once with a new token.
+/// Clones share the cached token.
+///
+/// With a [`TokenStore`], the first token is loaded from the store unless it expires within the margin, and every
+/// token fetched is saved to it. A token that fails to save is still used.
+///
+/// The client ID and secret are sent in the form body of the token request, along with the scope if one is set.
+///
+/// ```rust,no_run
+/// use api_client::ClientCredentials;
+///
+/// api_client::api!(pub struct ExampleApi with client_credentials, base = "https://example.com/v1");
+///
+/// let api = ExampleApi::with_client_credentials(
+///     ClientCredentials::new("https://auth.example.com/oauth/token", "client-id", "client-secret").scope("read"),
+/// );
+/// ```
+#[derive(Clone, Debug)]
+pub struct ClientCredentials {
+    /// The client the token requests are sent with.
+    client: Client,
+    /// The URL of the token endpoint.
+    token_url: String,
+    /// The client ID.
+    client_id: String,
+    /// The client secret.
+    client_secret: Secret,
+    /// The scope requested, if any.
+    scope: Option<String>,
+    /// How long before it expires a token is refreshed.
+    margin: Duration,
+    /// The cached token.
+    token: Arc<AuthState<Token>>,
+    /// Where tokens are kept, if anywhere.
+    store: Option<SharedStore>,
+    /// Set once the token was loaded from the store.
+    loaded: Arc<OnceCell<()>>,
+}
+
+impl ClientCredentials {
+    /// Creates an authenticator fetching tokens from `token_url` for the client `client_id`.
+    #[must_use]
+    pub fn new(
+        token_url: impl Into<String>,
+        client_id: impl Into<String>,
+        client_secret: impl Into<Secret>,
+    ) -> Self {
+        ClientCredentials {
+            client: Client::new(),
+            token_url: token_url.into(),
+            client_id: client_id.into(),
+            client_secret: client_secret.into(),
+            scope: None,
+            margin: Duration::from_secs(30),
+            token: Arc::new(AuthState::new()),
+            store: None,
+            loaded: Arc::new(OnceCell::new()),
+        }
+    }
+
+    /// Requests tokens for `scope`, a space-separated list of scopes.
+    #[must_use]
+    pub fn scope(mut self, scope: impl Into<String>) -> Self {
+        self.scope = Some(scope.into());
+        self
+    }
+
+    /// Sends token requests with `client` instead of a new one.
+    #[must_use]
+    pub fn client(mut self, client: Client) -> Self {
+        self.client = client;
+        self
+    }
+
+    /// Refreshes tokens `margin` before they expire instead of 30 seconds before.
+    #[must_use]
+    pub fn margin(mut self, margin: Duration) -> Self {
+        self.margin = margin;
+        self
+    }
+
+    /// Keeps tokens in `store`.
+    #[must_use]
+    pub fn store(mut self, store: impl TokenStore + 'static) -> Self {
+        self.store = Some(SharedStore(Arc::new(store)));
+        self
+    }
+
+    /// Returns the cached access token, fetching a new one if there is none or it's about to expire.
+    ///
+    /// # Errors
+    /// Returns an error if the token request fails, the token endpoint responds with an error status, or its response
+    /// has no `access_token`.
+    pub async fn token(&self) -> Result<Secret> {
+        let token = self
+            .token
+            .get_or_refresh_if(|token| self.expired(token), || self.refresh())
+            .await?;
+        Ok(token.value)
+    }
+
+    /// Discards the cached token, so the next request fetches a new one, such as after the server rejects it.
+    pub async fn invalidate(&self) {
+        self.token.invalidate().await;
+    }
+
+    /// Returns whether `token` expires within the margin.
+    fn expired(&self, token: &Token) -> bool {
+        token
+            .expires
+            .is_some_and(|expires| Instant::now() + self.margin >= expires)
+    }
+
+    /// Loads the token from the store the first time, or fetches a new one and saves it.
+    async fn refresh(&self) -> Result<Token> {
+        let Some(SharedStore(store)) = &self.store else {
+            return self.fetch().await;
+        };
+        let mut stored = None;
+        self.loaded
+            .get_or_init(|| async { stored = store.load().await })
+            .await;
+        if let Some(stored) = stored.filter(|stored| !stored.is_expired(self.margin)) {
+            return Ok(stored.into());
+        }
+        let token = self.fetch().await?;
+        if let Err(error) = store.save(&StoredToken::from(&token)).await {
+            log::warn!("failed to save OAuth 2.0 token: {error}");
+        }
+        Ok(token)
+    }
+
+    /// Requests a new token from the token endpoint.
+    async fn fetch(&self) -> Result<Token> {
+        let mut form = vec![
+            ("grant_type", "client_credentials"),
+            ("client_id", &self.client_id),
+            ("client_secret", self.client_secret.expose()),
+        ];
+        if let Some(scope) = &self.scope {
+            form.push(("scope", scope));
+        }
+        Ok(request_token(&self.client, &self.token_url, &form)
+            .await?
+            .into())
+    }
+}
+
+/// Posts `form` to the token endpoint at `url` and reads the token from its response.
+pub(crate) async fn request_token(
+    client: &Client,
+    url: &str,
+    form: &[(&str, &str)],
+) -> Result<StoredToken> {
+    let requested = SystemTime::now();
+    let response = client.post(url).form(form).send().await?;
+    let response = decode::expect_status(response, &[200..=299]).await?;
+    let bytes = decode::body(response, None, |_, _| {}).await?;
+    let body: Value =
+        serde_json::from_slice(&bytes).map_err(|error| Error::decode_redacted(&bytes, error))?;
+
+    let secret = |name: &str| body.get(name)?.as_str().map(Secret::new);
+    let access_token = secret("access_token")
+        .ok_or_else(|| Error::decode_redacted(&bytes, "token response has no `access_token`"))?;
+    // Some servers send `expires_in` as a string.
+    let expires_in = body.get("expires_in").and_then(|expires_in| {
+        expires_in
+            .as_u64()
+            .or_else(|| expires_in.as_str()?.parse().ok())
+    });
+    Ok(StoredToken {
+        access_token,
+        refresh_token: secret("refresh_token"),
+        expires_at: expires_in.map(|seconds| requested + Duration::from_secs(seconds)),
+    })
+}
+
+impl Authenticate for ClientCredentials {
+    async fn authenticate(&self, request: RequestBuilder) -> Result<RequestBuilder> {
+        bearer(request, &self.token().await?)
+    }
+
+    async fn reauthenticate(&self) -> Result<bool> {
+        self.invalidate().await;
+        Ok(true)
+    }
+}
@@ -0,0 +1,466 @@
+//! OAuth 2.0 authorization code grant with PKCE, for command-line tools that log users in with a browser.
+
+use std::{
+    sync::Arc,
+    time::{Duration, UNIX_EPOCH},
+};
+
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use reqwest::{Client, RequestBuilder, Url};
+use ring::{
+    digest,
+    rand::{SecureRandom, SystemRandom},
+};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{TcpListener, TcpStream},
+    sync::OnceCell,
+};
+
+use crate::{
+    credentials::bearer, oauth2::request_token, token_store::SharedStore, AuthState, Authenticate,
+    Error, Result, Secret, StoredToken, TokenStore,
+};
+
+/// The longest redirect request [`RedirectListener`] reads.
+const MAX_REQUEST: usize = 8 * 1024;
+/// How long [`RedirectListener`] waits for a connection to send its request before dropping it, so connections a
+/// browser opens ahead of time and leaves idle don't hold up the redirect.
+const READ_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// A PKCE code verifier and its `S256` code challenge, proving that the token exchange comes from the program that
+/// started the login (RFC 7636).
+#[derive(Clone, Debug)]
+pub struct Pkce {
+    /// The code verifier, sent with the token exchange.
+    verifier: Secret,
+    /// The code challenge, sent with the authorization request.
+    challenge: String,
+}
+
+impl Pkce {
+    /// Creates a random code verifier and its challenge.
+    ///
+    /// # Errors
+    /// Returns an error if the system's random number generator fails.
+    pub fn new() -> Result<Self> {
+        Ok(Pkce::from_verifier(random(32)?))
+    }
+
+    /// Creates the challenge of a known code verifier.
+    #[must_use]
+    pub fn from_verifier(verifier: impl Into<Secret>) -> Self {
+        let verifier = verifier.into();
+        let hash = digest::digest(&digest::SHA256, verifier.expose().as_bytes());
+        Pkce {
+            challenge: URL_SAFE_NO_PAD.encode(hash),
+            verifier,
+        }
+    }
+
+    /// Returns the code verifier.
+    #[must_use]
+    pub fn verifier(&self) -> &Secret {
+        &self.verifier
+    }
+
+    /// Returns the `S256` code challenge.
+    #[must_use]
+    pub fn challenge(&self) -> &str {
+        &self.challenge
+    }
+}
+
+/// Returns `bytes` random bytes, encoded as unpadded URL-safe base64.
+fn random(bytes: usize) -> Result<String> {
+    let mut buffer = vec![0; bytes];
+    SystemRandom::new()
+        .fill(&mut buffer)
+        .map_err(|_| Error::Service("failed to generate random bytes".into()))?;
+    Ok(URL_SAFE_NO_PAD.encode(buffer))
+}
+
+/// A one-shot HTTP listener on the loopback interface, receiving the redirect of an authorization request (requires
+/// the `oauth2-pkce` feature).
+#[derive(Debug)]
+pub struct RedirectListener {
+    /// The bound listener.
+    listener: TcpListener,
+    /// The port it's bound to.
+    port: u16,
+}
+
+impl RedirectListener {
+    /// Listens on `127.0.0.1:port`, or on a free port if `port` is 0.
+    ///
+    /// # Errors
+    /// Returns an error if the port can't be bound.
+    pub async fn bind(port: u16) -> Result<Self> {
+        let listener = TcpListener::bind(("127.0.0.1", port)).await?;
+        let port = listener.local_addr()?.port();
+        Ok(RedirectListener { listener, port })
+    }
+
+    /// Returns the redirect URI to register with the authorization server and send in the authorization request.
+    #[must_use]
+    pub fn redirect_uri(&self) -> String {
+        format!("http://127.0.0.1:{}/callback", self.port)
+    }
+
+    /// Waits for the browser to be redirected to [`RedirectListener::redirect_uri`] and returns the authorization
+    /// code. Requests for other paths, such as a favicon, get a `404 Not Found` and are otherwise ignored, as are
+    /// connections that send nothing for two seconds.
+    ///
+    /// # Errors
+    /// Returns [`Error::Protocol`] if the authorization server returned an error, or the redirect's `state` isn't
+    /// `state` or it has no code, and [`Error::Io`] if the connection fails.
+    pub async fn receive(self, state: &str) -> Result<String> {
+        loop {
+            let (mut stream, _) = self.listener.accept().await?;
+            let Ok(Ok(Some(line))) =
+                tokio::time::timeout(READ_TIMEOUT, read_line(&mut stream)).await
+            else {
+                continue;
+            };
+            let mut line = line.split(' ');
+            let target = match (line.next(), line.next()) {
+                (Some("GET"), Some(target)) => target,
+                _ => "",
+            };
+            let (path, query) = target.split_once('?').unwrap_or((target, ""));
+            if path != "/callback" {
+                // The browser may already have closed the connection, which doesn't matter for requests we ignore.
+                let _ = respond(&mut stream, "404 Not Found", "Not found.").await;
+                continue;
+            }
+
+            let param = |name: &str| {
+                form_urlencoded::parse(query.as_bytes())
+                    .find(|(key, _)| key == name)
+                    .map(|(_, value)| value.into_owned())
+            };
+            let result = if let Some(error) = param("error") {
+                Err(match param("error_description") {
+                    Some(description) => format!("authorization failed: {error}: {description}"),
+                    None => format!("authorization failed: {error}"),
+                })
+            } else if param("state").as_deref() != Some(state) {
+                Err("authorization response has a different `state`".to_string())
+            } else {
+                param("code").ok_or_else(|| "authorization response has no `code`".to_string())
+            };
+
+            return match result {
+                Ok(code) => {
+                    respond(
+                        &mut stream,
+                        "200 OK",
+                        "Logged in. You can close this window.",
+                    )
+                    .await?;
+                    Ok(code)
+                }
+                Err(message) => {
+                    respond(&mut stream, "400 Bad Request", &message).await?;
+                    Err(Error::Protocol { message })
+                }
+            };
+        }
+    }
+}
+
+/// Reads an HTTP request from `stream` and returns its request line, or `None` if the connection closed before sending
+/// anything.
+async fn read_line(stream: &mut TcpStream) -> Result<Option<String>> {
+    let mut request = Vec::new();
+    let mut buffer = [0; 1024];
+    while !request.windows(4).any(|window| window == b"\r\n\r\n") && request.len() < MAX_REQUEST {
+        let read = stream.read(&mut buffer).await?;
+        if read == 0 {
+            break;
+        }
+        request.extend_from_slice(&buffer[..read]);
+    }
+    if request.is_empty() {
+        return Ok(None);
+    }
+    let request = String::from_utf8_lossy(&request);
+    Ok(Some(request.lines().next().unwrap_or_default().to_string()))
+}
+
+/// Writes a plain text response with `status` and `body` to `stream`.
+async fn respond(stream: &mut TcpStream, status: &str, body: &str) -> Result<()> {
+    let response = format!(
+        "HTTP/1.1 {status}\r\ncontent-type: text/plain; charset=utf-8\r\ncontent-length: {}\r\nconnection: close\r\n\r\n{body}",
+        body.len()
+    );
+    stream.write_all(response.as_bytes()).await?;
+    stream.shutdown().await?;
+    Ok(())
+}
+
+/// Logs users in with the OAuth 2.0 authorization code grant and PKCE, and sends the access token as a bearer token,
+/// refreshing it with the refresh token when it expires. Used by structs declared `with authorization_code` (requires
+/// the `oauth2-pkce` feature).
+///
+/// [`AuthorizationCode::login`] runs the whole flow for a command-line tool: it listens for the redirect on a
+/// loopback port, has `open` show the authorization URL to the user, such as by opening it in their browser, and
+/// exchanges the code it receives for tokens. The pieces are public too: [`Pkce`], [`AuthorizationCode::authorize_url`],
+/// [`RedirectListener`] and [`AuthorizationCode::exchange`].
+///
+/// With a [`TokenStore`], tokens are loaded from it by the first request, so users only log in once, and every new
+/// token, including rotated refresh tokens, is saved to it. Clones share the tokens.
+///
+/// ```rust,no_run
+/// use api_client::{AuthorizationCode, MemoryTokenStore};
+///
+/// api_client::api!(pub struct ExampleApi with authorization_code, base = "https://example.com/v1");
+///
+/// # async fn run() -> api_client::Result<()> {
+/// let auth = AuthorizationCode::new(
+///     "https://auth.example.com/authorize",
+///     "https://auth.example.com/oauth/token",
+///     "client-id",
+/// )
+/// .scope("read offline_access")
+/// .store(MemoryTokenStore::new());
+/// if auth.token().await.is_err() {
+///     auth.login(|url| eprintln!("Open {url} to log in.")).await?;
+/// }
+/// let api = ExampleApi::with_authorization_code(auth);
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Clone, Debug)]
+pub struct AuthorizationCode {
+    /// The client the token requests are sent with.
+    client: Client,
+    /// The URL of the authorization endpoint.
+    authorize_url: String,
+    /// The URL of the token endpoint.
+    token_url: String,
+    /// The client ID.
+    client_id: String,
+    /// The client secret, if the client has one.
+    client_secret: Option<Secret>,
+    /// The scope requested, if any.
+    scope: Option<String>,
+    /// The port [`AuthorizationCode::login`] listens on, or 0 for a free one.
+    port: u16,
+    /// How long before it expires a token is refreshed.
+    margin: Duration,
+    /// The current tokens.
+    token: Arc<AuthState<StoredToken>>,
+    /// Where tokens are kept, if anywhere.
+    store: Option<SharedStore>,
+    /// Set once the tokens were loaded from the store, or a login made loading them moot.
+    loaded: Arc<OnceCell<()>>,
+}
+
+impl AuthorizationCode {
+    /// Creates an authenticator for the client `client_id`, logging in at `authorize_url` and fetching tokens from
+    /// `token_url`.
+    #[must_use]
+    pub fn new(
+        authorize_url: impl Into<String>,
+        token_url: impl Into<String>,
+        client_id: impl Into<String>,
+    ) -> Self {
+        AuthorizationCode {
+            client: Client::new(),
+            authorize_url: authorize_url.into(),
+            token_url: token_url.into(),
+            client_id: client_id.into(),
+            client_secret: None,
+            scope: None,
+            port: 0,
+            margin: Duration::from_secs(30),
+            token: Arc::new(AuthState::new()),
+            store: None,
+            loaded: Arc::new(OnceCell::new()),
+        }
+    }
+
+    /// Sends `secret` with token requests, for clients that have one.
+    #[must_use]
+    pub fn client_secret(mut self, secret: impl Into<Secret>) -> Self {
+        self.client_secret = Some(secret.into());
+        self
+    }
+
+    /// Requests tokens for `scope`, a space-separated list of scopes.
+    #[must_use]
+    pub fn scope(mut self, scope: impl Into<String>) -> Self {
+        self.scope = Some(scope.into());
+        self
+    }
+
+    /// Sends token requests with `client` instead of a new one.
+    #[must_use]
+    pub fn client(mut self, client: Client) -> Self {
+        self.client = client;
+        self
+    }
+
+    /// Listens for the redirect on `port` instead of a free one, for servers that only accept registered redirect
+    /// URIs.
+    #[must_use]
+    pub fn port(mut self, port: u16) -> Self {
+        self.port = port;
+        self
+    }
+
+    /// Refreshes tokens `margin` before they expire instead of 30 seconds before.
+    #[must_use]
+    pub fn margin(mut self, margin: Duration) -> Self {
+        self.margin = margin;
+        self
+    }
+
+    /// Keeps tokens in `store`.
+    #[must_use]
+    pub fn store(mut self, store: impl TokenStore + 'static) -> Self {
+        self.store = Some(SharedStore(Arc::new(store)));
+        self
+    }
+
+    /// Returns the URL the user logs in at, redirecting back to `redirect_uri` with `state`.
+    ///
+    /// # Errors
+    /// Returns [`Error::InvalidBaseUrl`] if the authorization URL isn't valid.
+    pub fn authorize_url(&self, pkce: &Pkce, redirect_uri: &str, state: &str) -> Result<Url> {
+        let mut url = Url::parse(&self.authorize_url).map_err(|_| Error::InvalidBaseUrl {
+            url: self.authorize_url.clone(),
+        })?;
+        {
+            let mut query = url.query_pairs_mut();
+            query
+                .append_pair("response_type", "code")
+                .append_pair("client_id", &self.client_id)
+                .append_pair("redirect_uri", redirect_uri);
+            if let Some(scope) = &self.scope {
+                query.append_pair("scope", scope);
+            }
+            query
+                .append_pair("state", state)
+                .append_pair("code_challenge", pkce.challenge())
+                .append_pair("code_challenge_method", "S256");
+        }
+        Ok(url)
+    }
+
+    /// Logs the user in: listens for the redirect, calls `open` with the authorization URL, and exchanges the code
+    /// the browser is redirected with for tokens.
+    ///
+    /// # Errors
+    /// Returns an error if the port can't be bound, the authorization server returns an error, or the token exchange
+    /// fails.
+    pub async fn login(&self, open: impl FnOnce(&Url)) -> Result<StoredToken> {
+        let listener = RedirectListener::bind(self.port).await?;
+        let redirect_uri = listener.redirect_uri();
+        let pkce = Pkce::new()?;
+        let state = random(16)?;
+        open(&self.authorize_url(&pkce, &redirect_uri, &state)?);
+        let code = listener.receive(&state).await?;
+        self.exchange(&code, &pkce, &redirect_uri).await
+    }
+
+    /// Exchanges an authorization code for tokens, which are used by later requests and saved to the store.
+    ///
+    /// # Errors
+    /// Returns an error if the token request fails, the token endpoint responds with an error status, its response
+    /// has no `access_token`, or the tokens can't be saved.
+    pub async fn exchange(
+        &self,
+        code: &str,
+        pkce: &Pkce,
+        redirect_uri: &str,
+    ) -> Result<StoredToken> {
+        let mut form = vec![
+            ("grant_type", "authorization_code"),
+            ("code", code),
+            ("redirect_uri", redirect_uri),
+            ("client_id", &self.client_id),
+            ("code_verifier", pkce.verifier().expose()),
+        ];
+        if let Some(secret) = &self.client_secret {
+            form.push(("client_secret", secret.expose()));
+        }
+        let token = request_token(&self.client, &self.token_url, &form).await?;
+        let _ = self.loaded.set(());
+        self.token.set(token.clone()).await;
+        if let Some(SharedStore(store)) = &self.store {
+            store.save(&token).await?;
+        }
+        Ok(token)
+    }
+
+    /// Returns the current access token, loading it from the store or refreshing it if needed.
+    ///
+    /// # Errors
+    /// Returns [`Error::LoginRequired`] if there's no token and no refresh token, so the user has to log in, or an error
+    /// if refreshing fails.
+    pub async fn token(&self) -> Result<Secret> {
+        let token = self
+            .token
+            .get_or_refresh_if(|token| token.is_expired(self.margin), || self.renew())
+            .await?;
+        Ok(token.access_token)
+    }
+
+    /// Loads the tokens from the store the first time, or refreshes them with the refresh token.
+    async fn renew(&self) -> Result<StoredToken> {
+        let mut current = self.token.get().await;
+        if let Some(SharedStore(store)) = &self.store {
+            let mut stored = None;
+            self.loaded
+                .get_or_init(|| async { stored = store.load().await })
+                .await;
+            current = stored.or(current);
+        }
+        let refresh_token = match current {
+            Some(token) if !token.is_expired(self.margin) => return Ok(token),
+            Some(StoredToken {
+                refresh_token: Some(refresh_token),
+                ..
+            }) => refresh_token,
+            _ => return Err(Error::LoginRequired),
+        };
+
+        let mut form = vec![
+            ("grant_type", "refresh_token"),
+            ("refresh_token", refresh_token.expose()),
+            ("client_id", &self.client_id),
+        ];
+        if let Some(secret) = &self.client_secret {
+            form.push(("client_secret", secret.expose()));
+        }
+        let mut token = request_token(&self.client, &self.token_url, &form).await?;
+        // Servers that don't rotate refresh tokens keep accepting the old one.
+        token.refresh_token = token.refresh_token.or(Some(refresh_token));
+        if let Some(SharedStore(store)) = &self.store {
+            if let Err(error) = store.save(&token).await {
+                log::warn!("failed to save OAuth 2.0 token: {error}");
+            }
+        }
+        Ok(token)
+    }
+}
+
+impl Authenticate for AuthorizationCode {
+    async fn authenticate(&self, request: RequestBuilder) -> Result<RequestBuilder> {
+        bearer(request, &self.token().await?)
+    }
+
+    async fn reauthenticate(&self) -> Result<bool> {
+        // Keep the refresh token, only marking the rejected access token as expired.
+        match self.token.get().await {
+            Some(mut token) if token.refresh_token.is_some() => {
+                token.expires_at = Some(UNIX_EPOCH);
+                self.token.set(token).await;
+                Ok(true)
+            }
+            _ => Ok(false),
+        }
+    }
+}
@@ -0,0 +1,161 @@
+//! Query parameters taken from endpoint parameters and [`Api::default_query`](crate::Api::default_query).
+
+use std::borrow::Cow;
+
+use reqwest::RequestBuilder;
+
+/// A value that can be sent as a query parameter by a `? key: parameter` clause.
+///
+/// Scalars have one value, `None` has none, so the parameter is left out entirely, and sequences have one per element.
+pub trait QueryValue {
+    /// Appends the values of this parameter to `values`.
+    fn query_values(&self, values: &mut Vec<String>);
+}
+
+impl<T: QueryValue + ?Sized> QueryValue for &T {
+    #[inline]
+    fn query_values(&self, values: &mut Vec<String>) {
+        (**self).query_values(values);
+    }
+}
+
+impl<T: QueryValue> QueryValue for Option<T> {
+    #[inline]
+    fn query_values(&self, values: &mut Vec<String>) {
+        if let Some(value) = self {
+            value.query_values(values);
+        }
+    }
+}
+
+impl<T: QueryValue> QueryValue for [T] {
+    #[inline]
+    fn query_values(&self, values: &mut Vec<String>) {
+        for value in self {
+            value.query_values(values);
+        }
+    }
+}
+
+impl<T: QueryValue, const N: usize> QueryValue for [T; N] {
+    #[inline]
+    fn query_values(&self, values: &mut Vec<String>) {
+        self[..].query_values(values);
+    }
+}
+
+impl<T: QueryValue> QueryValue for Vec<T> {
+    #[inline]
+    fn query_values(&self, values: &mut Vec<String>) {
+        self[..].query_values(values);
+    }
+}
+
+/// Implements [`QueryValue`] with the [`ToString`] form of each type.
+macro_rules! display {
+    ($($ty:ty),*) => {
+        $(
+            impl QueryValue for $ty {
+                #[inline]
+                fn query_values(&self, values: &mut Vec<String>) {
+                    values.push(self.to_string());
+                }
+            }
+        )*
+    };
+}
+
+display!(str, String, Cow<'_, str>, bool, char, f32, f64);
+display!(i8, i16, i32, i64, i128, isize, u8, u16, u32, u64, u128, usize);
+
+/// How a query parameter with several values is sent, chosen per endpoint with a `query_style = ...` clause.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum QueryStyle {
+    /// Repeats the key for each value: `id=1&id=2`. This is the default.
+    Repeat,
+    /// Joins the values with commas: `id=1,2`. Like any comma in a query value, the commas are percent-encoded, so
+    /// servers must decode the value before splitting it, and can't tell them from commas within values.
+    Comma,
+    /// Repeats the key with a `[]` suffix for each value: `id[]=1&id[]=2`.
+    Brackets,
+}
+
+impl Default for QueryStyle {
+    #[inline]
+    fn default() -> Self {
+        QueryStyle::Repeat
+    }
+}
+
+/// Adds the query pairs of `value` to the request built by `builder`, with [`RequestBuilder::query`].
+#[doc(hidden)]
+pub fn append<T: QueryValue + ?Sized>(
+    builder: RequestBuilder,
+    key: &str,
+    value: &T,
+    style: QueryStyle,
+) -> RequestBuilder {
+    let mut values = Vec::new();
+    value.query_values(&mut values);
+    if values.is_empty() {
+        return builder;
+    }
+
+    let pairs = match style {
+        QueryStyle::Repeat => values
+            .into_iter()
+            .map(|value| (key.to_string(), value))
+            .collect::<Vec<_>>(),
+        QueryStyle::Comma => vec![(key.to_string(), values.join(","))],
+        QueryStyle::Brackets => {
+            let key = format!("{key}[]");
+            values
+                .into_iter()
+                .map(|value| (key.clone(), value))
+                .collect()
+        }
+    };
+    builder.query(&pairs)
+}
+
+/// Returns the URL of the request built by `builder`, such as after [`append`] added its query, or `url` if the
+/// request can't be built.
+#[doc(hidden)]
+#[must_use]
+pub fn url(builder: RequestBuilder, url: String) -> String {
+    builder
+        .build()
+        .map_or(url, |request| request.url().to_string())
+}
+
+/// Appends the pairs of `defaults` whose keys aren't already in the query of the request built by `builder`.
+///
+/// # Errors
+/// Returns the error of `builder` if the request can't be built.
+#[doc(hidden)]
+pub fn defaults(
+    builder: RequestBuilder,
+    defaults: &[(String, String)],
+) -> reqwest::Result<RequestBuilder> {
+    if defaults.is_empty() {
+        return Ok(builder);
+    }
+
+    let (client, request) = builder.build_split();
+    let request = request?;
+    let keys = request
+        .url()
+        .query_pairs()
+        .map(|(key, _)| key.into_owned())
+        .collect::<Vec<_>>();
+    let missing = defaults
+        .iter()
+        .filter(|(key, _)| !keys.contains(key))
+        .collect::<Vec<_>>();
+    let builder = RequestBuilder::from_parts(client, request);
+    Ok(if missing.is_empty() {
+        builder
+    } else {
+        builder.query(&missing)
+    })
+}
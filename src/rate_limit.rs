@@ -0,0 +1,101 @@
+//! Rate limiting that delays requests to stay within a number of requests per interval.
+
+use std::{collections::VecDeque, sync::Mutex, time::Duration};
+
+use tokio::time::{sleep_until, Instant};
+
+/// Limits requests to a number per interval, delaying those that would exceed it.
+///
+/// Embed it in an [`Api`](crate::Api) implementation and acquire it from
+/// [`Api::acquire_permit`](crate::Api::acquire_permit), which every generated method awaits right before sending its
+/// request. Requests go out immediately until the limit is reached, and after that each one waits until the interval
+/// has passed since the request that many before it, so no interval ever sees more than the limit. Unlike a
+/// [`Budget`](crate::Budget), it never fails a request.
+///
+/// A request that is cancelled while waiting still uses up its turn, so the limit is never exceeded.
+///
+/// ```rust
+/// use std::time::Duration;
+///
+/// use api_client::{api, Api, RateLimiter};
+/// use reqwest::Client;
+///
+/// struct ExampleApi {
+///     client: Client,
+///     limiter: RateLimiter,
+/// }
+///
+/// impl Api for ExampleApi {
+///     fn client(&self) -> &Client {
+///         &self.client
+///     }
+///
+///     async fn acquire_permit(&self) {
+///         self.limiter.acquire().await;
+///     }
+/// }
+///
+/// impl ExampleApi {
+///     api! {
+///         fn example() -> String {
+///             GET "https://example.com"
+///         }
+///     }
+/// }
+///
+/// let api = ExampleApi {
+///     client: Client::new(),
+///     limiter: RateLimiter::new(5, Duration::from_secs(1)),
+/// };
+/// ```
+#[derive(Debug)]
+pub struct RateLimiter {
+    /// The number of requests allowed per interval.
+    requests: usize,
+    /// The interval the requests are counted over.
+    interval: Duration,
+    /// The times of the last requests, oldest first, including those still waiting to be sent.
+    sent: Mutex<VecDeque<Instant>>,
+}
+
+impl RateLimiter {
+    /// Creates a limiter allowing `requests` requests per `interval`.
+    ///
+    /// # Panics
+    /// Panics if `requests` is zero.
+    #[must_use]
+    pub fn new(requests: u32, interval: Duration) -> Self {
+        assert!(
+            requests > 0,
+            "a rate limiter must allow at least one request"
+        );
+        let requests = requests as usize;
+        RateLimiter {
+            requests,
+            interval,
+            sent: Mutex::new(VecDeque::with_capacity(requests)),
+        }
+    }
+
+    /// Waits until another request may be sent, and counts it.
+    pub async fn acquire(&self) {
+        let now = Instant::now();
+        let ready = {
+            let mut sent = self
+                .sent
+                .lock()
+                .unwrap_or_else(std::sync::PoisonError::into_inner);
+            let ready = if sent.len() < self.requests {
+                now
+            } else {
+                sent.pop_front()
+                    .map_or(now, |oldest| (oldest + self.interval).max(now))
+            };
+            sent.push_back(ready);
+            ready
+        };
+        if ready > now {
+            sleep_until(ready).await;
+        }
+    }
+}
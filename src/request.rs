@@ -0,0 +1,392 @@
+//! Sending requests for endpoints written by hand, the way methods generated by the [`api`](crate::api) macro do.
+
+use std::future::Future;
+
+use reqwest::{
+    header::{HeaderName, HeaderValue, ACCEPT},
+    Method, RequestBuilder, Response, StatusCode,
+};
+
+use crate::{header, middleware, url, Api, Body, Failure, Headers, MaybeSend, Result, Text};
+
+/// A type [`send`] decodes a response into, matching the return kind of the same name in the [`api`](crate::api)
+/// macro.
+pub trait FromResponse: Sized {
+    /// The `Accept` header sent for the response, unless [`Api::default_headers`] has one.
+    const ACCEPT: &'static str = "*/*";
+
+    /// Whether the whole body is read before decoding, so [`Api::after_receive`] sees it.
+    const BUFFERED: bool = false;
+
+    /// Whether a response with an error status fails with [`Error::UnexpectedStatus`](crate::Error::UnexpectedStatus)
+    /// instead of being decoded.
+    const CHECKS_STATUS: bool = true;
+
+    /// Decodes `response`, reading at most `limit` bytes of its body.
+    ///
+    /// # Errors
+    /// Returns an error if reading or decoding the body fails.
+    fn from_response(
+        response: Response,
+        limit: Option<u64>,
+    ) -> impl Future<Output = Result<Self>> + MaybeSend;
+}
+
+impl FromResponse for StatusCode {
+    const CHECKS_STATUS: bool = false;
+
+    async fn from_response(response: Response, _limit: Option<u64>) -> Result<Self> {
+        Ok(response.status())
+    }
+}
+
+impl FromResponse for Headers {
+    const CHECKS_STATUS: bool = false;
+
+    async fn from_response(response: Response, _limit: Option<u64>) -> Result<Self> {
+        Ok(Headers::new(response))
+    }
+}
+
+impl FromResponse for String {
+    const ACCEPT: &'static str = "text/plain, */*;q=0.8";
+    const BUFFERED: bool = true;
+
+    async fn from_response(response: Response, limit: Option<u64>) -> Result<Self> {
+        Ok(Text::new(response, limit, "utf-8").await?.text)
+    }
+}
+
+impl FromResponse for Text {
+    const ACCEPT: &'static str = "text/plain, */*;q=0.8";
+    const BUFFERED: bool = true;
+
+    async fn from_response(response: Response, limit: Option<u64>) -> Result<Self> {
+        Text::new(response, limit, "utf-8").await
+    }
+}
+
+#[cfg(feature = "json")]
+impl FromResponse for crate::Value {
+    const ACCEPT: &'static str = "application/json";
+    const BUFFERED: bool = true;
+
+    async fn from_response(response: Response, limit: Option<u64>) -> Result<Self> {
+        crate::decode::json(response, limit, None, None).await
+    }
+}
+
+#[cfg(feature = "json")]
+impl FromResponse for crate::JsonBytes {
+    const ACCEPT: &'static str = "application/json";
+    const BUFFERED: bool = true;
+
+    async fn from_response(response: Response, _limit: Option<u64>) -> Result<Self> {
+        crate::JsonBytes::new(response).await
+    }
+}
+
+#[cfg(feature = "stream")]
+impl FromResponse for crate::ByteStream {
+    async fn from_response(response: Response, _limit: Option<u64>) -> Result<Self> {
+        Ok(crate::ByteStream::new(response))
+    }
+}
+
+/// A JSON response body deserialized into `T`, the [`FromResponse`] counterpart of `-> Json<T>` endpoints.
+#[cfg(feature = "json")]
+#[cfg_attr(docsrs, doc(cfg(feature = "json")))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Json<T>(pub T);
+
+#[cfg(feature = "json")]
+impl<T: serde::de::DeserializeOwned + MaybeSend> FromResponse for Json<T> {
+    const ACCEPT: &'static str = "application/json";
+    const BUFFERED: bool = true;
+
+    async fn from_response(response: Response, limit: Option<u64>) -> Result<Self> {
+        crate::decode::json(response, limit, None, None)
+            .await
+            .map(Json)
+    }
+}
+
+/// How a request sent with [`send_with`] is prepared, chosen by [`SendOptions`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Prepare {
+    /// With [`prepare`].
+    Hooks,
+    /// Not at all.
+    Skip,
+    /// With [`Api::auth_for`] and the given scheme.
+    Auth(&'static str),
+}
+
+/// Prepares `builder` for `endpoint` with [`Api::pre_request_for`], `context` and [`Api::pre_request_async`].
+///
+/// Endpoints generated by the [`api`](crate::api) macro, [`send_with`] and retries after [`Api::reauthenticate`] all
+/// prepare requests here, so they take the same steps in the same order. Endpoints with the `no_pre_request` or
+/// `auth =` clause skip it, so those called from [`Api::pre_request_async`] or [`Api::reauthenticate`] don't reach it.
+///
+/// # Errors
+/// Returns the error of `context` or the hooks.
+pub async fn prepare<A, F>(
+    api: &A,
+    endpoint: &crate::EndpointInfo,
+    context: &F,
+    builder: RequestBuilder,
+) -> Result<RequestBuilder>
+where
+    A: Api + ?Sized,
+    F: Fn(RequestBuilder) -> Result<RequestBuilder>,
+{
+    let builder = context(api.pre_request_for(endpoint, builder)?)?;
+    api.pre_request_async(builder).await
+}
+
+/// Sends the request built by `builder` once a concurrency permit is free, through
+/// [`middleware::exchange_reauthenticating`], for requests prepared with [`prepare`].
+///
+/// # Errors
+/// Returns the errors of the exchange.
+#[allow(clippy::option_option)]
+pub async fn exchange<A, F>(
+    api: &A,
+    endpoint: (&'static str, &'static str, &'static [&'static str]),
+    context: &F,
+    builder: RequestBuilder,
+    buffer: Option<Option<u64>>,
+) -> Result<Response>
+where
+    A: Api + ?Sized,
+    F: Fn(RequestBuilder) -> Result<RequestBuilder>,
+{
+    let (name, url_template, headers) = endpoint;
+    let _permit = middleware::permit(api).await;
+    middleware::exchange_reauthenticating(
+        api,
+        name,
+        url_template,
+        headers,
+        builder,
+        buffer,
+        context,
+    )
+    .await
+}
+
+/// Like [`exchange`], but through [`middleware::exchange`], never retrying after [`Api::reauthenticate`].
+///
+/// It's kept apart so endpoints called from [`Api::reauthenticate`], such as a `login =` endpoint, don't reach it.
+///
+/// # Errors
+/// Returns the errors of the exchange.
+#[allow(clippy::option_option)]
+pub async fn exchange_once<A>(
+    api: &A,
+    endpoint: (&'static str, &'static str, &'static [&'static str]),
+    builder: RequestBuilder,
+    buffer: Option<Option<u64>>,
+) -> Result<Response>
+where
+    A: Api + ?Sized,
+{
+    let (name, url_template, headers) = endpoint;
+    let _permit = middleware::permit(api).await;
+    middleware::exchange(api, name, url_template, headers, builder, buffer).await
+}
+
+/// The clauses of an endpoint sent with [`send_with`], matching those of the same name in the [`api`](crate::api)
+/// macro.
+///
+/// ```rust,ignore
+/// let options = SendOptions::new()
+///     .name("patch_todo")
+///     .context(|request| api.pre_request_with(&tenant, request));
+/// ```
+#[derive(Debug, Clone, Copy)]
+pub struct SendOptions<F = fn(RequestBuilder) -> reqwest::Result<RequestBuilder>> {
+    /// The endpoint name passed to the hooks and [`Api::on_error`].
+    name: &'static str,
+    /// How the request is prepared.
+    prepare: Prepare,
+    /// Applies the endpoint's context.
+    context: F,
+}
+
+impl SendOptions {
+    /// Returns the options [`send`] uses: an endpoint named `send`, prepared by the hooks, without a context.
+    #[must_use]
+    pub fn new() -> Self {
+        SendOptions {
+            name: "send",
+            prepare: Prepare::Hooks,
+            context: Ok,
+        }
+    }
+}
+
+impl Default for SendOptions {
+    fn default() -> Self {
+        SendOptions::new()
+    }
+}
+
+impl<F> SendOptions<F> {
+    /// Sets the endpoint name passed to the hooks and [`Api::on_error`].
+    #[must_use]
+    pub fn name(mut self, name: &'static str) -> Self {
+        self.name = name;
+        self
+    }
+
+    /// Skips [`Api::pre_request_for`], the context and [`Api::pre_request_async`], like the `no_pre_request` clause.
+    #[must_use]
+    pub fn no_pre_request(mut self) -> Self {
+        self.prepare = Prepare::Skip;
+        self
+    }
+
+    /// Prepares the request with [`Api::auth_for`] and `scheme` instead of the other hooks, like the `auth =` clause.
+    #[must_use]
+    pub fn auth(mut self, scheme: &'static str) -> Self {
+        self.prepare = Prepare::Auth(scheme);
+        self
+    }
+
+    /// Applies `context` between [`Api::pre_request_for`] and [`Api::pre_request_async`], like the `context =`
+    /// clause, such as a closure calling [`PreRequestWith::pre_request_with`](crate::PreRequestWith::pre_request_with).
+    #[must_use]
+    pub fn context<G>(self, context: G) -> SendOptions<G>
+    where
+        G: Fn(RequestBuilder) -> reqwest::Result<RequestBuilder>,
+    {
+        SendOptions {
+            name: self.name,
+            prepare: self.prepare,
+            context,
+        }
+    }
+}
+
+/// Sends a request for an endpoint written by hand, taking the same steps as a method generated by the
+/// [`api`](crate::api) macro, and decodes the response into `T`. It's [`send_with`] and [`SendOptions::new`].
+///
+/// A relative `url` is resolved against [`Api::base_url`]. The request carries [`Api::default_headers`], an `Accept`
+/// header for `T`, and passes through [`Api::pre_request_for`] and [`Api::pre_request_async`] before
+/// [`Api::default_query`], `body` and `headers` are added, with `headers` replacing default headers of the same name.
+/// It's then sent through the caching hooks, [`Api::acquire_permit`], [`Api::sign`] and [`Api::middlewares`], retried
+/// after [`Api::reauthenticate`], and bodies are limited to [`Api::body_limit`]. Errors are passed to
+/// [`Api::on_error`].
+///
+/// The hooks see an endpoint named `send` whose URL template is empty.
+///
+/// ```rust,ignore
+/// let todo: Json<Todo> = api_client::send(
+///     &api,
+///     Method::PATCH,
+///     &format!("/todos/{id}"),
+///     Body::Json(&patch),
+///     &[(HeaderName::from_static("if-match"), etag)],
+/// )
+/// .await?;
+/// ```
+///
+/// # Errors
+/// Returns an error if the request can't be built, a hook or middleware fails, sending the request fails, or the
+/// response can't be decoded into `T`. Unless `T` is [`StatusCode`] or [`Headers`], a response with an error status
+/// fails with [`Error::UnexpectedStatus`](crate::Error::UnexpectedStatus).
+pub async fn send<A, T, B>(
+    api: &A,
+    method: Method,
+    url: &str,
+    body: Body<'_, B>,
+    headers: &[(HeaderName, HeaderValue)],
+) -> Result<T>
+where
+    A: Api + ?Sized,
+    T: FromResponse,
+    B: serde::Serialize + ?Sized,
+{
+    send_with(api, SendOptions::new(), method, url, body, headers).await
+}
+
+/// Like [`send`], but with the endpoint name and the `no_pre_request`, `auth =` and `context =` clauses set by
+/// `options`.
+///
+/// ```rust,ignore
+/// let todo: Json<Todo> = api_client::send_with(
+///     &api,
+///     SendOptions::new().auth("admin"),
+///     Method::DELETE,
+///     &format!("/todos/{id}"),
+///     Body::<()>::None,
+///     &[],
+/// )
+/// .await?;
+/// ```
+///
+/// # Errors
+/// Returns the errors of [`send`], and those of the context.
+pub async fn send_with<A, T, B, F>(
+    api: &A,
+    options: SendOptions<F>,
+    method: Method,
+    url: &str,
+    body: Body<'_, B>,
+    headers: &[(HeaderName, HeaderValue)],
+) -> Result<T>
+where
+    A: Api + ?Sized,
+    T: FromResponse,
+    B: serde::Serialize + ?Sized,
+    F: Fn(RequestBuilder) -> reqwest::Result<RequestBuilder>,
+{
+    let SendOptions {
+        name,
+        prepare: how,
+        context,
+    } = options;
+    let context = |builder| Ok(context(builder)?);
+    let result = async {
+        let url = url::resolve(api.base_url(), url.to_owned());
+        let endpoint = crate::EndpointInfo::new(name, method.clone(), "", &[]);
+        let defaults = api.default_headers();
+        let builder = api
+            .client()
+            .request(method, url.as_str())
+            .headers(defaults.clone());
+        let builder = header::add(builder, &defaults, ACCEPT, T::ACCEPT)?;
+        let builder = match how {
+            Prepare::Hooks => prepare(api, &endpoint, &context, builder).await?,
+            Prepare::Skip => builder,
+            Prepare::Auth(scheme) => api.auth_for(scheme, builder).await?,
+        };
+        let builder = crate::query::defaults(builder, api.default_query())?;
+        let mut builder = body.apply(builder);
+        for (name, value) in headers {
+            builder = header::add(builder, &defaults, name.clone(), value.clone())?;
+        }
+
+        let limit = api.body_limit();
+        let buffer = T::BUFFERED.then_some(limit);
+        let endpoint = (name, "", &[] as &[&str]);
+        let response = match how {
+            Prepare::Hooks => exchange(api, endpoint, &context, builder, buffer).await?,
+            Prepare::Skip | Prepare::Auth(_) => {
+                exchange_once(api, endpoint, builder, buffer).await?
+            }
+        };
+        let response = if T::CHECKS_STATUS {
+            crate::decode::expect_status(response, &[100..=399]).await?
+        } else {
+            response
+        };
+        T::from_response(response, limit).await
+    }
+    .await;
+    if let Err(error) = &result {
+        error.report(|error| api.on_error(name, error));
+    }
+    result
+}
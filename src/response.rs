@@ -0,0 +1,178 @@
+//! Types returned by return kinds that carry more than the decoded body.
+
+use encoding_rs::{Encoding, UTF_8};
+#[cfg(feature = "json")]
+use reqwest::{header::LOCATION, Url};
+use reqwest::{
+    header::{HeaderMap, CONTENT_TYPE},
+    Response, StatusCode,
+};
+
+use crate::Error;
+
+/// The status and headers of a response, returned by `-> Headers` endpoints.
+///
+/// The body is never read, which makes this the kind to use with `HEAD` requests.
+#[derive(Debug, Clone)]
+pub struct Headers {
+    /// The response status.
+    pub status: StatusCode,
+    /// The response headers.
+    pub headers: HeaderMap,
+}
+
+impl Headers {
+    /// Takes the status and headers of `response`, dropping it without reading the body.
+    #[doc(hidden)]
+    #[must_use]
+    pub fn new(mut response: Response) -> Self {
+        Headers {
+            status: response.status(),
+            headers: std::mem::take(response.headers_mut()),
+        }
+    }
+}
+
+/// A text response body decoded with the charset of its `Content-Type`, returned by `-> Text` endpoints.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Text {
+    /// The decoded body. Invalid sequences are replaced with `U+FFFD REPLACEMENT CHARACTER`.
+    pub text: String,
+    /// Name of the encoding the body was decoded with, such as `"windows-1252"`.
+    pub encoding: &'static str,
+}
+
+impl Text {
+    /// Decodes the body of `response` with the charset named by its `Content-Type`, or `fallback` if it has none.
+    ///
+    /// Unknown charsets decode as UTF-8, and a byte order mark overrides both, as with [`Response::text_with_charset`].
+    ///
+    /// # Errors
+    /// Returns an error if reading the body fails or it exceeds `limit`.
+    #[doc(hidden)]
+    pub async fn new(
+        response: Response,
+        limit: Option<u64>,
+        fallback: &str,
+    ) -> Result<Self, Error> {
+        let label = response
+            .headers()
+            .get(CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .and_then(charset)
+            .unwrap_or(fallback);
+        let encoding = Encoding::for_label(label.as_bytes()).unwrap_or(UTF_8);
+
+        let bytes = crate::decode::body(response, limit, |_, _| {}).await?;
+        let (text, encoding, _) = encoding.decode(&bytes);
+        Ok(Text {
+            text: text.into_owned(),
+            encoding: encoding.name(),
+        })
+    }
+}
+
+/// A JSON response body kept as [`Bytes`](bytes::Bytes), returned by `-> JsonBytes` endpoints.
+///
+/// [`JsonBytes::parse`] deserializes the body into types that borrow from it, such as those with `&str` or
+/// `#[serde(borrow)] Cow<str>` fields, so large responses can be read without allocating every string. The parsed
+/// value borrows from the `JsonBytes`, which has to outlive it:
+/// ```rust,ignore
+/// #[derive(Deserialize)]
+/// struct Item<'a> {
+///     name: &'a str,
+/// }
+///
+/// let body = api.items().await?;
+/// let items: Vec<Item> = body.parse()?;
+/// // `body` can't be dropped or moved while `items` is in use.
+/// ```
+#[cfg(feature = "json")]
+#[cfg_attr(docsrs, doc(cfg(feature = "json")))]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct JsonBytes(bytes::Bytes);
+
+#[cfg(feature = "json")]
+impl JsonBytes {
+    /// Reads the body of `response`.
+    ///
+    /// # Errors
+    /// Returns an error if reading the body fails.
+    #[doc(hidden)]
+    pub async fn new(response: Response) -> Result<Self, Error> {
+        Ok(JsonBytes(response.bytes().await?))
+    }
+
+    /// Deserializes the body into a `T` that may borrow from it.
+    ///
+    /// # Errors
+    /// Returns [`Error::Decode`] if the body is not valid JSON for `T`.
+    pub fn parse<'de, T: serde::Deserialize<'de>>(&'de self) -> Result<T, Error> {
+        serde_json::from_slice(&self.0).map_err(|source| Error::decode(&self.0, source))
+    }
+
+    /// Returns the raw body.
+    #[must_use]
+    pub fn bytes(&self) -> &bytes::Bytes {
+        &self.0
+    }
+
+    /// Returns the raw body, consuming `self`.
+    #[must_use]
+    pub fn into_bytes(self) -> bytes::Bytes {
+        self.0
+    }
+}
+
+/// Returns the `charset` parameter of a `Content-Type` value.
+fn charset(content_type: &str) -> Option<&str> {
+    content_type.split(';').skip(1).find_map(|parameter| {
+        let (name, value) = parameter.split_once('=')?;
+        name.trim()
+            .eq_ignore_ascii_case("charset")
+            .then(|| value.trim().trim_matches('"'))
+    })
+}
+
+/// The outcome of a request that created a resource, returned by `-> Created<Json<T>>` endpoints.
+#[cfg(feature = "json")]
+#[cfg_attr(docsrs, doc(cfg(feature = "json")))]
+#[derive(Debug, Clone)]
+pub struct Created<T> {
+    /// The response status, usually `201 Created`.
+    pub status: StatusCode,
+    /// The `Location` header, resolved against the URL of the response. `None` if it was missing or invalid.
+    pub location: Option<Url>,
+    /// The deserialized response body, or `None` if the body was empty.
+    pub body: Option<T>,
+}
+
+#[cfg(feature = "json")]
+impl<T: serde::de::DeserializeOwned> Created<T> {
+    /// Reads the status, `Location` header and optional JSON body of `response`.
+    ///
+    /// # Errors
+    /// Returns an error if reading the body fails, or if a non-empty body isn't a `T`.
+    #[doc(hidden)]
+    pub async fn new(response: Response) -> Result<Self, Error> {
+        let status = response.status();
+        let location = response
+            .headers()
+            .get(LOCATION)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| response.url().join(value).ok());
+
+        let bytes = response.bytes().await?;
+        let body = if bytes.iter().all(u8::is_ascii_whitespace) {
+            None
+        } else {
+            Some(serde_json::from_slice(&bytes).map_err(|source| Error::decode(&bytes, source))?)
+        };
+
+        Ok(Created {
+            status,
+            location,
+            body,
+        })
+    }
+}
@@ -0,0 +1,389 @@
+//! AWS Signature Version 4 request signing.
+
+use std::{
+    fmt::Write,
+    sync::Arc,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use reqwest::{
+    header::{HeaderName, HeaderValue, AUTHORIZATION},
+    Request, Response,
+};
+use ring::{digest, hmac};
+
+use crate::{credentials::sensitive, Error, Middleware, Next, Result, Secret};
+
+/// The payload hash sent for bodies that can't be read up front, such as streams.
+const UNSIGNED_PAYLOAD: &str = "UNSIGNED-PAYLOAD";
+
+/// Headers left out of the signature, since proxies and the HTTP client may change them.
+const UNSIGNED_HEADERS: [&str; 4] = ["authorization", "user-agent", "expect", "x-amzn-trace-id"];
+
+/// The credentials requests are signed with.
+#[derive(Clone, Debug)]
+pub struct AwsCredentials {
+    /// The access key ID.
+    access_key_id: String,
+    /// The secret access key.
+    secret_access_key: Secret,
+    /// The session token of temporary credentials.
+    session_token: Option<Secret>,
+}
+
+impl AwsCredentials {
+    /// Creates long-term credentials.
+    #[must_use]
+    pub fn new(access_key_id: impl Into<String>, secret_access_key: impl Into<Secret>) -> Self {
+        AwsCredentials {
+            access_key_id: access_key_id.into(),
+            secret_access_key: secret_access_key.into(),
+            session_token: None,
+        }
+    }
+
+    /// Adds the session token of temporary credentials, sent in an `x-amz-security-token` header.
+    #[must_use]
+    pub fn session_token(mut self, session_token: impl Into<Secret>) -> Self {
+        self.session_token = Some(session_token.into());
+        self
+    }
+}
+
+/// Provides the [`AwsCredentials`] each request is signed with, such as ones rotated in the background.
+///
+/// It's implemented for [`AwsCredentials`] themselves and for closures returning them.
+pub trait ProvideCredentials: Send + Sync {
+    /// Returns the credentials to sign the next request with.
+    ///
+    /// # Errors
+    /// Implementations may return an error to abort the request, such as when there are no credentials.
+    fn credentials(&self) -> Result<AwsCredentials>;
+}
+
+impl ProvideCredentials for AwsCredentials {
+    #[inline]
+    fn credentials(&self) -> Result<AwsCredentials> {
+        Ok(self.clone())
+    }
+}
+
+impl<F: Fn() -> Result<AwsCredentials> + Send + Sync> ProvideCredentials for F {
+    #[inline]
+    fn credentials(&self) -> Result<AwsCredentials> {
+        self()
+    }
+}
+
+/// Signs requests with AWS Signature Version 4 (requires the `sigv4` feature).
+///
+/// The signature covers the method, path, query, body and the headers the request has when it's signed, and is sent in
+/// the `Authorization` header along with `x-amz-date` and, unless turned off, `x-amz-content-sha256`. Bodies that
+/// can't be read up front, such as multipart forms, are sent with an `UNSIGNED-PAYLOAD` hash, which only some services
+/// accept.
+///
+/// Call [`SigV4::sign`] from [`Api::sign`](crate::Api::sign), or add it as the last [`Middleware`], such as with the
+/// `middleware` method of a generated struct's [`ApiBuilder`](crate::ApiBuilder), so the headers added by earlier
+/// middleware are signed too.
+///
+/// ```rust
+/// use api_client::{api, AwsCredentials, SigV4};
+///
+/// api!(pub struct ExampleApi, base = "https://execute-api.us-east-1.amazonaws.com/prod/");
+///
+/// let credentials = AwsCredentials::new("AKIDEXAMPLE", "wJalrXUtnFEMI/K7MDENG+bPxRfiCYEXAMPLEKEY");
+/// let api = ExampleApi::builder()
+///     .middleware(SigV4::new("us-east-1", "execute-api", credentials))
+///     .build()
+///     .unwrap();
+/// ```
+#[derive(Clone)]
+pub struct SigV4 {
+    /// The region of the service, such as `us-east-1`.
+    region: String,
+    /// The name of the service, such as `s3` or `execute-api`.
+    service: String,
+    /// Provides the credentials each request is signed with.
+    credentials: Arc<dyn ProvideCredentials>,
+    /// Whether to send the payload hash in an `x-amz-content-sha256` header.
+    content_sha256: bool,
+}
+
+impl std::fmt::Debug for SigV4 {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("SigV4")
+            .field("region", &self.region)
+            .field("service", &self.service)
+            .field("content_sha256", &self.content_sha256)
+            .finish_non_exhaustive()
+    }
+}
+
+impl SigV4 {
+    /// Creates a signer for `service` in `region`, signing with the credentials of `credentials`.
+    #[must_use]
+    pub fn new(
+        region: impl Into<String>,
+        service: impl Into<String>,
+        credentials: impl ProvideCredentials + 'static,
+    ) -> Self {
+        SigV4 {
+            region: region.into(),
+            service: service.into(),
+            credentials: Arc::new(credentials),
+            content_sha256: true,
+        }
+    }
+
+    /// Sets whether the payload hash is sent in an `x-amz-content-sha256` header, which S3 requires. It's sent by
+    /// default.
+    #[must_use]
+    pub fn content_sha256(mut self, content_sha256: bool) -> Self {
+        self.content_sha256 = content_sha256;
+        self
+    }
+
+    /// Signs `request` with the current time.
+    ///
+    /// # Errors
+    /// Returns the error of the credentials provider, or [`Error::InvalidHeaderValue`] if the credentials can't be
+    /// sent in a header.
+    pub fn sign(&self, request: &mut Request) -> Result<()> {
+        self.sign_at(request, SystemTime::now())
+    }
+
+    /// Signs `request` as if at `time`, such as to check a signature against known values.
+    ///
+    /// # Errors
+    /// Returns the error of the credentials provider, or [`Error::InvalidHeaderValue`] if the credentials can't be
+    /// sent in a header.
+    pub fn sign_at(&self, request: &mut Request, time: SystemTime) -> Result<()> {
+        let credentials = self.credentials.credentials()?;
+        let timestamp = timestamp(time);
+        let date = &timestamp[..8];
+
+        let payload_hash = match request.body() {
+            Some(body) => body
+                .as_bytes()
+                .map_or_else(|| UNSIGNED_PAYLOAD.to_string(), sha256),
+            None => sha256(b""),
+        };
+        let headers = request.headers_mut();
+        headers.remove(AUTHORIZATION);
+        headers.insert(
+            HeaderName::from_static("x-amz-date"),
+            header_value("x-amz-date", &timestamp)?,
+        );
+        if self.content_sha256 {
+            headers.insert(
+                HeaderName::from_static("x-amz-content-sha256"),
+                header_value("x-amz-content-sha256", &payload_hash)?,
+            );
+        }
+        if let Some(token) = &credentials.session_token {
+            headers.insert(
+                HeaderName::from_static("x-amz-security-token"),
+                sensitive("x-amz-security-token", token.expose())?,
+            );
+        }
+
+        let (canonical_headers, signed_headers) = canonical_headers(request);
+        let canonical_request = format!(
+            "{}\n{}\n{}\n{canonical_headers}\n{signed_headers}\n{payload_hash}",
+            request.method(),
+            self.canonical_path(request),
+            canonical_query(request),
+        );
+        let scope = format!("{date}/{}/{}/aws4_request", self.region, self.service);
+        let string_to_sign = format!(
+            "AWS4-HMAC-SHA256\n{timestamp}\n{scope}\n{}",
+            sha256(canonical_request.as_bytes())
+        );
+
+        let mut key = format!("AWS4{}", credentials.secret_access_key.expose()).into_bytes();
+        for part in [date, &self.region, &self.service, "aws4_request"] {
+            key = hmac_sha256(&key, part.as_bytes());
+        }
+        let signature = hex(&hmac_sha256(&key, string_to_sign.as_bytes()));
+
+        let authorization = format!(
+            "AWS4-HMAC-SHA256 Credential={}/{scope}, SignedHeaders={signed_headers}, Signature={signature}",
+            credentials.access_key_id
+        );
+        request.headers_mut().insert(
+            AUTHORIZATION,
+            sensitive(AUTHORIZATION.as_str(), &authorization)?,
+        );
+        Ok(())
+    }
+
+    /// Returns the URI-encoded path of `request`. The already encoded path is encoded again, except for S3, which
+    /// expects it encoded once.
+    fn canonical_path(&self, request: &Request) -> String {
+        let path = request.url().path();
+        let path = if path.is_empty() { "/" } else { path };
+        if self.service == "s3" {
+            let decoded = percent_encoding::percent_decode_str(path).collect::<Vec<u8>>();
+            encode(&decoded, false)
+        } else {
+            encode(path.as_bytes(), false)
+        }
+    }
+}
+
+#[cfg_attr(feature = "send", async_trait::async_trait)]
+#[cfg_attr(not(feature = "send"), async_trait::async_trait(?Send))]
+impl Middleware for SigV4 {
+    async fn handle(&self, mut request: Request, next: Next<'_>) -> Result<Response> {
+        self.sign(&mut request)?;
+        next.run(request).await
+    }
+}
+
+/// Converts `value` into a header value, or an [`Error::InvalidHeaderValue`] for `name`.
+fn header_value(name: &str, value: &str) -> Result<HeaderValue> {
+    HeaderValue::try_from(value).map_err(|_| Error::InvalidHeaderValue {
+        name: name.to_string(),
+    })
+}
+
+/// Returns the canonical headers of `request`, including its host, and the list of their names.
+fn canonical_headers(request: &Request) -> (String, String) {
+    let url = request.url();
+    let mut host = url.host_str().unwrap_or_default().to_string();
+    if let Some(port) = url.port() {
+        let _ = write!(host, ":{port}");
+    }
+
+    let mut headers: Vec<(String, String)> = vec![("host".to_string(), host)];
+    for name in request.headers().keys() {
+        let name = name.as_str();
+        if name == "host" || UNSIGNED_HEADERS.contains(&name) {
+            continue;
+        }
+        let values: Vec<String> = request
+            .headers()
+            .get_all(name)
+            .iter()
+            .map(|value| {
+                String::from_utf8_lossy(value.as_bytes())
+                    .split_whitespace()
+                    .collect::<Vec<_>>()
+                    .join(" ")
+            })
+            .collect();
+        headers.push((name.to_string(), values.join(",")));
+    }
+    headers.sort();
+
+    let canonical = headers
+        .iter()
+        .fold(String::new(), |mut canonical, (name, value)| {
+            let _ = writeln!(canonical, "{name}:{value}");
+            canonical
+        });
+    let signed = headers
+        .iter()
+        .map(|(name, _)| name.as_str())
+        .collect::<Vec<_>>()
+        .join(";");
+    (canonical, signed)
+}
+
+/// Returns the query parameters of `request`, URI-encoded and sorted.
+///
+/// The raw query is split and percent-decoded without form semantics, so a `+` stays a `+` rather than becoming a
+/// space the way [`Url::query_pairs`](reqwest::Url::query_pairs) would decode it.
+fn canonical_query(request: &Request) -> String {
+    let decode = |part: &str| percent_encoding::percent_decode_str(part).collect::<Vec<u8>>();
+    let mut pairs: Vec<(String, String)> = request
+        .url()
+        .query()
+        .unwrap_or_default()
+        .split('&')
+        .filter(|pair| !pair.is_empty())
+        .map(|pair| {
+            let (key, value) = pair.split_once('=').unwrap_or((pair, ""));
+            (encode(&decode(key), true), encode(&decode(value), true))
+        })
+        .collect();
+    pairs.sort();
+    pairs
+        .iter()
+        .map(|(key, value)| format!("{key}={value}"))
+        .collect::<Vec<_>>()
+        .join("&")
+}
+
+/// URI-encodes `bytes` the way Signature Version 4 expects, leaving unreserved characters as they are, and slashes unless
+/// `encode_slash`.
+fn encode(bytes: &[u8], encode_slash: bool) -> String {
+    let mut encoded = String::with_capacity(bytes.len());
+    for &byte in bytes {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                encoded.push(char::from(byte));
+            }
+            b'/' if !encode_slash => encoded.push('/'),
+            _ => {
+                let _ = write!(encoded, "%{byte:02X}");
+            }
+        }
+    }
+    encoded
+}
+
+/// Returns the hex-encoded SHA-256 hash of `bytes`.
+fn sha256(bytes: &[u8]) -> String {
+    hex(digest::digest(&digest::SHA256, bytes).as_ref())
+}
+
+/// Returns the HMAC-SHA256 of `message` with `key`.
+fn hmac_sha256(key: &[u8], message: &[u8]) -> Vec<u8> {
+    hmac::sign(&hmac::Key::new(hmac::HMAC_SHA256, key), message)
+        .as_ref()
+        .to_vec()
+}
+
+/// Encodes `bytes` as lowercase hex.
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().fold(String::new(), |mut hex, byte| {
+        let _ = write!(hex, "{byte:02x}");
+        hex
+    })
+}
+
+/// Formats `time` as a Signature Version 4 timestamp in UTC, such as `20150830T123600Z`.
+fn timestamp(time: SystemTime) -> String {
+    let seconds = time
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |since| since.as_secs());
+    let (days, seconds) = (seconds / 86_400, seconds % 86_400);
+    let (year, month, day) = civil_from_days(days);
+    format!(
+        "{year:04}{month:02}{day:02}T{:02}{:02}{:02}Z",
+        seconds / 3600,
+        seconds / 60 % 60,
+        seconds % 60
+    )
+}
+
+/// Converts days since the Unix epoch into a year, month and day of the proleptic Gregorian calendar.
+fn civil_from_days(days: u64) -> (u64, u64, u64) {
+    // Howard Hinnant's `civil_from_days`, with eras starting on March 1st, 0000.
+    let days = days + 719_468;
+    let era = days / 146_097;
+    let day_of_era = days % 146_097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month = if month_index < 10 {
+        month_index + 3
+    } else {
+        month_index - 9
+    };
+    let year = year_of_era + era * 400 + u64::from(month <= 2);
+    (year, month, day)
+}
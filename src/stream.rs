@@ -0,0 +1,329 @@
+//! Streaming response bodies.
+
+#[cfg(feature = "json")]
+use std::marker::PhantomData;
+use std::{
+    fmt, mem,
+    pin::Pin,
+    task::{Context, Poll},
+    time::Duration,
+};
+
+use bytes::{Bytes, BytesMut};
+use futures_core::Stream;
+
+use crate::Error;
+
+/// A response body streamed in chunks, returned by `-> ByteStream` endpoints.
+///
+/// The stream owns the response, so it doesn't borrow the API struct and can be moved into spawned tasks.
+pub struct ByteStream(Pin<Box<dyn Stream<Item = reqwest::Result<Bytes>> + Send>>);
+
+impl ByteStream {
+    /// Streams the body of `response`.
+    #[doc(hidden)]
+    #[must_use]
+    pub fn new(response: reqwest::Response) -> Self {
+        ByteStream(Box::pin(response.bytes_stream()))
+    }
+}
+
+impl Stream for ByteStream {
+    type Item = reqwest::Result<Bytes>;
+
+    #[inline]
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        self.0.as_mut().poll_next(cx)
+    }
+}
+
+impl fmt::Debug for ByteStream {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ByteStream").finish_non_exhaustive()
+    }
+}
+
+/// A newline-delimited JSON response body, returned by `-> Lines<T>` endpoints.
+///
+/// Each non-blank line is deserialized into a `T` as soon as it has been received in full, regardless of how the
+/// body is split into chunks. A final line without a trailing newline is deserialized once the body ends.
+#[cfg(feature = "json")]
+#[cfg_attr(docsrs, doc(cfg(feature = "json")))]
+pub struct Lines<T> {
+    /// The undecoded response body.
+    body: ByteStream,
+    /// Bytes received but not yet decoded, starting at the beginning of a line. Lines are split off its front without
+    /// moving the rest.
+    buffer: BytesMut,
+    /// Length of the prefix of `buffer` known not to contain a newline.
+    scanned: usize,
+    /// Whether `body` has ended.
+    done: bool,
+    /// The type each line is deserialized into.
+    item: PhantomData<fn() -> T>,
+}
+
+#[cfg(feature = "json")]
+impl<T> Lines<T> {
+    /// Streams the lines of the body of `response`.
+    #[doc(hidden)]
+    #[must_use]
+    pub fn new(response: reqwest::Response) -> Self {
+        Lines {
+            body: ByteStream::new(response),
+            buffer: BytesMut::new(),
+            scanned: 0,
+            done: false,
+            item: PhantomData,
+        }
+    }
+
+    /// Removes the next complete line from the buffer, including its newline.
+    fn next_line(&mut self) -> Option<BytesMut> {
+        let newline = self.buffer[self.scanned..]
+            .iter()
+            .position(|&byte| byte == b'\n');
+        match newline {
+            Some(position) => {
+                let line = self.buffer.split_to(self.scanned + position + 1);
+                self.scanned = 0;
+                Some(line)
+            }
+            None if self.done && !self.buffer.is_empty() => {
+                self.scanned = 0;
+                Some(self.buffer.split())
+            }
+            None => {
+                self.scanned = self.buffer.len();
+                None
+            }
+        }
+    }
+}
+
+#[cfg(feature = "json")]
+impl<T: serde::de::DeserializeOwned> Stream for Lines<T> {
+    type Item = Result<T, Error>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        loop {
+            while let Some(line) = self.next_line() {
+                if line.iter().all(u8::is_ascii_whitespace) {
+                    continue;
+                }
+                return Poll::Ready(Some(
+                    serde_json::from_slice(&line).map_err(|source| Error::decode(&line, source)),
+                ));
+            }
+
+            if self.done {
+                return Poll::Ready(None);
+            }
+
+            match Pin::new(&mut self.body).poll_next(cx) {
+                Poll::Ready(Some(Ok(chunk))) => self.buffer.extend_from_slice(&chunk),
+                Poll::Ready(Some(Err(error))) => return Poll::Ready(Some(Err(error.into()))),
+                Poll::Ready(None) => self.done = true,
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+    }
+}
+
+#[cfg(feature = "json")]
+impl<T> fmt::Debug for Lines<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Lines")
+            .field("buffered", &self.buffer.len())
+            .field("done", &self.done)
+            .finish_non_exhaustive()
+    }
+}
+
+/// A single server-sent event.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Event<T = String> {
+    /// The last event ID set by the stream, if any.
+    pub id: Option<String>,
+    /// The event type, or `None` for the default `message` type.
+    pub event: Option<String>,
+    /// The event data, with multiple `data:` lines joined by newlines.
+    pub data: T,
+    /// The reconnection time requested by the server since the previous event, if any.
+    pub retry: Option<Duration>,
+}
+
+/// A `text/event-stream` response body, returned by `-> Events` and `-> Sse<T>` endpoints.
+///
+/// Comments and keep-alive lines are skipped, and events split across chunks are reassembled. An event that is still
+/// incomplete when the body ends is discarded, as the specification requires.
+pub struct Events<T = String> {
+    /// The unparsed response body.
+    body: ByteStream,
+    /// Bytes received but not yet parsed, starting at the beginning of a line. Lines are split off its front without
+    /// moving the rest.
+    buffer: BytesMut,
+    /// Length of the prefix of `buffer` known not to contain a line ending.
+    scanned: usize,
+    /// Whether `body` has ended.
+    done: bool,
+    /// The last event ID set by the stream.
+    last_id: Option<String>,
+    /// The type of the event being received.
+    event: Option<String>,
+    /// The data of the event being received, with a newline after each `data:` line.
+    data: String,
+    /// The reconnection time received since the previous event.
+    retry: Option<Duration>,
+    /// Converts the data of each event into a `T`.
+    decode: fn(String) -> Result<T, Error>,
+}
+
+impl Events {
+    /// Streams the events in the body of `response`.
+    #[doc(hidden)]
+    #[must_use]
+    pub fn new(response: reqwest::Response) -> Self {
+        Events::with_decoder(response, Ok)
+    }
+}
+
+#[cfg(feature = "json")]
+impl<T: serde::de::DeserializeOwned> Events<T> {
+    /// Streams the events in the body of `response`, deserializing their data from JSON.
+    #[doc(hidden)]
+    #[must_use]
+    pub fn json(response: reqwest::Response) -> Self {
+        Events::with_decoder(response, |data| {
+            serde_json::from_str(&data).map_err(|source| Error::decode(data.as_bytes(), source))
+        })
+    }
+}
+
+impl<T> Events<T> {
+    /// Streams the events in the body of `response`, converting their data with `decode`.
+    fn with_decoder(response: reqwest::Response, decode: fn(String) -> Result<T, Error>) -> Self {
+        Events {
+            body: ByteStream::new(response),
+            buffer: BytesMut::new(),
+            scanned: 0,
+            done: false,
+            last_id: None,
+            event: None,
+            data: String::new(),
+            retry: None,
+            decode,
+        }
+    }
+
+    /// Removes the next complete line from the buffer, without its line ending.
+    fn next_line(&mut self) -> Option<String> {
+        let Some(position) = self.buffer[self.scanned..]
+            .iter()
+            .position(|&byte| byte == b'\r' || byte == b'\n')
+        else {
+            self.scanned = self.buffer.len();
+            return None;
+        };
+        let end = self.scanned + position;
+        let terminator = match self.buffer.get(end + 1) {
+            Some(b'\n') if self.buffer[end] == b'\r' => 2,
+            // A trailing `\r` might be the first half of a `\r\n` split across chunks.
+            None if self.buffer[end] == b'\r' && !self.done => {
+                self.scanned = end;
+                return None;
+            }
+            _ => 1,
+        };
+
+        let line = self.buffer.split_to(end + terminator);
+        self.scanned = 0;
+        Some(String::from_utf8_lossy(&line[..end]).into_owned())
+    }
+
+    /// Processes a single line, returning an event if the line completes one.
+    fn process_line(&mut self, line: &str) -> Option<Event> {
+        if line.is_empty() {
+            return self.dispatch();
+        }
+        if line.starts_with(':') {
+            return None;
+        }
+
+        let (field, value) = match line.split_once(':') {
+            Some((field, value)) => (field, value.strip_prefix(' ').unwrap_or(value)),
+            None => (line, ""),
+        };
+        match field {
+            "event" => self.event = Some(value.to_string()),
+            "data" => {
+                self.data.push_str(value);
+                self.data.push('\n');
+            }
+            "id" if !value.contains('\0') => self.last_id = Some(value.to_string()),
+            "retry" if !value.is_empty() && value.bytes().all(|byte| byte.is_ascii_digit()) => {
+                self.retry = value.parse().ok().map(Duration::from_millis);
+            }
+            _ => {}
+        }
+        None
+    }
+
+    /// Completes the event being received, if it has any data.
+    fn dispatch(&mut self) -> Option<Event> {
+        let event = self.event.take();
+        if self.data.is_empty() {
+            return None;
+        }
+
+        let mut data = mem::take(&mut self.data);
+        data.pop();
+        Some(Event {
+            id: self.last_id.clone(),
+            event,
+            data,
+            retry: self.retry.take(),
+        })
+    }
+}
+
+impl<T> Stream for Events<T> {
+    type Item = Result<Event<T>, Error>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        loop {
+            while let Some(line) = self.next_line() {
+                if let Some(event) = self.process_line(&line) {
+                    let data = (self.decode)(event.data);
+                    return Poll::Ready(Some(data.map(|data| Event {
+                        id: event.id,
+                        event: event.event,
+                        data,
+                        retry: event.retry,
+                    })));
+                }
+            }
+
+            if self.done {
+                return Poll::Ready(None);
+            }
+
+            match Pin::new(&mut self.body).poll_next(cx) {
+                Poll::Ready(Some(Ok(chunk))) => self.buffer.extend_from_slice(&chunk),
+                Poll::Ready(Some(Err(error))) => return Poll::Ready(Some(Err(error.into()))),
+                Poll::Ready(None) => self.done = true,
+                Poll::Pending => return Poll::Pending,
+            }
+        }
+    }
+}
+
+impl<T> fmt::Debug for Events<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Events")
+            .field("buffered", &self.buffer.len())
+            .field("done", &self.done)
+            .field("last_id", &self.last_id)
+            .finish_non_exhaustive()
+    }
+}
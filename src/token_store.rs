@@ -0,0 +1,327 @@
+//! Storage that keeps tokens across restarts of the process.
+
+use std::{
+    fmt,
+    sync::{Arc, Mutex, PoisonError},
+    time::{Duration, SystemTime},
+};
+
+use crate::{Result, Secret};
+
+/// A token kept by a [`TokenStore`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct StoredToken {
+    /// The access token.
+    pub access_token: Secret,
+    /// The refresh token, if the server issued one.
+    pub refresh_token: Option<Secret>,
+    /// When the access token expires, if the server said so.
+    pub expires_at: Option<SystemTime>,
+}
+
+impl StoredToken {
+    /// Creates a token that never expires and has no refresh token.
+    #[must_use]
+    pub fn new(access_token: impl Into<Secret>) -> Self {
+        StoredToken {
+            access_token: access_token.into(),
+            refresh_token: None,
+            expires_at: None,
+        }
+    }
+
+    /// Returns whether the access token has expired or expires within `leeway`. Tokens without an expiry never do.
+    #[must_use]
+    pub fn is_expired(&self, leeway: Duration) -> bool {
+        self.expires_at
+            .is_some_and(|expires_at| SystemTime::now() + leeway >= expires_at)
+    }
+}
+
+/// Keeps the token of a [`BearerAuth`](crate::BearerAuth) or `ClientCredentials`, so it survives restarts of the
+/// process or is shared between processes.
+///
+/// [`MemoryTokenStore`] keeps it in memory, `FileTokenStore` in a JSON file (requires the `json` feature) and
+/// `KeyringTokenStore` in the system keyring (requires the `keyring` feature). Other storage implements the trait
+/// itself.
+///
+/// ```rust,ignore
+/// struct DatabaseStore(Database);
+///
+#[cfg_attr(feature = "send", doc = "#[async_trait::async_trait]")]
+#[cfg_attr(not(feature = "send"), doc = "#[async_trait::async_trait(?Send)]")]
+/// impl TokenStore for DatabaseStore {
+///     async fn load(&self) -> Option<StoredToken> {
+///         self.0.get("token").await.map(StoredToken::new)
+///     }
+///
+///     async fn save(&self, token: &StoredToken) -> api_client::Result<()> {
+///         self.0.set("token", token.access_token.expose()).await.map_err(|error| Error::Service(error.into()))
+///     }
+/// }
+/// ```
+#[cfg_attr(feature = "send", async_trait::async_trait)]
+#[cfg_attr(not(feature = "send"), async_trait::async_trait(?Send))]
+pub trait TokenStore: Send + Sync {
+    /// Returns the stored token, if there is one. Tokens that can't be read are treated as missing.
+    async fn load(&self) -> Option<StoredToken>;
+
+    /// Replaces the stored token with `token`.
+    ///
+    /// # Errors
+    /// Implementations may return an error if the token can't be stored.
+    async fn save(&self, token: &StoredToken) -> Result<()>;
+}
+
+/// A [`TokenStore`] keeping the token in memory, so it's lost when the process exits.
+#[derive(Debug, Default)]
+pub struct MemoryTokenStore {
+    /// The stored token, if there is one.
+    token: Mutex<Option<StoredToken>>,
+}
+
+impl MemoryTokenStore {
+    /// Creates an empty store.
+    #[must_use]
+    pub fn new() -> Self {
+        MemoryTokenStore::default()
+    }
+}
+
+#[cfg_attr(feature = "send", async_trait::async_trait)]
+#[cfg_attr(not(feature = "send"), async_trait::async_trait(?Send))]
+impl TokenStore for MemoryTokenStore {
+    async fn load(&self) -> Option<StoredToken> {
+        self.token
+            .lock()
+            .unwrap_or_else(PoisonError::into_inner)
+            .clone()
+    }
+
+    async fn save(&self, token: &StoredToken) -> Result<()> {
+        *self.token.lock().unwrap_or_else(PoisonError::into_inner) = Some(token.clone());
+        Ok(())
+    }
+}
+
+/// A [`TokenStore`] shared by the clones of an authenticator.
+#[derive(Clone)]
+pub(crate) struct SharedStore(pub(crate) Arc<dyn TokenStore>);
+
+impl fmt::Debug for SharedStore {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("TokenStore")
+    }
+}
+
+#[cfg_attr(feature = "send", async_trait::async_trait)]
+#[cfg_attr(not(feature = "send"), async_trait::async_trait(?Send))]
+impl<T: TokenStore + ?Sized> TokenStore for Arc<T> {
+    async fn load(&self) -> Option<StoredToken> {
+        (**self).load().await
+    }
+
+    async fn save(&self, token: &StoredToken) -> Result<()> {
+        (**self).save(token).await
+    }
+}
+
+#[cfg(feature = "json")]
+pub use file::FileTokenStore;
+#[cfg(feature = "keyring")]
+pub use keyring::KeyringTokenStore;
+
+/// Serializing tokens to JSON, the format of [`FileTokenStore`] and `KeyringTokenStore`.
+#[cfg(feature = "json")]
+mod json {
+    use std::time::{Duration, UNIX_EPOCH};
+
+    use serde_json::{json, Value};
+
+    use super::StoredToken;
+    use crate::Secret;
+
+    /// Reads a token from `bytes`, or returns `None` if they don't hold one.
+    pub(super) fn from_slice(bytes: &[u8]) -> Option<StoredToken> {
+        let json: Value = serde_json::from_slice(bytes).ok()?;
+        let secret = |name: &str| json.get(name)?.as_str().map(Secret::new);
+        Some(StoredToken {
+            access_token: secret("access_token")?,
+            refresh_token: secret("refresh_token"),
+            expires_at: json
+                .get("expires_at")
+                .and_then(Value::as_u64)
+                .map(|seconds| UNIX_EPOCH + Duration::from_secs(seconds)),
+        })
+    }
+
+    /// Writes `token` as JSON, with its expiry in seconds since the Unix epoch.
+    pub(super) fn to_string(token: &StoredToken) -> String {
+        json!({
+            "access_token": token.access_token.expose(),
+            "refresh_token": token.refresh_token.as_ref().map(Secret::expose),
+            "expires_at": token
+                .expires_at
+                .and_then(|expires_at| expires_at.duration_since(UNIX_EPOCH).ok())
+                .map(|since| since.as_secs()),
+        })
+        .to_string()
+    }
+}
+
+/// Numbers the temporary files of saves, so concurrent saves never write to the same one.
+#[cfg(feature = "json")]
+static SAVES: std::sync::atomic::AtomicU64 = std::sync::atomic::AtomicU64::new(0);
+
+/// Replaces the file at `path` with `contents`, by writing them to a temporary file next to it, readable only by its
+/// owner on Unix, which is then renamed over it. Concurrent writes leave one complete file rather than a mix of them,
+/// and a read never sees a partly written file.
+#[cfg(feature = "json")]
+pub(crate) async fn write_file(path: &std::path::Path, contents: &[u8]) -> std::io::Result<()> {
+    use tokio::{
+        fs::{self, OpenOptions},
+        io::AsyncWriteExt,
+    };
+
+    let mut name = path.file_name().unwrap_or_default().to_os_string();
+    name.push(format!(
+        ".{}.{}.tmp",
+        std::process::id(),
+        SAVES.fetch_add(1, std::sync::atomic::Ordering::Relaxed)
+    ));
+    let temporary = path.with_file_name(name);
+
+    let mut options = OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    options.mode(0o600);
+    let written = async {
+        let mut file = options.open(&temporary).await?;
+        file.write_all(contents).await?;
+        file.sync_all().await?;
+        fs::rename(&temporary, path).await
+    }
+    .await;
+    if written.is_err() {
+        let _ = fs::remove_file(&temporary).await;
+    }
+    written
+}
+
+/// Storing tokens in a JSON file.
+#[cfg(feature = "json")]
+mod file {
+    use std::path::PathBuf;
+
+    use tokio::fs;
+
+    use super::{json, write_file, StoredToken, TokenStore};
+    use crate::Result;
+
+    /// A [`TokenStore`] keeping the token in a JSON file (requires the `json` feature).
+    ///
+    /// The token is written to a temporary file next to it, readable only by its owner on Unix, which is then renamed
+    /// over the file. Concurrent saves from any number of tasks or processes leave one complete token rather than a
+    /// mix of them, and a load never sees a partly written file. Files are read and written with [`tokio::fs`], so
+    /// the runtime isn't blocked.
+    ///
+    /// ```rust,no_run
+    /// use api_client::{BearerAuth, FileTokenStore};
+    ///
+    /// let auth = BearerAuth::default().store(FileTokenStore::new("/home/user/.config/example/token.json"));
+    /// ```
+    #[derive(Clone, Debug)]
+    pub struct FileTokenStore {
+        /// The path of the file.
+        path: PathBuf,
+    }
+
+    impl FileTokenStore {
+        /// Creates a store keeping the token at `path`.
+        #[must_use]
+        pub fn new(path: impl Into<PathBuf>) -> Self {
+            FileTokenStore { path: path.into() }
+        }
+    }
+
+    #[cfg_attr(feature = "send", async_trait::async_trait)]
+    #[cfg_attr(not(feature = "send"), async_trait::async_trait(?Send))]
+    impl TokenStore for FileTokenStore {
+        async fn load(&self) -> Option<StoredToken> {
+            json::from_slice(&fs::read(&self.path).await.ok()?)
+        }
+
+        async fn save(&self, token: &StoredToken) -> Result<()> {
+            write_file(&self.path, json::to_string(token).as_bytes()).await?;
+            Ok(())
+        }
+    }
+}
+
+/// Storing tokens in the system keyring.
+#[cfg(feature = "keyring")]
+mod keyring {
+    use std::sync::Arc;
+
+    use keyring_crate::Entry;
+
+    use super::{json, StoredToken, TokenStore};
+    use crate::{Error, Result};
+
+    /// A [`TokenStore`] keeping the token in the system keyring, such as the macOS Keychain, the Windows Credential
+    /// Manager or the Linux kernel keyring (requires the `keyring` feature).
+    ///
+    /// The token is kept as JSON in the password of the entry for a service and user. The keyring is accessed on
+    /// [`tokio::task::spawn_blocking`] threads, as its calls block. The stores of each platform are features of the
+    /// `keyring` crate, such as `apple-native`, `windows-native` or `linux-native`, which the application enables;
+    /// without one, tokens are only kept in memory.
+    ///
+    /// ```rust,no_run
+    /// use api_client::{BearerAuth, KeyringTokenStore};
+    ///
+    /// # fn run() -> api_client::Result<()> {
+    /// let auth = BearerAuth::default().store(KeyringTokenStore::new("example-cli", "default")?);
+    /// # Ok(())
+    /// # }
+    /// ```
+    #[derive(Clone, Debug)]
+    pub struct KeyringTokenStore {
+        /// The keyring entry.
+        entry: Arc<Entry>,
+    }
+
+    impl KeyringTokenStore {
+        /// Creates a store keeping the token in the keyring entry of `service` and `user`.
+        ///
+        /// # Errors
+        /// Returns [`Error::Service`] if the keyring can't create an entry with those names.
+        pub fn new(service: &str, user: &str) -> Result<Self> {
+            let entry = Entry::new(service, user).map_err(|error| Error::Service(error.into()))?;
+            Ok(KeyringTokenStore {
+                entry: Arc::new(entry),
+            })
+        }
+    }
+
+    #[cfg_attr(feature = "send", async_trait::async_trait)]
+    #[cfg_attr(not(feature = "send"), async_trait::async_trait(?Send))]
+    impl TokenStore for KeyringTokenStore {
+        async fn load(&self) -> Option<StoredToken> {
+            let entry = self.entry.clone();
+            let password = tokio::task::spawn_blocking(move || entry.get_password())
+                .await
+                .ok()?
+                .ok()?;
+            json::from_slice(password.as_bytes())
+        }
+
+        async fn save(&self, token: &StoredToken) -> Result<()> {
+            let entry = self.entry.clone();
+            let password = json::to_string(token);
+            tokio::task::spawn_blocking(move || entry.set_password(&password))
+                .await
+                .map_err(|error| Error::Service(error.into()))?
+                .map_err(|error| Error::Service(error.into()))
+        }
+    }
+}
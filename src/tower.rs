@@ -0,0 +1,53 @@
+//! Sending requests through a [`tower`](https://docs.rs/tower) service.
+
+use std::{error::Error as StdError, future::poll_fn};
+
+use reqwest::{Request, Response};
+use tower_service::Service;
+
+use crate::{middleware::service_error, MaybeSend, Middleware, Next, Result};
+
+/// A [`Middleware`] that sends requests with a tower [`Service`] instead of the [`Api::client`](crate::Api::client),
+/// so tower layers such as timeouts, rate limits and retries can wrap it.
+///
+/// reqwest's [`Client`](reqwest::Client) is itself a service, so it can be wrapped with layers and then passed to
+/// [`TowerService::new`], or to the `service` method of a generated struct's [`ApiBuilder`](crate::ApiBuilder).
+/// Being the last stop, it ignores any middleware after it.
+///
+/// ```rust,ignore
+/// let service = ServiceBuilder::new()
+///     .concurrency_limit(8)
+///     .timeout(Duration::from_secs(10))
+///     .service(Client::new());
+/// let api = ExampleApi::builder().service(service).build()?;
+/// ```
+#[derive(Clone, Debug)]
+pub struct TowerService<S> {
+    /// The service that sends requests, cloned for each request.
+    service: S,
+}
+
+impl<S> TowerService<S> {
+    /// Creates a middleware that sends requests with `service`.
+    #[must_use]
+    pub fn new(service: S) -> Self {
+        TowerService { service }
+    }
+}
+
+#[cfg_attr(feature = "send", async_trait::async_trait)]
+#[cfg_attr(not(feature = "send"), async_trait::async_trait(?Send))]
+impl<S> Middleware for TowerService<S>
+where
+    S: Service<Request, Response = Response> + Clone + Send + Sync,
+    S::Future: MaybeSend,
+    S::Error: Into<Box<dyn StdError + Send + Sync>>,
+{
+    async fn handle(&self, request: Request, _next: Next<'_>) -> Result<Response> {
+        let mut service = self.service.clone();
+        poll_fn(|cx| service.poll_ready(cx))
+            .await
+            .map_err(service_error)?;
+        service.call(request).await.map_err(service_error)
+    }
+}
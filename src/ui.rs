@@ -0,0 +1,185 @@
+//! Declarations the [`api`](crate::api) macro must reject, checked as `compile_fail` doctests. The messages of
+//! the main ones are checked by the trybuild cases in `tests/ui`.
+
+/// An endpoint block without a method.
+///
+/// ```compile_fail
+/// api_client::api!(pub struct ExampleApi);
+///
+/// impl ExampleApi {
+///     api_client::api! {
+///         fn example() -> String {
+///             "https://example.com"
+///         }
+///     }
+/// }
+/// ```
+pub struct MissingMethod;
+
+/// A header without a value.
+///
+/// ```compile_fail
+/// api_client::api!(pub struct ExampleApi);
+///
+/// impl ExampleApi {
+///     api_client::api! {
+///         fn example() -> String {
+///             GET "https://example.com"
+///             USER_AGENT
+///         }
+///     }
+/// }
+/// ```
+pub struct MissingHeaderValue;
+
+/// A parameter without a type.
+///
+/// ```compile_fail
+/// api_client::api!(pub struct ExampleApi);
+///
+/// impl ExampleApi {
+///     api_client::api! {
+///         fn example(id) -> String {
+///             GET "https://example.com/{id}"
+///         }
+///     }
+/// }
+/// ```
+pub struct UntypedParameter;
+
+/// A parameter the endpoint never uses.
+///
+/// ```compile_fail
+/// api_client::api!(pub struct ExampleApi);
+///
+/// impl ExampleApi {
+///     api_client::api! {
+///         fn delete(id: u32, force: bool) -> StatusCode {
+///             DELETE "https://example.com/items/{id}"
+///         }
+///     }
+/// }
+/// ```
+pub struct UnusedParameter;
+
+/// Something other than a struct or endpoint.
+///
+/// ```compile_fail
+/// api_client::api!(pub enum ExampleApi);
+/// ```
+pub struct NotAnEndpoint;
+
+/// A misspelled return kind.
+///
+/// ```compile_fail
+/// api_client::api!(pub struct ExampleApi);
+///
+/// impl ExampleApi {
+///     api_client::api! {
+///         fn example() -> Jsn<u32> {
+///             GET "https://example.com"
+///         }
+///     }
+/// }
+/// ```
+pub struct UnknownReturnKind;
+
+/// A return kind missing its type.
+///
+/// ```compile_fail
+/// api_client::api!(pub struct ExampleApi);
+///
+/// impl ExampleApi {
+///     api_client::api! {
+///         fn example() -> Json {
+///             GET "https://example.com"
+///         }
+///     }
+/// }
+/// ```
+pub struct MissingResponseType;
+
+/// A URL placeholder that isn't a parameter.
+///
+/// ```compile_fail,E0425
+/// api_client::api!(pub struct ExampleApi);
+///
+/// impl ExampleApi {
+///     api_client::api! {
+///         fn example(id: u32) -> String {
+///             GET "https://example.com/todos/{idd}"
+///         }
+///     }
+/// }
+/// ```
+pub struct UnknownUrlPlaceholder;
+
+/// A header placeholder that isn't a parameter.
+///
+/// ```compile_fail,E0425
+/// api_client::api!(pub struct ExampleApi);
+///
+/// impl ExampleApi {
+///     api_client::api! {
+///         fn example(id: u32) -> String {
+///             GET "https://example.com/todos/{id}"
+///             "x-request-id": "{request}"
+///         }
+///     }
+/// }
+/// ```
+pub struct UnknownHeaderPlaceholder;
+
+/// Two request bodies on one endpoint.
+///
+/// ```compile_fail
+/// api_client::api!(pub struct ExampleApi);
+///
+/// impl ExampleApi {
+///     api_client::api! {
+///         fn example(first: Json<u32>, second: Form<u32>) -> String {
+///             POST "https://example.com"
+///         }
+///     }
+/// }
+/// ```
+pub struct TwoBodies;
+
+/// An unknown struct option.
+///
+/// ```compile_fail
+/// api_client::api!(pub struct ExampleApi, cookie_jar);
+/// ```
+pub struct UnknownStructOption;
+
+/// An unknown authentication scheme.
+///
+/// ```compile_fail
+/// api_client::api!(pub struct ExampleApi with oauth);
+/// ```
+pub struct UnknownAuthScheme;
+
+/// A string literal method listed in an endpoint constant.
+///
+/// ```compile_fail
+/// api_client::api!(pub struct ExampleApi);
+///
+/// impl ExampleApi {
+///     api_client::api! {
+///         pub const ENDPOINTS;
+///
+///         fn example() -> String {
+///             "PURGE" "https://example.com"
+///         }
+///     }
+/// }
+/// ```
+pub struct LiteralMethodConstant;
+
+/// The `cookie_store` option without the `cookies` feature.
+///
+#[cfg_attr(not(feature = "cookies"), doc = "```compile_fail")]
+#[cfg_attr(feature = "cookies", doc = "```ignore")]
+/// api_client::api!(pub struct ExampleApi, cookie_store);
+/// ```
+pub struct CookieStoreWithoutFeature;
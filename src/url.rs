@@ -0,0 +1,325 @@
+//! Base URL helpers used internally by the [`api`](crate::api) macro.
+
+use std::fmt::{self, Write};
+
+use percent_encoding::{utf8_percent_encode, AsciiSet, CONTROLS};
+use reqwest::Url;
+
+/// Marks the start of a parameter interpolated into an endpoint URL.
+const START: char = '\u{fdd0}';
+/// Marks the end of a parameter interpolated into an endpoint URL.
+const END: char = '\u{fdd1}';
+/// Escapes [`START`], [`END`] and itself when they appear inside a parameter, so a value can't end its own parameter.
+const ESCAPE: char = '\u{fdd2}';
+
+/// Characters percent-encoded in parameters within the path of an endpoint URL.
+const SEGMENT: &AsciiSet = &CONTROLS
+    .add(b' ')
+    .add(b'"')
+    .add(b'#')
+    .add(b'%')
+    .add(b'/')
+    .add(b'<')
+    .add(b'>')
+    .add(b'?')
+    .add(b'`')
+    .add(b'{')
+    .add(b'}');
+
+/// Characters percent-encoded in parameters within the query or fragment of an endpoint URL.
+const COMPONENT: &AsciiSet = &CONTROLS
+    .add(b' ')
+    .add(b'"')
+    .add(b'#')
+    .add(b'%')
+    .add(b'&')
+    .add(b'+')
+    .add(b'<')
+    .add(b'=')
+    .add(b'>');
+
+/// Wraps an endpoint parameter while its URL is formatted, so [`encode`] can find and encode it.
+pub struct Param<'a, T: ?Sized>(pub &'a T);
+
+impl<T: fmt::Display + ?Sized> fmt::Display for Param<'_, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{START}")?;
+        let value = self.0.to_string();
+        if value.contains([START, END, ESCAPE]) {
+            escape(f, &value)?;
+        } else {
+            self.0.fmt(f)?;
+        }
+        write!(f, "{END}")
+    }
+}
+
+impl<T: fmt::Debug + ?Sized> fmt::Debug for Param<'_, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{START}")?;
+        let value = format!("{:?}", self.0);
+        if value.contains([START, END, ESCAPE]) {
+            escape(f, &value)?;
+        } else {
+            self.0.fmt(f)?;
+        }
+        write!(f, "{END}")
+    }
+}
+
+/// Writes `value` with the parameter markers escaped, so [`encode`] treats all of it as one parameter.
+///
+/// Format flags such as width are dropped for these values, which can only come from untrusted input.
+fn escape(f: &mut fmt::Formatter<'_>, value: &str) -> fmt::Result {
+    for c in value.chars() {
+        match c {
+            START => write!(f, "{ESCAPE}0")?,
+            END => write!(f, "{ESCAPE}1")?,
+            ESCAPE => write!(f, "{ESCAPE}2")?,
+            c => f.write_char(c)?,
+        }
+    }
+    Ok(())
+}
+
+/// Reverses [`escape`].
+fn unescape(value: &str) -> String {
+    let mut unescaped = String::with_capacity(value.len());
+    let mut chars = value.chars();
+    while let Some(c) = chars.next() {
+        unescaped.push(match c {
+            ESCAPE => match chars.next() {
+                Some('0') => START,
+                Some('1') => END,
+                _ => ESCAPE,
+            },
+            c => c,
+        });
+    }
+    unescaped
+}
+
+/// Percent-encodes the parameters marked by [`Param`] in a formatted endpoint URL.
+///
+/// Parameters in the path are encoded as a single path segment, so `/`, `?` and `#` can't change the request path.
+/// Parameters in the query or fragment are encoded as a component, so `&` and `=` can't add query pairs. Parameters
+/// before the path, such as a base URL at the start, are left as they are.
+#[must_use]
+pub fn encode(url: &str) -> String {
+    if !url.contains(START) {
+        return url.to_string();
+    }
+
+    let mut encoded = String::with_capacity(url.len());
+    // The URL so far with parameters left as they are, used to tell which part of the URL a parameter is in.
+    let mut raw = String::with_capacity(url.len());
+    let mut rest = url;
+    while let Some(start) = rest.find(START) {
+        let (literal, tail) = rest.split_at(start);
+        encoded.push_str(literal);
+        raw.push_str(literal);
+
+        let tail = &tail[START.len_utf8()..];
+        let end = tail.find(END).unwrap_or(tail.len());
+        let value = unescape(&tail[..end]);
+        let value = value.as_str();
+        match path_start(&raw) {
+            None => encoded.push_str(value),
+            Some(path) if raw[path..].contains(['?', '#']) => {
+                encoded.extend(utf8_percent_encode(value, COMPONENT));
+            }
+            Some(_) => encoded.extend(utf8_percent_encode(value, SEGMENT)),
+        }
+        raw.push_str(value);
+        rest = tail.get(end + END.len_utf8()..).unwrap_or("");
+    }
+    encoded.push_str(rest);
+    encoded
+}
+
+/// Returns whether the endpoint parameter `name` is used in `tokens`, the stringified URL and clauses of an endpoint.
+///
+/// Inside string literals only `{name}` and `{name:...}` placeholders count as uses, so a URL or header name merely
+/// containing the name as text doesn't. Elsewhere the name has to be a whole identifier. Names starting with `_` are
+/// always treated as used.
+#[must_use]
+pub const fn mentions(name: &str, tokens: &str) -> bool {
+    let name = unraw(name.as_bytes());
+    if let [b'_', ..] = name {
+        return true;
+    }
+
+    let tokens = tokens.as_bytes();
+    let mut index = 0;
+    while index < tokens.len() {
+        if let Some((hashes, content)) = string_start(tokens, index) {
+            let end = string_end(tokens, content, hashes);
+            if has_placeholder(name, tokens, content, end) {
+                return true;
+            }
+            index = end + 1 + hashes;
+        } else if is_ident(tokens[index]) {
+            let mut start = index;
+            if tokens[index] == b'r' && index + 1 < tokens.len() && tokens[index + 1] == b'#' {
+                start += 2;
+                index += 2;
+            }
+            while index < tokens.len() && is_ident(tokens[index]) {
+                index += 1;
+            }
+            if equals(name, tokens, start, index) {
+                return true;
+            }
+        } else {
+            index += 1;
+        }
+    }
+    false
+}
+
+/// Strips the `r#` prefix of a raw identifier.
+const fn unraw(name: &[u8]) -> &[u8] {
+    match name {
+        [b'r', b'#', rest @ ..] => rest,
+        _ => name,
+    }
+}
+
+/// Returns the number of `#`s and the start of the contents if a string literal, raw or not, starts at `index`.
+const fn string_start(tokens: &[u8], index: usize) -> Option<(usize, usize)> {
+    let mut index = index;
+    if index < tokens.len() && tokens[index] == b'b' {
+        index += 1;
+    }
+    let mut hashes = 0;
+    if index < tokens.len() && tokens[index] == b'r' {
+        index += 1;
+        while index < tokens.len() && tokens[index] == b'#' {
+            hashes += 1;
+            index += 1;
+        }
+    }
+    if index < tokens.len() && tokens[index] == b'"' {
+        Some((hashes, index + 1))
+    } else {
+        None
+    }
+}
+
+/// Returns the index of the `"` closing the string literal whose contents start at `index`.
+const fn string_end(tokens: &[u8], index: usize, hashes: usize) -> usize {
+    let mut index = index;
+    while index < tokens.len() {
+        if hashes == 0 && tokens[index] == b'\\' {
+            index += 2;
+            continue;
+        }
+        if tokens[index] == b'"' {
+            let mut closing = 0;
+            while closing < hashes
+                && index + 1 + closing < tokens.len()
+                && tokens[index + 1 + closing] == b'#'
+            {
+                closing += 1;
+            }
+            if closing == hashes {
+                return index;
+            }
+        }
+        index += 1;
+    }
+    tokens.len()
+}
+
+/// Returns whether `tokens[start..end]`, the contents of a string literal, has a `{name}` or `{name:...}` placeholder.
+const fn has_placeholder(name: &[u8], tokens: &[u8], start: usize, end: usize) -> bool {
+    let mut index = start;
+    while index < end {
+        if tokens[index] != b'{' {
+            index += 1;
+            continue;
+        }
+        if index + 1 < end && tokens[index + 1] == b'{' {
+            index += 2;
+            continue;
+        }
+        let placeholder = index + 1;
+        index = placeholder;
+        while index < end && tokens[index] != b'}' && tokens[index] != b':' {
+            index += 1;
+        }
+        if equals(name, tokens, placeholder, index) {
+            return true;
+        }
+    }
+    false
+}
+
+/// Returns whether `tokens[start..end]` is `name`.
+const fn equals(name: &[u8], tokens: &[u8], start: usize, end: usize) -> bool {
+    if end - start != name.len() {
+        return false;
+    }
+    let mut index = 0;
+    while index < name.len() {
+        if tokens[start + index] != name[index] {
+            return false;
+        }
+        index += 1;
+    }
+    true
+}
+
+/// Returns whether `byte` can be part of an identifier.
+const fn is_ident(byte: u8) -> bool {
+    byte.is_ascii_alphanumeric() || byte == b'_'
+}
+
+/// Returns where the path of a partial URL starts, or `None` if it hasn't yet.
+///
+/// Relative URLs are all path, unless they're still empty.
+fn path_start(url: &str) -> Option<usize> {
+    match url.find("://") {
+        Some(scheme) => {
+            let authority = scheme + 3;
+            url[authority..].find('/').map(|path| authority + path)
+        }
+        None if url.is_empty() => None,
+        None => Some(0),
+    }
+}
+
+/// Parses a base URL declared on an `api!` struct.
+///
+/// # Panics
+/// Panics if `url` is not a valid absolute URL.
+#[must_use]
+pub fn parse(url: &str) -> Url {
+    match Url::parse(url) {
+        Ok(url) => base(url),
+        Err(error) => panic!("invalid base URL `{url}`: {error}"),
+    }
+}
+
+/// Adds a trailing slash to the path of `url`, so relative endpoint URLs are joined onto it rather than replacing its
+/// last segment.
+#[must_use]
+pub fn base(mut url: Url) -> Url {
+    if !url.path().ends_with('/') {
+        let path = format!("{}/", url.path());
+        url.set_path(&path);
+    }
+    url
+}
+
+/// Joins an endpoint URL onto `base`, ignoring leading slashes so `"/users"` and `"users"` both end up under the base
+/// path. Absolute endpoint URLs are left as they are.
+#[must_use]
+pub fn resolve(base: Option<&Url>, url: String) -> String {
+    match base {
+        Some(base) => base
+            .join(url.trim_start_matches('/'))
+            .map_or(url, String::from),
+        None => url,
+    }
+}
@@ -0,0 +1,56 @@
+//! Validation of decoded responses, run by endpoints with a `validate` clause.
+
+use crate::Error;
+
+/// Checks that a decoded response makes sense beyond its structure, such as that required lists aren't empty, for
+/// endpoints declared with a `validate` clause.
+///
+/// ```rust
+/// use api_client::Validate;
+///
+/// struct Page {
+///     items: Vec<u32>,
+/// }
+///
+/// impl Validate for Page {
+///     fn validate(&self) -> Result<(), String> {
+///         if self.items.is_empty() {
+///             return Err("page has no items".to_string());
+///         }
+///         Ok(())
+///     }
+/// }
+/// ```
+pub trait Validate {
+    /// Checks the value.
+    ///
+    /// # Errors
+    /// Returns a message describing why the value is invalid.
+    fn validate(&self) -> Result<(), String>;
+}
+
+impl<T: Validate> Validate for Vec<T> {
+    #[inline]
+    fn validate(&self) -> Result<(), String> {
+        self.iter().try_for_each(Validate::validate)
+    }
+}
+
+impl<T: Validate> Validate for Option<T> {
+    #[inline]
+    fn validate(&self) -> Result<(), String> {
+        self.as_ref().map_or(Ok(()), Validate::validate)
+    }
+}
+
+/// Returns `value` if it's valid.
+///
+/// # Errors
+/// Returns [`Error::Invalid`] with the message from [`Validate::validate`] if it isn't.
+#[doc(hidden)]
+pub fn check<T: Validate>(value: T) -> Result<T, Error> {
+    match value.validate() {
+        Ok(()) => Ok(value),
+        Err(message) => Err(Error::Invalid { message }),
+    }
+}
@@ -0,0 +1,9 @@
+//! Checks the messages the `api` macro reports for declarations it rejects, against the `.stderr` files in `tests/ui`.
+//!
+//! Run with `TRYBUILD=overwrite` to update them after changing a message.
+
+#[test]
+fn ui() {
+    let cases = trybuild::TestCases::new();
+    cases.compile_fail("tests/ui/*.rs");
+}
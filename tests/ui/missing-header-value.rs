@@ -0,0 +1,12 @@
+api_client::api!(pub struct ExampleApi);
+
+impl ExampleApi {
+    api_client::api! {
+        fn example() -> String {
+            GET "https://example.com"
+            USER_AGENT
+        }
+    }
+}
+
+fn main() {}
@@ -0,0 +1,11 @@
+api_client::api!(pub struct ExampleApi);
+
+impl ExampleApi {
+    api_client::api! {
+        fn example() -> Json {
+            GET "https://example.com"
+        }
+    }
+}
+
+fn main() {}
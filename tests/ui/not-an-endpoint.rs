@@ -0,0 +1,3 @@
+api_client::api!(pub enum ExampleApi);
+
+fn main() {}
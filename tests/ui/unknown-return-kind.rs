@@ -0,0 +1,11 @@
+api_client::api!(pub struct ExampleApi);
+
+impl ExampleApi {
+    api_client::api! {
+        fn example() -> Jsn<u32> {
+            GET "https://example.com"
+        }
+    }
+}
+
+fn main() {}
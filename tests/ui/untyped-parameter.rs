@@ -0,0 +1,11 @@
+api_client::api!(pub struct ExampleApi);
+
+impl ExampleApi {
+    api_client::api! {
+        fn example(id) -> String {
+            GET "https://example.com/{id}"
+        }
+    }
+}
+
+fn main() {}